@@ -0,0 +1,238 @@
+/// Multiple APIC Description Table (MADT, signature "APIC")
+/// 
+/// The MADT lists the interrupt controllers of the machine: one local APIC
+/// per CPU, the I/O APICs and how the legacy ISA IRQs are wired to them.
+/// 
+/// ```text
+/// Offset  Size  Field
+/// 0       36    Standard table header
+/// 36      4     Local APIC physical address
+/// 40      4     Flags (bit 0: legacy 8259 PICs present)
+/// 44      ...   Variable-length entries (type, length, data)
+/// ```
+/// 
+/// ## Interrupt source overrides
+/// 
+/// ISA IRQ n is normally connected to I/O APIC input (GSI) n, edge
+/// triggered and active high. Interrupt source overrides describe the
+/// exceptions; on most machines (including QEMU) the PIT's IRQ 0 is wired
+/// to GSI 2, since IRQ 2 is taken by the PIC cascade.
+
+use super::Sdt;
+use crate::arch::x86_64::boot::multiboot2::{read_u16, read_u32, read_u64};
+
+/// MADT flag: the machine also has dual 8259 PICs
+pub const PCAT_COMPAT: u32 = 1 << 0;
+
+/// Number of legacy ISA IRQs
+pub const ISA_IRQ_COUNT: u8 = 16;
+
+/// Pin polarity of an interrupt input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// Trigger mode of an interrupt input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriggerMode {
+    Edge,
+    Level,
+}
+
+/// MPS INTI flags of overrides and NMI entries
+/// 
+/// Bits 0-1 hold the polarity and bits 2-3 the trigger mode; 00 means
+/// "conforms to the bus", which is active high and edge triggered for ISA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntiFlags(pub u16);
+
+impl IntiFlags {
+    /// Pin polarity, resolving "bus conforming" for ISA
+    pub fn polarity(&self) -> Polarity {
+        match self.0 & 0b11 {
+            0b11 => Polarity::ActiveLow,
+            _ => Polarity::ActiveHigh,
+        }
+    }
+    
+    /// Trigger mode, resolving "bus conforming" for ISA
+    pub fn trigger_mode(&self) -> TriggerMode {
+        match (self.0 >> 2) & 0b11 {
+            0b11 => TriggerMode::Level,
+            _ => TriggerMode::Edge,
+        }
+    }
+}
+
+/// A single MADT entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MadtEntry {
+    /// Type 0: a processor and its local APIC
+    LocalApic { processor_id: u8, apic_id: u8, flags: u32 },
+    /// Type 1: an I/O APIC and the first GSI it handles
+    IoApic { id: u8, address: u32, gsi_base: u32 },
+    /// Type 2: an ISA IRQ that is not identity mapped to a GSI
+    InterruptSourceOverride { bus: u8, source: u8, gsi: u32, flags: IntiFlags },
+    /// Type 3: a GSI that should be delivered as an NMI
+    NmiSource { flags: IntiFlags, gsi: u32 },
+    /// Type 4: a local APIC LINT pin wired to NMI (processor 0xFF = all)
+    LocalApicNmi { processor_id: u8, flags: IntiFlags, lint: u8 },
+    /// Type 5: 64-bit local APIC address replacing the one in the header
+    LocalApicAddressOverride { address: u64 },
+    /// Type 9: a processor whose APIC ID does not fit in 8 bits
+    LocalX2Apic { x2apic_id: u32, flags: u32, processor_uid: u32 },
+    /// Any entry type not decoded above
+    Unknown { entry_type: u8, length: u8 },
+}
+
+impl MadtEntry {
+    /// Decode an entry from its type and body (after the 2-byte entry header)
+    fn parse(entry_type: u8, length: u8, body: &[u8]) -> Option<Self> {
+        let byte = |offset: usize| body.get(offset).copied();
+        
+        Some(match entry_type {
+            0 => MadtEntry::LocalApic { processor_id: byte(0)?, apic_id: byte(1)?, flags: read_u32(body, 2)? },
+            1 => MadtEntry::IoApic { id: byte(0)?, address: read_u32(body, 2)?, gsi_base: read_u32(body, 6)? },
+            2 => MadtEntry::InterruptSourceOverride {
+                bus: byte(0)?,
+                source: byte(1)?,
+                gsi: read_u32(body, 2)?,
+                flags: IntiFlags(read_u16(body, 6)?),
+            },
+            3 => MadtEntry::NmiSource { flags: IntiFlags(read_u16(body, 0)?), gsi: read_u32(body, 2)? },
+            4 => MadtEntry::LocalApicNmi { processor_id: byte(0)?, flags: IntiFlags(read_u16(body, 1)?), lint: byte(3)? },
+            5 => MadtEntry::LocalApicAddressOverride { address: read_u64(body, 2)? },
+            9 => MadtEntry::LocalX2Apic {
+                x2apic_id: read_u32(body, 2)?,
+                flags: read_u32(body, 6)?,
+                processor_uid: read_u32(body, 10)?,
+            },
+            _ => MadtEntry::Unknown { entry_type, length },
+        })
+    }
+}
+
+/// Iterator over the entries of a MADT
+/// 
+/// Stops at the first entry whose length is invalid.
+pub struct MadtEntries {
+    data: &'static [u8],
+    offset: usize,
+}
+
+impl Iterator for MadtEntries {
+    type Item = MadtEntry;
+    
+    fn next(&mut self) -> Option<MadtEntry> {
+        let entry_type = *self.data.get(self.offset)?;
+        let length = *self.data.get(self.offset + 1)?;
+        let end = self.offset + length as usize;
+        if length < 2 || end > self.data.len() {
+            self.offset = self.data.len();
+            return None;
+        }
+        
+        let body = &self.data[self.offset + 2..end];
+        self.offset = end;
+        MadtEntry::parse(entry_type, length, body).or(Some(MadtEntry::Unknown { entry_type, length }))
+    }
+}
+
+/// A route from an ISA IRQ to an I/O APIC input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IsaRoute {
+    pub gsi: u32,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+}
+
+/// Parsed MADT
+#[derive(Debug, Clone, Copy)]
+pub struct Madt {
+    /// Physical address of the local APIC registers (after any override)
+    pub local_apic_address: u64,
+    /// MADT flags (see `PCAT_COMPAT`)
+    pub flags: u32,
+    /// Entry area of the table
+    entries: &'static [u8],
+}
+
+impl Madt {
+    /// Parse a MADT from a validated table
+    pub fn parse(table: Sdt) -> Option<Self> {
+        let data = table.data();
+        let mut madt = Madt {
+            local_apic_address: read_u32(data, 0)? as u64,
+            flags: read_u32(data, 4)?,
+            entries: data.get(8..)?,
+        };
+        
+        if let Some(address) = madt.entries().find_map(|entry| match entry {
+            MadtEntry::LocalApicAddressOverride { address } => Some(address),
+            _ => None,
+        }) {
+            madt.local_apic_address = address;
+        }
+        
+        Some(madt)
+    }
+    
+    /// Iterate over all entries
+    pub fn entries(&self) -> MadtEntries {
+        MadtEntries { data: self.entries, offset: 0 }
+    }
+    
+    /// Whether the machine also has the legacy 8259 PICs
+    pub fn has_legacy_pics(&self) -> bool {
+        self.flags & PCAT_COMPAT != 0
+    }
+    
+    /// I/O APICs as (id, address, first GSI)
+    pub fn io_apics(&self) -> impl Iterator<Item = (u8, u32, u32)> {
+        self.entries().filter_map(|entry| match entry {
+            MadtEntry::IoApic { id, address, gsi_base } => Some((id, address, gsi_base)),
+            _ => None,
+        })
+    }
+    
+    /// Interrupt source override for an ISA IRQ, if any
+    fn isa_override(&self, irq: u8) -> Option<(u32, IntiFlags)> {
+        self.entries().find_map(|entry| match entry {
+            MadtEntry::InterruptSourceOverride { bus: 0, source, gsi, flags } if source == irq => Some((gsi, flags)),
+            _ => None,
+        })
+    }
+    
+    /// I/O APIC input, polarity and trigger mode of an ISA IRQ
+    /// 
+    /// Returns None if the IRQ's identity-mapped GSI has been taken over by
+    /// another IRQ (for example GSI 2, which usually carries IRQ 0).
+    pub fn isa_route(&self, irq: u8) -> Option<IsaRoute> {
+        if let Some((gsi, flags)) = self.isa_override(irq) {
+            return Some(IsaRoute { gsi, polarity: flags.polarity(), trigger_mode: flags.trigger_mode() });
+        }
+        
+        let claimed = self.entries().any(|entry| {
+            matches!(entry, MadtEntry::InterruptSourceOverride { bus: 0, source, gsi, .. }
+                if source != irq && gsi == irq as u32)
+        });
+        if claimed {
+            return None;
+        }
+        
+        Some(IsaRoute { gsi: irq as u32, polarity: Polarity::ActiveHigh, trigger_mode: TriggerMode::Edge })
+    }
+    
+    /// Number of enabled processors (local APIC entries with the enabled bit)
+    #[allow(dead_code)]
+    pub fn processor_count(&self) -> usize {
+        self.entries()
+            .filter(|entry| match entry {
+                MadtEntry::LocalApic { flags, .. } | MadtEntry::LocalX2Apic { flags, .. } => flags & 1 != 0,
+                _ => false,
+            })
+            .count()
+    }
+}
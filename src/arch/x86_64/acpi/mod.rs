@@ -0,0 +1,313 @@
+/// ACPI Table Discovery
+/// 
+/// The firmware describes the machine's interrupt controllers, timers and
+/// other platform devices in ACPI tables. They are found through the Root
+/// System Description Pointer (RSDP), which GRUB copies into the boot
+/// information (tag 14 for ACPI 1.0, tag 15 for ACPI 2.0+):
+/// 
+/// ```text
+/// RSDP -> RSDT (32-bit table pointers, ACPI 1.0)
+///      -> XSDT (64-bit table pointers, ACPI 2.0+)
+///           -> "APIC" (MADT), "FACP" (FADT), "HPET", ...
+/// ```
+/// 
+/// Every table starts with the same 36-byte header and is protected by a
+/// checksum: all bytes of the table must add up to zero. Tables are
+/// identity mapped on first access, since they may lie above the first
+/// 1GB mapped at boot.
+/// 
+/// `copy_tables` copies the root table and the tables it lists to the
+/// kernel heap, after which they are read from there and the memory the
+/// firmware stored them in can be reclaimed. Tables only reached through
+/// the FADT (the DSDT and FACS) are not copied: the FACS is shared with the
+/// firmware, and the copied FADT still points at both. `fadt_tables`
+/// returns their ranges, which reclaiming leaves in place.
+/// 
+/// ## Usage
+/// 
+/// ```rust
+/// acpi::init(&boot_info)?;
+/// if let Some(madt) = acpi::madt() {
+///     println!("Local APIC at {:#x}", madt.local_apic_address);
+/// }
+/// ```
+
+pub mod madt;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use super::boot::multiboot2::{read_u32, read_u64, BootInfo};
+use super::memory::{identity_map_region, PageTableFlags};
+pub use madt::Madt;
+
+/// Size of the header shared by all system description tables
+pub const SDT_HEADER_SIZE: usize = 36;
+
+/// Largest table accepted (guards against a corrupt length field)
+const MAX_TABLE_SIZE: u32 = 1024 * 1024;
+
+/// Offsets of the 32-bit FACS and DSDT addresses in the FADT data (right
+/// after the header)
+const FADT_FIRMWARE_CTRL: usize = 0;
+const FADT_DSDT: usize = 40 - 36;
+/// Offsets of their 64-bit forms (ACPI 2.0+), used when not zero
+const FADT_X_FIRMWARE_CTRL: usize = 132 - 36;
+const FADT_X_DSDT: usize = 140 - 36;
+
+/// Errors that can occur while locating the ACPI tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// The boot information has no RSDP tag
+    NoRsdp,
+    /// The RSDP signature or checksum is wrong
+    InvalidRsdp,
+    /// A table could not be mapped
+    MapFailed(u64),
+    /// A table has a bad length or checksum
+    InvalidTable(u64),
+}
+
+impl core::fmt::Display for AcpiError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            AcpiError::NoRsdp => write!(f, "no RSDP in the boot information"),
+            AcpiError::InvalidRsdp => write!(f, "invalid RSDP"),
+            AcpiError::MapFailed(addr) => write!(f, "could not map table at {:#x}", addr),
+            AcpiError::InvalidTable(addr) => write!(f, "invalid table at {:#x}", addr),
+        }
+    }
+}
+
+/// A validated system description table
+#[derive(Debug, Clone, Copy)]
+pub struct Sdt {
+    /// Physical address the firmware stored the table at
+    pub addr: u64,
+    /// The whole table, including the header (in place or a copy)
+    bytes: &'static [u8],
+}
+
+impl Sdt {
+    /// Map a table and check its length and checksum
+    /// 
+    /// # Safety
+    /// `addr` must be the physical address of an ACPI table.
+    unsafe fn load(addr: u64) -> Result<Self, AcpiError> {
+        let flags = PageTableFlags::PRESENT;
+        identity_map_region(addr, SDT_HEADER_SIZE as u64, flags).map_err(|_| AcpiError::MapFailed(addr))?;
+        
+        let length = *((addr + 4) as *const u32);
+        if (length as usize) < SDT_HEADER_SIZE || length > MAX_TABLE_SIZE {
+            return Err(AcpiError::InvalidTable(addr));
+        }
+        identity_map_region(addr, length as u64, flags).map_err(|_| AcpiError::MapFailed(addr))?;
+        
+        let bytes = core::slice::from_raw_parts(addr as *const u8, length as usize);
+        if checksum(bytes) != 0 {
+            return Err(AcpiError::InvalidTable(addr));
+        }
+        Ok(Sdt { addr, bytes })
+    }
+    
+    /// Copy the table to the kernel heap, where it is kept for good
+    fn copy(&self) -> Sdt {
+        Sdt { addr: self.addr, bytes: Box::leak(Box::from(self.bytes)) }
+    }
+    
+    /// Four-character table signature (e.g. "APIC")
+    pub fn signature(&self) -> &'static str {
+        core::str::from_utf8(&self.bytes[0..4]).unwrap_or("????")
+    }
+    
+    /// Table revision
+    pub fn revision(&self) -> u8 {
+        self.bytes[8]
+    }
+    
+    /// OEM identifier
+    pub fn oem_id(&self) -> &'static str {
+        core::str::from_utf8(&self.bytes[10..16]).unwrap_or("")
+    }
+    
+    /// Table length in bytes
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+    
+    /// Table contents after the header
+    pub fn data(&self) -> &'static [u8] {
+        &self.bytes[SDT_HEADER_SIZE..]
+    }
+}
+
+/// Sum of all bytes, modulo 256 (zero for a valid table)
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+/// Root table (RSDT or XSDT) and the size of its table pointers
+#[derive(Debug, Clone, Copy)]
+struct RootTable {
+    sdt: Sdt,
+    pointer_size: usize,
+}
+
+impl RootTable {
+    /// Physical addresses of all tables listed in the root table
+    fn table_addresses(&self) -> impl Iterator<Item = u64> {
+        let data = self.sdt.data();
+        let pointer_size = self.pointer_size;
+        (0..data.len() / pointer_size).filter_map(move |i| match pointer_size {
+            8 => read_u64(data, i * 8),
+            _ => read_u32(data, i * 4).map(u64::from),
+        })
+    }
+}
+
+/// Root table, set once by `init`
+static mut ROOT_TABLE: Option<RootTable> = None;
+
+/// Copies of the listed tables, set once by `copy_tables`
+static mut TABLE_COPIES: Option<&'static [Sdt]> = None;
+
+/// Locate the ACPI tables through the RSDP copy in the boot information
+/// 
+/// Returns the ACPI revision from the RSDP (0 for ACPI 1.0).
+pub fn init(boot_info: &BootInfo) -> Result<u8, AcpiError> {
+    let rsdp = boot_info.acpi_new_rsdp().or_else(|| boot_info.acpi_old_rsdp()).ok_or(AcpiError::NoRsdp)?;
+    
+    // RSDP: "RSD PTR " signature, checksum over the first 20 bytes,
+    // revision at 15, RSDT address at 16; ACPI 2.0+ adds the length at 20,
+    // the XSDT address at 24 and an extended checksum over the whole RSDP
+    if rsdp.len() < 20 || &rsdp[0..8] != b"RSD PTR " || checksum(&rsdp[..20]) != 0 {
+        return Err(AcpiError::InvalidRsdp);
+    }
+    let revision = rsdp[15];
+    
+    let xsdt_address = match read_u32(rsdp, 20) {
+        Some(length) if revision >= 2 && length as usize <= rsdp.len() && checksum(&rsdp[..length as usize]) == 0 => {
+            read_u64(rsdp, 24).filter(|&addr| addr != 0)
+        }
+        _ => None,
+    };
+    
+    let root = match xsdt_address {
+        Some(addr) => RootTable { sdt: unsafe { Sdt::load(addr)? }, pointer_size: 8 },
+        None => {
+            let addr = read_u32(rsdp, 16).ok_or(AcpiError::InvalidRsdp)? as u64;
+            RootTable { sdt: unsafe { Sdt::load(addr)? }, pointer_size: 4 }
+        }
+    };
+    
+    unsafe { *core::ptr::addr_of_mut!(ROOT_TABLE) = Some(root) };
+    Ok(revision)
+}
+
+/// Root table, if `init` succeeded
+fn root_table() -> Option<RootTable> {
+    unsafe { *core::ptr::addr_of!(ROOT_TABLE) }
+}
+
+/// Copies of the tables, if `copy_tables` has run
+fn table_copies() -> Option<&'static [Sdt]> {
+    unsafe { *core::ptr::addr_of!(TABLE_COPIES) }
+}
+
+/// Copy the root table and every valid table it lists to the kernel heap
+/// 
+/// From then on all tables are read from the copies. Returns the number of
+/// tables copied, or None if `init` has not found the tables.
+pub fn copy_tables() -> Option<usize> {
+    if let Some(copies) = table_copies() {
+        return Some(copies.len());
+    }
+    let root = root_table()?;
+    let copies: Vec<Sdt> = tables().map(|table| table.copy()).collect();
+    let count = copies.len();
+    unsafe {
+        *core::ptr::addr_of_mut!(ROOT_TABLE) = Some(RootTable { sdt: root.sdt.copy(), ..root });
+        *core::ptr::addr_of_mut!(TABLE_COPIES) = Some(copies.leak());
+    }
+    Some(count)
+}
+
+/// Iterate over all valid tables listed in the root table
+/// 
+/// Tables that cannot be mapped or fail their checksum are skipped.
+pub fn tables() -> impl Iterator<Item = Sdt> {
+    let copies = table_copies();
+    let in_place = root_table().filter(|_| copies.is_none());
+    copies.into_iter().flatten().copied().chain(
+        in_place
+            .into_iter()
+            .flat_map(|root| root.table_addresses())
+            .filter_map(|addr| unsafe { Sdt::load(addr) }.ok()),
+    )
+}
+
+/// Find a table by its signature
+pub fn find_table(signature: &str) -> Option<Sdt> {
+    tables().find(|table| table.signature() == signature)
+}
+
+/// The Multiple APIC Description Table, if present
+pub fn madt() -> Option<Madt> {
+    Madt::parse(find_table("APIC")?)
+}
+
+/// Physical address of a table the FADT points at, preferring the 64-bit
+/// field
+fn fadt_pointer(fadt: &Sdt, offset: usize, x_offset: usize) -> Option<u64> {
+    let data = fadt.data();
+    read_u64(data, x_offset)
+        .filter(|&addr| addr != 0)
+        .or_else(|| read_u32(data, offset).map(u64::from))
+        .filter(|&addr| addr != 0)
+}
+
+/// The Differentiated System Description Table, read in place
+pub fn dsdt() -> Option<Sdt> {
+    let fadt = find_table("FACP")?;
+    let addr = fadt_pointer(&fadt, FADT_DSDT, FADT_X_DSDT)?;
+    unsafe { Sdt::load(addr) }.ok()
+}
+
+/// Physical ranges of the DSDT and FACS, which the FADT points at
+/// 
+/// Both stay where the firmware put them, so their memory must not be
+/// reclaimed.
+pub fn fadt_tables() -> impl Iterator<Item = (u64, u64)> {
+    let dsdt = dsdt().map(|table| (table.addr, table.addr + table.len() as u64));
+    let facs = find_table("FACP")
+        .and_then(|fadt| fadt_pointer(&fadt, FADT_FIRMWARE_CTRL, FADT_X_FIRMWARE_CTRL))
+        .and_then(|addr| {
+            // The FACS has the length at the same place as other tables, but
+            // no checksum
+            identity_map_region(addr, SDT_HEADER_SIZE as u64, PageTableFlags::PRESENT).ok()?;
+            let length = unsafe { *((addr + 4) as *const u32) };
+            Some((addr, addr + length.min(MAX_TABLE_SIZE) as u64))
+        });
+    dsdt.into_iter().chain(facs)
+}
+
+/// Print the signature, address and OEM of every table
+pub fn print_tables() {
+    use crate::println;
+    
+    let Some(root) = root_table() else {
+        println!("ACPI tables not available");
+        return;
+    };
+    
+    println!("ACPI tables ({} at {:#x}):", root.sdt.signature(), root.sdt.addr);
+    for table in tables() {
+        println!(
+            "  {} at {:#010x}, {} bytes, revision {}, OEM {}",
+            table.signature(),
+            table.addr,
+            table.len(),
+            table.revision(),
+            table.oem_id()
+        );
+    }
+}
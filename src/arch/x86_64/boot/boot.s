@@ -279,6 +279,7 @@ long_mode_start:
     mov rdi, [multiboot_info_ptr]  ; Load multiboot info address into RDI
     mov rsi, [multiboot_magic]      ; Load multiboot magic number into RSI
     extern kernel_main
+    xor rbp, rbp                   ; Null frame pointer ends the backtrace chain
     call kernel_main               ; Call the Rust kernel entry point
     
     ; If kernel_main ever returns (it shouldn't - it's marked as -> !), halt
@@ -314,6 +315,8 @@ page_table_l2:
 
 ; Stack space (64KB = 65536 bytes)
 ; Stack grows downward from stack_top toward stack_bottom
+; stack_bottom is exported so the kernel can turn the lowest page into a guard page
+global stack_bottom
 stack_bottom:
     resb 65536               ; Reserve 64KB for stack
 stack_top:                   ; Top of stack (highest address)
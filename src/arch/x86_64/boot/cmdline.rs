@@ -0,0 +1,314 @@
+/// Kernel Command Line
+/// 
+/// GRUB passes everything after the kernel path on the `multiboot2` line
+/// to the kernel in tag 1 of the boot information:
+/// 
+/// ```text
+/// multiboot2 /boot/noodleos.bin console=serial loglevel=warn mem=512M
+/// ```
+/// 
+/// Options are separated by spaces and are either `name=value` pairs or
+/// bare flags. A value containing spaces can be quoted (`name="a b"`). If
+/// an option appears more than once, the last occurrence wins.
+/// 
+/// ## Declaring options
+/// 
+/// Every option has a name and a kind that says how its value is parsed.
+/// A module declares its options in a static table, which is registered
+/// during boot before the options are read:
+/// 
+/// ```rust
+/// pub static BOOT_OPTIONS: [BootOption; 1] = [
+///     BootOption::new("mem", OptionKind::Size, "Limit usable physical memory"),
+/// ];
+/// 
+/// if let Some(limit) = cmdline::size("mem") {
+///     // ...
+/// }
+/// ```
+/// 
+/// Reading an option that was not registered with the matching kind
+/// returns nothing, as does an option whose value does not parse.
+/// `report_problems` prints a warning for every option on the command line
+/// that no module declared or whose value is invalid.
+
+use crate::arch::x86_64::sync::SpinLock;
+use crate::println;
+
+/// Maximum number of registered option tables
+const MAX_OPTION_TABLES: usize = 16;
+
+/// Options handled by the command line parser itself
+pub static BOOT_OPTIONS: [BootOption; 1] = [
+    BootOption::new("help", OptionKind::Flag, "List the boot options at startup"),
+];
+
+/// How the value of an option is parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionKind {
+    /// Bare flag without a value (`noapic`)
+    Flag,
+    /// Decimal or `0x`-prefixed hexadecimal number (`loglevel=3`)
+    Number,
+    /// Number of bytes with an optional K, M or G suffix (`mem=512M`)
+    Size,
+    /// Any text (`root="disk 0"`)
+    Str,
+    /// Comma-separated list of words (`console=serial,vga`)
+    List,
+    /// One of a fixed set of words (`loglevel=debug`)
+    Choice(&'static [&'static str]),
+}
+
+/// A boot option declared by a module
+#[derive(Debug, Clone, Copy)]
+pub struct BootOption {
+    /// Name of the option on the command line
+    pub name: &'static str,
+    /// How the value is parsed
+    pub kind: OptionKind,
+    /// One-line description of the option
+    pub help: &'static str,
+}
+
+impl BootOption {
+    /// Declare a boot option
+    pub const fn new(name: &'static str, kind: OptionKind, help: &'static str) -> Self {
+        Self { name, kind, help }
+    }
+    
+    /// Check that a value from the command line parses for this option
+    fn accepts(&self, value: Option<&str>) -> bool {
+        match (self.kind, value) {
+            (OptionKind::Flag, None) => true,
+            (OptionKind::Number, Some(value)) => parse_number(value).is_some(),
+            (OptionKind::Size, Some(value)) => parse_size(value).is_some(),
+            (OptionKind::Str, Some(_)) => true,
+            (OptionKind::List, Some(_)) => true,
+            (OptionKind::Choice(choices), Some(value)) => choices.contains(&value),
+            _ => false,
+        }
+    }
+}
+
+/// Errors that can occur when registering options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmdlineError {
+    /// All option table slots are in use
+    RegistryFull,
+    /// An option with the same name is already registered
+    AlreadyRegistered,
+}
+
+/// Registered option tables
+static OPTIONS: SpinLock<[Option<&'static [BootOption]>; MAX_OPTION_TABLES]> =
+    SpinLock::new([None; MAX_OPTION_TABLES]);
+
+/// Command line passed by the bootloader, set once by `init`
+static mut COMMAND_LINE: &str = "";
+
+/// Set the command line the options are read from
+/// 
+/// # Safety
+/// Must be called once during early boot, before any option is read.
+pub unsafe fn init(command_line: &'static str) {
+    *core::ptr::addr_of_mut!(COMMAND_LINE) = command_line;
+}
+
+/// The full command line passed by the bootloader
+pub fn command_line() -> &'static str {
+    unsafe { *core::ptr::addr_of!(COMMAND_LINE) }
+}
+
+/// Register a table of options declared by a module
+pub fn register_options(options: &'static [BootOption]) -> Result<(), CmdlineError> {
+    let mut tables = OPTIONS.lock();
+    
+    let registered = |name: &str| tables.iter().flatten().any(|table| table.iter().any(|o| o.name == name));
+    if options.iter().any(|option| registered(option.name)) {
+        return Err(CmdlineError::AlreadyRegistered);
+    }
+    
+    match tables.iter_mut().find(|t| t.is_none()) {
+        Some(slot) => {
+            *slot = Some(options);
+            Ok(())
+        }
+        None => Err(CmdlineError::RegistryFull),
+    }
+}
+
+/// Find a registered option by name
+pub fn find_option(name: &str) -> Option<BootOption> {
+    let tables = OPTIONS.lock();
+    tables.iter().flatten().flat_map(|table| table.iter()).find(|o| o.name == name).copied()
+}
+
+/// Print every registered option with its description
+pub fn print_options() {
+    let tables = OPTIONS.lock();
+    
+    println!("Boot options:");
+    for option in tables.iter().flatten().flat_map(|table| table.iter()) {
+        println!("  {:<12} {}", option.name, option.help);
+    }
+}
+
+/// Iterator over the options on a command line, as `(name, value)` pairs
+pub struct Tokens {
+    rest: &'static str,
+}
+
+impl Tokens {
+    /// Split a command line into options
+    pub fn new(command_line: &'static str) -> Self {
+        Self { rest: command_line }
+    }
+}
+
+impl Iterator for Tokens {
+    type Item = (&'static str, Option<&'static str>);
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        let rest = self.rest.trim_start();
+        if rest.is_empty() {
+            self.rest = rest;
+            return None;
+        }
+        
+        let name_end = rest.find(|c: char| c == '=' || c.is_whitespace()).unwrap_or(rest.len());
+        let name = &rest[..name_end];
+        let after_name = &rest[name_end..];
+        
+        let Some(value) = after_name.strip_prefix('=') else {
+            self.rest = after_name;
+            return Some((name, None));
+        };
+        
+        // A quoted value runs to the closing quote (or the end of the line)
+        let (value, rest) = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.find('"') {
+                Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                None => (quoted, ""),
+            },
+            None => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+        
+        self.rest = rest;
+        Some((name, Some(value)))
+    }
+}
+
+/// Value of the last occurrence of an option registered with a kind
+/// accepted by `kind`
+/// 
+/// Returns `Some(None)` for a bare flag and None if the option is not on
+/// the command line, is not registered with a matching kind, or has a
+/// value that does not parse.
+fn value_of(name: &str, kind: impl Fn(OptionKind) -> bool) -> Option<Option<&'static str>> {
+    let option = find_option(name).filter(|o| kind(o.kind))?;
+    let value = Tokens::new(command_line()).filter(|&(n, _)| n == name).last()?.1;
+    option.accepts(value).then_some(value)
+}
+
+/// Check whether a flag option is present
+pub fn flag(name: &str) -> bool {
+    value_of(name, |kind| kind == OptionKind::Flag).is_some()
+}
+
+/// Value of a number option
+pub fn number(name: &str) -> Option<u64> {
+    parse_number(value_of(name, |kind| kind == OptionKind::Number)??)
+}
+
+/// Value of a size option, in bytes
+pub fn size(name: &str) -> Option<u64> {
+    parse_size(value_of(name, |kind| kind == OptionKind::Size)??)
+}
+
+/// Value of a string option
+pub fn string(name: &str) -> Option<&'static str> {
+    value_of(name, |kind| kind == OptionKind::Str)?
+}
+
+/// Items of a list option (empty items are skipped)
+pub fn list(name: &str) -> Option<impl Iterator<Item = &'static str>> {
+    let value = value_of(name, |kind| kind == OptionKind::List)??;
+    Some(value.split(',').filter(|item| !item.is_empty()))
+}
+
+/// Value of a choice option
+pub fn choice(name: &str) -> Option<&'static str> {
+    value_of(name, |kind| matches!(kind, OptionKind::Choice(_)))?
+}
+
+/// Print a warning for every option on the command line that is not
+/// registered or has an invalid value
+/// 
+/// Returns the number of problems found.
+pub fn report_problems() -> usize {
+    let mut problems = 0;
+    
+    for (name, value) in Tokens::new(command_line()) {
+        match find_option(name) {
+            None => {
+                println!("Warning: unknown boot option '{}'", name);
+                problems += 1;
+            }
+            Some(option) if !option.accepts(value) => {
+                println!("Warning: invalid value for boot option '{}' ({})", name, KindName(option.kind));
+                problems += 1;
+            }
+            Some(_) => {}
+        }
+    }
+    
+    problems
+}
+
+/// Display wrapper describing the value an option kind expects
+struct KindName(OptionKind);
+
+impl core::fmt::Display for KindName {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.0 {
+            OptionKind::Flag => write!(f, "flag, takes no value"),
+            OptionKind::Number => write!(f, "expected a number"),
+            OptionKind::Size => write!(f, "expected a size such as 512M"),
+            OptionKind::Str => write!(f, "expected a value"),
+            OptionKind::List => write!(f, "expected a comma-separated list"),
+            OptionKind::Choice(choices) => {
+                write!(f, "expected one of ")?;
+                for (i, choice) in choices.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", choice)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parse a decimal or `0x`-prefixed hexadecimal number
+pub fn parse_number(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+/// Parse a size with an optional K, M or G suffix
+pub fn parse_size(value: &str) -> Option<u64> {
+    let (number, shift) = match value.as_bytes().last()? {
+        b'K' | b'k' => (&value[..value.len() - 1], 10),
+        b'M' | b'm' => (&value[..value.len() - 1], 20),
+        b'G' | b'g' => (&value[..value.len() - 1], 30),
+        _ => (value, 0),
+    };
+    parse_number(number)?.checked_mul(1 << shift)
+}
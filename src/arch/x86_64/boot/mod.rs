@@ -5,7 +5,9 @@
 /// - Long mode transition from 32-bit protected mode
 /// - Initial page table setup
 /// - GDT configuration for 64-bit mode
+/// - Multiboot2 boot information and kernel command line parsing
 
+pub mod cmdline;
 pub mod multiboot2;
 
-pub use multiboot2::{BootInfo, MULTIBOOT2_MAGIC};
+pub use multiboot2::{init, BootInfo};
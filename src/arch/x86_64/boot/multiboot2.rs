@@ -2,9 +2,37 @@
 /// 
 /// This module provides structures and functions to parse the Multiboot2
 /// boot information structure provided by GRUB.
+/// 
+/// The structure is a header (`total_size`, reserved) followed by a list of
+/// 8-byte aligned tags, each starting with a `type` and `size` field and the
+/// last one being the end tag. `BootInfo::new` walks the whole list once
+/// and checks every tag against `total_size`, so a malformed structure is
+/// rejected with a `BootInfoError` instead of reading wild memory. After
+/// that, each typed accessor only reads within its own tag.
+/// 
+/// ## Usage
+/// 
+/// ```rust
+/// let boot_info = boot::init(multiboot_info_addr, multiboot_magic)?;
+/// 
+/// if let Some(cmdline) = boot_info.command_line() {
+///     println!("Command line: {}", cmdline);
+/// }
+/// for module in boot_info.modules() {
+///     println!("Module {:#x}-{:#x} {}", module.start, module.end, module.cmdline);
+/// }
+/// ```
+
+use core::fmt;
 
 pub const MULTIBOOT2_MAGIC: u32 = 0x36d76289;
 
+/// Largest boot information structure we accept (GRUB's is a few KB)
+const MAX_TOTAL_SIZE: usize = 1024 * 1024;
+
+/// Size of the boot information header and of every tag header
+const HEADER_SIZE: usize = 8;
+
 /// Multiboot2 tag types
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -33,6 +61,51 @@ pub enum TagType {
     ImageLoadBasePhysicalAddress = 21,
 }
 
+impl TagType {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        use TagType::*;
+        
+        const TYPES: [TagType; 22] = [
+            End, BootCommandLine, BootLoaderName, Module, BasicMemInfo, BiosBootDevice,
+            MemoryMap, VbeInfo, FramebufferInfo, ElfSymbols, ApmTable, Efi32BitSystemTable,
+            Efi64BitSystemTable, SmbiosTables, AcpiOldRsdp, AcpiNewRsdp, NetworkingInfo,
+            EfiMemoryMap, EfiBootServicesNotTerminated, Efi32BitImageHandle,
+            Efi64BitImageHandle, ImageLoadBasePhysicalAddress,
+        ];
+        TYPES.get(value as usize).copied()
+    }
+}
+
+/// Errors found while validating the boot information structure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootInfoError {
+    /// The bootloader did not pass the Multiboot2 magic number
+    InvalidMagic(u32),
+    /// The structure address is null
+    NullAddress,
+    /// The structure address is not 8-byte aligned
+    Misaligned(usize),
+    /// `total_size` is too small or too large
+    InvalidTotalSize(u32),
+    /// A tag at this offset is too small or extends past `total_size`
+    TagOutOfBounds(usize),
+    /// The tag list does not end with an end tag
+    MissingEndTag,
+}
+
+impl fmt::Display for BootInfoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BootInfoError::InvalidMagic(magic) => write!(f, "invalid Multiboot2 magic {:#010x}", magic),
+            BootInfoError::NullAddress => write!(f, "boot information address is null"),
+            BootInfoError::Misaligned(addr) => write!(f, "boot information at {:#x} is not 8-byte aligned", addr),
+            BootInfoError::InvalidTotalSize(size) => write!(f, "invalid total size {}", size),
+            BootInfoError::TagOutOfBounds(offset) => write!(f, "tag at offset {:#x} is out of bounds", offset),
+            BootInfoError::MissingEndTag => write!(f, "missing end tag"),
+        }
+    }
+}
+
 /// Memory map entry type
 #[repr(u32)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -77,37 +150,83 @@ pub struct MemoryMapEntry {
     _reserved: u32,
 }
 
-/// Generic Multiboot2 tag header
-#[repr(C, packed)]
-struct TagHeader {
-    tag_type: u32,
-    size: u32,
+/// Read a little-endian u16 at `offset`, if it is in bounds
+pub(crate) fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(offset..offset + 2)?.try_into().ok()?))
 }
 
-/// Memory map tag structure
-#[repr(C, packed)]
-struct MemoryMapTag {
-    tag_type: u32,
-    size: u32,
-    entry_size: u32,
-    entry_version: u32,
-    // Followed by memory map entries
+/// Read a little-endian u32 at `offset`, if it is in bounds
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(offset..offset + 4)?.try_into().ok()?))
 }
 
-/// Boot info structure header
-#[repr(C, packed)]
-struct BootInfoHeader {
-    total_size: u32,
-    _reserved: u32,
-    // Followed by tags
+/// Read a little-endian u64 at `offset`, if it is in bounds
+pub(crate) fn read_u64(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(offset..offset + 8)?.try_into().ok()?))
+}
+
+/// Read a null-terminated UTF-8 string starting at `offset`
+fn read_str(bytes: &[u8], offset: usize) -> Option<&str> {
+    let bytes = bytes.get(offset..)?;
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    core::str::from_utf8(&bytes[..len]).ok()
+}
+
+/// A single tag of the boot information structure
+#[derive(Debug, Clone, Copy)]
+pub struct Tag {
+    /// Raw tag type (see `TagType`)
+    pub tag_type: u32,
+    /// The whole tag, including its 8-byte header
+    bytes: &'static [u8],
+}
+
+impl Tag {
+    /// Tag type, if it is one we know
+    pub fn kind(&self) -> Option<TagType> {
+        TagType::from_u32(self.tag_type)
+    }
+    
+    /// Tag size in bytes, including the header
+    pub fn size(&self) -> usize {
+        self.bytes.len()
+    }
+    
+    /// The tag contents after the 8-byte header
+    pub fn payload(&self) -> &'static [u8] {
+        &self.bytes[HEADER_SIZE..]
+    }
+}
+
+/// Iterator over the tags of a validated boot information structure
+pub struct TagIter {
+    remaining: &'static [u8],
+}
+
+impl Iterator for TagIter {
+    type Item = Tag;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        // BootInfo::new has checked every tag, so these reads are in bounds
+        let tag_type = read_u32(self.remaining, 0)?;
+        let size = read_u32(self.remaining, 4)? as usize;
+        if tag_type == TagType::End as u32 {
+            return None;
+        }
+        
+        let bytes = self.remaining.get(..size)?;
+        // Tags start on 8-byte boundaries
+        let next = size.next_multiple_of(8).min(self.remaining.len());
+        self.remaining = &self.remaining[next..];
+        Some(Tag { tag_type, bytes })
+    }
 }
 
 /// Iterator over memory map entries
 /// This implements the standard Rust Iterator trait so we can use for loops
 pub struct MemoryMapIter {
-    current: *const u8,     // Points to the current entry we're reading
-    end: *const u8,         // Points past the last entry
-    entry_size: u32,        // How many bytes each entry takes (from Multiboot2)
+    entries: &'static [u8],  // The remaining entries
+    entry_size: usize,       // How many bytes each entry takes (from Multiboot2)
 }
 
 impl Iterator for MemoryMapIter {
@@ -115,101 +234,518 @@ impl Iterator for MemoryMapIter {
     
     fn next(&mut self) -> Option<Self::Item> {
         // Check if we've reached the end
-        if self.current >= self.end {
-            return None;
-        }
+        let entry = self.entries.get(..self.entry_size)?;
+        self.entries = &self.entries[self.entry_size..];
         
-        unsafe {
-            // Cast the raw pointer to MemoryMapEntry and dereference it
-            // This works because Multiboot2 guarantees the memory layout matches our struct
-            let entry = *(self.current as *const MemoryMapEntry);
-            // Move pointer forward by entry_size bytes to the next entry
-            self.current = self.current.add(self.entry_size as usize);
-            Some(entry)
-        }
+        // entry_size is at least the size of MemoryMapEntry (checked when
+        // the iterator was created)
+        Some(unsafe { core::ptr::read_unaligned(entry.as_ptr() as *const MemoryMapEntry) })
+    }
+}
+
+/// ELF64 section header (`Elf64_Shdr`) for one of the kernel's sections
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ElfSectionHeader {
+    pub name: u32,
+    pub section_type: u32,
+    pub flags: u64,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    pub link: u32,
+    pub info: u32,
+    pub addralign: u64,
+    pub entsize: u64,
+}
+
+impl ElfSectionHeader {
+    /// Section holds a symbol table
+    pub const TYPE_SYMTAB: u32 = 2;
+    /// Section holds a string table
+    pub const TYPE_STRTAB: u32 = 3;
+    /// Section occupies memory while the kernel runs
+    pub const FLAG_ALLOC: u64 = 0x2;
+    
+    /// Returns true if the section is part of the loaded kernel image
+    pub fn is_allocated(&self) -> bool {
+        self.flags & Self::FLAG_ALLOC != 0
+    }
+    
+    /// Returns true if the bootloader copied this non-allocated section
+    /// (for example `.symtab` or `.strtab`) into memory outside the
+    /// kernel image
+    pub fn is_loaded_by_bootloader(&self) -> bool {
+        !self.is_allocated() && self.addr != 0 && self.size != 0
+    }
+}
+
+/// Iterator over the kernel's ELF section headers
+pub struct ElfSectionIter {
+    headers: &'static [u8],
+    entry_size: usize,
+}
+
+impl Iterator for ElfSectionIter {
+    type Item = ElfSectionHeader;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.headers.get(..self.entry_size)?;
+        self.headers = &self.headers[self.entry_size..];
+        
+        // Section headers are only 4-byte aligned inside the tag
+        Some(unsafe { core::ptr::read_unaligned(header.as_ptr() as *const ElfSectionHeader) })
+    }
+}
+
+/// A boot module (for example an initrd) loaded by the bootloader
+#[derive(Debug, Clone, Copy)]
+pub struct Module {
+    /// Physical start address
+    pub start: u32,
+    /// Physical end address (exclusive)
+    pub end: u32,
+    /// Module command line (as given in the GRUB config)
+    pub cmdline: &'static str,
+}
+
+/// Amount of lower and upper memory in KB (tag 4)
+#[derive(Debug, Clone, Copy)]
+pub struct BasicMemoryInfo {
+    /// Memory below 1MB, in KB
+    pub mem_lower: u32,
+    /// Memory above 1MB up to the first hole, in KB
+    pub mem_upper: u32,
+}
+
+/// BIOS device the kernel was loaded from (tag 5)
+#[derive(Debug, Clone, Copy)]
+pub struct BiosBootDevice {
+    /// BIOS drive number (0x80 is the first hard disk)
+    pub bios_device: u32,
+    /// Top-level partition number, 0xFFFFFFFF if none
+    pub partition: u32,
+    /// Sub-partition number, 0xFFFFFFFF if none
+    pub sub_partition: u32,
+}
+
+/// Pixel format of the framebuffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramebufferKind {
+    /// Palette-based colours, with this many palette entries
+    Indexed { palette_colors: u32 },
+    /// Direct RGB colour, with the bit position and size of each channel
+    Rgb {
+        red_position: u8,
+        red_size: u8,
+        green_position: u8,
+        green_size: u8,
+        blue_position: u8,
+        blue_size: u8,
+    },
+    /// EGA text mode (like the VGA text buffer)
+    EgaText,
+}
+
+/// Framebuffer set up by the bootloader (tag 8)
+#[derive(Debug, Clone, Copy)]
+pub struct FramebufferInfo {
+    /// Physical address of the framebuffer
+    pub addr: u64,
+    /// Bytes per line
+    pub pitch: u32,
+    /// Width in pixels (or characters for text mode)
+    pub width: u32,
+    /// Height in pixels (or characters for text mode)
+    pub height: u32,
+    /// Bits per pixel
+    pub bpp: u8,
+    /// Pixel format
+    pub kind: FramebufferKind,
+}
+
+/// SMBIOS tables copied by the bootloader (tag 13)
+#[derive(Debug, Clone, Copy)]
+pub struct SmbiosTables {
+    pub major: u8,
+    pub minor: u8,
+    /// The SMBIOS entry point structure and tables
+    pub tables: &'static [u8],
+}
+
+/// EFI memory descriptor (`EFI_MEMORY_DESCRIPTOR`)
+#[derive(Debug, Clone, Copy)]
+pub struct EfiMemoryDescriptor {
+    pub mem_type: u32,
+    pub physical_start: u64,
+    pub virtual_start: u64,
+    pub page_count: u64,
+    pub attributes: u64,
+}
+
+/// EFI memory type (`EFI_MEMORY_TYPE`)
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EfiMemoryType {
+    Reserved = 0,
+    LoaderCode = 1,
+    LoaderData = 2,
+    BootServicesCode = 3,
+    BootServicesData = 4,
+    RuntimeServicesCode = 5,
+    RuntimeServicesData = 6,
+    Conventional = 7,
+    Unusable = 8,
+    AcpiReclaim = 9,
+    AcpiNvs = 10,
+    MemoryMappedIo = 11,
+    MemoryMappedIoPortSpace = 12,
+    PalCode = 13,
+    Persistent = 14,
+}
+
+impl EfiMemoryType {
+    pub fn from_u32(value: u32) -> Option<Self> {
+        use EfiMemoryType::*;
+        
+        const TYPES: [EfiMemoryType; 15] = [
+            Reserved, LoaderCode, LoaderData, BootServicesCode, BootServicesData,
+            RuntimeServicesCode, RuntimeServicesData, Conventional, Unusable, AcpiReclaim,
+            AcpiNvs, MemoryMappedIo, MemoryMappedIoPortSpace, PalCode, Persistent,
+        ];
+        TYPES.get(value as usize).copied()
+    }
+}
+
+/// Iterator over the EFI memory map (tag 17)
+pub struct EfiMemoryMapIter {
+    descriptors: &'static [u8],
+    descriptor_size: usize,
+}
+
+impl Iterator for EfiMemoryMapIter {
+    type Item = EfiMemoryDescriptor;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        let descriptor = self.descriptors.get(..self.descriptor_size)?;
+        self.descriptors = &self.descriptors[self.descriptor_size..];
+        
+        // The type is followed by 4 bytes of padding
+        Some(EfiMemoryDescriptor {
+            mem_type: read_u32(descriptor, 0)?,
+            physical_start: read_u64(descriptor, 8)?,
+            virtual_start: read_u64(descriptor, 16)?,
+            page_count: read_u64(descriptor, 24)?,
+            attributes: read_u64(descriptor, 32)?,
+        })
     }
 }
 
+/// Size of an EFI memory descriptor up to and including `attributes`
+const EFI_DESCRIPTOR_MIN_SIZE: usize = 40;
+
 /// Scans and parses Multiboot2 boot information
+#[derive(Debug, Clone, Copy)]
 pub struct BootInfo {
-    addr: usize,
+    /// The whole structure, `total_size` bytes long
+    bytes: &'static [u8],
 }
 
 impl BootInfo {
     /// Creates a new BootInfo from the address provided by the bootloader
     /// 
+    /// Checks the header and every tag header against `total_size` and
+    /// requires the tag list to end with an end tag.
+    /// 
     /// # Safety
-    /// The caller must ensure that `addr` points to a valid Multiboot2 structure
-    pub unsafe fn new(addr: usize) -> Option<Self> {
+    /// The caller must ensure that `addr` points to readable memory holding
+    /// the structure the bootloader passed, and that the memory is not
+    /// reused while the BootInfo is alive
+    pub unsafe fn new(addr: usize) -> Result<Self, BootInfoError> {
         if addr == 0 {
-            return None;
+            return Err(BootInfoError::NullAddress);
+        }
+        if !addr.is_multiple_of(8) {
+            return Err(BootInfoError::Misaligned(addr));
+        }
+        
+        let total_size = *(addr as *const u32);
+        if (total_size as usize) < HEADER_SIZE * 2 || total_size as usize > MAX_TOTAL_SIZE {
+            return Err(BootInfoError::InvalidTotalSize(total_size));
+        }
+        
+        let bytes = core::slice::from_raw_parts(addr as *const u8, total_size as usize);
+        Self::validate(bytes)?;
+        Ok(BootInfo { bytes })
+    }
+    
+    /// Check that every tag lies within the structure and that the list is
+    /// terminated by an end tag
+    fn validate(bytes: &[u8]) -> Result<(), BootInfoError> {
+        let mut offset = HEADER_SIZE;
+        
+        while offset < bytes.len() {
+            let (tag_type, size) = match (read_u32(bytes, offset), read_u32(bytes, offset + 4)) {
+                (Some(tag_type), Some(size)) => (tag_type, size as usize),
+                _ => return Err(BootInfoError::TagOutOfBounds(offset)),
+            };
+            
+            if size < HEADER_SIZE || size > bytes.len() - offset {
+                return Err(BootInfoError::TagOutOfBounds(offset));
+            }
+            
+            if tag_type == TagType::End as u32 {
+                return Ok(());
+            }
+            
+            // Move to next tag (align to 8-byte boundary)
+            offset += size.next_multiple_of(8);
         }
         
-        Some(BootInfo { addr })
+        Err(BootInfoError::MissingEndTag)
+    }
+    
+    /// Returns the physical address of the boot information structure
+    pub fn addr(&self) -> usize {
+        self.bytes.as_ptr() as usize
     }
     
     /// Returns the total size of the boot information structure
     pub fn total_size(&self) -> u32 {
-        unsafe {
-            let header = self.addr as *const BootInfoHeader;
-            (*header).total_size
-        }
+        self.bytes.len() as u32
+    }
+    
+    /// Returns an iterator over all tags (excluding the end tag)
+    pub fn tags(&self) -> TagIter {
+        TagIter { remaining: &self.bytes[HEADER_SIZE..] }
+    }
+    
+    /// Finds the first tag of the given type
+    pub fn find_tag(&self, tag_type: TagType) -> Option<Tag> {
+        self.tags().find(|tag| tag.tag_type == tag_type as u32)
+    }
+    
+    /// Kernel command line (tag 1)
+    pub fn command_line(&self) -> Option<&'static str> {
+        read_str(self.find_tag(TagType::BootCommandLine)?.payload(), 0)
+    }
+    
+    /// Name of the bootloader (tag 2)
+    pub fn bootloader_name(&self) -> Option<&'static str> {
+        read_str(self.find_tag(TagType::BootLoaderName)?.payload(), 0)
+    }
+    
+    /// Boot modules (tag 3, one tag per module)
+    pub fn modules(&self) -> impl Iterator<Item = Module> {
+        self.tags()
+            .filter(|tag| tag.tag_type == TagType::Module as u32)
+            .filter_map(|tag| {
+                let payload = tag.payload();
+                Some(Module {
+                    start: read_u32(payload, 0)?,
+                    end: read_u32(payload, 4)?,
+                    cmdline: read_str(payload, 8)?,
+                })
+            })
+    }
+    
+    /// Lower and upper memory sizes (tag 4)
+    pub fn basic_memory_info(&self) -> Option<BasicMemoryInfo> {
+        let payload = self.find_tag(TagType::BasicMemInfo)?.payload();
+        Some(BasicMemoryInfo {
+            mem_lower: read_u32(payload, 0)?,
+            mem_upper: read_u32(payload, 4)?,
+        })
     }
     
-    /// Finds and returns an iterator over memory map entries
+    /// BIOS boot device (tag 5)
+    pub fn bios_boot_device(&self) -> Option<BiosBootDevice> {
+        let payload = self.find_tag(TagType::BiosBootDevice)?.payload();
+        Some(BiosBootDevice {
+            bios_device: read_u32(payload, 0)?,
+            partition: read_u32(payload, 4)?,
+            sub_partition: read_u32(payload, 8)?,
+        })
+    }
+    
+    /// Finds and returns an iterator over memory map entries (tag 6)
     pub fn memory_map(&self) -> Option<MemoryMapIter> {
-        unsafe {
-            // Skip the first 8 bytes (BootInfoHeader: total_size + _reserved)
-            // The Multiboot2 spec defines the structure as:
-            //   u32 total_size
-            //   u32 reserved (must be 0)
-            //   followed by tags
-            let mut current = (self.addr + 8) as *const TagHeader;
-            let end = (self.addr + self.total_size() as usize) as *const TagHeader;
-            
-            while (current as usize) < (end as usize) {
-                // We can cast to TagHeader because the Multiboot2 spec guarantees
-                // that every tag starts with: u32 type, u32 size
-                // Our TagHeader struct matches this exact layout (#[repr(C, packed)])
-                let tag = &*current;
-                
-                if tag.tag_type == TagType::End as u32 {
-                    break;
-                }
-                
-                if tag.tag_type == TagType::MemoryMap as u32 {
-                    // Cast to MemoryMapTag because we know this is a memory map tag
-                    // The Multiboot2 spec defines memory map tags as:
-                    //   u32 type, u32 size, u32 entry_size, u32 entry_version
-                    //   followed by the actual memory entries
-                    let mmap_tag = current as *const MemoryMapTag;
-                    let entry_size = (*mmap_tag).entry_size;
-                    // Skip the 16 bytes of MemoryMapTag header to get to entries
-                    let entries_start = (mmap_tag as *const u8).add(16);
-                    let entries_end = (current as *const u8).add((*mmap_tag).size as usize);
-                    
-                    return Some(MemoryMapIter {
-                        current: entries_start,
-                        end: entries_end,
-                        entry_size,
-                    });
+        // The Multiboot2 spec defines memory map tags as:
+        //   u32 type, u32 size, u32 entry_size, u32 entry_version
+        //   followed by the actual memory entries
+        let payload = self.find_tag(TagType::MemoryMap)?.payload();
+        let entry_size = read_u32(payload, 0)? as usize;
+        if entry_size < core::mem::size_of::<MemoryMapEntry>() {
+            return None;
+        }
+        
+        Some(MemoryMapIter {
+            entries: payload.get(8..)?,
+            entry_size,
+        })
+    }
+    
+    /// Framebuffer information (tag 8)
+    pub fn framebuffer(&self) -> Option<FramebufferInfo> {
+        let payload = self.find_tag(TagType::FramebufferInfo)?.payload();
+        
+        // u64 addr, u32 pitch, u32 width, u32 height, u8 bpp, u8 type,
+        // u16 reserved, then the colour info for the type
+        let kind = match *payload.get(21)? {
+            0 => FramebufferKind::Indexed { palette_colors: read_u16(payload, 24)? as u32 },
+            1 => {
+                let color = payload.get(24..30)?;
+                FramebufferKind::Rgb {
+                    red_position: color[0],
+                    red_size: color[1],
+                    green_position: color[2],
+                    green_size: color[3],
+                    blue_position: color[4],
+                    blue_size: color[5],
                 }
-                
-                // Move to next tag (align to 8-byte boundary)
-                let next_addr = (current as usize + tag.size as usize + 7) & !7;
-                current = next_addr as *const TagHeader;
             }
+            2 => FramebufferKind::EgaText,
+            _ => return None,
+        };
+        
+        Some(FramebufferInfo {
+            addr: read_u64(payload, 0)?,
+            pitch: read_u32(payload, 8)?,
+            width: read_u32(payload, 12)?,
+            height: read_u32(payload, 16)?,
+            bpp: *payload.get(20)?,
+            kind,
+        })
+    }
+    
+    /// Finds and returns an iterator over the kernel's ELF section headers
+    /// (tag 9)
+    /// 
+    /// GRUB provides the section headers of the loaded kernel image and
+    /// also copies sections that are not part of the image (such as the
+    /// symbol and string tables) into memory, updating their `addr` field.
+    pub fn elf_sections(&self) -> Option<ElfSectionIter> {
+        // The Multiboot2 specification lists `num`, `entsize` and `shndx`
+        // as u16 fields, but GRUB (and every other loader in practice)
+        // writes them as u32
+        let payload = self.find_tag(TagType::ElfSymbols)?.payload();
+        let count = read_u32(payload, 0)? as usize;
+        let entry_size = read_u32(payload, 4)? as usize;
+        if entry_size < core::mem::size_of::<ElfSectionHeader>() {
+            return None;
+        }
+        
+        let headers = payload.get(12..)?;
+        let len = count.checked_mul(entry_size)?.min(headers.len());
+        Some(ElfSectionIter {
+            headers: &headers[..len],
+            entry_size,
+        })
+    }
+    
+    /// Copy of the ACPI 1.0 RSDP (tag 14)
+    pub fn acpi_old_rsdp(&self) -> Option<&'static [u8]> {
+        Some(self.find_tag(TagType::AcpiOldRsdp)?.payload())
+    }
+    
+    /// Copy of the ACPI 2.0+ RSDP (tag 15)
+    pub fn acpi_new_rsdp(&self) -> Option<&'static [u8]> {
+        Some(self.find_tag(TagType::AcpiNewRsdp)?.payload())
+    }
+    
+    /// SMBIOS tables (tag 13)
+    pub fn smbios_tables(&self) -> Option<SmbiosTables> {
+        let payload = self.find_tag(TagType::SmbiosTables)?.payload();
+        
+        // u8 major, u8 minor, 6 reserved bytes, then the tables
+        Some(SmbiosTables {
+            major: *payload.first()?,
+            minor: *payload.get(1)?,
+            tables: payload.get(8..)?,
+        })
+    }
+    
+    /// EFI memory map (tag 17), present when booted through UEFI
+    pub fn efi_memory_map(&self) -> Option<EfiMemoryMapIter> {
+        // u32 descriptor_size, u32 descriptor_version, then the descriptors
+        let payload = self.find_tag(TagType::EfiMemoryMap)?.payload();
+        let descriptor_size = read_u32(payload, 0)? as usize;
+        if descriptor_size < EFI_DESCRIPTOR_MIN_SIZE {
+            return None;
+        }
+        
+        Some(EfiMemoryMapIter {
+            descriptors: payload.get(8..)?,
+            descriptor_size,
+        })
+    }
+    
+    /// Whether the EFI boot services are still running (tag 18), so the
+    /// memory they use is not free
+    pub fn efi_boot_services_active(&self) -> bool {
+        self.find_tag(TagType::EfiBootServicesNotTerminated).is_some()
+    }
+    
+    /// Physical address the kernel image was loaded at (tag 21), present
+    /// for relocatable images
+    pub fn image_load_base(&self) -> Option<u32> {
+        read_u32(self.find_tag(TagType::ImageLoadBasePhysicalAddress)?.payload(), 0)
+    }
+    
+    /// Prints a summary of the boot information to the console
+    pub fn print_summary(&self) {
+        use crate::println;
+        
+        println!();
+        println!("=== Boot Information ===");
+        println!("  Structure: {:#X} ({} bytes, {} tags)", self.addr(), self.total_size(), self.tags().count());
+        println!("  Bootloader: {}", self.bootloader_name().unwrap_or("unknown"));
+        println!("  Command line: {}", self.command_line().unwrap_or(""));
+        
+        if let Some(info) = self.basic_memory_info() {
+            println!("  Lower memory: {} KB  Upper memory: {} KB", info.mem_lower, info.mem_upper);
+        }
+        
+        if let Some(device) = self.bios_boot_device() {
+            println!("  Boot device: {:#X}", device.bios_device);
+        }
+        
+        for module in self.modules() {
+            println!("  Module: {:#X} - {:#X} {}", module.start, module.end, module.cmdline);
+        }
+        
+        if let Some(fb) = self.framebuffer() {
+            println!("  Framebuffer: {:#X} {}x{} {} bpp", fb.addr, fb.width, fb.height, fb.bpp);
+        }
+        
+        if self.acpi_new_rsdp().is_some() {
+            println!("  ACPI: 2.0+ RSDP");
+        } else if self.acpi_old_rsdp().is_some() {
+            println!("  ACPI: 1.0 RSDP");
+        }
+        
+        if let Some(smbios) = self.smbios_tables() {
+            println!("  SMBIOS: {}.{}", smbios.major, smbios.minor);
+        }
+        
+        if self.efi_memory_map().is_some() {
+            println!("  EFI memory map present");
         }
         
-        None
+        if let Some(base) = self.image_load_base() {
+            println!("  Image load base: {:#X}", base);
+        }
+        
+        println!("========================");
     }
     
     /// Prints the memory map to the console
     pub fn print_memory_map(&self) {
-        use crate::arch::println;
+        use crate::arch::ByteSize;
+        use crate::println;
         
-        println("");
-        println("=== Memory Map ===");
+        println!();
+        println!("=== Memory Map ===");
         
         if let Some(mmap) = self.memory_map() {
             let mut total_available = 0u64;
@@ -226,90 +762,47 @@ impl BootInfo {
                 }
                 
                 // Print entry
-                crate::arch::print("  Base: 0x");
-                print_hex(entry.base_addr);
-                crate::arch::print("  Length: 0x");
-                print_hex(entry.length);
-                crate::arch::print("  (");
-                print_size(entry.length);
-                crate::arch::print(")  Type: ");
-                println(mem_type.as_str());
+                println!(
+                    "  Base: {:#018X}  Length: {:#018X}  ({})  Type: {}",
+                    { entry.base_addr },
+                    { entry.length },
+                    ByteSize(entry.length),
+                    mem_type.as_str()
+                );
             }
             
-            println("");
-            crate::arch::print("Total Available: ");
-            print_size(total_available);
-            println("");
-            crate::arch::print("Total Reserved:  ");
-            print_size(total_reserved);
-            println("");
+            println!();
+            println!("Total Available: {}", ByteSize(total_available));
+            println!("Total Reserved:  {}", ByteSize(total_reserved));
         } else {
-            println("No memory map found!");
+            println!("No memory map found!");
         }
         
-        println("==================");
-        println("");
+        println!("==================");
+        println!();
     }
 }
 
-/// Helper function to print a hex value
-fn print_hex(value: u64) {
-    use crate::arch::print;
-    
-    const HEX_CHARS: &[u8; 16] = b"0123456789ABCDEF";
-    let mut buffer = [0u8; 16];
-    
-    for i in 0..16 {
-        let nibble = ((value >> (60 - i * 4)) & 0xF) as usize;
-        buffer[i] = HEX_CHARS[nibble];
-    }
-    
-    let s = unsafe { core::str::from_utf8_unchecked(&buffer) };
-    print(s);
-}
+/// Boot information passed to the kernel, set once by `init`
+static mut BOOT_INFO: Option<BootInfo> = None;
 
-/// Helper function to print size in human-readable format
-fn print_size(bytes: u64) {
-    use crate::arch::print;
-    
-    let kb = bytes / 1024;
-    let mb = kb / 1024;
-    
-    if mb > 0 {
-        print_decimal(mb);
-        print(" MB");
-    } else if kb > 0 {
-        print_decimal(kb);
-        print(" KB");
-    } else {
-        print_decimal(bytes);
-        print(" bytes");
+/// Validate the boot information passed by the bootloader and make it
+/// available through `boot_info()`
+/// 
+/// # Safety
+/// Must be called once during early boot with the values the bootloader
+/// passed to `kernel_main`.
+pub unsafe fn init(multiboot_info_addr: usize, multiboot_magic: usize) -> Result<BootInfo, BootInfoError> {
+    if multiboot_magic != MULTIBOOT2_MAGIC as usize {
+        return Err(BootInfoError::InvalidMagic(multiboot_magic as u32));
     }
+    
+    let boot_info = BootInfo::new(multiboot_info_addr)?;
+    *core::ptr::addr_of_mut!(BOOT_INFO) = Some(boot_info);
+    Ok(boot_info)
 }
 
-/// Helper function to print a decimal number
-fn print_decimal(mut value: u64) {
-    use crate::arch::print;
-    
-    if value == 0 {
-        print("0");
-        return;
-    }
-    
-    let mut buffer = [0u8; 20];
-    let mut i = 0;
-    
-    while value > 0 {
-        buffer[i] = b'0' + (value % 10) as u8;
-        value /= 10;
-        i += 1;
-    }
-    
-    // Reverse the buffer
-    for j in 0..i/2 {
-        buffer.swap(j, i - 1 - j);
-    }
-    
-    let s = unsafe { core::str::from_utf8_unchecked(&buffer[..i]) };
-    print(s);
+/// Boot information passed by the bootloader, if it was valid
+pub fn boot_info() -> Option<BootInfo> {
+    unsafe { *core::ptr::addr_of!(BOOT_INFO) }
 }
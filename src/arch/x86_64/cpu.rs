@@ -0,0 +1,73 @@
+/// CPU Feature Detection and Model-Specific Registers
+/// 
+/// `cpuid` reports which optional features the processor supports, and
+/// model-specific registers (MSRs) control features such as the local APIC.
+/// The time-stamp counter (TSC) counts CPU clock cycles since reset.
+/// 
+/// MSR access is unsafe: writing an MSR can change paging, interrupt
+/// delivery or the memory type of physical memory.
+
+use core::arch::x86_64::{CpuidResult, __cpuid_count};
+
+/// IA32_APIC_BASE: local APIC base address and enable bits
+pub const IA32_APIC_BASE: u32 = 0x1B;
+
+/// Execute CPUID for a leaf and sub-leaf
+pub fn cpuid(leaf: u32, subleaf: u32) -> CpuidResult {
+    __cpuid_count(leaf, subleaf)
+}
+
+/// Check whether the CPU has a time-stamp counter (CPUID.1:EDX bit 4)
+pub fn has_tsc() -> bool {
+    cpuid(1, 0).edx & (1 << 4) != 0
+}
+
+/// Check whether the TSC runs at a constant rate in every power state
+/// (CPUID.80000007H:EDX bit 8)
+pub fn has_invariant_tsc() -> bool {
+    cpuid(0x8000_0000, 0).eax >= 0x8000_0007 && cpuid(0x8000_0007, 0).edx & (1 << 8) != 0
+}
+
+/// Read the time-stamp counter
+pub fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+/// Check whether the CPU has an on-chip local APIC (CPUID.1:EDX bit 9)
+pub fn has_apic() -> bool {
+    cpuid(1, 0).edx & (1 << 9) != 0
+}
+
+/// Check whether the local APIC supports x2APIC mode (CPUID.1:ECX bit 21)
+pub fn has_x2apic() -> bool {
+    cpuid(1, 0).ecx & (1 << 21) != 0
+}
+
+/// APIC ID of the current CPU as assigned at reset (CPUID.1:EBX bits 24-31)
+pub fn initial_apic_id() -> u32 {
+    cpuid(1, 0).ebx >> 24
+}
+
+/// Read a model-specific register
+/// 
+/// # Safety
+/// The MSR must exist on this CPU, otherwise a #GP is raised.
+pub unsafe fn rdmsr(msr: u32) -> u64 {
+    let (low, high): (u32, u32);
+    core::arch::asm!("rdmsr", in("ecx") msr, out("eax") low, out("edx") high, options(nomem, nostack, preserves_flags));
+    (high as u64) << 32 | low as u64
+}
+
+/// Write a model-specific register
+/// 
+/// # Safety
+/// The MSR must exist on this CPU and the value must be valid for it.
+pub unsafe fn wrmsr(msr: u32, value: u64) {
+    core::arch::asm!(
+        "wrmsr",
+        in("ecx") msr,
+        in("eax") value as u32,
+        in("edx") (value >> 32) as u32,
+        options(nostack, preserves_flags),
+    );
+}
@@ -0,0 +1,243 @@
+/// Kernel Console
+/// 
+/// Formatted kernel output (`print!`, `println!`, `eprint!`, `eprintln!`)
+/// is fanned out to every registered console sink, so the same text shows
+/// up on the VGA screen, the serial port and any other output device
+/// (for example a framebuffer console) at the same time.
+/// 
+/// ## Interrupt safety
+/// 
+/// Each macro call formats and writes its whole output while holding the
+/// console lock, which disables interrupts. An interrupt handler that
+/// prints therefore cannot land in the middle of another line or corrupt a
+/// sink's cursor state.
+/// 
+/// Exceptions and NMIs can still interrupt a print. If the lock is already
+/// held when printing, the output is written without the lock instead of
+/// spinning forever (the holder cannot run until the handler returns).
+/// 
+/// ## Usage
+/// 
+/// ```rust
+/// use crate::println;
+/// 
+/// println!("Free frames: {} ({})", free, ByteSize(free_bytes));
+/// eprintln!("Page fault at {:#x}", address);
+/// ```
+
+use core::fmt::{self, Write};
+use crate::arch::x86_64::boot::cmdline;
+use crate::arch::x86_64::sync::SpinLock;
+
+/// Maximum number of registered sinks
+const MAX_SINKS: usize = 8;
+
+/// Output stream a message is written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// Normal output (`print!`, `println!`)
+    Out,
+    /// Error output (`eprint!`, `eprintln!`)
+    Err,
+}
+
+/// Verbosity of boot messages, selected with the `loglevel` boot option
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Errors only
+    Error,
+    /// Errors and warnings
+    Warn,
+    /// Normal boot messages (the default)
+    Info,
+    /// Detailed diagnostic output
+    Debug,
+}
+
+impl LogLevel {
+    /// Names accepted by the `loglevel` boot option, in level order
+    pub const NAMES: [&'static str; 4] = ["error", "warn", "info", "debug"];
+    
+    /// Log level for a name from `NAMES`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// Current log level from the `loglevel` boot option
+pub fn log_level() -> LogLevel {
+    cmdline::choice("loglevel").and_then(LogLevel::from_name).unwrap_or(LogLevel::Info)
+}
+
+/// Check whether messages at `level` should be printed
+pub fn log_enabled(level: LogLevel) -> bool {
+    level <= log_level()
+}
+
+/// Function that writes console output to a device
+pub type SinkWriteFn = fn(Stream, &str);
+
+/// An output device registered with the console
+#[derive(Debug, Clone, Copy)]
+pub struct ConsoleSink {
+    /// Unique name of the sink
+    pub name: &'static str,
+    /// Write function
+    pub write: SinkWriteFn,
+}
+
+/// Errors that can occur when registering a sink
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleError {
+    /// All sink slots are in use
+    RegistryFull,
+    /// A sink with the same name is already registered
+    AlreadyRegistered,
+}
+
+/// Registered sinks
+static SINKS: SpinLock<[Option<ConsoleSink>; MAX_SINKS]> = SpinLock::new([None; MAX_SINKS]);
+
+/// Register an output device with the console
+pub fn register_sink(sink: ConsoleSink) -> Result<(), ConsoleError> {
+    let mut sinks = SINKS.lock();
+    
+    if sinks.iter().flatten().any(|s| s.name == sink.name) {
+        return Err(ConsoleError::AlreadyRegistered);
+    }
+    
+    match sinks.iter_mut().find(|s| s.is_none()) {
+        Some(slot) => {
+            *slot = Some(sink);
+            Ok(())
+        }
+        None => Err(ConsoleError::RegistryFull),
+    }
+}
+
+/// Remove a sink by name
+/// 
+/// Returns true if a sink was removed.
+pub fn unregister_sink(name: &str) -> bool {
+    let mut sinks = SINKS.lock();
+    
+    for slot in sinks.iter_mut() {
+        if matches!(slot, Some(sink) if sink.name == name) {
+            *slot = None;
+            return true;
+        }
+    }
+    false
+}
+
+/// `fmt::Write` adapter that writes to every sink
+struct SinkWriter<'a> {
+    sinks: &'a [Option<ConsoleSink>; MAX_SINKS],
+    stream: Stream,
+}
+
+impl Write for SinkWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for sink in self.sinks.iter().flatten() {
+            (sink.write)(self.stream, s);
+        }
+        Ok(())
+    }
+}
+
+/// Format and write a message to all sinks
+pub fn write_fmt(stream: Stream, args: fmt::Arguments) {
+    match SINKS.try_lock() {
+        Some(sinks) => {
+            let _ = SinkWriter { sinks: &sinks, stream }.write_fmt(args);
+        }
+        None => {
+            // Re-entered from an exception or NMI: print without the lock
+            let sinks = unsafe { &*SINKS.data_ptr() };
+            let _ = SinkWriter { sinks, stream }.write_fmt(args);
+        }
+    }
+}
+
+/// Write a plain string to all sinks
+pub fn write_str(stream: Stream, s: &str) {
+    write_fmt(stream, format_args!("{}", s));
+}
+
+/// Implementation detail of `print!` and `println!`
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    write_fmt(Stream::Out, args);
+}
+
+/// Implementation detail of `eprint!` and `eprintln!`
+#[doc(hidden)]
+pub fn _eprint(args: fmt::Arguments) {
+    write_fmt(Stream::Err, args);
+}
+
+/// Print formatted text to the kernel console
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => {
+        $crate::arch::drivers::console::_print(format_args!($($arg)*))
+    };
+}
+
+/// Print formatted text and a newline to the kernel console
+#[macro_export]
+macro_rules! println {
+    () => {
+        $crate::print!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::arch::drivers::console::_print(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// Print formatted text to the kernel console's error stream
+#[macro_export]
+macro_rules! eprint {
+    ($($arg:tt)*) => {
+        $crate::arch::drivers::console::_eprint(format_args!($($arg)*))
+    };
+}
+
+/// Print formatted text and a newline to the kernel console's error stream
+#[macro_export]
+macro_rules! eprintln {
+    () => {
+        $crate::eprint!("\n")
+    };
+    ($($arg:tt)*) => {
+        $crate::arch::drivers::console::_eprint(format_args!("{}\n", format_args!($($arg)*)))
+    };
+}
+
+/// Display wrapper that formats a byte count in human-readable units
+/// 
+/// Rounds down to whole MB or KB, e.g. `ByteSize(3 * 1024 * 1024)` is
+/// shown as "3 MB" and `ByteSize(512)` as "512 bytes".
+#[derive(Debug, Clone, Copy)]
+pub struct ByteSize(pub u64);
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kb = self.0 / 1024;
+        let mb = kb / 1024;
+        
+        if mb > 0 {
+            write!(f, "{} MB", mb)
+        } else if kb > 0 {
+            write!(f, "{} KB", kb)
+        } else {
+            write!(f, "{} bytes", self.0)
+        }
+    }
+}
@@ -0,0 +1,340 @@
+/// Intel 8042 PS/2 Controller
+/// 
+/// The controller connects the PS/2 keyboard (first port, IRQ 1) and mouse
+/// (second port, IRQ 12) to two I/O ports:
+/// 
+/// ```text
+/// 0x60  Data     Bytes from the devices (read), to a device (write)
+/// 0x64  Status   bit 0: output full, bit 1: input full, bit 5: byte from port 2 (read)
+///       Command  Controller command (write)
+/// ```
+/// 
+/// Commands that return a byte put it in the output buffer, and commands
+/// that take a parameter expect it on the data port. Every access waits for
+/// the status bits with a timeout, since a missing controller reads as 0xFF.
+/// 
+/// ## Configuration byte
+/// 
+/// ```text
+/// bit 0  Port 1 interrupt (IRQ 1)     bit 4  Port 1 clock disabled
+/// bit 1  Port 2 interrupt (IRQ 12)    bit 5  Port 2 clock disabled
+/// bit 2  System flag (POST passed)    bit 6  Port 1 scancode translation (set 2 to set 1)
+/// ```
+
+use core::time::Duration;
+use crate::arch::x86_64::port::{inb, outb};
+use crate::arch::x86_64::timer;
+
+/// Data port
+const DATA_PORT: u16 = 0x60;
+/// Status register (read) and command register (write)
+const STATUS_PORT: u16 = 0x64;
+
+/// Status: a byte is waiting in the output buffer
+pub const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+/// Status: the controller has not taken the last byte written yet
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+/// Status: the byte in the output buffer came from port 2
+pub const STATUS_AUX_DATA: u8 = 1 << 5;
+
+/// Configuration: port 1 interrupt enabled
+pub const CONFIG_PORT1_IRQ: u8 = 1 << 0;
+/// Configuration: port 2 interrupt enabled
+pub const CONFIG_PORT2_IRQ: u8 = 1 << 1;
+/// Configuration: port 1 clock disabled
+pub const CONFIG_PORT1_CLOCK_OFF: u8 = 1 << 4;
+/// Configuration: port 2 clock disabled
+pub const CONFIG_PORT2_CLOCK_OFF: u8 = 1 << 5;
+/// Configuration: translate port 1 scancodes from set 2 to set 1
+pub const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+const CMD_READ_CONFIG: u8 = 0x20;
+const CMD_WRITE_CONFIG: u8 = 0x60;
+const CMD_DISABLE_PORT2: u8 = 0xA7;
+const CMD_ENABLE_PORT2: u8 = 0xA8;
+const CMD_TEST_PORT2: u8 = 0xA9;
+const CMD_SELF_TEST: u8 = 0xAA;
+const CMD_TEST_PORT1: u8 = 0xAB;
+const CMD_DISABLE_PORT1: u8 = 0xAD;
+const CMD_ENABLE_PORT1: u8 = 0xAE;
+const CMD_WRITE_PORT2: u8 = 0xD4;
+/// Pulse output line 0, which is wired to the CPU reset
+const CMD_PULSE_RESET: u8 = 0xFE;
+
+/// Self-test passed
+const SELF_TEST_OK: u8 = 0x55;
+/// Port test passed
+const PORT_TEST_OK: u8 = 0x00;
+
+/// Device response: command acknowledged
+pub const DEVICE_ACK: u8 = 0xFA;
+/// Device response: resend the last byte
+pub const DEVICE_RESEND: u8 = 0xFE;
+/// Device response: self-test passed (after reset)
+pub const DEVICE_SELF_TEST_OK: u8 = 0xAA;
+
+/// Device command: reset and self-test
+const DEVICE_RESET: u8 = 0xFF;
+
+/// Timeout of a controller access
+const TIMEOUT: Duration = Duration::from_millis(10);
+/// Timeout of a device command acknowledgement
+const ACK_TIMEOUT: Duration = Duration::from_millis(100);
+/// Time a device may take for its reset self-test
+const RESET_TIMEOUT: Duration = Duration::from_millis(1000);
+/// Attempts for a command the device asks to resend
+const MAX_RESENDS: usize = 3;
+
+/// Errors returned by the controller functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum I8042Error {
+    /// No controller responds (status reads as 0xFF)
+    NotPresent,
+    /// The controller or device did not respond in time
+    Timeout,
+    /// Controller self-test failed with the given response
+    SelfTestFailed(u8),
+    /// Port test failed with the given response
+    PortTestFailed(Port, u8),
+    /// The controller has no second port
+    NoSecondPort,
+    /// A device answered a command with something other than ACK
+    NoAck(u8),
+}
+
+impl core::fmt::Display for I8042Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            I8042Error::NotPresent => write!(f, "no PS/2 controller"),
+            I8042Error::Timeout => write!(f, "PS/2 controller timeout"),
+            I8042Error::SelfTestFailed(response) => write!(f, "PS/2 controller self-test failed ({:#04x})", response),
+            I8042Error::PortTestFailed(port, response) => write!(f, "PS/2 {:?} test failed ({:#04x})", port, response),
+            I8042Error::NoSecondPort => write!(f, "PS/2 controller has no second port"),
+            I8042Error::NoAck(response) => write!(f, "PS/2 device did not acknowledge ({:#04x})", response),
+        }
+    }
+}
+
+/// A controller port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Port {
+    /// First port, normally the keyboard
+    Port1,
+    /// Second (auxiliary) port, normally the mouse
+    Port2,
+}
+
+/// Whether `init` found a second port
+static mut DUAL_CHANNEL: bool = false;
+/// Whether `init` has completed
+static mut INITIALIZED: bool = false;
+
+/// Read the status register
+pub fn status() -> u8 {
+    unsafe { inb(STATUS_PORT) }
+}
+
+/// Read the data port without waiting
+pub fn read_data() -> u8 {
+    unsafe { inb(DATA_PORT) }
+}
+
+/// Wait until the controller can take a byte
+fn wait_input_empty() -> Result<(), I8042Error> {
+    timer::poll_until(TIMEOUT, || status() & STATUS_INPUT_FULL == 0).map_err(|_| I8042Error::Timeout)
+}
+
+/// Wait for a byte in the output buffer and read it
+pub fn read_timeout(timeout: Duration) -> Result<u8, I8042Error> {
+    timer::poll_until(timeout, || status() & STATUS_OUTPUT_FULL != 0).map_err(|_| I8042Error::Timeout)?;
+    Ok(read_data())
+}
+
+/// Wait for a byte from a device and read it
+/// 
+/// Bytes that arrive from the other port in the meantime are discarded.
+fn read_device(port: Port, timeout: Duration) -> Result<u8, I8042Error> {
+    let aux = if port == Port::Port2 { STATUS_AUX_DATA } else { 0 };
+    let mut byte = None;
+    timer::poll_until(timeout, || {
+        let status = status();
+        if status & STATUS_OUTPUT_FULL == 0 {
+            return false;
+        }
+        let data = read_data();
+        if status & STATUS_AUX_DATA == aux {
+            byte = Some(data);
+        }
+        byte.is_some()
+    }).map_err(|_| I8042Error::Timeout)?;
+    byte.ok_or(I8042Error::Timeout)
+}
+
+/// Send a command to the controller
+fn command(cmd: u8) -> Result<(), I8042Error> {
+    wait_input_empty()?;
+    unsafe { outb(STATUS_PORT, cmd) };
+    Ok(())
+}
+
+/// Send a command to the controller and read its response
+fn command_read(cmd: u8) -> Result<u8, I8042Error> {
+    command(cmd)?;
+    read_timeout(TIMEOUT)
+}
+
+/// Send a command with a parameter byte to the controller
+fn command_write(cmd: u8, data: u8) -> Result<(), I8042Error> {
+    command(cmd)?;
+    wait_input_empty()?;
+    unsafe { outb(DATA_PORT, data) };
+    Ok(())
+}
+
+/// Discard any bytes waiting in the output buffer
+pub fn flush_output() {
+    // The buffer holds at most a few bytes; the bound guards against a
+    // controller whose status never clears
+    for _ in 0..32 {
+        if status() & STATUS_OUTPUT_FULL == 0 {
+            break;
+        }
+        read_data();
+    }
+}
+
+/// Read the configuration byte
+pub fn read_config() -> Result<u8, I8042Error> {
+    command_read(CMD_READ_CONFIG)
+}
+
+/// Write the configuration byte
+pub fn write_config(config: u8) -> Result<(), I8042Error> {
+    command_write(CMD_WRITE_CONFIG, config)
+}
+
+/// Set and clear bits of the configuration byte
+pub fn update_config(set: u8, clear: u8) -> Result<(), I8042Error> {
+    let config = read_config()?;
+    write_config((config & !clear) | set)
+}
+
+/// Whether the controller has a second port
+pub fn has_second_port() -> bool {
+    unsafe { *core::ptr::addr_of!(DUAL_CHANNEL) }
+}
+
+/// Whether `init` has completed
+pub fn is_initialized() -> bool {
+    unsafe { *core::ptr::addr_of!(INITIALIZED) }
+}
+
+/// Initialize the controller
+/// 
+/// Disables both ports and their interrupts, runs the controller
+/// self-test, detects and tests the second port, and leaves the working
+/// ports enabled with interrupts off. Device drivers then reset their
+/// device and enable its interrupt. Needs a running clock for the timeouts.
+/// 
+/// Both the keyboard and the mouse driver call this; once it has succeeded
+/// later calls return immediately so they do not undo the other driver's
+/// setup.
+pub fn init() -> Result<(), I8042Error> {
+    if is_initialized() {
+        return Ok(());
+    }
+    if status() == 0xFF {
+        return Err(I8042Error::NotPresent);
+    }
+    
+    command(CMD_DISABLE_PORT1)?;
+    command(CMD_DISABLE_PORT2)?;
+    flush_output();
+    
+    let config = read_config()? & !(CONFIG_PORT1_IRQ | CONFIG_PORT2_IRQ | CONFIG_TRANSLATION);
+    write_config(config)?;
+    
+    let result = command_read(CMD_SELF_TEST)?;
+    if result != SELF_TEST_OK {
+        return Err(I8042Error::SelfTestFailed(result));
+    }
+    // Some controllers reset the configuration during the self-test
+    write_config(config)?;
+    
+    // A single-channel controller ignores the enable command, so the
+    // port 2 clock stays disabled
+    command(CMD_ENABLE_PORT2)?;
+    let dual = read_config()? & CONFIG_PORT2_CLOCK_OFF == 0;
+    command(CMD_DISABLE_PORT2)?;
+    
+    let result = command_read(CMD_TEST_PORT1)?;
+    if result != PORT_TEST_OK {
+        return Err(I8042Error::PortTestFailed(Port::Port1, result));
+    }
+    let dual = dual && command_read(CMD_TEST_PORT2)? == PORT_TEST_OK;
+    unsafe { *core::ptr::addr_of_mut!(DUAL_CHANNEL) = dual };
+    
+    command(CMD_ENABLE_PORT1)?;
+    if dual {
+        command(CMD_ENABLE_PORT2)?;
+    }
+    flush_output();
+    unsafe { *core::ptr::addr_of_mut!(INITIALIZED) = true };
+    Ok(())
+}
+
+/// Reset the CPU through the controller's reset line
+/// 
+/// Returns if the controller does not take the command or the line is
+/// not connected.
+pub fn pulse_reset() {
+    let _ = command(CMD_PULSE_RESET);
+}
+
+/// Write a byte to a device without waiting for its response
+pub fn write_device(port: Port, byte: u8) -> Result<(), I8042Error> {
+    if port == Port::Port2 {
+        if !has_second_port() {
+            return Err(I8042Error::NoSecondPort);
+        }
+        command(CMD_WRITE_PORT2)?;
+    }
+    wait_input_empty()?;
+    unsafe { outb(DATA_PORT, byte) };
+    Ok(())
+}
+
+/// Send a byte to a device and wait for its acknowledgement
+/// 
+/// Resends the byte when the device asks for it. Only usable while the
+/// port's interrupt is disabled, since the interrupt handler would take
+/// the response.
+pub fn send_device(port: Port, byte: u8) -> Result<(), I8042Error> {
+    for _ in 0..MAX_RESENDS {
+        write_device(port, byte)?;
+        match read_device(port, ACK_TIMEOUT)? {
+            DEVICE_ACK => return Ok(()),
+            DEVICE_RESEND => continue,
+            response => return Err(I8042Error::NoAck(response)),
+        }
+    }
+    Err(I8042Error::NoAck(DEVICE_RESEND))
+}
+
+/// Reset a device and wait for its self-test to pass
+/// 
+/// Returns the bytes that follow the self-test result, such as the device
+/// ID a mouse sends (0 if none arrives).
+pub fn reset_device(port: Port) -> Result<u8, I8042Error> {
+    send_device(port, DEVICE_RESET)?;
+    match read_device(port, RESET_TIMEOUT)? {
+        DEVICE_SELF_TEST_OK => Ok(read_device(port, TIMEOUT).unwrap_or(0)),
+        response => Err(I8042Error::NoAck(response)),
+    }
+}
+
+/// Send a command to a device and read the byte it answers with
+pub fn query_device(port: Port, byte: u8) -> Result<u8, I8042Error> {
+    send_device(port, byte)?;
+    read_device(port, ACK_TIMEOUT)
+}
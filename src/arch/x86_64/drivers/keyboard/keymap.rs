@@ -0,0 +1,132 @@
+/// Keyboard layouts
+/// 
+/// A keymap gives the characters of the keys that differ between layouts:
+/// the number row, the three letter rows and the extra ISO key. Each row
+/// is a string with one character per key, in set 1 code order:
+/// 
+/// ```text
+/// Row 0  0x02-0x0D  1 2 3 4 5 6 7 8 9 0 - =
+/// Row 1  0x10-0x1B  Q W E R T Y U I O P [ ]
+/// Row 2  0x1E-0x29  A S D F G H J K L ; ' `
+/// Row 3  0x2B-0x35  \ Z X C V B N M , . /
+/// Row 4  0x56       ISO key next to left shift
+/// ```
+/// 
+/// A `\0` marks a key with no character on that level. Keys outside the
+/// rows (Enter, Tab, the keypad, ...) are the same on every layout.
+/// Dead keys are not supported; accents produce their spacing character.
+
+use super::scancode::KeyCode;
+use super::Modifiers;
+
+/// First set 1 code and length of each row
+const ROWS: [(u8, usize); 5] = [(0x02, 12), (0x10, 12), (0x1E, 12), (0x2B, 11), (0x56, 1)];
+
+/// A keyboard layout
+#[derive(Debug)]
+pub struct Keymap {
+    /// Name used by the `keymap` boot option
+    pub name: &'static str,
+    /// Characters without modifiers
+    normal: [&'static str; 5],
+    /// Characters with Shift
+    shifted: [&'static str; 5],
+    /// Characters with AltGr (right Alt)
+    altgr: [&'static str; 5],
+}
+
+/// US QWERTY
+pub static US: Keymap = Keymap {
+    name: "us",
+    normal: ["1234567890-=", "qwertyuiop[]", "asdfghjkl;'`", "\\zxcvbnm,./", "\\"],
+    shifted: ["!@#$%^&*()_+", "QWERTYUIOP{}", "ASDFGHJKL:\"~", "|ZXCVBNM<>?", "|"],
+    altgr: ["", "", "", "", ""],
+};
+
+/// German QWERTZ
+pub static DE: Keymap = Keymap {
+    name: "de",
+    normal: ["1234567890ß´", "qwertzuiopü+", "asdfghjklöä^", "#yxcvbnm,.-", "<"],
+    shifted: ["!\"§$%&/()=?`", "QWERTZUIOPÜ*", "ASDFGHJKLÖÄ°", "'YXCVBNM;:_", ">"],
+    altgr: ["\0²³\0\0\0{[]}\\\0", "@\0€\0\0\0\0\0\0\0\0~", "", "\0\0\0\0\0\0\0µ", "|"],
+};
+
+/// All built-in keymaps
+pub static KEYMAPS: [&Keymap; 2] = [&US, &DE];
+
+/// Names of the built-in keymaps, for the `keymap` boot option
+pub const KEYMAP_NAMES: [&str; 2] = ["us", "de"];
+
+/// Look up a built-in keymap by name
+pub fn find(name: &str) -> Option<&'static Keymap> {
+    KEYMAPS.iter().copied().find(|keymap| keymap.name == name)
+}
+
+/// Character of a layout row entry
+fn row_char(rows: &[&'static str; 5], code: u8) -> Option<char> {
+    let (row, index) = ROWS.iter().enumerate().find_map(|(row, &(first, len))| {
+        (first..first + len as u8).contains(&code).then(|| (row, (code - first) as usize))
+    })?;
+    rows[row].chars().nth(index).filter(|&c| c != '\0')
+}
+
+/// Character of a key that is the same on every layout
+fn fixed_char(key: KeyCode, num_lock: bool) -> Option<char> {
+    use KeyCode::*;
+    let c = match key {
+        Enter | KeypadEnter => '\n',
+        Tab => '\t',
+        Backspace => '\x08',
+        Escape => '\x1b',
+        Space => ' ',
+        KeypadDivide => '/',
+        KeypadMultiply => '*',
+        KeypadMinus => '-',
+        KeypadPlus => '+',
+        _ if !num_lock => return None,
+        Keypad0 => '0',
+        Keypad1 => '1',
+        Keypad2 => '2',
+        Keypad3 => '3',
+        Keypad4 => '4',
+        Keypad5 => '5',
+        Keypad6 => '6',
+        Keypad7 => '7',
+        Keypad8 => '8',
+        Keypad9 => '9',
+        KeypadPeriod => '.',
+        _ => return None,
+    };
+    Some(c)
+}
+
+impl Keymap {
+    /// Whether the layout has an AltGr level (otherwise right Alt is Alt)
+    pub fn has_altgr(&self) -> bool {
+        self.altgr.iter().any(|row| !row.is_empty())
+    }
+    
+    /// Character produced by a key press with the given modifiers
+    /// 
+    /// Caps Lock inverts Shift for letters only. Ctrl turns letters into
+    /// control characters (Ctrl+C is 0x03).
+    pub fn char_for(&self, key: KeyCode, modifiers: Modifiers) -> Option<char> {
+        if let Some(c) = fixed_char(key, modifiers.num_lock()) {
+            return Some(c);
+        }
+        
+        let code = key as u8;
+        let c = if modifiers.altgr() && self.has_altgr() {
+            row_char(&self.altgr, code)?
+        } else {
+            let normal = row_char(&self.normal, code)?;
+            let shift = modifiers.shift() ^ (modifiers.caps_lock() && normal.is_alphabetic());
+            if shift { row_char(&self.shifted, code)? } else { normal }
+        };
+        
+        if modifiers.ctrl() && c.is_ascii_alphabetic() {
+            return Some((c.to_ascii_lowercase() as u8 & 0x1F) as char);
+        }
+        Some(c)
+    }
+}
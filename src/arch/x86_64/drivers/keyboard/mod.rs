@@ -0,0 +1,394 @@
+/// PS/2 Keyboard Driver
+/// 
+/// Drives the keyboard on the first i8042 port (IRQ 1):
+/// 
+/// - `scancode`: set 1 and set 2 decoding into `KeyCode`s
+/// - `keymap`: layouts turning key presses into characters (US, German)
+/// 
+/// The interrupt handler decodes each byte, tracks the modifier and lock
+/// keys, and pushes a `KeyEvent` into a lock-free `EventQueue` that consumers
+/// drain with `read_event` or `read_char`. Lock key changes are sent to
+/// the keyboard LEDs from the interrupt handler, one command byte per
+/// acknowledgement, so no code ever waits for the keyboard.
+/// 
+/// ## Scancode sets
+/// 
+/// By default the controller's translation is turned off and set 2 is
+/// decoded directly. With `kbdset=1` translation is left on and the
+/// decoder sees set 1.
+
+pub mod keymap;
+pub mod scancode;
+
+use super::i8042::{self, I8042Error, Port, DEVICE_ACK, DEVICE_RESEND, DEVICE_SELF_TEST_OK};
+use crate::arch::x86_64::boot::cmdline::{self, BootOption, OptionKind};
+use crate::arch::x86_64::interrupts::hardware;
+use crate::arch::x86_64::interrupts::irq::{self, IrqError, IrqSharing};
+use crate::arch::x86_64::sync::{EventQueue, SpinLock};
+use keymap::Keymap;
+use scancode::{Decoder, KeyCode, ScancodeSet};
+
+/// Names accepted by the `kbdset` boot option
+const SET_NAMES: [&str; 2] = ["1", "2"];
+
+/// Boot options of the keyboard driver
+pub static BOOT_OPTIONS: [BootOption; 3] = [
+    BootOption::new("keymap", OptionKind::Choice(&keymap::KEYMAP_NAMES), "Keyboard layout (us, de)"),
+    BootOption::new("kbdset", OptionKind::Choice(&SET_NAMES), "Scancode set to decode (1 uses controller translation, default 2)"),
+    BootOption::new("nokeyboard", OptionKind::Flag, "Do not initialize the PS/2 keyboard"),
+];
+
+/// ISA IRQ of the keyboard
+pub const KEYBOARD_IRQ: u8 = 1;
+
+/// Keyboard command: set the LEDs
+const CMD_SET_LEDS: u8 = 0xED;
+/// Keyboard command: get or set the scancode set
+const CMD_SCANCODE_SET: u8 = 0xF0;
+/// Keyboard command: start sending scancodes
+const CMD_ENABLE_SCANNING: u8 = 0xF4;
+
+/// LED bits of the set LEDs command
+const LED_SCROLL_LOCK: u8 = 1 << 0;
+const LED_NUM_LOCK: u8 = 1 << 1;
+const LED_CAPS_LOCK: u8 = 1 << 2;
+
+/// Capacity of the key event queue
+pub const EVENT_QUEUE_SIZE: usize = 64;
+
+/// Errors returned by the keyboard driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardError {
+    /// The controller or keyboard did not respond as expected
+    Controller(I8042Error),
+    /// The IRQ 1 handler could not be registered
+    Irq(IrqError),
+    /// No keymap has this name
+    UnknownKeymap,
+}
+
+impl From<I8042Error> for KeyboardError {
+    fn from(error: I8042Error) -> Self {
+        KeyboardError::Controller(error)
+    }
+}
+
+impl From<IrqError> for KeyboardError {
+    fn from(error: IrqError) -> Self {
+        KeyboardError::Irq(error)
+    }
+}
+
+impl core::fmt::Display for KeyboardError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            KeyboardError::Controller(error) => write!(f, "{}", error),
+            KeyboardError::Irq(error) => write!(f, "keyboard interrupt: {}", error),
+            KeyboardError::UnknownKeymap => write!(f, "unknown keymap"),
+        }
+    }
+}
+
+/// State of the modifier and lock keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Modifiers(u16);
+
+impl Modifiers {
+    pub const LEFT_SHIFT: u16 = 1 << 0;
+    pub const RIGHT_SHIFT: u16 = 1 << 1;
+    pub const LEFT_CTRL: u16 = 1 << 2;
+    pub const RIGHT_CTRL: u16 = 1 << 3;
+    pub const LEFT_ALT: u16 = 1 << 4;
+    pub const RIGHT_ALT: u16 = 1 << 5;
+    pub const LEFT_META: u16 = 1 << 6;
+    pub const RIGHT_META: u16 = 1 << 7;
+    pub const CAPS_LOCK: u16 = 1 << 8;
+    pub const NUM_LOCK: u16 = 1 << 9;
+    pub const SCROLL_LOCK: u16 = 1 << 10;
+    
+    pub const fn empty() -> Self {
+        Modifiers(0)
+    }
+    
+    pub const fn from_bits(bits: u16) -> Self {
+        Modifiers(bits)
+    }
+    
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+    
+    pub fn contains(&self, bits: u16) -> bool {
+        self.0 & bits == bits
+    }
+    
+    fn any(&self, bits: u16) -> bool {
+        self.0 & bits != 0
+    }
+    
+    pub fn shift(&self) -> bool {
+        self.any(Self::LEFT_SHIFT | Self::RIGHT_SHIFT)
+    }
+    
+    pub fn ctrl(&self) -> bool {
+        self.any(Self::LEFT_CTRL | Self::RIGHT_CTRL)
+    }
+    
+    pub fn alt(&self) -> bool {
+        self.any(Self::LEFT_ALT | Self::RIGHT_ALT)
+    }
+    
+    /// Right Alt, which selects the third level on international layouts
+    pub fn altgr(&self) -> bool {
+        self.any(Self::RIGHT_ALT)
+    }
+    
+    pub fn meta(&self) -> bool {
+        self.any(Self::LEFT_META | Self::RIGHT_META)
+    }
+    
+    pub fn caps_lock(&self) -> bool {
+        self.any(Self::CAPS_LOCK)
+    }
+    
+    pub fn num_lock(&self) -> bool {
+        self.any(Self::NUM_LOCK)
+    }
+    
+    pub fn scroll_lock(&self) -> bool {
+        self.any(Self::SCROLL_LOCK)
+    }
+    
+    /// Modifier bit held by a key, if it is a modifier
+    fn held_by(key: KeyCode) -> Option<u16> {
+        Some(match key {
+            KeyCode::LeftShift => Self::LEFT_SHIFT,
+            KeyCode::RightShift => Self::RIGHT_SHIFT,
+            KeyCode::LeftCtrl => Self::LEFT_CTRL,
+            KeyCode::RightCtrl => Self::RIGHT_CTRL,
+            KeyCode::LeftAlt => Self::LEFT_ALT,
+            KeyCode::RightAlt => Self::RIGHT_ALT,
+            KeyCode::LeftMeta => Self::LEFT_META,
+            KeyCode::RightMeta => Self::RIGHT_META,
+            _ => return None,
+        })
+    }
+    
+    /// Lock bit toggled by a key, if it is a lock key
+    fn toggled_by(key: KeyCode) -> Option<u16> {
+        Some(match key {
+            KeyCode::CapsLock => Self::CAPS_LOCK,
+            KeyCode::NumLock => Self::NUM_LOCK,
+            KeyCode::ScrollLock => Self::SCROLL_LOCK,
+            _ => return None,
+        })
+    }
+    
+    /// Keyboard LED bits of the lock keys
+    fn leds(&self) -> u8 {
+        let mut leds = 0;
+        if self.caps_lock() {
+            leds |= LED_CAPS_LOCK;
+        }
+        if self.num_lock() {
+            leds |= LED_NUM_LOCK;
+        }
+        if self.scroll_lock() {
+            leds |= LED_SCROLL_LOCK;
+        }
+        leds
+    }
+}
+
+/// A decoded key press or release
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: KeyCode,
+    pub pressed: bool,
+    /// Modifiers after this event
+    pub modifiers: Modifiers,
+    /// Character typed by a press under the current keymap
+    pub ch: Option<char>,
+}
+
+/// Progress of an LED update sent from the interrupt handler
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LedUpdate {
+    Idle,
+    /// `CMD_SET_LEDS` sent, waiting for its ACK
+    CommandSent,
+    /// LED byte sent, waiting for its ACK
+    DataSent(u8),
+}
+
+/// Keyboard state, owned by the interrupt handler
+struct Keyboard {
+    decoder: Decoder,
+    modifiers: Modifiers,
+    keymap: &'static Keymap,
+    led_update: LedUpdate,
+    /// LEDs currently shown by the keyboard
+    leds: u8,
+    /// Last byte sent, for resend requests
+    last_sent: u8,
+}
+
+impl Keyboard {
+    /// Send a byte to the keyboard without waiting for the response
+    fn send(&mut self, byte: u8) {
+        self.last_sent = byte;
+        let _ = i8042::write_device(Port::Port1, byte);
+    }
+    
+    /// Start an LED update if the lock keys no longer match the LEDs
+    fn sync_leds(&mut self) {
+        if self.led_update == LedUpdate::Idle && self.leds != self.modifiers.leds() {
+            self.send(CMD_SET_LEDS);
+            self.led_update = LedUpdate::CommandSent;
+        }
+    }
+    
+    /// Handle a keyboard response byte; returns false if it is a scancode
+    fn handle_response(&mut self, byte: u8) -> bool {
+        match (byte, self.led_update) {
+            (DEVICE_ACK, LedUpdate::CommandSent) => {
+                let leds = self.modifiers.leds();
+                self.send(leds);
+                self.led_update = LedUpdate::DataSent(leds);
+            }
+            (DEVICE_ACK, LedUpdate::DataSent(leds)) => {
+                self.leds = leds;
+                self.led_update = LedUpdate::Idle;
+                self.sync_leds();
+            }
+            (DEVICE_RESEND, LedUpdate::CommandSent | LedUpdate::DataSent(_)) => {
+                let byte = self.last_sent;
+                self.send(byte);
+            }
+            // A keyboard that was plugged in again restarts in its default
+            // state (in set 1 the same byte is a left shift release)
+            (DEVICE_SELF_TEST_OK, LedUpdate::Idle) if self.decoder.set() == ScancodeSet::Set2 => {
+                self.decoder.reset();
+                self.leds = 0;
+                self.sync_leds();
+            }
+            (DEVICE_ACK | DEVICE_RESEND, LedUpdate::Idle) => {}
+            _ => return false,
+        }
+        true
+    }
+    
+    /// Handle a byte from the keyboard, returning the decoded event
+    fn handle_byte(&mut self, byte: u8) -> Option<KeyEvent> {
+        if self.handle_response(byte) {
+            return None;
+        }
+        
+        let (key, pressed) = self.decoder.feed(byte)?;
+        if let Some(bit) = Modifiers::held_by(key) {
+            self.modifiers.0 = if pressed { self.modifiers.0 | bit } else { self.modifiers.0 & !bit };
+        }
+        if let Some(bit) = Modifiers::toggled_by(key).filter(|_| pressed) {
+            self.modifiers.0 ^= bit;
+            self.sync_leds();
+        }
+        
+        let ch = if pressed { self.keymap.char_for(key, self.modifiers) } else { None };
+        Some(KeyEvent { key, pressed, modifiers: self.modifiers, ch })
+    }
+}
+
+static KEYBOARD: SpinLock<Keyboard> = SpinLock::new(Keyboard {
+    decoder: Decoder::new(ScancodeSet::Set2),
+    modifiers: Modifiers::empty(),
+    keymap: &keymap::US,
+    led_update: LedUpdate::Idle,
+    leds: 0,
+    last_sent: 0,
+});
+
+/// Decoded key events
+static EVENTS: EventQueue<KeyEvent, EVENT_QUEUE_SIZE> = EventQueue::new();
+
+/// Initialize the PS/2 controller and keyboard
+/// 
+/// Resets the keyboard, selects the scancode set, turns the LEDs off and
+/// registers the IRQ 1 handler. Needs a running clock for the command
+/// timeouts, so it is called after `time::init`.
+pub fn init() -> Result<ScancodeSet, KeyboardError> {
+    let set = match cmdline::choice("kbdset") {
+        Some("1") => ScancodeSet::Set1,
+        _ => ScancodeSet::Set2,
+    };
+    let keymap = cmdline::choice("keymap").and_then(keymap::find).unwrap_or(&keymap::US);
+    
+    i8042::init()?;
+    i8042::reset_device(Port::Port1)?;
+    // Translation turns set 2 into set 1, so the keyboard sends set 2 either way
+    i8042::send_device(Port::Port1, CMD_SCANCODE_SET)?;
+    i8042::send_device(Port::Port1, 2)?;
+    i8042::send_device(Port::Port1, CMD_SET_LEDS)?;
+    i8042::send_device(Port::Port1, 0)?;
+    i8042::send_device(Port::Port1, CMD_ENABLE_SCANNING)?;
+    
+    {
+        let mut keyboard = KEYBOARD.lock();
+        keyboard.decoder = Decoder::new(set);
+        keyboard.keymap = keymap;
+        keyboard.modifiers = Modifiers::empty();
+        keyboard.leds = 0;
+        keyboard.led_update = LedUpdate::Idle;
+    }
+    
+    irq::register_irq(KEYBOARD_IRQ, "keyboard", hardware::keyboard_interrupt_handler, 0, IrqSharing::Exclusive)?;
+    let translation = if set == ScancodeSet::Set1 { i8042::CONFIG_TRANSLATION } else { 0 };
+    i8042::update_config(i8042::CONFIG_PORT1_IRQ | translation, i8042::CONFIG_PORT1_CLOCK_OFF)?;
+    Ok(set)
+}
+
+/// Handle a byte read from the keyboard
+/// 
+/// Called from the IRQ 1 handler.
+pub fn handle_byte(byte: u8) {
+    let event = KEYBOARD.lock().handle_byte(byte);
+    if let Some(event) = event {
+        EVENTS.push(event);
+    }
+}
+
+/// Take the oldest key event
+pub fn read_event() -> Option<KeyEvent> {
+    EVENTS.pop()
+}
+
+/// Take key events until one types a character
+pub fn read_char() -> Option<char> {
+    while let Some(event) = read_event() {
+        if event.ch.is_some() {
+            return event.ch;
+        }
+    }
+    None
+}
+
+/// Key events dropped because nobody read them
+pub fn dropped_events() -> u64 {
+    EVENTS.dropped()
+}
+
+/// Current modifier and lock key state
+pub fn modifiers() -> Modifiers {
+    KEYBOARD.lock().modifiers
+}
+
+/// Name of the active keymap
+pub fn keymap_name() -> &'static str {
+    KEYBOARD.lock().keymap.name
+}
+
+/// Switch to a built-in keymap
+pub fn set_keymap(name: &str) -> Result<(), KeyboardError> {
+    let keymap = keymap::find(name).ok_or(KeyboardError::UnknownKeymap)?;
+    KEYBOARD.lock().keymap = keymap;
+    Ok(())
+}
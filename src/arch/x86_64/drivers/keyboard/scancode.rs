@@ -0,0 +1,298 @@
+/// Scancode decoding
+/// 
+/// Keyboards report each key press and release as a short byte sequence.
+/// Two scancode sets are in use:
+/// 
+/// - **Set 1** (XT): one byte per key, with bit 7 set on release. Keys
+///   added later are prefixed by 0xE0.
+/// - **Set 2** (AT, the keyboard default): releases are prefixed by 0xF0
+///   instead, and the codes are different. The i8042 translates set 2 to
+///   set 1 when its translation bit is set.
+/// 
+/// Set 2 is decoded by applying the same translation table as the
+/// controller, so both sets share the set 1 key table. Pause has no
+/// release and sends a fixed 6-byte (set 1) or 8-byte (set 2) sequence
+/// starting with 0xE1.
+/// 
+/// Print Screen and the navigation keys are surrounded by "fake shift"
+/// codes (0xE0 0x2A, 0xE0 0xAA and the right shift equivalents) that keep
+/// old software working with Num Lock or Shift held; they are dropped.
+
+/// Scancode set sent to the decoder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScancodeSet {
+    Set1,
+    Set2,
+}
+
+impl ScancodeSet {
+    /// Number of the set (1 or 2)
+    pub fn number(&self) -> u8 {
+        match self {
+            ScancodeSet::Set1 => 1,
+            ScancodeSet::Set2 => 2,
+        }
+    }
+}
+
+/// A key, identified by its position on a US keyboard
+/// 
+/// The discriminant is the key's set 1 code, with bit 7 set for keys that
+/// use the 0xE0 prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum KeyCode {
+    Escape = 0x01,
+    Key1 = 0x02, Key2 = 0x03, Key3 = 0x04, Key4 = 0x05, Key5 = 0x06,
+    Key6 = 0x07, Key7 = 0x08, Key8 = 0x09, Key9 = 0x0A, Key0 = 0x0B,
+    Minus = 0x0C,
+    Equals = 0x0D,
+    Backspace = 0x0E,
+    Tab = 0x0F,
+    Q = 0x10, W = 0x11, E = 0x12, R = 0x13, T = 0x14, Y = 0x15, U = 0x16, I = 0x17, O = 0x18, P = 0x19,
+    LeftBracket = 0x1A,
+    RightBracket = 0x1B,
+    Enter = 0x1C,
+    LeftCtrl = 0x1D,
+    A = 0x1E, S = 0x1F, D = 0x20, F = 0x21, G = 0x22, H = 0x23, J = 0x24, K = 0x25, L = 0x26,
+    Semicolon = 0x27,
+    Apostrophe = 0x28,
+    Backtick = 0x29,
+    LeftShift = 0x2A,
+    Backslash = 0x2B,
+    Z = 0x2C, X = 0x2D, C = 0x2E, V = 0x2F, B = 0x30, N = 0x31, M = 0x32,
+    Comma = 0x33,
+    Period = 0x34,
+    Slash = 0x35,
+    RightShift = 0x36,
+    KeypadMultiply = 0x37,
+    LeftAlt = 0x38,
+    Space = 0x39,
+    CapsLock = 0x3A,
+    F1 = 0x3B, F2 = 0x3C, F3 = 0x3D, F4 = 0x3E, F5 = 0x3F,
+    F6 = 0x40, F7 = 0x41, F8 = 0x42, F9 = 0x43, F10 = 0x44,
+    NumLock = 0x45,
+    ScrollLock = 0x46,
+    Keypad7 = 0x47, Keypad8 = 0x48, Keypad9 = 0x49,
+    KeypadMinus = 0x4A,
+    Keypad4 = 0x4B, Keypad5 = 0x4C, Keypad6 = 0x4D,
+    KeypadPlus = 0x4E,
+    Keypad1 = 0x4F, Keypad2 = 0x50, Keypad3 = 0x51,
+    Keypad0 = 0x52,
+    KeypadPeriod = 0x53,
+    /// The extra key next to left shift on ISO keyboards
+    NonUsBackslash = 0x56,
+    F11 = 0x57,
+    F12 = 0x58,
+    KeypadEnter = 0x9C,
+    RightCtrl = 0x9D,
+    KeypadDivide = 0xB5,
+    PrintScreen = 0xB7,
+    /// Right Alt, AltGr on international layouts
+    RightAlt = 0xB8,
+    Pause = 0xC6,
+    Home = 0xC7,
+    Up = 0xC8,
+    PageUp = 0xC9,
+    Left = 0xCB,
+    Right = 0xCD,
+    End = 0xCF,
+    Down = 0xD0,
+    PageDown = 0xD1,
+    Insert = 0xD2,
+    Delete = 0xD3,
+    LeftMeta = 0xDB,
+    RightMeta = 0xDC,
+    Menu = 0xDD,
+}
+
+impl KeyCode {
+    /// Key of a set 1 code (without the release bit)
+    pub fn from_set1(code: u8, extended: bool) -> Option<KeyCode> {
+        use KeyCode::*;
+        Some(match (extended, code) {
+            (false, 0x01) => Escape,
+            (false, 0x02) => Key1, (false, 0x03) => Key2, (false, 0x04) => Key3, (false, 0x05) => Key4,
+            (false, 0x06) => Key5, (false, 0x07) => Key6, (false, 0x08) => Key7, (false, 0x09) => Key8,
+            (false, 0x0A) => Key9, (false, 0x0B) => Key0,
+            (false, 0x0C) => Minus,
+            (false, 0x0D) => Equals,
+            (false, 0x0E) => Backspace,
+            (false, 0x0F) => Tab,
+            (false, 0x10) => Q, (false, 0x11) => W, (false, 0x12) => E, (false, 0x13) => R, (false, 0x14) => T,
+            (false, 0x15) => Y, (false, 0x16) => U, (false, 0x17) => I, (false, 0x18) => O, (false, 0x19) => P,
+            (false, 0x1A) => LeftBracket,
+            (false, 0x1B) => RightBracket,
+            (false, 0x1C) => Enter,
+            (false, 0x1D) => LeftCtrl,
+            (false, 0x1E) => A, (false, 0x1F) => S, (false, 0x20) => D, (false, 0x21) => F, (false, 0x22) => G,
+            (false, 0x23) => H, (false, 0x24) => J, (false, 0x25) => K, (false, 0x26) => L,
+            (false, 0x27) => Semicolon,
+            (false, 0x28) => Apostrophe,
+            (false, 0x29) => Backtick,
+            (false, 0x2A) => LeftShift,
+            (false, 0x2B) => Backslash,
+            (false, 0x2C) => Z, (false, 0x2D) => X, (false, 0x2E) => C, (false, 0x2F) => V, (false, 0x30) => B,
+            (false, 0x31) => N, (false, 0x32) => M,
+            (false, 0x33) => Comma,
+            (false, 0x34) => Period,
+            (false, 0x35) => Slash,
+            (false, 0x36) => RightShift,
+            (false, 0x37) => KeypadMultiply,
+            (false, 0x38) => LeftAlt,
+            (false, 0x39) => Space,
+            (false, 0x3A) => CapsLock,
+            (false, 0x3B) => F1, (false, 0x3C) => F2, (false, 0x3D) => F3, (false, 0x3E) => F4, (false, 0x3F) => F5,
+            (false, 0x40) => F6, (false, 0x41) => F7, (false, 0x42) => F8, (false, 0x43) => F9, (false, 0x44) => F10,
+            (false, 0x45) => NumLock,
+            (false, 0x46) => ScrollLock,
+            (false, 0x47) => Keypad7, (false, 0x48) => Keypad8, (false, 0x49) => Keypad9,
+            (false, 0x4A) => KeypadMinus,
+            (false, 0x4B) => Keypad4, (false, 0x4C) => Keypad5, (false, 0x4D) => Keypad6,
+            (false, 0x4E) => KeypadPlus,
+            (false, 0x4F) => Keypad1, (false, 0x50) => Keypad2, (false, 0x51) => Keypad3,
+            (false, 0x52) => Keypad0,
+            (false, 0x53) => KeypadPeriod,
+            (false, 0x56) => NonUsBackslash,
+            (false, 0x57) => F11,
+            (false, 0x58) => F12,
+            (true, 0x1C) => KeypadEnter,
+            (true, 0x1D) => RightCtrl,
+            (true, 0x35) => KeypadDivide,
+            (true, 0x37) => PrintScreen,
+            (true, 0x38) => RightAlt,
+            // Ctrl+Pause sends Break instead of the Pause sequence
+            (true, 0x46) => Pause,
+            (true, 0x47) => Home,
+            (true, 0x48) => Up,
+            (true, 0x49) => PageUp,
+            (true, 0x4B) => Left,
+            (true, 0x4D) => Right,
+            (true, 0x4F) => End,
+            (true, 0x50) => Down,
+            (true, 0x51) => PageDown,
+            (true, 0x52) => Insert,
+            (true, 0x53) => Delete,
+            (true, 0x5B) => LeftMeta,
+            (true, 0x5C) => RightMeta,
+            (true, 0x5D) => Menu,
+            _ => return None,
+        })
+    }
+    
+    /// Whether the key is on the numeric keypad
+    pub fn is_keypad(&self) -> bool {
+        matches!(*self as u8, 0x37 | 0x47..=0x53 | 0x9C | 0xB5)
+    }
+}
+
+/// Set 1 code of every set 2 code below 0x80 (the i8042 translation table)
+/// 
+/// 0x00 marks codes with no key.
+const SET2_TO_SET1: [u8; 0x80] = [
+    0x00, 0x43, 0x41, 0x3F, 0x3D, 0x3B, 0x3C, 0x58, 0x64, 0x44, 0x42, 0x40, 0x3E, 0x0F, 0x29, 0x59,
+    0x65, 0x38, 0x2A, 0x70, 0x1D, 0x10, 0x02, 0x5A, 0x66, 0x71, 0x2C, 0x1F, 0x1E, 0x11, 0x03, 0x5B,
+    0x67, 0x2E, 0x2D, 0x20, 0x12, 0x05, 0x04, 0x5C, 0x68, 0x39, 0x2F, 0x21, 0x14, 0x13, 0x06, 0x5D,
+    0x69, 0x31, 0x30, 0x23, 0x22, 0x15, 0x07, 0x5E, 0x6A, 0x72, 0x32, 0x24, 0x16, 0x08, 0x09, 0x5F,
+    0x6B, 0x33, 0x25, 0x17, 0x18, 0x0B, 0x0A, 0x60, 0x6C, 0x34, 0x35, 0x26, 0x27, 0x19, 0x0C, 0x61,
+    0x6D, 0x73, 0x28, 0x74, 0x1A, 0x0D, 0x62, 0x6E, 0x3A, 0x36, 0x1C, 0x1B, 0x75, 0x2B, 0x63, 0x76,
+    0x55, 0x56, 0x77, 0x78, 0x79, 0x7A, 0x0E, 0x7B, 0x7C, 0x4F, 0x7D, 0x4B, 0x47, 0x7E, 0x7F, 0x6F,
+    0x52, 0x53, 0x50, 0x4C, 0x4D, 0x48, 0x01, 0x45, 0x57, 0x4E, 0x51, 0x4A, 0x37, 0x49, 0x46, 0x54,
+];
+
+/// Set 2 code of F7, the only key above 0x7F
+const SET2_F7: u8 = 0x83;
+
+/// Prefix of extended keys
+const PREFIX_EXTENDED: u8 = 0xE0;
+/// Prefix of the Pause sequence
+const PREFIX_PAUSE: u8 = 0xE1;
+/// Set 2 release prefix
+const PREFIX_RELEASE: u8 = 0xF0;
+/// Set 1 release bit
+const RELEASE_BIT: u8 = 0x80;
+
+/// Set 1 codes of the fake shifts sent around extended keys
+const FAKE_SHIFTS: [u8; 2] = [0x2A, 0x36];
+
+/// Bytes that follow 0xE1 in the Pause sequence of each set
+const PAUSE_TAIL_SET1: usize = 5;
+const PAUSE_TAIL_SET2: usize = 7;
+
+/// Incremental scancode decoder
+/// 
+/// Fed one byte at a time from the keyboard interrupt; returns a key and
+/// whether it was pressed once a sequence is complete.
+#[derive(Debug, Clone, Copy)]
+pub struct Decoder {
+    set: ScancodeSet,
+    /// 0xE0 seen
+    extended: bool,
+    /// 0xF0 seen (set 2)
+    release: bool,
+    /// Bytes of the Pause sequence still to skip
+    pause_remaining: usize,
+}
+
+impl Decoder {
+    pub const fn new(set: ScancodeSet) -> Self {
+        Self { set, extended: false, release: false, pause_remaining: 0 }
+    }
+    
+    /// Scancode set being decoded
+    pub fn set(&self) -> ScancodeSet {
+        self.set
+    }
+    
+    /// Forget any partial sequence
+    pub fn reset(&mut self) {
+        *self = Self::new(self.set);
+    }
+    
+    /// Decode the next byte
+    pub fn feed(&mut self, byte: u8) -> Option<(KeyCode, bool)> {
+        if self.pause_remaining > 0 {
+            self.pause_remaining -= 1;
+            return None;
+        }
+        
+        match byte {
+            PREFIX_EXTENDED => {
+                self.extended = true;
+                return None;
+            }
+            PREFIX_PAUSE => {
+                self.reset();
+                self.pause_remaining = match self.set {
+                    ScancodeSet::Set1 => PAUSE_TAIL_SET1,
+                    ScancodeSet::Set2 => PAUSE_TAIL_SET2,
+                };
+                return Some((KeyCode::Pause, true));
+            }
+            PREFIX_RELEASE if self.set == ScancodeSet::Set2 => {
+                self.release = true;
+                return None;
+            }
+            _ => {}
+        }
+        
+        let (code, pressed) = match self.set {
+            ScancodeSet::Set1 => (byte & !RELEASE_BIT, byte & RELEASE_BIT == 0),
+            ScancodeSet::Set2 => {
+                let code = match byte {
+                    SET2_F7 => 0x41,
+                    byte => SET2_TO_SET1.get(byte as usize).copied().unwrap_or(0),
+                };
+                (code, !self.release)
+            }
+        };
+        let extended = self.extended;
+        self.extended = false;
+        self.release = false;
+        
+        if extended && FAKE_SHIFTS.contains(&code) {
+            return None;
+        }
+        KeyCode::from_set1(code, extended).map(|key| (key, pressed))
+    }
+}
@@ -3,7 +3,103 @@
 /// This module contains device drivers and hardware abstraction layers
 /// specific to the x86_64 architecture.
 
+pub mod console;
 pub mod vga;
+pub mod serial;
+pub mod pit;
+pub mod rtc;
+pub mod i8042;
+pub mod keyboard;
+pub mod mouse;
+
+use console::{ConsoleSink, LogLevel, Stream};
+use crate::arch::x86_64::boot::cmdline::{self, BootOption, OptionKind};
+use crate::arch::x86_64::interrupts::hardware;
+use crate::arch::x86_64::interrupts::irq::{self, IrqSharing};
+use serial::{ComPort, SerialConfig, SerialError};
 
 // Re-export commonly used driver functionality
-pub use vga::{clear_screen, print, println};
+pub use vga::clear_screen;
+
+/// Serial port that mirrors all console output
+pub const CONSOLE_SERIAL_PORT: ComPort = ComPort::Com1;
+
+/// Boot options read by the drivers
+pub static BOOT_OPTIONS: [BootOption; 2] = [
+    BootOption::new("console", OptionKind::List, "Console devices to use (serial, vga)"),
+    BootOption::new("loglevel", OptionKind::Choice(&LogLevel::NAMES), "Boot message verbosity (error, warn, info, debug)"),
+];
+
+/// Console sink for the VGA text screen
+const VGA_SINK: ConsoleSink = ConsoleSink { name: "vga", write: vga::console_write };
+
+/// Console sink for the console serial port
+const SERIAL_SINK: ConsoleSink = ConsoleSink { name: "serial", write: serial_console_write };
+
+/// Write console output to the console serial port
+fn serial_console_write(_stream: Stream, message: &str) {
+    serial::write_str(CONSOLE_SERIAL_PORT, message);
+}
+
+/// Check whether the `console` boot option selects a console device
+/// 
+/// Without the option every device is used.
+fn console_selected(name: &str) -> bool {
+    match cmdline::list("console") {
+        Some(mut devices) => devices.any(|device| device == name),
+        None => true,
+    }
+}
+
+/// Initialize the kernel console
+/// 
+/// Registers the VGA screen, then initializes COM1 and registers it too.
+/// If COM1 is not present, output goes to the VGA screen only. The
+/// `console` boot option limits the console to the devices it lists, but
+/// the VGA screen is always used when serial output is not, so boot
+/// messages are never lost.
+pub fn init_console() -> Result<(), SerialError> {
+    let serial_result = if console_selected(SERIAL_SINK.name) {
+        serial::init(CONSOLE_SERIAL_PORT, &SerialConfig::new())
+    } else {
+        Ok(())
+    };
+    let use_serial = console_selected(SERIAL_SINK.name) && serial_result.is_ok();
+    
+    if console_selected(VGA_SINK.name) || !use_serial {
+        let _ = console::register_sink(VGA_SINK);
+    }
+    if use_serial {
+        let _ = console::register_sink(SERIAL_SINK);
+    }
+    
+    serial_result
+}
+
+/// Switch the console serial port to interrupt-driven operation
+/// 
+/// Must be called after the interrupt controller is initialized. Registers
+/// the serial handler on the port's (shared) IRQ, so output is sent from
+/// the serial interrupt once interrupts are enabled. The port stays in
+/// polling mode if the handler cannot be registered.
+pub fn enable_console_interrupts() -> Result<(), SerialError> {
+    let port = CONSOLE_SERIAL_PORT;
+    serial::enable_interrupts(port)?;
+    
+    let handler = hardware::serial_interrupt_handler;
+    if let Err(error) = irq::register_irq(port.irq(), port.name(), handler, port as usize, IrqSharing::Shared) {
+        serial::disable_interrupts(port);
+        crate::println!("Warning: {} stays in polling mode: {}", port.name(), error);
+    }
+    Ok(())
+}
+
+/// Print a string to all console sinks
+pub fn print(message: &str) {
+    console::write_str(Stream::Out, message);
+}
+
+/// Print a string and move to the next line
+pub fn println(message: &str) {
+    console::write_fmt(Stream::Out, format_args!("{}\n", message));
+}
@@ -0,0 +1,402 @@
+/// PS/2 Mouse Driver
+/// 
+/// Drives the mouse on the second i8042 port (IRQ 12). The mouse sends a
+/// packet for every movement or button change:
+/// 
+/// ```text
+/// Byte 0  bit 0: left    bit 1: right   bit 2: middle   bit 3: always 1
+///         bit 4: X sign  bit 5: Y sign  bit 6: X overflow  bit 7: Y overflow
+/// Byte 1  X movement (low 8 bits of a 9-bit two's complement value)
+/// Byte 2  Y movement (positive is up)
+/// Byte 3  IntelliMouse only: wheel movement
+///         ID 3: signed byte
+///         ID 4: bits 0-3 signed wheel, bit 4: button 4, bit 5: button 5
+/// ```
+/// 
+/// The IntelliMouse extensions are enabled by "magic" sample rate
+/// sequences: 200, 100, 80 turns on the wheel (device ID 3), then
+/// 200, 200, 80 turns on buttons 4 and 5 (device ID 4). A mouse that does
+/// not know a sequence keeps its old ID.
+/// 
+/// ## Resynchronisation
+/// 
+/// Packets carry no start marker apart from bit 3 of the first byte, so
+/// a lost byte would shift every following packet. The decoder drops
+/// first bytes without bit 3, and drops a partial packet when its next
+/// byte arrives more than `PACKET_GAP_NS` later, since the bytes of one
+/// packet arrive back to back.
+/// 
+/// The interrupt handler pushes a `MouseEvent` for every packet that
+/// moves or changes a button into a lock-free `EventQueue`, which
+/// consumers drain with `read_event`.
+
+use crate::arch::x86_64::boot::cmdline::{BootOption, OptionKind};
+use crate::arch::x86_64::interrupts::hardware;
+use crate::arch::x86_64::interrupts::irq::{self, IrqError, IrqSharing};
+use crate::arch::x86_64::sync::{EventQueue, SpinLock};
+use crate::arch::x86_64::time;
+use super::i8042::{self, I8042Error, Port};
+
+/// Boot options of the mouse driver
+pub static BOOT_OPTIONS: [BootOption; 1] = [
+    BootOption::new("nomouse", OptionKind::Flag, "Do not initialize the PS/2 mouse"),
+];
+
+/// ISA IRQ of the mouse
+pub const MOUSE_IRQ: u8 = 12;
+
+/// Mouse command: read the device ID
+const CMD_GET_ID: u8 = 0xF2;
+/// Mouse command: set the sample rate (packets per second)
+const CMD_SET_SAMPLE_RATE: u8 = 0xF3;
+/// Mouse command: start sending packets
+const CMD_ENABLE_REPORTING: u8 = 0xF4;
+/// Mouse command: restore the default settings
+const CMD_SET_DEFAULTS: u8 = 0xF6;
+
+/// Sample rates that enable the scroll wheel
+const WHEEL_SEQUENCE: [u8; 3] = [200, 100, 80];
+/// Sample rates that enable buttons 4 and 5
+const FIVE_BUTTON_SEQUENCE: [u8; 3] = [200, 200, 80];
+/// Sample rate used once detection is done
+const SAMPLE_RATE: u8 = 100;
+
+/// Packet byte 0: always set, used to find the start of a packet
+const PACKET_ALWAYS_ONE: u8 = 1 << 3;
+const PACKET_X_SIGN: u8 = 1 << 4;
+const PACKET_Y_SIGN: u8 = 1 << 5;
+const PACKET_X_OVERFLOW: u8 = 1 << 6;
+const PACKET_Y_OVERFLOW: u8 = 1 << 7;
+/// Packet byte 0: left, right and middle button
+const PACKET_BUTTONS: u8 = 0x07;
+
+/// Longest pause between two bytes of one packet
+pub const PACKET_GAP_NS: u64 = 50_000_000;
+
+/// Capacity of the mouse event queue
+pub const EVENT_QUEUE_SIZE: usize = 64;
+
+/// Errors returned by the mouse driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseError {
+    /// The controller or mouse did not respond as expected
+    Controller(I8042Error),
+    /// The IRQ 12 handler could not be registered
+    Irq(IrqError),
+}
+
+impl From<I8042Error> for MouseError {
+    fn from(error: I8042Error) -> Self {
+        MouseError::Controller(error)
+    }
+}
+
+impl From<IrqError> for MouseError {
+    fn from(error: IrqError) -> Self {
+        MouseError::Irq(error)
+    }
+}
+
+impl core::fmt::Display for MouseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            MouseError::Controller(error) => write!(f, "{}", error),
+            MouseError::Irq(error) => write!(f, "mouse interrupt: {}", error),
+        }
+    }
+}
+
+/// Protocol spoken by the mouse
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseKind {
+    /// Three buttons, 3-byte packets (device ID 0)
+    Standard,
+    /// IntelliMouse with a scroll wheel, 4-byte packets (device ID 3)
+    Wheel,
+    /// IntelliMouse Explorer with a wheel and five buttons (device ID 4)
+    FiveButton,
+}
+
+impl MouseKind {
+    /// Device ID reported by a mouse speaking this protocol
+    pub fn id(&self) -> u8 {
+        match self {
+            MouseKind::Standard => 0,
+            MouseKind::Wheel => 3,
+            MouseKind::FiveButton => 4,
+        }
+    }
+    
+    /// Bytes per packet
+    pub fn packet_size(&self) -> usize {
+        match self {
+            MouseKind::Standard => 3,
+            MouseKind::Wheel | MouseKind::FiveButton => 4,
+        }
+    }
+    
+    pub fn name(&self) -> &'static str {
+        match self {
+            MouseKind::Standard => "standard",
+            MouseKind::Wheel => "wheel",
+            MouseKind::FiveButton => "wheel, 5 buttons",
+        }
+    }
+}
+
+/// State of the mouse buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MouseButtons(u8);
+
+impl MouseButtons {
+    pub const LEFT: u8 = 1 << 0;
+    pub const RIGHT: u8 = 1 << 1;
+    pub const MIDDLE: u8 = 1 << 2;
+    pub const BUTTON4: u8 = 1 << 3;
+    pub const BUTTON5: u8 = 1 << 4;
+    
+    pub const fn empty() -> Self {
+        MouseButtons(0)
+    }
+    
+    pub const fn from_bits(bits: u8) -> Self {
+        MouseButtons(bits)
+    }
+    
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+    
+    pub fn contains(&self, bits: u8) -> bool {
+        self.0 & bits == bits
+    }
+    
+    pub fn left(&self) -> bool {
+        self.contains(Self::LEFT)
+    }
+    
+    pub fn right(&self) -> bool {
+        self.contains(Self::RIGHT)
+    }
+    
+    pub fn middle(&self) -> bool {
+        self.contains(Self::MIDDLE)
+    }
+}
+
+/// Movement and button changes reported by one packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseEvent {
+    /// Horizontal movement, positive to the right
+    pub dx: i16,
+    /// Vertical movement, positive downwards (screen direction)
+    pub dy: i16,
+    /// Wheel movement, positive towards the user (scrolling down)
+    pub wheel: i8,
+    /// Buttons held after this packet
+    pub buttons: MouseButtons,
+    /// Buttons pressed or released by this packet
+    pub changed: MouseButtons,
+}
+
+/// Assembles packets from mouse bytes
+#[derive(Debug, Clone)]
+pub struct PacketDecoder {
+    kind: MouseKind,
+    packet: [u8; 4],
+    len: usize,
+    /// Time of the last byte, for the gap check
+    last_byte_ns: u64,
+    buttons: MouseButtons,
+    resyncs: u64,
+}
+
+impl PacketDecoder {
+    pub const fn new(kind: MouseKind) -> Self {
+        PacketDecoder {
+            kind,
+            packet: [0; 4],
+            len: 0,
+            last_byte_ns: 0,
+            buttons: MouseButtons::empty(),
+            resyncs: 0,
+        }
+    }
+    
+    pub fn kind(&self) -> MouseKind {
+        self.kind
+    }
+    
+    /// Number of times bytes were dropped to find the next packet
+    pub fn resyncs(&self) -> u64 {
+        self.resyncs
+    }
+    
+    /// Drop a partial packet and forget the button state
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.buttons = MouseButtons::empty();
+    }
+    
+    /// Feed a byte received at `now_ns`, returning the event of a
+    /// completed packet that moves or changes a button
+    pub fn feed(&mut self, byte: u8, now_ns: u64) -> Option<MouseEvent> {
+        if self.len > 0 && now_ns.saturating_sub(self.last_byte_ns) > PACKET_GAP_NS {
+            self.len = 0;
+            self.resyncs += 1;
+        }
+        self.last_byte_ns = now_ns;
+        
+        if self.len == 0 && byte & PACKET_ALWAYS_ONE == 0 {
+            self.resyncs += 1;
+            return None;
+        }
+        self.packet[self.len] = byte;
+        self.len += 1;
+        if self.len < self.kind.packet_size() {
+            return None;
+        }
+        self.len = 0;
+        self.decode()
+    }
+    
+    /// Turn a complete packet into an event
+    fn decode(&mut self) -> Option<MouseEvent> {
+        let [flags, x, y, extra] = self.packet;
+        
+        // Overflowed movement is meaningless, but the buttons are valid
+        let (dx, dy) = if flags & (PACKET_X_OVERFLOW | PACKET_Y_OVERFLOW) != 0 {
+            (0, 0)
+        } else {
+            (sign_extend(x, flags & PACKET_X_SIGN != 0), -sign_extend(y, flags & PACKET_Y_SIGN != 0))
+        };
+        
+        let mut buttons = flags & PACKET_BUTTONS;
+        let wheel = match self.kind {
+            MouseKind::Standard => 0,
+            MouseKind::Wheel => extra as i8,
+            MouseKind::FiveButton => {
+                if extra & (1 << 4) != 0 {
+                    buttons |= MouseButtons::BUTTON4;
+                }
+                if extra & (1 << 5) != 0 {
+                    buttons |= MouseButtons::BUTTON5;
+                }
+                // Sign-extend the low 4 bits
+                ((extra << 4) as i8) >> 4
+            }
+        };
+        
+        let changed = MouseButtons(buttons ^ self.buttons.0);
+        self.buttons = MouseButtons(buttons);
+        if dx == 0 && dy == 0 && wheel == 0 && changed.is_empty() {
+            return None;
+        }
+        Some(MouseEvent { dx, dy, wheel, buttons: self.buttons, changed })
+    }
+}
+
+/// Value of a 9-bit movement from its low byte and sign bit
+fn sign_extend(low: u8, negative: bool) -> i16 {
+    if negative { low as i16 - 0x100 } else { low as i16 }
+}
+
+/// Mouse state, owned by the interrupt handler
+static MOUSE: SpinLock<PacketDecoder> = SpinLock::new(PacketDecoder::new(MouseKind::Standard));
+
+/// Decoded mouse events
+static EVENTS: EventQueue<MouseEvent, EVENT_QUEUE_SIZE> = EventQueue::new();
+
+/// Set the sample rate
+fn set_sample_rate(rate: u8) -> Result<(), I8042Error> {
+    i8042::send_device(Port::Port2, CMD_SET_SAMPLE_RATE)?;
+    i8042::send_device(Port::Port2, rate)
+}
+
+/// Send a magic sample rate sequence and check the resulting device ID
+fn try_extension(sequence: &[u8], kind: MouseKind) -> Result<bool, I8042Error> {
+    for &rate in sequence {
+        set_sample_rate(rate)?;
+    }
+    Ok(i8042::query_device(Port::Port2, CMD_GET_ID)? == kind.id())
+}
+
+/// Initialize the PS/2 mouse
+/// 
+/// Initializes the controller if the keyboard driver has not, resets the
+/// mouse, detects the IntelliMouse extensions and registers the IRQ 12
+/// handler. Needs a running clock for the command timeouts, so it is
+/// called after `time::init`.
+pub fn init() -> Result<MouseKind, MouseError> {
+    i8042::init()?;
+    if !i8042::has_second_port() {
+        return Err(I8042Error::NoSecondPort.into());
+    }
+    
+    i8042::reset_device(Port::Port2)?;
+    i8042::send_device(Port::Port2, CMD_SET_DEFAULTS)?;
+    let mut kind = MouseKind::Standard;
+    if try_extension(&WHEEL_SEQUENCE, MouseKind::Wheel)? {
+        kind = MouseKind::Wheel;
+        if try_extension(&FIVE_BUTTON_SEQUENCE, MouseKind::FiveButton)? {
+            kind = MouseKind::FiveButton;
+        }
+    }
+    set_sample_rate(SAMPLE_RATE)?;
+    i8042::send_device(Port::Port2, CMD_ENABLE_REPORTING)?;
+    
+    *MOUSE.lock() = PacketDecoder::new(kind);
+    irq::register_irq(MOUSE_IRQ, "mouse", hardware::mouse_interrupt_handler, 0, IrqSharing::Exclusive)?;
+    i8042::update_config(i8042::CONFIG_PORT2_IRQ, i8042::CONFIG_PORT2_CLOCK_OFF)?;
+    
+    // A byte that arrived before the interrupt was enabled raises no
+    // interrupt and would block the controller's output buffer. The lock
+    // keeps the interrupt handler out while it is taken.
+    let mut mouse = MOUSE.lock();
+    let pending = i8042::STATUS_OUTPUT_FULL | i8042::STATUS_AUX_DATA;
+    if i8042::status() & pending == pending {
+        if let Some(event) = mouse.feed(i8042::read_data(), time::monotonic_ns()) {
+            EVENTS.push(event);
+        }
+    }
+    Ok(kind)
+}
+
+/// Handle a byte read from the mouse
+/// 
+/// Called from the IRQ 12 handler.
+pub fn handle_byte(byte: u8) {
+    let event = MOUSE.lock().feed(byte, time::monotonic_ns());
+    if let Some(event) = event {
+        EVENTS.push(event);
+    }
+}
+
+/// Take the oldest mouse event
+pub fn read_event() -> Option<MouseEvent> {
+    EVENTS.pop()
+}
+
+/// Mouse events dropped because nobody read them
+pub fn dropped_events() -> u64 {
+    EVENTS.dropped()
+}
+
+/// Times the packet decoder dropped bytes to resynchronise
+pub fn resyncs() -> u64 {
+    MOUSE.lock().resyncs()
+}
+
+/// Protocol of the initialized mouse
+pub fn mouse_kind() -> MouseKind {
+    MOUSE.lock().kind()
+}
+
+/// Buttons currently held
+pub fn buttons() -> MouseButtons {
+    MOUSE.lock().buttons
+}
@@ -0,0 +1,112 @@
+/// 8253/8254 Programmable Interval Timer
+/// 
+/// The PIT has three 16-bit down counters driven by a 1.193182 MHz clock:
+/// 
+/// ```text
+/// Channel 0  Port 0x40  Output wired to IRQ 0 (system tick)
+/// Channel 1  Port 0x41  Historically DRAM refresh, unused
+/// Channel 2  Port 0x42  PC speaker; gate and output readable at port 0x61
+/// ```
+/// 
+/// Channel 0 is the fallback tick source when the local APIC timer is not
+/// available. Channel 2 can be started and polled without interrupts,
+/// which makes it the reference for calibrating the TSC and the APIC timer.
+
+use crate::arch::x86_64::port::{inb, outb};
+
+/// Input clock frequency of all channels in Hz
+pub const PIT_FREQUENCY: u32 = 1_193_182;
+
+/// Channel 0 data port
+const CHANNEL0_DATA: u16 = 0x40;
+/// Channel 2 data port
+const CHANNEL2_DATA: u16 = 0x42;
+/// Mode/command register
+const COMMAND: u16 = 0x43;
+/// Keyboard controller port B: channel 2 gate (bit 0), speaker enable
+/// (bit 1) and channel 2 output (bit 5)
+const PORT_B: u16 = 0x61;
+
+/// Command: select channel 0
+const SELECT_CHANNEL0: u8 = 0b00 << 6;
+/// Command: select channel 2
+const SELECT_CHANNEL2: u8 = 0b10 << 6;
+/// Command: access low byte then high byte
+const ACCESS_LOW_HIGH: u8 = 0b11 << 4;
+/// Command: mode 0, interrupt on terminal count (one-shot)
+const MODE_ONESHOT: u8 = 0b000 << 1;
+/// Command: mode 2, rate generator (periodic)
+const MODE_RATE_GENERATOR: u8 = 0b010 << 1;
+
+/// Port B: channel 2 gate
+const PORT_B_GATE: u8 = 1 << 0;
+/// Port B: speaker data enable
+const PORT_B_SPEAKER: u8 = 1 << 1;
+/// Port B: channel 2 output
+const PORT_B_OUT2: u8 = 1 << 5;
+
+/// Counter reload value for a frequency (65536 is written as 0)
+fn divisor_for(hz: u32) -> u32 {
+    let hz = hz.max(1);
+    ((PIT_FREQUENCY + hz / 2) / hz).clamp(1, 65536)
+}
+
+/// Make channel 0 raise IRQ 0 periodically
+/// 
+/// Returns the actual period in nanoseconds, which differs slightly from
+/// `1 / hz` since the counter divides the input clock by an integer.
+pub fn start_periodic(hz: u32) -> u64 {
+    let divisor = divisor_for(hz);
+    
+    unsafe {
+        outb(COMMAND, SELECT_CHANNEL0 | ACCESS_LOW_HIGH | MODE_RATE_GENERATOR);
+        outb(CHANNEL0_DATA, divisor as u8);
+        outb(CHANNEL0_DATA, (divisor >> 8) as u8);
+    }
+    
+    divisor as u64 * 1_000_000_000 / PIT_FREQUENCY as u64
+}
+
+/// Stop channel 0 from raising further periodic interrupts
+/// 
+/// Switching to mode 0 stops the counter until a new count is written.
+pub fn stop() {
+    unsafe { outb(COMMAND, SELECT_CHANNEL0 | ACCESS_LOW_HIGH | MODE_ONESHOT) };
+}
+
+/// Load channel 2 with a one-shot count, without starting it
+/// 
+/// The count starts when `start_channel2` raises the gate; `channel2_done`
+/// reports when it has reached zero. The speaker stays off.
+pub fn prepare_channel2(count: u16) {
+    unsafe {
+        let port_b = inb(PORT_B) & !(PORT_B_GATE | PORT_B_SPEAKER);
+        outb(PORT_B, port_b);
+        
+        outb(COMMAND, SELECT_CHANNEL2 | ACCESS_LOW_HIGH | MODE_ONESHOT);
+        outb(CHANNEL2_DATA, count as u8);
+        outb(CHANNEL2_DATA, (count >> 8) as u8);
+    }
+}
+
+/// Start the count loaded by `prepare_channel2`
+pub fn start_channel2() {
+    unsafe { outb(PORT_B, inb(PORT_B) | PORT_B_GATE) };
+}
+
+/// Check whether the channel 2 count has reached zero
+pub fn channel2_done() -> bool {
+    unsafe { inb(PORT_B) & PORT_B_OUT2 != 0 }
+}
+
+/// Busy-wait on channel 2 for up to 54 ms
+/// 
+/// Does not need interrupts or any calibrated clock.
+pub fn wait_us(us: u32) {
+    let count = (PIT_FREQUENCY as u64 * us as u64 / 1_000_000).clamp(1, u16::MAX as u64) as u16;
+    prepare_channel2(count);
+    start_channel2();
+    while !channel2_done() {
+        core::hint::spin_loop();
+    }
+}
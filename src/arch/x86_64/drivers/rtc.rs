@@ -0,0 +1,449 @@
+/// CMOS Real-Time Clock
+/// 
+/// The RTC keeps the date and time while the machine is off. Its registers
+/// are reached through the CMOS index (0x70) and data (0x71) ports:
+/// 
+/// ```text
+/// 0x00 Seconds    0x02 Minutes    0x04 Hours      0x06 Weekday
+/// 0x07 Day        0x08 Month      0x09 Year       0x32 Century (usually)
+/// 0x0A Status A   bit 7: update in progress, bits 0-3: periodic rate
+/// 0x0B Status B   bit 1: 24-hour, bit 2: binary, bits 4-6: interrupt enables
+/// 0x0C Status C   interrupt flags, cleared by reading
+/// ```
+/// 
+/// ## Reading the time
+/// 
+/// Once a second the RTC spends about 2 ms updating its registers, and
+/// reads during the update can return a mix of old and new values. The
+/// registers are read after the update-in-progress flag clears, and again
+/// until two reads agree. The values may be BCD or binary and the hour
+/// may be in 12-hour format (bit 7 set for PM), depending on status B.
+/// 
+/// ## Interrupts
+/// 
+/// The RTC can raise IRQ 8 periodically (2-8192 Hz) and at an alarm time.
+/// Status C must be read after every interrupt, or the RTC raises no more.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use crate::arch::x86_64::acpi;
+use crate::arch::x86_64::interrupts::hardware;
+use crate::arch::x86_64::interrupts::irq::{self, IrqError, IrqSharing};
+use crate::arch::x86_64::port::{inb, outb};
+use crate::arch::x86_64::sync::SpinLock;
+
+/// CMOS register index port
+const CMOS_INDEX: u16 = 0x70;
+/// CMOS register data port
+const CMOS_DATA: u16 = 0x71;
+
+/// ISA IRQ of the RTC
+pub const RTC_IRQ: u8 = 8;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_SECONDS_ALARM: u8 = 0x01;
+const REG_MINUTES: u8 = 0x02;
+const REG_MINUTES_ALARM: u8 = 0x03;
+const REG_HOURS: u8 = 0x04;
+const REG_HOURS_ALARM: u8 = 0x05;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_A: u8 = 0x0A;
+const REG_STATUS_B: u8 = 0x0B;
+const REG_STATUS_C: u8 = 0x0C;
+
+/// Century register used when the FADT does not name one
+const DEFAULT_CENTURY_REG: u8 = 0x32;
+/// Offset of the century register index in the FADT
+const FADT_CENTURY: usize = 108 - 36;
+
+/// Status A: update in progress
+const STATUS_A_UIP: u8 = 1 << 7;
+/// Status A: periodic interrupt rate selector
+const STATUS_A_RATE: u8 = 0x0F;
+/// Status B: 24-hour mode
+const STATUS_B_24H: u8 = 1 << 1;
+/// Status B: binary (not BCD) values
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// Status B: alarm interrupt enable
+const STATUS_B_AIE: u8 = 1 << 5;
+/// Status B: periodic interrupt enable
+const STATUS_B_PIE: u8 = 1 << 6;
+/// Status C: periodic interrupt flag
+const STATUS_C_PF: u8 = 1 << 6;
+/// Status C: alarm interrupt flag
+const STATUS_C_AF: u8 = 1 << 5;
+
+/// Hour register: PM flag in 12-hour mode
+const HOUR_PM: u8 = 1 << 7;
+
+/// Alarm field value matching any hour, minute or second
+const ALARM_ANY: u8 = 0xC0;
+
+/// Slowest and fastest periodic interrupt rates in Hz
+pub const MIN_PERIODIC_HZ: u32 = 2;
+pub const MAX_PERIODIC_HZ: u32 = 8192;
+
+/// Errors returned by the RTC functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RtcError {
+    /// The registers hold an impossible date or time
+    InvalidTime,
+    /// Periodic rate not a power of two between 2 and 8192 Hz
+    InvalidRate(u32),
+    /// The IRQ 8 handler could not be registered
+    Irq(IrqError),
+}
+
+impl From<IrqError> for RtcError {
+    fn from(error: IrqError) -> Self {
+        RtcError::Irq(error)
+    }
+}
+
+impl core::fmt::Display for RtcError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            RtcError::InvalidTime => write!(f, "RTC holds an invalid date"),
+            RtcError::InvalidRate(hz) => write!(f, "invalid RTC periodic rate {} Hz", hz),
+            RtcError::Irq(error) => write!(f, "RTC interrupt: {}", error),
+        }
+    }
+}
+
+/// A calendar date and time (UTC)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DateTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl DateTime {
+    /// Seconds since 1970-01-01 00:00:00 UTC
+    pub fn to_unix(&self) -> u64 {
+        let days = days_from_civil(self.year as i64, self.month as i64, self.day as i64);
+        (days * 86400) as u64 + self.hour as u64 * 3600 + self.minute as u64 * 60 + self.second as u64
+    }
+    
+    /// Date and time of a Unix timestamp
+    pub fn from_unix(timestamp: u64) -> Self {
+        let (year, month, day) = civil_from_days((timestamp / 86400) as i64);
+        let seconds = timestamp % 86400;
+        DateTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+    
+    /// Day of the week, 0 = Sunday
+    pub fn weekday(&self) -> u8 {
+        // 1970-01-01 was a Thursday
+        ((days_from_civil(self.year as i64, self.month as i64, self.day as i64) + 4).rem_euclid(7)) as u8
+    }
+    
+    /// Check the fields against the calendar (years 1970-9999)
+    fn is_valid(&self) -> bool {
+        (1970..=9999).contains(&self.year)
+            && (1..=12).contains(&self.month)
+            && self.day >= 1
+            && self.day <= days_in_month(self.year, self.month)
+            && self.hour < 24
+            && self.minute < 60
+            && self.second < 60
+    }
+}
+
+impl core::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(f, "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC", self.year, self.month, self.day, self.hour, self.minute, self.second)
+    }
+}
+
+/// Number of days in a month
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date
+/// 
+/// Counts in eras of 400 years starting in March, so the leap day is the
+/// last day of the year.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Proleptic Gregorian date of a day count since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Access to the CMOS registers
+/// 
+/// Selecting a register and accessing it are two port writes, so both
+/// happen under the lock.
+struct Cmos;
+
+impl Cmos {
+    fn read(&mut self, reg: u8) -> u8 {
+        unsafe {
+            outb(CMOS_INDEX, reg);
+            inb(CMOS_DATA)
+        }
+    }
+    
+    fn write(&mut self, reg: u8, value: u8) {
+        unsafe {
+            outb(CMOS_INDEX, reg);
+            outb(CMOS_DATA, value);
+        }
+    }
+}
+
+static CMOS: SpinLock<Cmos> = SpinLock::new(Cmos);
+
+/// Periodic interrupts received
+static PERIODIC_COUNT: AtomicU64 = AtomicU64::new(0);
+/// Alarm interrupts received
+static ALARM_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Raw register values of one read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RawTime {
+    second: u8,
+    minute: u8,
+    hour: u8,
+    day: u8,
+    month: u8,
+    year: u8,
+    century: u8,
+}
+
+/// CMOS register holding the century, as named by the ACPI FADT
+/// 
+/// Returns None if the FADT says there is no century register. Without
+/// ACPI the register usually found at 0x32 is assumed.
+fn century_register() -> Option<u8> {
+    match acpi::find_table("FACP").and_then(|fadt| fadt.data().get(FADT_CENTURY).copied()) {
+        Some(0) => None,
+        Some(reg) => Some(reg),
+        None => Some(DEFAULT_CENTURY_REG),
+    }
+}
+
+/// Read the time registers once the current update has finished
+fn read_raw(cmos: &mut Cmos, century_reg: Option<u8>) -> RawTime {
+    while cmos.read(REG_STATUS_A) & STATUS_A_UIP != 0 {
+        core::hint::spin_loop();
+    }
+    
+    RawTime {
+        second: cmos.read(REG_SECONDS),
+        minute: cmos.read(REG_MINUTES),
+        hour: cmos.read(REG_HOURS),
+        day: cmos.read(REG_DAY),
+        month: cmos.read(REG_MONTH),
+        year: cmos.read(REG_YEAR),
+        century: century_reg.map_or(0, |reg| cmos.read(reg)),
+    }
+}
+
+/// Convert a BCD byte to binary
+fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0F)
+}
+
+/// Decode raw register values according to status B
+fn decode(raw: RawTime, status_b: u8) -> DateTime {
+    let binary = status_b & STATUS_B_BINARY != 0;
+    let convert = |value: u8| if binary { value } else { from_bcd(value) };
+    
+    // 12-hour mode: 12 AM is midnight, 12 PM is noon
+    let mut hour = convert(raw.hour & !HOUR_PM);
+    if status_b & STATUS_B_24H == 0 {
+        hour %= 12;
+        if raw.hour & HOUR_PM != 0 {
+            hour += 12;
+        }
+    }
+    
+    let year = convert(raw.year) as u16;
+    let year = match raw.century {
+        0 if year < 70 => 2000 + year,
+        0 => 1900 + year,
+        century => convert(century) as u16 * 100 + year,
+    };
+    
+    DateTime {
+        year,
+        month: convert(raw.month),
+        day: convert(raw.day),
+        hour,
+        minute: convert(raw.minute),
+        second: convert(raw.second),
+    }
+}
+
+/// Read the current date and time
+/// 
+/// Takes up to a few milliseconds if an update is in progress.
+pub fn read_time() -> Result<DateTime, RtcError> {
+    let century_reg = century_register();
+    
+    let (raw, status_b) = {
+        let mut cmos = CMOS.lock();
+        let mut raw = read_raw(&mut cmos, century_reg);
+        loop {
+            let again = read_raw(&mut cmos, century_reg);
+            if again == raw {
+                break;
+            }
+            raw = again;
+        }
+        (raw, cmos.read(REG_STATUS_B))
+    };
+    
+    let time = decode(raw, status_b);
+    if time.is_valid() { Ok(time) } else { Err(RtcError::InvalidTime) }
+}
+
+/// Register the IRQ 8 handler if it is not registered yet
+fn register_handler() -> Result<(), RtcError> {
+    match irq::register_irq(RTC_IRQ, "rtc", hardware::rtc_interrupt_handler, 0, IrqSharing::Exclusive) {
+        Ok(()) | Err(IrqError::AlreadyRegistered) => Ok(()),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Unregister the IRQ 8 handler once no interrupt source is enabled
+fn release_handler(status_b: u8) {
+    if status_b & (STATUS_B_PIE | STATUS_B_AIE) == 0 {
+        let _ = irq::unregister_irq(RTC_IRQ, "rtc");
+    }
+}
+
+/// Enable the periodic interrupt at a rate of `hz`
+/// 
+/// The rate must be a power of two from 2 to 8192 Hz.
+pub fn enable_periodic(hz: u32) -> Result<(), RtcError> {
+    if !hz.is_power_of_two() || !(MIN_PERIODIC_HZ..=MAX_PERIODIC_HZ).contains(&hz) {
+        return Err(RtcError::InvalidRate(hz));
+    }
+    // 32768 Hz >> (rate - 1)
+    let rate = 16 - hz.trailing_zeros() as u8;
+    
+    register_handler()?;
+    let mut cmos = CMOS.lock();
+    let status_a = cmos.read(REG_STATUS_A);
+    cmos.write(REG_STATUS_A, (status_a & !STATUS_A_RATE) | rate);
+    let status_b = cmos.read(REG_STATUS_B);
+    cmos.write(REG_STATUS_B, status_b | STATUS_B_PIE);
+    cmos.read(REG_STATUS_C);
+    Ok(())
+}
+
+/// Disable the periodic interrupt
+pub fn disable_periodic() {
+    let status_b = {
+        let mut cmos = CMOS.lock();
+        let status_b = cmos.read(REG_STATUS_B) & !STATUS_B_PIE;
+        cmos.write(REG_STATUS_B, status_b);
+        status_b
+    };
+    release_handler(status_b);
+}
+
+/// Raise the alarm interrupt at a time of day (UTC)
+/// 
+/// A `None` field matches any value, so `set_alarm(None, None, Some(0))`
+/// fires at the start of every minute.
+pub fn set_alarm(hour: Option<u8>, minute: Option<u8>, second: Option<u8>) -> Result<(), RtcError> {
+    if hour.is_some_and(|h| h > 23) || minute.is_some_and(|m| m > 59) || second.is_some_and(|s| s > 59) {
+        return Err(RtcError::InvalidTime);
+    }
+    
+    register_handler()?;
+    let mut cmos = CMOS.lock();
+    let status_b = cmos.read(REG_STATUS_B);
+    let encode = |value: Option<u8>, is_hour: bool| match value {
+        None => ALARM_ANY,
+        Some(value) => {
+            // 12-hour mode: hours 1-12 with the PM flag
+            let (value, pm) = match is_hour && status_b & STATUS_B_24H == 0 {
+                true => ((value + 11) % 12 + 1, if value >= 12 { HOUR_PM } else { 0 }),
+                false => (value, 0),
+            };
+            let value = if status_b & STATUS_B_BINARY != 0 { value } else { ((value / 10) << 4) | (value % 10) };
+            value | pm
+        }
+    };
+    
+    cmos.write(REG_HOURS_ALARM, encode(hour, true));
+    cmos.write(REG_MINUTES_ALARM, encode(minute, false));
+    cmos.write(REG_SECONDS_ALARM, encode(second, false));
+    cmos.write(REG_STATUS_B, status_b | STATUS_B_AIE);
+    cmos.read(REG_STATUS_C);
+    Ok(())
+}
+
+/// Disable the alarm interrupt
+pub fn clear_alarm() {
+    let status_b = {
+        let mut cmos = CMOS.lock();
+        let status_b = cmos.read(REG_STATUS_B) & !STATUS_B_AIE;
+        cmos.write(REG_STATUS_B, status_b);
+        status_b
+    };
+    release_handler(status_b);
+}
+
+/// Periodic interrupts received so far
+pub fn periodic_count() -> u64 {
+    PERIODIC_COUNT.load(Ordering::Relaxed)
+}
+
+/// Alarm interrupts received so far
+pub fn alarm_count() -> u64 {
+    ALARM_COUNT.load(Ordering::Relaxed)
+}
+
+/// Acknowledge an RTC interrupt and count its causes
+/// 
+/// Called from the IRQ 8 handler. Returns false if the RTC did not raise
+/// an interrupt.
+pub fn handle_interrupt() -> bool {
+    let flags = CMOS.lock().read(REG_STATUS_C);
+    if flags & STATUS_C_PF != 0 {
+        PERIODIC_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+    if flags & STATUS_C_AF != 0 {
+        ALARM_COUNT.fetch_add(1, Ordering::Relaxed);
+    }
+    flags & (STATUS_C_PF | STATUS_C_AF) != 0
+}
@@ -0,0 +1,655 @@
+/// 16550 UART Serial Port Driver
+/// 
+/// Drives the four legacy PC serial ports (COM1-COM4). Kernel output is
+/// mirrored to COM1 so that it can be captured when QEMU runs headless
+/// with `-serial stdio`.
+/// 
+/// ## Modes
+/// 
+/// - **Polling** (default after `init`): every byte is written by waiting
+///   for the transmitter to become empty. Works before the IDT and the
+///   interrupt controller are set up.
+/// - **Interrupt-driven** (after `enable_interrupts`): bytes are queued in
+///   ring buffers and moved to and from the UART FIFOs by `handle_interrupt`.
+///   While CPU interrupts are disabled, writes still drain the transmit
+///   buffer by polling, so output is never stuck in the buffer.
+/// 
+/// Each port is protected by a `SpinLock`. Writers that find the lock held
+/// (an exception or NMI that interrupted a writer) fall back to writing the
+/// UART directly instead of deadlocking.
+/// 
+/// ## Usage
+/// 
+/// ```rust
+/// serial::init(ComPort::Com1, &SerialConfig::new())?;
+/// serial::write_str(ComPort::Com1, "Hello, serial!\n");
+/// ```
+
+use crate::arch::x86_64::interrupts::setup::interrupts_enabled;
+use crate::arch::x86_64::port::{inb, outb};
+use crate::arch::x86_64::sync::SpinLock;
+
+// UART register offsets from the port base
+const DATA: u16 = 0;                // RBR (read) / THR (write), divisor low when DLAB=1
+const INTERRUPT_ENABLE: u16 = 1;    // IER, divisor high when DLAB=1
+const FIFO_CONTROL: u16 = 2;        // FCR (write) / IIR (read)
+const LINE_CONTROL: u16 = 3;        // LCR
+const MODEM_CONTROL: u16 = 4;       // MCR
+const LINE_STATUS: u16 = 5;         // LSR
+const MODEM_STATUS: u16 = 6;        // MSR
+
+/// Base clock of the UART divided by 16 (the maximum baud rate)
+const UART_CLOCK: u32 = 115200;
+
+// Interrupt Enable Register bits
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const IER_TX_EMPTY: u8 = 1 << 1;
+const IER_LINE_STATUS: u8 = 1 << 2;
+
+// FIFO Control Register bits
+const FCR_ENABLE: u8 = 1 << 0;
+const FCR_CLEAR_RX: u8 = 1 << 1;
+const FCR_CLEAR_TX: u8 = 1 << 2;
+
+// Line Control Register bits
+const LCR_DLAB: u8 = 1 << 7;
+
+// Modem Control Register bits
+const MCR_DTR: u8 = 1 << 0;
+const MCR_RTS: u8 = 1 << 1;
+const MCR_OUT1: u8 = 1 << 2;
+const MCR_OUT2: u8 = 1 << 3;  // Gates the UART interrupt line to the PIC
+const MCR_LOOPBACK: u8 = 1 << 4;
+
+// Line Status Register bits
+const LSR_DATA_READY: u8 = 1 << 0;
+const LSR_OVERRUN: u8 = 1 << 1;
+const LSR_PARITY_ERROR: u8 = 1 << 2;
+const LSR_FRAMING_ERROR: u8 = 1 << 3;
+const LSR_BREAK: u8 = 1 << 4;
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+// Interrupt Identification Register values (bits 0-3)
+const IIR_NO_INTERRUPT: u8 = 0x01;
+const IIR_MODEM_STATUS: u8 = 0x00;
+const IIR_TX_EMPTY: u8 = 0x02;
+const IIR_RX_AVAILABLE: u8 = 0x04;
+const IIR_LINE_STATUS: u8 = 0x06;
+const IIR_RX_TIMEOUT: u8 = 0x0C;
+
+/// IIR bits 6-7 are both set when a working FIFO is enabled (16550A)
+const IIR_FIFO_ENABLED: u8 = 0xC0;
+
+/// Size of the 16550A transmit FIFO
+const TX_FIFO_SIZE: usize = 16;
+
+/// Receive buffer size in bytes
+const RX_BUFFER_SIZE: usize = 1024;
+
+/// Transmit buffer size in bytes
+const TX_BUFFER_SIZE: usize = 4096;
+
+/// Byte written and read back during the loopback self-test
+const LOOPBACK_TEST_BYTE: u8 = 0xAE;
+
+/// Legacy PC serial ports
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComPort {
+    Com1,
+    Com2,
+    Com3,
+    Com4,
+}
+
+impl ComPort {
+    /// All serial ports
+    pub const ALL: [ComPort; 4] = [ComPort::Com1, ComPort::Com2, ComPort::Com3, ComPort::Com4];
+    
+    /// I/O port base address
+    pub const fn base(self) -> u16 {
+        match self {
+            ComPort::Com1 => 0x3F8,
+            ComPort::Com2 => 0x2F8,
+            ComPort::Com3 => 0x3E8,
+            ComPort::Com4 => 0x2E8,
+        }
+    }
+    
+    /// ISA IRQ line (COM1/COM3 share IRQ 4, COM2/COM4 share IRQ 3)
+    pub const fn irq(self) -> u8 {
+        match self {
+            ComPort::Com1 | ComPort::Com3 => 4,
+            ComPort::Com2 | ComPort::Com4 => 3,
+        }
+    }
+    
+    /// Human readable name
+    pub const fn name(self) -> &'static str {
+        match self {
+            ComPort::Com1 => "COM1",
+            ComPort::Com2 => "COM2",
+            ComPort::Com3 => "COM3",
+            ComPort::Com4 => "COM4",
+        }
+    }
+    
+    const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Number of data bits per character
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum DataBits {
+    Five = 0b00,
+    Six = 0b01,
+    Seven = 0b10,
+    Eight = 0b11,
+}
+
+/// Parity mode
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum Parity {
+    None = 0b000 << 3,
+    Odd = 0b001 << 3,
+    Even = 0b011 << 3,
+    Mark = 0b101 << 3,
+    Space = 0b111 << 3,
+}
+
+/// Number of stop bits
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopBits {
+    One = 0,
+    /// Two stop bits (1.5 with five data bits)
+    Two = 1 << 2,
+}
+
+/// Number of received bytes that triggers a receive interrupt
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum FifoTrigger {
+    One = 0x00,
+    Four = 0x40,
+    Eight = 0x80,
+    Fourteen = 0xC0,
+}
+
+/// Serial line configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialConfig {
+    /// Baud rate (must divide 115200 evenly)
+    pub baud_rate: u32,
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+    pub fifo_trigger: FifoTrigger,
+}
+
+impl SerialConfig {
+    /// 115200 baud, 8 data bits, no parity, 1 stop bit (8N1)
+    pub const fn new() -> Self {
+        Self {
+            baud_rate: 115200,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            fifo_trigger: FifoTrigger::Fourteen,
+        }
+    }
+    
+    /// Line Control Register value for this configuration
+    const fn line_control(&self) -> u8 {
+        self.data_bits as u8 | self.parity as u8 | self.stop_bits as u8
+    }
+    
+    /// Baud rate divisor, or None if the baud rate is not supported
+    const fn divisor(&self) -> Option<u16> {
+        if self.baud_rate == 0 || !UART_CLOCK.is_multiple_of(self.baud_rate) {
+            return None;
+        }
+        Some((UART_CLOCK / self.baud_rate) as u16)
+    }
+}
+
+impl Default for SerialConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Errors that can occur when configuring a serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialError {
+    /// The baud rate does not divide 115200 evenly
+    InvalidBaudRate,
+    /// The loopback self-test failed (no UART at this address)
+    NotPresent,
+    /// The port has not been initialized
+    NotInitialized,
+}
+
+/// Line error counters
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineErrors {
+    pub overrun: u32,
+    pub parity: u32,
+    pub framing: u32,
+    pub breaks: u32,
+}
+
+/// Fixed-size byte queue
+struct RingBuffer<const N: usize> {
+    data: [u8; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    const fn new() -> Self {
+        Self { data: [0; N], head: 0, len: 0 }
+    }
+    
+    /// Append a byte, returning false if the buffer is full
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.data[(self.head + self.len) % N] = byte;
+        self.len += 1;
+        true
+    }
+    
+    /// Remove the oldest byte
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.data[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+    
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    
+    fn is_full(&self) -> bool {
+        self.len == N
+    }
+    
+    fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+/// State of one UART
+struct SerialPort {
+    base: u16,
+    initialized: bool,
+    interrupt_driven: bool,
+    /// Bytes that can be written per transmitter-empty event
+    fifo_size: usize,
+    rx: RingBuffer<RX_BUFFER_SIZE>,
+    tx: RingBuffer<TX_BUFFER_SIZE>,
+    rx_dropped: u32,
+    errors: LineErrors,
+}
+
+impl SerialPort {
+    const fn new(port: ComPort) -> Self {
+        Self {
+            base: port.base(),
+            initialized: false,
+            interrupt_driven: false,
+            fifo_size: 1,
+            rx: RingBuffer::new(),
+            tx: RingBuffer::new(),
+            rx_dropped: 0,
+            errors: LineErrors { overrun: 0, parity: 0, framing: 0, breaks: 0 },
+        }
+    }
+    
+    fn read_reg(&self, reg: u16) -> u8 {
+        unsafe { inb(self.base + reg) }
+    }
+    
+    fn write_reg(&self, reg: u16, value: u8) {
+        unsafe { outb(self.base + reg, value) }
+    }
+    
+    /// Program the line settings and FIFOs, then run the loopback self-test
+    fn init(&mut self, config: &SerialConfig) -> Result<(), SerialError> {
+        let divisor = config.divisor().ok_or(SerialError::InvalidBaudRate)?;
+        
+        self.initialized = false;
+        self.interrupt_driven = false;
+        self.rx.clear();
+        self.tx.clear();
+        
+        // Disable all UART interrupts while reprogramming
+        self.write_reg(INTERRUPT_ENABLE, 0);
+        
+        // Set the baud rate divisor
+        self.write_reg(LINE_CONTROL, LCR_DLAB);
+        self.write_reg(DATA, (divisor & 0xFF) as u8);
+        self.write_reg(INTERRUPT_ENABLE, (divisor >> 8) as u8);
+        
+        // Set data bits, parity and stop bits (also clears DLAB)
+        self.write_reg(LINE_CONTROL, config.line_control());
+        
+        // Enable and clear the FIFOs
+        self.write_reg(FIFO_CONTROL, FCR_ENABLE | FCR_CLEAR_RX | FCR_CLEAR_TX | config.fifo_trigger as u8);
+        self.fifo_size = if self.read_reg(FIFO_CONTROL) & IIR_FIFO_ENABLED == IIR_FIFO_ENABLED {
+            TX_FIFO_SIZE
+        } else {
+            1
+        };
+        
+        // Loopback self-test: the byte we send should come straight back
+        self.write_reg(MODEM_CONTROL, MCR_RTS | MCR_OUT1 | MCR_OUT2 | MCR_LOOPBACK);
+        self.write_reg(DATA, LOOPBACK_TEST_BYTE);
+        if self.read_reg(DATA) != LOOPBACK_TEST_BYTE {
+            self.write_reg(MODEM_CONTROL, 0);
+            return Err(SerialError::NotPresent);
+        }
+        
+        // Normal operation with the interrupt line still gated off
+        self.write_reg(MODEM_CONTROL, MCR_DTR | MCR_RTS | MCR_OUT1);
+        
+        self.initialized = true;
+        Ok(())
+    }
+    
+    /// Switch to interrupt-driven receive and transmit
+    fn enable_interrupts(&mut self) -> Result<(), SerialError> {
+        if !self.initialized {
+            return Err(SerialError::NotInitialized);
+        }
+        
+        self.interrupt_driven = true;
+        self.write_reg(MODEM_CONTROL, MCR_DTR | MCR_RTS | MCR_OUT1 | MCR_OUT2);
+        self.write_reg(INTERRUPT_ENABLE, IER_RX_AVAILABLE | IER_LINE_STATUS);
+        Ok(())
+    }
+    
+    /// Switch back to polling mode, flushing any queued output
+    fn disable_interrupts(&mut self) {
+        if !self.interrupt_driven {
+            return;
+        }
+        
+        self.write_reg(INTERRUPT_ENABLE, 0);
+        self.write_reg(MODEM_CONTROL, MCR_DTR | MCR_RTS | MCR_OUT1);
+        self.interrupt_driven = false;
+        self.flush();
+    }
+    
+    fn transmitter_empty(&self) -> bool {
+        self.read_reg(LINE_STATUS) & LSR_THR_EMPTY != 0
+    }
+    
+    /// Write a byte by waiting for the transmitter
+    fn write_byte_polling(&self, byte: u8) {
+        while !self.transmitter_empty() {
+            core::hint::spin_loop();
+        }
+        self.write_reg(DATA, byte);
+    }
+    
+    /// Move queued bytes into the transmit FIFO if it is empty
+    fn fill_tx_fifo(&mut self) {
+        if !self.transmitter_empty() {
+            return;
+        }
+        for _ in 0..self.fifo_size {
+            match self.tx.pop() {
+                Some(byte) => self.write_reg(DATA, byte),
+                None => break,
+            }
+        }
+    }
+    
+    /// Enable or disable the transmitter-empty interrupt
+    fn set_tx_interrupt(&self, enabled: bool) {
+        let ier = self.read_reg(INTERRUPT_ENABLE);
+        let new = if enabled { ier | IER_TX_EMPTY } else { ier & !IER_TX_EMPTY };
+        if new != ier {
+            self.write_reg(INTERRUPT_ENABLE, new);
+        }
+    }
+    
+    /// Write all queued bytes by polling
+    fn flush(&mut self) {
+        while let Some(byte) = self.tx.pop() {
+            self.write_byte_polling(byte);
+        }
+        self.set_tx_interrupt(false);
+    }
+    
+    /// Queue or write a byte, depending on the mode
+    fn write_byte(&mut self, byte: u8) {
+        if !self.interrupt_driven {
+            self.write_byte_polling(byte);
+            return;
+        }
+        
+        while self.tx.is_full() {
+            // Make room by sending the oldest byte ourselves
+            if let Some(old) = self.tx.pop() {
+                self.write_byte_polling(old);
+            }
+        }
+        self.tx.push(byte);
+    }
+    
+    /// Write bytes, translating `\n` to `\r\n` for terminals
+    fn write_bytes(&mut self, bytes: &[u8], can_interrupt: bool) {
+        if !self.initialized {
+            return;
+        }
+        
+        for &byte in bytes {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+            self.write_byte(byte);
+        }
+        
+        if self.interrupt_driven {
+            if can_interrupt {
+                // Start transmission; the interrupt handler sends the rest
+                self.fill_tx_fifo();
+                self.set_tx_interrupt(!self.tx.is_empty());
+            } else {
+                // No interrupt will come to drain the buffer
+                self.flush();
+            }
+        }
+    }
+    
+    /// Read a byte from the receive buffer or the UART
+    fn read_byte(&mut self) -> Option<u8> {
+        if !self.initialized {
+            return None;
+        }
+        
+        if self.interrupt_driven {
+            self.rx.pop()
+        } else if self.read_reg(LINE_STATUS) & LSR_DATA_READY != 0 {
+            Some(self.read_reg(DATA))
+        } else {
+            None
+        }
+    }
+    
+    /// Record line errors reported in a Line Status Register value
+    fn record_line_status(&mut self, status: u8) {
+        if status & LSR_OVERRUN != 0 {
+            self.errors.overrun += 1;
+        }
+        if status & LSR_PARITY_ERROR != 0 {
+            self.errors.parity += 1;
+        }
+        if status & LSR_FRAMING_ERROR != 0 {
+            self.errors.framing += 1;
+        }
+        if status & LSR_BREAK != 0 {
+            self.errors.breaks += 1;
+        }
+    }
+    
+    /// Drain the receive FIFO into the receive buffer
+    fn receive(&mut self) {
+        loop {
+            let status = self.read_reg(LINE_STATUS);
+            self.record_line_status(status);
+            if status & LSR_DATA_READY == 0 {
+                break;
+            }
+            
+            let byte = self.read_reg(DATA);
+            if !self.rx.push(byte) {
+                self.rx_dropped += 1;
+            }
+        }
+    }
+    
+    /// Service every pending interrupt condition of this UART
+    /// 
+    /// Returns false if nothing was pending.
+    fn handle_interrupt(&mut self) -> bool {
+        if !self.interrupt_driven {
+            return false;
+        }
+        
+        let mut serviced = false;
+        loop {
+            let iir = self.read_reg(FIFO_CONTROL) & 0x0F;
+            match iir {
+                IIR_NO_INTERRUPT => break,
+                IIR_LINE_STATUS => {
+                    let status = self.read_reg(LINE_STATUS);
+                    self.record_line_status(status);
+                }
+                IIR_RX_AVAILABLE | IIR_RX_TIMEOUT => self.receive(),
+                IIR_TX_EMPTY => {
+                    self.fill_tx_fifo();
+                    if self.tx.is_empty() {
+                        self.set_tx_interrupt(false);
+                    }
+                }
+                IIR_MODEM_STATUS => {
+                    // Reading MSR acknowledges the interrupt
+                    self.read_reg(MODEM_STATUS);
+                }
+                _ => break,
+            }
+            serviced = true;
+        }
+        serviced
+    }
+}
+
+/// Driver state for COM1-COM4
+static PORTS: [SpinLock<SerialPort>; 4] = [
+    SpinLock::new(SerialPort::new(ComPort::Com1)),
+    SpinLock::new(SerialPort::new(ComPort::Com2)),
+    SpinLock::new(SerialPort::new(ComPort::Com3)),
+    SpinLock::new(SerialPort::new(ComPort::Com4)),
+];
+
+/// Initialize a serial port in polling mode
+/// 
+/// Returns `SerialError::NotPresent` if the loopback self-test fails, in
+/// which case the port stays unused and writes to it are ignored.
+pub fn init(port: ComPort, config: &SerialConfig) -> Result<(), SerialError> {
+    PORTS[port.index()].lock().init(config)
+}
+
+/// Check whether a port passed initialization
+pub fn is_initialized(port: ComPort) -> bool {
+    PORTS[port.index()].lock().initialized
+}
+
+/// Switch a port to buffered, interrupt-driven operation
+/// 
+/// The IRQ for the port (see `ComPort::irq`) must be routed to the serial
+/// interrupt handler and unmasked at the interrupt controller.
+pub fn enable_interrupts(port: ComPort) -> Result<(), SerialError> {
+    PORTS[port.index()].lock().enable_interrupts()
+}
+
+/// Switch a port back to polling mode
+pub fn disable_interrupts(port: ComPort) {
+    PORTS[port.index()].lock().disable_interrupts();
+}
+
+/// Write a string to a port
+pub fn write_str(port: ComPort, s: &str) {
+    write_bytes(port, s.as_bytes());
+}
+
+/// Write bytes to a port
+/// 
+/// `\n` is sent as `\r\n`. Does nothing if the port is not initialized.
+pub fn write_bytes(port: ComPort, bytes: &[u8]) {
+    let can_interrupt = interrupts_enabled();
+    
+    match PORTS[port.index()].try_lock() {
+        Some(mut serial) => serial.write_bytes(bytes, can_interrupt),
+        None => {
+            // The lock is held by the code we interrupted (we are in an
+            // exception or NMI handler). Spinning would never end, so write
+            // straight to the UART instead.
+            let serial = unsafe { &*PORTS[port.index()].data_ptr() };
+            if serial.initialized {
+                for &byte in bytes {
+                    if byte == b'\n' {
+                        serial.write_byte_polling(b'\r');
+                    }
+                    serial.write_byte_polling(byte);
+                }
+            }
+        }
+    }
+}
+
+/// Read a received byte, if any
+pub fn read_byte(port: ComPort) -> Option<u8> {
+    PORTS[port.index()].try_lock()?.read_byte()
+}
+
+/// Send all queued output for a port
+/// 
+/// Useful before halting, since queued bytes are otherwise lost.
+pub fn flush(port: ComPort) {
+    if let Some(mut serial) = PORTS[port.index()].try_lock() {
+        serial.flush();
+    }
+}
+
+/// Get the line error counters for a port
+pub fn line_errors(port: ComPort) -> LineErrors {
+    PORTS[port.index()].lock().errors
+}
+
+/// Service pending interrupts on an interrupt-driven port
+/// 
+/// Called from the port's IRQ handler. Returns false if the port had no
+/// interrupt pending, so that another device on the shared IRQ line can
+/// claim it.
+pub fn handle_interrupt(port: ComPort) -> bool {
+    match PORTS[port.index()].try_lock() {
+        Some(mut serial) => serial.handle_interrupt(),
+        None => false,
+    }
+}
@@ -1,82 +1,201 @@
+/// VGA Text Mode Driver
+/// 
+/// Writes characters to the memory-mapped VGA text buffer at 0xb8000.
+/// The cursor position and colour are kept in a `Writer` behind a
+/// `SpinLock`, so output from interrupt handlers cannot corrupt them.
+/// The blinking hardware cursor follows the write position.
+
+use crate::arch::x86_64::port::outb;
+use crate::arch::x86_64::sync::SpinLock;
+use super::console::Stream;
+
 /// VGA text buffer memory address
 const VGA_BUFFER: *mut u8 = 0xb8000 as *mut u8;
 const BUFFER_WIDTH: usize = 80;
 const BUFFER_HEIGHT: usize = 25;
 
-/// Current cursor position (static for simple implementation)
-static mut CURSOR_POS: usize = 0;
+/// CRT controller index and data registers
+const CRTC_INDEX: u16 = 0x3D4;
+const CRTC_DATA: u16 = 0x3D5;
+/// CRT controller registers holding the cursor cell index
+const CRTC_CURSOR_HIGH: u8 = 0x0E;
+const CRTC_CURSOR_LOW: u8 = 0x0F;
 
-/// Clear the screen with black background
-pub fn clear_screen() {
-    let vga_buffer = VGA_BUFFER;
-    unsafe {
-        CURSOR_POS = 0; // Reset cursor position
-        for i in 0..(BUFFER_WIDTH * BUFFER_HEIGHT * 2) {
-            if i % 2 == 0 {
-                *vga_buffer.add(i) = b' '; // space character
-            } else {
-                *vga_buffer.add(i) = 0x07; // white text on black background
-            }
-        }
+/// VGA text mode colours
+#[allow(dead_code)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Black = 0,
+    Blue = 1,
+    Green = 2,
+    Cyan = 3,
+    Red = 4,
+    Magenta = 5,
+    Brown = 6,
+    LightGray = 7,
+    DarkGray = 8,
+    LightBlue = 9,
+    LightGreen = 10,
+    LightCyan = 11,
+    LightRed = 12,
+    Pink = 13,
+    Yellow = 14,
+    White = 15,
+}
+
+/// Attribute byte combining a foreground and background colour
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct ColorCode(u8);
+
+impl ColorCode {
+    /// Create a colour code
+    pub const fn new(foreground: Color, background: Color) -> Self {
+        Self((background as u8) << 4 | (foreground as u8))
     }
 }
 
-/// Scroll the screen up by one line
-fn scroll_up() {
-    let vga_buffer = VGA_BUFFER;
-    unsafe {
-        // Copy each line to the previous line
-        for row in 1..BUFFER_HEIGHT {
-            for col in 0..BUFFER_WIDTH {
-                let src = (row * BUFFER_WIDTH + col) * 2;
-                let dst = ((row - 1) * BUFFER_WIDTH + col) * 2;
-                *vga_buffer.add(dst) = *vga_buffer.add(src);         // character
-                *vga_buffer.add(dst + 1) = *vga_buffer.add(src + 1); // color
-            }
+/// Colour used for normal output (white on black)
+const OUT_COLOR: ColorCode = ColorCode::new(Color::White, Color::Black);
+
+/// Colour used for error output (light red on black)
+const ERR_COLOR: ColorCode = ColorCode::new(Color::LightRed, Color::Black);
+
+/// Colour used for cleared cells (light gray on black)
+const BLANK_COLOR: ColorCode = ColorCode::new(Color::LightGray, Color::Black);
+
+/// Text buffer cursor state
+struct Writer {
+    /// Current cursor position (cell index)
+    position: usize,
+}
+
+impl Writer {
+    const fn new() -> Self {
+        Self { position: 0 }
+    }
+    
+    /// Write one cell of the text buffer
+    fn write_cell(index: usize, byte: u8, color: ColorCode) {
+        unsafe {
+            *VGA_BUFFER.add(index * 2) = byte;
+            *VGA_BUFFER.add(index * 2 + 1) = color.0;
+        }
+    }
+    
+    /// Clear the screen and move the cursor to the top left
+    fn clear(&mut self) {
+        self.position = 0;
+        for i in 0..(BUFFER_WIDTH * BUFFER_HEIGHT) {
+            Self::write_cell(i, b' ', BLANK_COLOR);
+        }
+        self.update_cursor();
+    }
+    
+    /// Move the hardware cursor to the write position
+    fn update_cursor(&self) {
+        // After the last cell the next write scrolls; show the cursor
+        // on the last cell until then
+        let cell = self.position.min(BUFFER_WIDTH * BUFFER_HEIGHT - 1) as u16;
+        unsafe {
+            outb(CRTC_INDEX, CRTC_CURSOR_HIGH);
+            outb(CRTC_DATA, (cell >> 8) as u8);
+            outb(CRTC_INDEX, CRTC_CURSOR_LOW);
+            outb(CRTC_DATA, cell as u8);
+        }
+    }
+    
+    /// Scroll the screen up by one line
+    fn scroll_up(&mut self) {
+        unsafe {
+            // Copy each line to the previous line (character and colour)
+            core::ptr::copy(
+                VGA_BUFFER.add(BUFFER_WIDTH * 2),
+                VGA_BUFFER,
+                (BUFFER_HEIGHT - 1) * BUFFER_WIDTH * 2,
+            );
         }
         
         // Clear the last line
         let last_row = BUFFER_HEIGHT - 1;
         for col in 0..BUFFER_WIDTH {
-            let offset = (last_row * BUFFER_WIDTH + col) * 2;
-            *vga_buffer.add(offset) = b' ';     // space
-            *vga_buffer.add(offset + 1) = 0x0F; // white on black
+            Self::write_cell(last_row * BUFFER_WIDTH + col, b' ', OUT_COLOR);
         }
+        
+        self.position = last_row * BUFFER_WIDTH;
     }
-}
-
-/// Print a string at the current cursor position
-pub fn print_string(message: &str) {
-    let vga_buffer = VGA_BUFFER;
     
-    unsafe {
-        for &byte in message.as_bytes().iter() {
-            if byte == b'\n' {
-                // Move to next line
-                CURSOR_POS = ((CURSOR_POS / BUFFER_WIDTH) + 1) * BUFFER_WIDTH;
-            } else {
-                // Check if we need to scroll
-                if CURSOR_POS >= BUFFER_WIDTH * BUFFER_HEIGHT {
-                    scroll_up();
-                    CURSOR_POS = (BUFFER_HEIGHT - 1) * BUFFER_WIDTH;
+    /// Write a string at the cursor position
+    fn write_str(&mut self, message: &str, color: ColorCode) {
+        for &byte in message.as_bytes() {
+            match byte {
+                b'\n' => {
+                    // Move to next line
+                    self.position = ((self.position / BUFFER_WIDTH) + 1) * BUFFER_WIDTH;
+                }
+                b'\r' => {
+                    // Move to start of line
+                    self.position = (self.position / BUFFER_WIDTH) * BUFFER_WIDTH;
+                }
+                b'\x08' => {
+                    // Move back one cell without erasing it, like a terminal
+                    if !self.position.is_multiple_of(BUFFER_WIDTH) {
+                        self.position -= 1;
+                    }
+                }
+                _ => {
+                    // Check if we need to scroll
+                    if self.position >= BUFFER_WIDTH * BUFFER_HEIGHT {
+                        self.scroll_up();
+                    }
+                    
+                    // Show bytes outside printable ASCII (including the
+                    // parts of multi-byte UTF-8 characters) as a block
+                    let byte = if (0x20..0x7F).contains(&byte) { byte } else { 0xFE };
+                    Self::write_cell(self.position, byte, color);
+                    self.position += 1;
                 }
-                
-                let offset = CURSOR_POS * 2;
-                *vga_buffer.add(offset) = byte;        // character
-                *vga_buffer.add(offset + 1) = 0x0F;    // white on black
-                CURSOR_POS += 1;
             }
         }
+        self.update_cursor();
     }
 }
 
-/// Print a string without newline
+/// The VGA writer
+static WRITER: SpinLock<Writer> = SpinLock::new(Writer::new());
+
+/// Run `f` with the writer, without deadlocking if the lock is held by
+/// code that an exception interrupted
+fn with_writer(f: impl FnOnce(&mut Writer)) {
+    match WRITER.try_lock() {
+        Some(mut writer) => f(&mut writer),
+        None => f(unsafe { &mut *WRITER.data_ptr() }),
+    }
+}
+
+/// Clear the screen with black background
+pub fn clear_screen() {
+    with_writer(|writer| writer.clear());
+}
+
+/// Print a string at the current cursor position
+pub fn print_string(message: &str) {
+    with_writer(|writer| writer.write_str(message, OUT_COLOR));
+}
+
+/// Print a string to the VGA screen only
 pub fn print(message: &str) {
     print_string(message);
 }
 
-/// Print a string and move to the next line
-pub fn println(message: &str) {
-    print_string(message);
-    print_string("\n");
+/// Console sink write function for the VGA screen
+/// 
+/// Error output is shown in light red.
+pub fn console_write(stream: Stream, message: &str) {
+    let color = match stream {
+        Stream::Out => OUT_COLOR,
+        Stream::Err => ERR_COLOR,
+    };
+    with_writer(|writer| writer.write_str(message, color));
 }
@@ -0,0 +1,236 @@
+/// GDT (Global Descriptor Table) and TSS (Task State Segment)
+/// 
+/// The GDT set up in `boot.s` is only good enough to enter long mode. This
+/// module replaces it with a GDT that also contains a 64-bit TSS descriptor.
+/// 
+/// In long mode the TSS is no longer used for task switching. Its job is to
+/// hold the Interrupt Stack Table (IST): up to seven known-good stacks that
+/// the CPU switches to unconditionally when an IDT entry selects them. This
+/// lets the double fault handler run even when the fault was caused by the
+/// kernel stack overflowing.
+/// 
+/// ## Layout
+/// 
+/// ```text
+///     0x00  Null descriptor
+///     0x08  Kernel code segment (64-bit)
+///     0x10  Kernel data segment
+///     0x18  TSS descriptor (16 bytes, two slots)
+/// ```
+/// 
+/// The code and data selectors match the ones used by `boot.s`.
+/// 
+/// ## IST stacks
+/// 
+/// `init()` starts out with small static stacks so that critical exceptions
+/// are handled from the very beginning. Once the physical memory allocator is
+/// up, `init_ist_stacks()` replaces them with stacks mapped in the kernel
+/// stack region, each with an unmapped guard page below it.
+
+use core::mem::size_of;
+use core::ptr::{addr_of, addr_of_mut};
+use crate::arch::println;
+use super::memory::allocate_kernel_stack;
+
+/// Kernel code segment selector
+pub const KERNEL_CODE_SELECTOR: u16 = 0x08;
+
+/// Kernel data segment selector
+pub const KERNEL_DATA_SELECTOR: u16 = 0x10;
+
+/// TSS segment selector
+pub const TSS_SELECTOR: u16 = 0x18;
+
+/// IST index used by the double fault handler (#DF)
+pub const DOUBLE_FAULT_IST_INDEX: u8 = 1;
+
+/// IST index used by the non-maskable interrupt handler (NMI)
+pub const NMI_IST_INDEX: u8 = 2;
+
+/// IST index used by the machine check handler (#MC)
+pub const MACHINE_CHECK_IST_INDEX: u8 = 3;
+
+/// Number of IST stacks in use
+const IST_STACK_COUNT: usize = 3;
+
+/// Size of each IST stack in 4KB pages
+const IST_STACK_PAGES: usize = 4;
+
+/// Size of each IST stack in bytes
+const IST_STACK_SIZE: usize = IST_STACK_PAGES * 4096;
+
+/// Number of GDT slots (the TSS descriptor takes two)
+const GDT_ENTRIES: usize = 5;
+
+/// 64-bit code segment: executable, code/data, present, long mode
+const KERNEL_CODE_DESCRIPTOR: u64 = (1 << 43) | (1 << 44) | (1 << 47) | (1 << 53);
+
+/// Data segment: writable, code/data, present
+const KERNEL_DATA_DESCRIPTOR: u64 = (1 << 41) | (1 << 44) | (1 << 47);
+
+/// 64-bit Task State Segment
+/// 
+/// Only the stack pointer fields are used in long mode.
+#[repr(C, packed(4))]
+#[derive(Debug, Clone, Copy)]
+pub struct TaskStateSegment {
+    reserved_1: u32,
+    /// Stack pointers loaded on a privilege change to rings 0-2
+    pub privilege_stack_table: [u64; 3],
+    reserved_2: u64,
+    /// Interrupt Stack Table (IST1 - IST7)
+    pub interrupt_stack_table: [u64; 7],
+    reserved_3: u64,
+    reserved_4: u16,
+    /// Offset of the I/O permission bitmap from the start of the TSS
+    pub iomap_base: u16,
+}
+
+impl TaskStateSegment {
+    /// Create an empty TSS with no I/O permission bitmap
+    pub const fn new() -> Self {
+        Self {
+            reserved_1: 0,
+            privilege_stack_table: [0; 3],
+            reserved_2: 0,
+            interrupt_stack_table: [0; 7],
+            reserved_3: 0,
+            reserved_4: 0,
+            // Pointing past the end of the TSS means "no bitmap"
+            iomap_base: size_of::<TaskStateSegment>() as u16,
+        }
+    }
+}
+
+/// GDT descriptor structure for LGDT instruction
+#[repr(C, packed)]
+#[derive(Debug)]
+struct GdtDescriptor {
+    /// Size of GDT in bytes minus 1
+    limit: u16,
+    /// Linear address of GDT
+    base: u64,
+}
+
+/// Statically allocated stack used before guarded stacks can be mapped
+#[repr(C, align(16))]
+struct EarlyStack([u8; IST_STACK_SIZE]);
+
+/// Early IST stacks, one per IST index in use
+static mut EARLY_IST_STACKS: [EarlyStack; IST_STACK_COUNT] = [
+    EarlyStack([0; IST_STACK_SIZE]),
+    EarlyStack([0; IST_STACK_SIZE]),
+    EarlyStack([0; IST_STACK_SIZE]),
+];
+
+/// The kernel TSS
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+
+/// The kernel GDT
+static mut GDT: [u64; GDT_ENTRIES] = [0; GDT_ENTRIES];
+
+/// Build the two-slot descriptor for a 64-bit TSS
+fn tss_descriptor(tss: *const TaskStateSegment) -> [u64; 2] {
+    let base = tss as u64;
+    let limit = (size_of::<TaskStateSegment>() - 1) as u64;
+    
+    let mut low = limit & 0xFFFF;                   // Limit bits 0-15
+    low |= (base & 0xFF_FFFF) << 16;                // Base bits 0-23
+    low |= 0b1001 << 40;                            // Type: available 64-bit TSS
+    low |= 1 << 47;                                 // Present
+    low |= ((limit >> 16) & 0xF) << 48;             // Limit bits 16-19
+    low |= ((base >> 24) & 0xFF) << 56;             // Base bits 24-31
+    
+    let high = base >> 32;                          // Base bits 32-63
+    
+    [low, high]
+}
+
+/// Set the stack used for an IST index (1-7)
+fn set_ist_stack(index: u8, stack_top: u64) {
+    unsafe {
+        (*addr_of_mut!(TSS)).interrupt_stack_table[index as usize - 1] = stack_top;
+    }
+}
+
+/// Get the stack currently used for an IST index (1-7)
+pub fn ist_stack(index: u8) -> u64 {
+    unsafe { (*addr_of!(TSS)).interrupt_stack_table[index as usize - 1] }
+}
+
+/// Build and load the kernel GDT and TSS
+/// 
+/// This reloads every segment register, so it must run before any code
+/// depends on the boot GDT (in particular before the IDT is loaded).
+pub fn init() {
+    unsafe {
+        // Point the IST entries at the early static stacks
+        let early = addr_of!(EARLY_IST_STACKS) as *const EarlyStack;
+        for (i, index) in [DOUBLE_FAULT_IST_INDEX, NMI_IST_INDEX, MACHINE_CHECK_IST_INDEX]
+            .into_iter()
+            .enumerate()
+        {
+            let stack_top = early.add(i) as u64 + IST_STACK_SIZE as u64;
+            set_ist_stack(index, stack_top);
+        }
+        
+        // Build the GDT
+        let gdt = &mut *addr_of_mut!(GDT);
+        let [tss_low, tss_high] = tss_descriptor(addr_of!(TSS));
+        gdt[0] = 0;
+        gdt[1] = KERNEL_CODE_DESCRIPTOR;
+        gdt[2] = KERNEL_DATA_DESCRIPTOR;
+        gdt[3] = tss_low;
+        gdt[4] = tss_high;
+        
+        let descriptor = GdtDescriptor {
+            limit: (size_of::<[u64; GDT_ENTRIES]>() - 1) as u16,
+            base: gdt.as_ptr() as u64,
+        };
+        
+        core::arch::asm!("lgdt [{}]", in(reg) &descriptor, options(readonly, nostack, preserves_flags));
+        
+        load_segments();
+        
+        core::arch::asm!("ltr {0:x}", in(reg) TSS_SELECTOR, options(nostack, preserves_flags));
+    }
+}
+
+/// Reload CS and the data segment registers from the new GDT
+/// 
+/// CS cannot be written with `mov`, so it is reloaded with a far return to
+/// the next instruction.
+unsafe fn load_segments() {
+    core::arch::asm!(
+        "push {code}",
+        "lea {tmp}, [rip + 2f]",
+        "push {tmp}",
+        "retfq",
+        "2:",
+        "mov ds, {data:x}",
+        "mov es, {data:x}",
+        "mov ss, {data:x}",
+        "mov fs, {data:x}",
+        "mov gs, {data:x}",
+        code = in(reg) KERNEL_CODE_SELECTOR as u64,
+        data = in(reg) KERNEL_DATA_SELECTOR as u64,
+        tmp = out(reg) _,
+        options(preserves_flags),
+    );
+}
+
+/// Replace the early IST stacks with guarded stacks
+/// 
+/// Must be called after the physical memory allocator is initialized. If a
+/// stack cannot be allocated, the early static stack stays in use.
+pub fn init_ist_stacks() {
+    for index in [DOUBLE_FAULT_IST_INDEX, NMI_IST_INDEX, MACHINE_CHECK_IST_INDEX] {
+        match allocate_kernel_stack(IST_STACK_PAGES) {
+            Ok(stack) => set_ist_stack(index, stack.top().as_u64()),
+            Err(_) => {
+                println("Warning: could not map guarded IST stack, keeping early stack");
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,371 @@
+/// Local APIC (xAPIC and x2APIC)
+/// 
+/// Every CPU has a local APIC that receives interrupts from the I/O APICs
+/// and other CPUs, and has its own timer. It replaces the 8259 PICs for
+/// interrupt delivery.
+/// 
+/// ## Register access
+/// 
+/// In xAPIC mode the registers are memory mapped (normally at 0xFEE00000,
+/// 16-byte aligned 32-bit registers). In x2APIC mode the same registers are
+/// model-specific registers: register offset `n` becomes MSR `0x800 + n/16`,
+/// and the interrupt command register is a single 64-bit MSR. x2APIC is
+/// used when the CPU supports it, unless the `nox2apic` boot option is set.
+/// 
+/// ## Vectors
+/// 
+/// ```text
+/// 0xFE  APIC error (ERROR_VECTOR)
+/// 0xFF  Spurious interrupt (SPURIOUS_VECTOR), needs no EOI
+/// ```
+/// 
+/// The spurious vector must have its low four bits set on older
+/// processors, hence 0xFF.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::arch::x86_64::acpi::madt::{Madt, MadtEntry, Polarity, TriggerMode};
+use crate::arch::x86_64::cpu::{self, IA32_APIC_BASE};
+use crate::arch::x86_64::memory::{identity_map_region, PageTableFlags};
+
+/// IA32_APIC_BASE: APIC global enable
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+/// IA32_APIC_BASE: x2APIC mode enable
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+/// IA32_APIC_BASE: bits holding the xAPIC register base address
+const APIC_BASE_ADDRESS_MASK: u64 = 0x000F_FFFF_FFFF_F000;
+
+/// First x2APIC MSR (register offset 0)
+const X2APIC_MSR_BASE: u32 = 0x800;
+
+// Register offsets (xAPIC layout)
+const REG_ID: u32 = 0x020;
+const REG_VERSION: u32 = 0x030;
+const REG_TPR: u32 = 0x080;
+const REG_EOI: u32 = 0x0B0;
+const REG_SVR: u32 = 0x0F0;
+const REG_ESR: u32 = 0x280;
+const REG_ICR_LOW: u32 = 0x300;
+const REG_ICR_HIGH: u32 = 0x310;
+const REG_LVT_TIMER: u32 = 0x320;
+const REG_LVT_LINT0: u32 = 0x350;
+const REG_LVT_LINT1: u32 = 0x360;
+const REG_LVT_ERROR: u32 = 0x370;
+const REG_TIMER_INITIAL: u32 = 0x380;
+const REG_TIMER_CURRENT: u32 = 0x390;
+const REG_TIMER_DIVIDE: u32 = 0x3E0;
+
+/// Spurious-interrupt vector register: APIC software enable
+const SVR_ENABLE: u32 = 1 << 8;
+
+/// LVT: interrupt masked
+const LVT_MASKED: u32 = 1 << 16;
+/// LVT: level triggered (LINT pins only)
+const LVT_LEVEL: u32 = 1 << 15;
+/// LVT: active low (LINT pins only)
+const LVT_ACTIVE_LOW: u32 = 1 << 13;
+/// LVT: NMI delivery mode
+const LVT_NMI: u32 = 0b100 << 8;
+/// LVT timer: periodic mode
+const LVT_TIMER_PERIODIC: u32 = 1 << 17;
+
+/// ICR: delivery status (xAPIC only, set while the IPI is being sent)
+const ICR_PENDING: u32 = 1 << 12;
+/// ICR: assert level
+const ICR_ASSERT: u32 = 1 << 14;
+/// ICR: destination shorthand field position
+const ICR_SHORTHAND_SHIFT: u32 = 18;
+
+/// Vector used for spurious APIC interrupts
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+/// Vector used for APIC internal errors
+pub const ERROR_VECTOR: u8 = 0xFE;
+
+/// Errors that can occur while enabling the local APIC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApicError {
+    /// CPUID reports no local APIC
+    NotSupported,
+    /// The xAPIC registers could not be mapped
+    MapFailed,
+}
+
+/// Local APIC operating mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApicMode {
+    XApic,
+    X2Apic,
+}
+
+/// Timer mode of the local APIC timer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerMode {
+    /// Count down once and raise a single interrupt
+    OneShot,
+    /// Reload the initial count and interrupt every time it reaches zero
+    Periodic,
+}
+
+/// Destination of an inter-processor interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum IpiDestination {
+    /// A single CPU, by APIC ID
+    Apic(u32),
+    /// The sending CPU
+    ToSelf,
+    /// Every CPU, including the sender
+    AllIncludingSelf,
+    /// Every CPU except the sender
+    AllExcludingSelf,
+}
+
+impl IpiDestination {
+    /// Destination shorthand encoding for the ICR
+    fn shorthand(&self) -> u32 {
+        match self {
+            IpiDestination::Apic(_) => 0b00,
+            IpiDestination::ToSelf => 0b01,
+            IpiDestination::AllIncludingSelf => 0b10,
+            IpiDestination::AllExcludingSelf => 0b11,
+        }
+    }
+}
+
+/// Set once the local APIC is enabled
+static ENABLED: AtomicBool = AtomicBool::new(false);
+/// Set when the local APIC is in x2APIC mode
+static X2APIC: AtomicBool = AtomicBool::new(false);
+/// Virtual address of the xAPIC registers
+static MMIO_BASE: AtomicU64 = AtomicU64::new(0);
+
+/// Read a local APIC register
+fn read(reg: u32) -> u32 {
+    unsafe {
+        if X2APIC.load(Ordering::Relaxed) {
+            cpu::rdmsr(X2APIC_MSR_BASE + (reg >> 4)) as u32
+        } else {
+            core::ptr::read_volatile((MMIO_BASE.load(Ordering::Relaxed) + reg as u64) as *const u32)
+        }
+    }
+}
+
+/// Write a local APIC register
+fn write(reg: u32, value: u32) {
+    unsafe {
+        if X2APIC.load(Ordering::Relaxed) {
+            cpu::wrmsr(X2APIC_MSR_BASE + (reg >> 4), value as u64);
+        } else {
+            core::ptr::write_volatile((MMIO_BASE.load(Ordering::Relaxed) + reg as u64) as *mut u32, value);
+        }
+    }
+}
+
+/// Enable the local APIC of the boot CPU
+/// 
+/// Puts the APIC in x2APIC mode if supported and `allow_x2apic` is set,
+/// installs the spurious and error vectors, masks the timer and LINT0, and
+/// programs the NMI pins described by the MADT. The 8259 PICs should be
+/// masked before calling this, since LINT0 no longer passes their
+/// interrupts through.
+pub fn init(madt: &Madt, allow_x2apic: bool) -> Result<ApicMode, ApicError> {
+    if !cpu::has_apic() {
+        return Err(ApicError::NotSupported);
+    }
+    
+    let x2apic = allow_x2apic && cpu::has_x2apic();
+    let mut base = unsafe { cpu::rdmsr(IA32_APIC_BASE) };
+    
+    // The enable bit must be set before (or together with) the x2APIC bit
+    base |= APIC_BASE_ENABLE;
+    unsafe { cpu::wrmsr(IA32_APIC_BASE, base) };
+    
+    if x2apic {
+        unsafe { cpu::wrmsr(IA32_APIC_BASE, base | APIC_BASE_X2APIC) };
+    } else {
+        // The MSR is authoritative; the MADT address is only a fallback
+        let address = match base & APIC_BASE_ADDRESS_MASK {
+            0 => madt.local_apic_address,
+            address => address,
+        };
+        identity_map_region(address, 0x1000, PageTableFlags::WRITABLE.union(PageTableFlags::NO_CACHE))
+            .map_err(|_| ApicError::MapFailed)?;
+        MMIO_BASE.store(address, Ordering::Relaxed);
+    }
+    X2APIC.store(x2apic, Ordering::Relaxed);
+    
+    // Accept every interrupt priority
+    write(REG_TPR, 0);
+    
+    // Timer off until a timer driver claims it; LINT0 carried the PIC's
+    // ExtINT interrupts, which are no longer wanted
+    write(REG_LVT_TIMER, LVT_MASKED);
+    write(REG_LVT_LINT0, LVT_MASKED);
+    write(REG_LVT_LINT1, LVT_MASKED);
+    configure_nmi_pins(madt);
+    
+    // Report internal errors; the ESR must be written before it is read
+    write(REG_LVT_ERROR, ERROR_VECTOR as u32);
+    write(REG_ESR, 0);
+    write(REG_ESR, 0);
+    
+    write(REG_SVR, SPURIOUS_VECTOR as u32 | SVR_ENABLE);
+    
+    // Acknowledge anything left in service by the firmware
+    eoi();
+    
+    ENABLED.store(true, Ordering::Release);
+    Ok(if x2apic { ApicMode::X2Apic } else { ApicMode::XApic })
+}
+
+/// Program the LINT pins the MADT marks as NMI inputs for this CPU
+fn configure_nmi_pins(madt: &Madt) {
+    let apic_id = id();
+    
+    // The MADT refers to processors by ACPI processor ID
+    let processor_id = madt.entries().find_map(|entry| match entry {
+        MadtEntry::LocalApic { processor_id, apic_id: entry_id, .. } if entry_id as u32 == apic_id => {
+            Some(processor_id as u32)
+        }
+        MadtEntry::LocalX2Apic { x2apic_id, processor_uid, .. } if x2apic_id == apic_id => Some(processor_uid),
+        _ => None,
+    });
+    
+    for entry in madt.entries() {
+        let MadtEntry::LocalApicNmi { processor_id: target, flags, lint } = entry else {
+            continue;
+        };
+        if target != 0xFF && Some(target as u32) != processor_id {
+            continue;
+        }
+        
+        let mut lvt = LVT_NMI;
+        if flags.polarity() == Polarity::ActiveLow {
+            lvt |= LVT_ACTIVE_LOW;
+        }
+        if flags.trigger_mode() == TriggerMode::Level {
+            lvt |= LVT_LEVEL;
+        }
+        match lint {
+            0 => write(REG_LVT_LINT0, lvt),
+            1 => write(REG_LVT_LINT1, lvt),
+            _ => {}
+        }
+    }
+}
+
+/// Check whether the local APIC has been enabled
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Current operating mode, if the local APIC is enabled
+pub fn mode() -> Option<ApicMode> {
+    match (is_enabled(), X2APIC.load(Ordering::Relaxed)) {
+        (false, _) => None,
+        (true, false) => Some(ApicMode::XApic),
+        (true, true) => Some(ApicMode::X2Apic),
+    }
+}
+
+/// APIC ID of the current CPU
+pub fn id() -> u32 {
+    let id = read(REG_ID);
+    if X2APIC.load(Ordering::Relaxed) {
+        id
+    } else {
+        id >> 24
+    }
+}
+
+/// Version register (bits 0-7: version, bits 16-23: highest LVT entry)
+pub fn version() -> u32 {
+    read(REG_VERSION)
+}
+
+/// Signal the end of an interrupt
+/// 
+/// Must be called by every handler of an APIC-delivered interrupt except
+/// the spurious vector.
+pub fn eoi() {
+    write(REG_EOI, 0);
+}
+
+/// Read and clear the error status register
+pub fn read_error_status() -> u32 {
+    write(REG_ESR, 0);
+    read(REG_ESR)
+}
+
+/// Divide configuration register encoding of a timer divisor
+fn divide_config(divisor: u8) -> u32 {
+    match divisor {
+        1 => 0b1011,
+        2 => 0b0000,
+        4 => 0b0001,
+        8 => 0b0010,
+        16 => 0b0011,
+        32 => 0b1000,
+        64 => 0b1001,
+        128 => 0b1010,
+        _ => panic!("invalid APIC timer divisor {}", divisor),
+    }
+}
+
+/// Start the local APIC timer
+/// 
+/// The counter decrements at the bus (or core crystal) clock divided by
+/// `divisor` (a power of two from 1 to 128) and raises `vector` when it
+/// reaches zero.
+pub fn start_timer(vector: u8, mode: TimerMode, divisor: u8, initial_count: u32) {
+    let mut lvt = vector as u32;
+    if mode == TimerMode::Periodic {
+        lvt |= LVT_TIMER_PERIODIC;
+    }
+    
+    write(REG_TIMER_DIVIDE, divide_config(divisor));
+    write(REG_LVT_TIMER, lvt);
+    // Writing the initial count starts the countdown
+    write(REG_TIMER_INITIAL, initial_count);
+}
+
+/// Start the local APIC timer from its maximum count with the interrupt masked
+/// 
+/// Used to measure the timer frequency: the count decreases at the same
+/// rate as with `start_timer`, but reaching zero raises nothing.
+pub fn start_counting(divisor: u8) {
+    write(REG_TIMER_DIVIDE, divide_config(divisor));
+    write(REG_LVT_TIMER, LVT_MASKED);
+    write(REG_TIMER_INITIAL, u32::MAX);
+}
+
+/// Stop the local APIC timer and mask its interrupt
+pub fn stop_timer() {
+    write(REG_LVT_TIMER, LVT_MASKED);
+    write(REG_TIMER_INITIAL, 0);
+}
+
+/// Current count of the local APIC timer
+pub fn timer_count() -> u32 {
+    read(REG_TIMER_CURRENT)
+}
+
+/// Send an inter-processor interrupt with a fixed vector
+pub fn send_ipi(destination: IpiDestination, vector: u8) {
+    let low = vector as u32 | ICR_ASSERT | (destination.shorthand() << ICR_SHORTHAND_SHIFT);
+    let target = match destination {
+        IpiDestination::Apic(id) => id,
+        _ => 0,
+    };
+    
+    if X2APIC.load(Ordering::Relaxed) {
+        // A single 64-bit write; the destination is the full 32-bit ID
+        unsafe { cpu::wrmsr(X2APIC_MSR_BASE + (REG_ICR_LOW >> 4), ((target as u64) << 32) | low as u64) };
+    } else {
+        // Writing the low half sends the IPI, so the high half goes first
+        write(REG_ICR_HIGH, target << 24);
+        write(REG_ICR_LOW, low);
+        while read(REG_ICR_LOW) & ICR_PENDING != 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
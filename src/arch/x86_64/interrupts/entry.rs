@@ -0,0 +1,245 @@
+/// Interrupt Entry Layer
+/// 
+/// This module contains the assembly entry stubs that every IDT vector points
+/// to, and the Rust dispatcher they call into.
+/// 
+/// ## How an interrupt reaches a Rust handler
+/// 
+/// 1. The CPU pushes SS, RSP, RFLAGS, CS and RIP (and an error code for some
+///    exceptions) onto the stack and jumps to the stub for that vector.
+/// 2. The stub pushes a dummy error code if the CPU did not push one, so that
+///    every vector has the same stack layout, then pushes its vector number.
+/// 3. The common entry saves all general purpose registers and calls
+///    `interrupt_dispatch` with a pointer to the resulting `InterruptContext`.
+/// 4. The dispatcher looks up the Rust handler registered for the vector.
+/// 5. When the handler returns, the registers are restored (including any
+///    changes the handler made to them) and `iretq` resumes execution.
+/// 
+/// Stack layout at the time `interrupt_dispatch` is called (growing down):
+/// 
+/// ```text
+///     SS            <- pushed by CPU
+///     RSP           <- pushed by CPU
+///     RFLAGS        <- pushed by CPU
+///     CS            <- pushed by CPU
+///     RIP           <- pushed by CPU
+///     Error code    <- pushed by CPU or stub (0 if none)
+///     Vector        <- pushed by stub
+///     RAX ... R15   <- pushed by common entry
+/// ```
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Size of each entry stub in bytes
+/// 
+/// Stubs are laid out back to back and aligned to this size, so the address
+/// of the stub for a vector is `interrupt_stubs + vector * STUB_SIZE`.
+const STUB_SIZE: u64 = 16;
+
+/// Number of interrupt vectors
+const VECTOR_COUNT: usize = 256;
+
+/// Stack frame pushed by the CPU when an interrupt or exception occurs
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptStackFrame {
+    /// Instruction pointer to resume at
+    pub rip: u64,
+    /// Code segment selector
+    pub cs: u64,
+    /// CPU flags at the time of the interrupt
+    pub rflags: u64,
+    /// Stack pointer at the time of the interrupt
+    pub rsp: u64,
+    /// Stack segment selector
+    pub ss: u64,
+}
+
+/// General purpose registers saved by the common entry stub
+/// 
+/// The field order matches the push order in `interrupt_common` (the last
+/// register pushed is at the lowest address).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SavedRegisters {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rbp: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rdx: u64,
+    pub rcx: u64,
+    pub rbx: u64,
+    pub rax: u64,
+}
+
+/// Complete CPU state at the time of an interrupt
+/// 
+/// Handlers receive a mutable reference to this structure. Any changes made
+/// to it (for example advancing `frame.rip` past a faulting instruction) take
+/// effect when the handler returns.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InterruptContext {
+    /// Saved general purpose registers
+    pub regs: SavedRegisters,
+    /// Interrupt vector number (0-255)
+    pub vector: u64,
+    /// Raw error code slot (0 for vectors without an error code)
+    raw_error_code: u64,
+    /// Stack frame pushed by the CPU
+    pub frame: InterruptStackFrame,
+}
+
+impl InterruptContext {
+    /// Get the error code pushed by the CPU, if this vector has one
+    pub fn error_code(&self) -> Option<u64> {
+        if vector_has_error_code(self.vector as u8) {
+            Some(self.raw_error_code)
+        } else {
+            None
+        }
+    }
+}
+
+/// Signature of a Rust interrupt handler
+pub type InterruptHandler = fn(&mut InterruptContext);
+
+/// Check whether the CPU pushes an error code for the given vector
+/// 
+/// Error codes are pushed for #DF (8), #TS (10), #NP (11), #SS (12),
+/// #GP (13), #PF (14), #AC (17), #CP (21), #VC (29) and #SX (30).
+/// This list must match the `.if` condition in the stub generator below.
+pub const fn vector_has_error_code(vector: u8) -> bool {
+    matches!(vector, 8 | 10..=14 | 17 | 21 | 29 | 30)
+}
+
+// Entry stubs and common entry path
+//
+// The stubs are generated with `.rept` so that every one of the 256 vectors
+// gets its own stub that records which vector fired.
+core::arch::global_asm!(
+    r#"
+.section .text
+.balign 16
+.global interrupt_stubs
+interrupt_stubs:
+.set isr_vector, 0
+.rept 256
+    .balign 16
+    .if isr_vector == 8 || (isr_vector >= 10 && isr_vector <= 14) || isr_vector == 17 || isr_vector == 21 || isr_vector == 29 || isr_vector == 30
+    .else
+    push 0                      /* dummy error code */
+    .endif
+    push isr_vector             /* vector number */
+    jmp interrupt_common
+    .set isr_vector, isr_vector + 1
+.endr
+
+interrupt_common:
+    /* Save general purpose registers (order must match SavedRegisters) */
+    push rax
+    push rbx
+    push rcx
+    push rdx
+    push rsi
+    push rdi
+    push rbp
+    push r8
+    push r9
+    push r10
+    push r11
+    push r12
+    push r13
+    push r14
+    push r15
+
+    /* 22 quadwords have been pushed on a 16-byte aligned stack, so RSP is
+       still 16-byte aligned here as required by the System V ABI */
+    mov rdi, rsp                /* first argument: &mut InterruptContext */
+    cld                         /* ABI requires the direction flag clear */
+    call {dispatch}
+
+    /* Restore general purpose registers */
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop r11
+    pop r10
+    pop r9
+    pop r8
+    pop rbp
+    pop rdi
+    pop rsi
+    pop rdx
+    pop rcx
+    pop rbx
+    pop rax
+
+    /* Drop the vector number and error code, then return from interrupt */
+    add rsp, 16
+    iretq
+"#,
+    dispatch = sym interrupt_dispatch,
+);
+
+extern "C" {
+    /// Start of the generated entry stubs (see `global_asm!` above)
+    static interrupt_stubs: u8;
+}
+
+/// Get the address of the entry stub for a vector
+/// 
+/// This is the address that should be installed in the IDT for the vector.
+pub fn stub_address(vector: u8) -> u64 {
+    let base = unsafe { &interrupt_stubs as *const u8 as u64 };
+    base + vector as u64 * STUB_SIZE
+}
+
+/// Rust handlers for each vector
+static mut HANDLERS: [Option<InterruptHandler>; VECTOR_COUNT] = [None; VECTOR_COUNT];
+
+/// Register the Rust handler that is called for a vector
+/// 
+/// The IDT entry for the vector must point to `stub_address(vector)`.
+pub fn set_handler(vector: u8, handler: InterruptHandler) {
+    unsafe {
+        (*core::ptr::addr_of_mut!(HANDLERS))[vector as usize] = Some(handler);
+    }
+}
+
+/// Get the Rust handler registered for a vector
+pub fn handler(vector: u8) -> Option<InterruptHandler> {
+    unsafe { (*core::ptr::addr_of!(HANDLERS))[vector as usize] }
+}
+
+/// Number of times each vector has been delivered
+static COUNTS: [AtomicU64; VECTOR_COUNT] = [const { AtomicU64::new(0) }; VECTOR_COUNT];
+
+/// Number of times a vector has been delivered since boot
+pub fn interrupt_count(vector: u8) -> u64 {
+    COUNTS[vector as usize].load(Ordering::Relaxed)
+}
+
+/// Common Rust entry point called by `interrupt_common`
+/// 
+/// Counts the interrupt, looks up the registered handler for the vector
+/// and calls it. Vectors without a handler fall back to
+/// `unhandled_interrupt_handler`.
+extern "C" fn interrupt_dispatch(context: &mut InterruptContext) {
+    COUNTS[context.vector as usize].fetch_add(1, Ordering::Relaxed);
+    
+    let handler = unsafe { (*core::ptr::addr_of!(HANDLERS))[context.vector as usize] };
+    
+    match handler {
+        Some(handler) => handler(context),
+        None => super::hardware::unhandled_interrupt_handler(context),
+    }
+}
@@ -1,206 +1,512 @@
 /// CPU Exception Handlers
 /// 
 /// This module contains handlers for CPU exceptions (vectors 0-31).
-/// Each exception has its own handler function with appropriate error reporting.
+/// Each exception has its own handler function. Every handler prints a crash
+/// report containing the saved register state, the decoded error code (for
+/// exceptions that push one) and CR2 for page faults.
 
-use crate::arch::drivers::vga::println;
+use crate::{eprint, eprintln};
+use super::entry::{InterruptContext, InterruptStackFrame, SavedRegisters};
+use crate::arch::gdt::{ist_stack, DOUBLE_FAULT_IST_INDEX, MACHINE_CHECK_IST_INDEX, NMI_IST_INDEX};
+use crate::arch::panic::print_backtrace;
+use crate::arch::symbols::Symbolized;
 
-/// Divide by zero exception handler (Vector 0)
+/// Resume Flag (RF) in RFLAGS
 /// 
-/// This handler is called when the CPU encounters a division by zero.
-/// It provides useful debugging information before halting the system.
-pub extern "C" fn divide_by_zero_handler() {
-    // Clear a few lines to make the error visible
-    println("");
-    println("========================================");
-    println("EXCEPTION: Division by Zero (#DE)");
-    println("========================================");
-    println("");
-    println("The CPU encountered a division by zero operation.");
-    println("This is a fatal error that cannot be recovered from.");
-    println("");
-    println("Exception Details:");
-    println("  Vector: 0 (Divide Error)");
-    println("  Type: Fault");
-    println("  Error Code: None");
-    println("");
-    println("System halted. Please reset to continue.");
-    println("========================================");
+/// When set in the saved RFLAGS, instruction breakpoints are suppressed for
+/// the instruction execution resumes at, so returning from a #DB fault does
+/// not immediately trigger the same breakpoint again.
+const RFLAGS_RESUME_FLAG: u64 = 1 << 16;
+
+/// Exception classes as defined by the Intel SDM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionKind {
+    /// Reported before the faulting instruction; RIP points at it
+    Fault,
+    /// Reported after the trapping instruction; RIP points past it
+    Trap,
+    /// Severe error; the program cannot be restarted
+    Abort,
+    /// External interrupt delivered through an exception vector (NMI)
+    Interrupt,
+    /// Can be either a fault or a trap depending on the cause (#DB)
+    FaultOrTrap,
+    /// Reserved by Intel
+    Reserved,
+}
+
+impl ExceptionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExceptionKind::Fault => "Fault",
+            ExceptionKind::Trap => "Trap",
+            ExceptionKind::Abort => "Abort",
+            ExceptionKind::Interrupt => "Interrupt",
+            ExceptionKind::FaultOrTrap => "Fault/Trap",
+            ExceptionKind::Reserved => "Reserved",
+        }
+    }
+}
+
+/// Static description of a CPU exception vector
+pub struct ExceptionInfo {
+    /// Human readable name
+    pub name: &'static str,
+    /// Intel mnemonic (e.g. "#GP"), empty for vectors without one
+    pub mnemonic: &'static str,
+    /// Exception class
+    pub kind: ExceptionKind,
+}
+
+/// Descriptions of exception vectors 0-31, indexed by vector number
+pub static EXCEPTIONS: [ExceptionInfo; 32] = [
+    ExceptionInfo { name: "Division by Zero", mnemonic: "#DE", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Debug", mnemonic: "#DB", kind: ExceptionKind::FaultOrTrap },
+    ExceptionInfo { name: "Non-Maskable Interrupt", mnemonic: "NMI", kind: ExceptionKind::Interrupt },
+    ExceptionInfo { name: "Breakpoint", mnemonic: "#BP", kind: ExceptionKind::Trap },
+    ExceptionInfo { name: "Overflow", mnemonic: "#OF", kind: ExceptionKind::Trap },
+    ExceptionInfo { name: "Bound Range Exceeded", mnemonic: "#BR", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Invalid Opcode", mnemonic: "#UD", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Device Not Available", mnemonic: "#NM", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Double Fault", mnemonic: "#DF", kind: ExceptionKind::Abort },
+    ExceptionInfo { name: "Coprocessor Segment Overrun", mnemonic: "", kind: ExceptionKind::Abort },
+    ExceptionInfo { name: "Invalid TSS", mnemonic: "#TS", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Segment Not Present", mnemonic: "#NP", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Stack-Segment Fault", mnemonic: "#SS", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "General Protection Fault", mnemonic: "#GP", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Page Fault", mnemonic: "#PF", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Reserved", mnemonic: "", kind: ExceptionKind::Reserved },
+    ExceptionInfo { name: "x87 Floating-Point Exception", mnemonic: "#MF", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Alignment Check", mnemonic: "#AC", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Machine Check", mnemonic: "#MC", kind: ExceptionKind::Abort },
+    ExceptionInfo { name: "SIMD Floating-Point Exception", mnemonic: "#XM", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Virtualization Exception", mnemonic: "#VE", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Control Protection Exception", mnemonic: "#CP", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Reserved", mnemonic: "", kind: ExceptionKind::Reserved },
+    ExceptionInfo { name: "Reserved", mnemonic: "", kind: ExceptionKind::Reserved },
+    ExceptionInfo { name: "Reserved", mnemonic: "", kind: ExceptionKind::Reserved },
+    ExceptionInfo { name: "Reserved", mnemonic: "", kind: ExceptionKind::Reserved },
+    ExceptionInfo { name: "Reserved", mnemonic: "", kind: ExceptionKind::Reserved },
+    ExceptionInfo { name: "Reserved", mnemonic: "", kind: ExceptionKind::Reserved },
+    ExceptionInfo { name: "Hypervisor Injection Exception", mnemonic: "#HV", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "VMM Communication Exception", mnemonic: "#VC", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Security Exception", mnemonic: "#SX", kind: ExceptionKind::Fault },
+    ExceptionInfo { name: "Reserved", mnemonic: "", kind: ExceptionKind::Reserved },
+];
+
+/// Decoded selector error code (#TS, #NP, #SS, #GP)
+/// 
+/// Layout:
+/// - Bit 0: EXT - the exception was caused by an event external to the program
+/// - Bits 1-2: Table - 0b00 = GDT, 0b01/0b11 = IDT, 0b10 = LDT
+/// - Bits 3-15: Selector index
+#[derive(Debug, Clone, Copy)]
+pub struct SelectorErrorCode(pub u64);
+
+impl SelectorErrorCode {
+    /// The exception originated from an external event (interrupt, NMI)
+    pub fn external(&self) -> bool {
+        self.0 & 1 != 0
+    }
     
-    // Halt the CPU in a loop
-    loop {
-        unsafe {
-            core::arch::asm!("hlt");
+    /// Name of the descriptor table the selector index refers to
+    pub fn table(&self) -> &'static str {
+        match (self.0 >> 1) & 0b11 {
+            0b00 => "GDT",
+            0b10 => "LDT",
+            _ => "IDT",
         }
     }
+    
+    /// Index into the descriptor table
+    pub fn index(&self) -> u64 {
+        (self.0 >> 3) & 0x1FFF
+    }
+}
+
+/// Decoded page fault error code (#PF)
+#[derive(Debug, Clone, Copy)]
+pub struct PageFaultErrorCode(pub u64);
+
+impl PageFaultErrorCode {
+    /// P: 0 = page not present, 1 = protection violation
+    pub const PROTECTION_VIOLATION: u64 = 1 << 0;
+    /// W/R: 0 = read access, 1 = write access
+    pub const WRITE: u64 = 1 << 1;
+    /// U/S: 0 = supervisor mode access, 1 = user mode access
+    pub const USER: u64 = 1 << 2;
+    /// RSVD: a reserved bit was set in a paging structure entry
+    pub const RESERVED_BIT: u64 = 1 << 3;
+    /// I/D: the fault was caused by an instruction fetch
+    pub const INSTRUCTION_FETCH: u64 = 1 << 4;
+    /// PK: protection key violation
+    pub const PROTECTION_KEY: u64 = 1 << 5;
+    /// SS: shadow stack access
+    pub const SHADOW_STACK: u64 = 1 << 6;
+    /// SGX: SGX-specific access control violation
+    pub const SGX: u64 = 1 << 15;
+    
+    /// Check whether a flag is set
+    pub fn contains(&self, flag: u64) -> bool {
+        self.0 & flag != 0
+    }
+}
+
+// === Exception Handlers (Vectors 0-31) ===
+
+/// Divide by zero exception handler (Vector 0)
+/// 
+/// This handler is called when the CPU encounters a division by zero.
+/// It provides useful debugging information before halting the system.
+pub fn divide_by_zero_handler(context: &mut InterruptContext) {
+    report_exception(context, "The CPU encountered a division by zero operation.");
+    halt();
 }
 
 /// Debug exception handler (Vector 1)
 /// 
 /// Handles debug exceptions including hardware breakpoints and single-step.
-pub extern "C" fn debug_handler() {
-    println("");
-    println("========================================");
-    println("EXCEPTION: Debug (#DB)");
-    println("========================================");
-    println("");
-    println("A debug exception occurred.");
-    println("This could be from a hardware breakpoint or single-step.");
-    println("");
-    println("Exception Details:");
-    println("  Vector: 1 (Debug Exception)");
-    println("  Type: Fault/Trap");
-    println("  Error Code: None");
-    println("");
-    println("System halted. Please reset to continue.");
-    println("========================================");
+/// Debug exceptions are not errors, so execution resumes after reporting.
+pub fn debug_handler(context: &mut InterruptContext) {
+    report_exception(context, "A hardware breakpoint or single-step trap occurred.");
+    eprintln!("Resuming execution.");
     
-    loop {
-        unsafe {
-            core::arch::asm!("hlt");
-        }
-    }
+    // Instruction breakpoints are faults, so set RF to avoid re-triggering
+    // the breakpoint on the instruction we return to
+    context.frame.rflags |= RFLAGS_RESUME_FLAG;
+}
+
+/// Non-maskable interrupt handler (Vector 2)
+/// 
+/// NMIs usually signal hardware errors (memory parity, watchdog). The cause
+/// is reported and execution resumes.
+pub fn nmi_handler(context: &mut InterruptContext) {
+    report_exception(context, "A non-maskable interrupt was received.");
+    eprintln!("Resuming execution.");
 }
 
 /// Breakpoint exception handler (Vector 3)
 /// 
 /// Handles INT3 breakpoint instructions.
-pub extern "C" fn breakpoint_handler() {
-    println("");
-    println("========================================");
-    println("EXCEPTION: Breakpoint (#BP)");
-    println("========================================");
-    println("");
-    println("A breakpoint exception occurred (INT3 instruction).");
-    println("This is typically used by debuggers.");
-    println("");
-    println("Exception Details:");
-    println("  Vector: 3 (Breakpoint)");
-    println("  Type: Trap");
-    println("  Error Code: None");
-    println("");
-    println("System halted. Please reset to continue.");
-    println("========================================");
-    
-    loop {
-        unsafe {
-            core::arch::asm!("hlt");
-        }
-    }
+/// #BP is a trap, so the saved RIP already points past the INT3 instruction
+/// and execution continues normally when the handler returns.
+pub fn breakpoint_handler(context: &mut InterruptContext) {
+    report_exception(context, "A breakpoint exception occurred (INT3 instruction).");
+    eprintln!("Resuming execution.");
+}
+
+/// Overflow exception handler (Vector 4)
+/// 
+/// Raised by INTO when the overflow flag is set. #OF is a trap, so execution
+/// resumes after the instruction.
+pub fn overflow_handler(context: &mut InterruptContext) {
+    report_exception(context, "An INTO instruction was executed with OF set.");
+    eprintln!("Resuming execution.");
+}
+
+/// Bound range exceeded handler (Vector 5)
+pub fn bound_range_handler(context: &mut InterruptContext) {
+    report_exception(context, "A BOUND instruction found an index out of range.");
+    halt();
 }
 
 /// Invalid opcode exception handler (Vector 6)
 /// 
 /// Handles attempts to execute invalid or unsupported instructions.
-pub extern "C" fn invalid_opcode_handler() {
-    println("");
-    println("========================================");
-    println("EXCEPTION: Invalid Opcode (#UD)");
-    println("========================================");
-    println("");
-    println("The CPU encountered an invalid or unsupported instruction.");
-    println("This could indicate corrupted code or unsupported CPU features.");
-    println("");
-    println("Exception Details:");
-    println("  Vector: 6 (Invalid Opcode)");
-    println("  Type: Fault");
-    println("  Error Code: None");
-    println("");
-    println("System halted. Please reset to continue.");
-    println("========================================");
-    
-    loop {
-        unsafe {
-            core::arch::asm!("hlt");
-        }
-    }
+pub fn invalid_opcode_handler(context: &mut InterruptContext) {
+    report_exception(context, "The CPU encountered an invalid or unsupported instruction.");
+    halt();
+}
+
+/// Device not available handler (Vector 7)
+/// 
+/// Raised when an x87/SSE instruction executes while CR0.TS or CR0.EM is set.
+/// The kernel is built without floating point, so this is always fatal.
+pub fn device_not_available_handler(context: &mut InterruptContext) {
+    report_exception(context, "A floating point instruction was used but the FPU is unavailable.");
+    halt();
 }
 
 /// Double fault exception handler (Vector 8)
 /// 
 /// Handles double faults - when an exception occurs while handling another exception.
 /// This is a critical error that indicates serious system problems.
-pub extern "C" fn double_fault_handler() {
-    println("");
-    println("========================================");
-    println("CRITICAL: Double Fault (#DF)");
-    println("========================================");
-    println("");
-    println("A double fault occurred!");
-    println("This means an exception happened while handling another exception.");
-    println("This is a critical system error.");
-    println("");
-    println("Exception Details:");
-    println("  Vector: 8 (Double Fault)");
-    println("  Type: Abort");
-    println("  Error Code: Always 0");
-    println("");
-    println("System halted. Please reset to continue.");
-    println("========================================");
-    
-    loop {
-        unsafe {
-            core::arch::asm!("hlt");
-        }
-    }
+pub fn double_fault_handler(context: &mut InterruptContext) {
+    report_exception(context, "An exception occurred while handling another exception.");
+    halt();
+}
+
+/// Coprocessor segment overrun handler (Vector 9)
+/// 
+/// Legacy exception that modern processors never generate.
+pub fn coprocessor_segment_overrun_handler(context: &mut InterruptContext) {
+    report_exception(context, "Legacy coprocessor segment overrun.");
+    halt();
+}
+
+/// Invalid TSS exception handler (Vector 10)
+pub fn invalid_tss_handler(context: &mut InterruptContext) {
+    report_exception(context, "A task switch or TSS access referenced an invalid TSS.");
+    halt();
+}
+
+/// Segment not present exception handler (Vector 11)
+pub fn segment_not_present_handler(context: &mut InterruptContext) {
+    report_exception(context, "A segment or gate descriptor with the present bit clear was loaded.");
+    halt();
+}
+
+/// Stack-segment fault handler (Vector 12)
+pub fn stack_segment_fault_handler(context: &mut InterruptContext) {
+    report_exception(context, "A stack operation used a non-canonical address or invalid SS.");
+    halt();
 }
 
 /// General protection fault handler (Vector 13)
 /// 
 /// Handles general protection violations including privilege violations,
 /// segment violations, and other protection mechanism violations.
-pub extern "C" fn general_protection_fault_handler() {
-    println("");
-    println("========================================");
-    println("EXCEPTION: General Protection Fault (#GP)");
-    println("========================================");
-    println("");
-    println("A general protection fault occurred.");
-    println("This indicates a violation of the protection mechanism:");
-    println("- Privilege level violation");
-    println("- Segment limit violation");
-    println("- Invalid segment selector");
-    println("- Other protection violations");
-    println("");
-    println("Exception Details:");
-    println("  Vector: 13 (General Protection Fault)");
-    println("  Type: Fault");
-    println("  Error Code: Yes (segment selector related)");
-    println("");
-    println("System halted. Please reset to continue.");
-    println("========================================");
-    
-    loop {
-        unsafe {
-            core::arch::asm!("hlt");
-        }
-    }
+pub fn general_protection_fault_handler(context: &mut InterruptContext) {
+    report_exception(context, "A protection violation occurred (privilege, segment or canonical address).");
+    halt();
 }
 
 /// Page fault exception handler (Vector 14)
 /// 
 /// Handles page faults - memory access violations.
 /// This is one of the most important exception handlers for memory management.
-pub extern "C" fn page_fault_handler() {
-    println("");
-    println("========================================");
-    println("EXCEPTION: Page Fault (#PF)");
-    println("========================================");
-    println("");
-    println("A page fault occurred.");
-    println("This indicates a memory access violation:");
-    println("- Access to non-present page");
-    println("- Write to read-only page");
-    println("- User access to supervisor page");
-    println("- Instruction fetch from non-executable page");
-    println("");
-    println("Exception Details:");
-    println("  Vector: 14 (Page Fault)");
-    println("  Type: Fault");
-    println("  Error Code: Yes (page fault error code)");
-    println("");
-    println("System halted. Please reset to continue.");
-    println("========================================");
+pub fn page_fault_handler(context: &mut InterruptContext) {
+    report_exception(context, "A memory access violated the page tables.");
+    halt();
+}
+
+/// x87 floating point exception handler (Vector 16)
+pub fn x87_floating_point_handler(context: &mut InterruptContext) {
+    report_exception(context, "An unmasked x87 floating point exception occurred.");
+    halt();
+}
+
+/// Alignment check exception handler (Vector 17)
+pub fn alignment_check_handler(context: &mut InterruptContext) {
+    report_exception(context, "An unaligned memory access occurred with alignment checking enabled.");
+    halt();
+}
+
+/// Machine check exception handler (Vector 18)
+/// 
+/// Signals an internal CPU or bus error. Never recoverable here.
+pub fn machine_check_handler(context: &mut InterruptContext) {
+    report_exception(context, "The processor detected an internal machine error.");
+    halt();
+}
+
+/// SIMD floating point exception handler (Vector 19)
+pub fn simd_floating_point_handler(context: &mut InterruptContext) {
+    report_exception(context, "An unmasked SSE floating point exception occurred.");
+    halt();
+}
+
+/// Virtualization exception handler (Vector 20)
+pub fn virtualization_handler(context: &mut InterruptContext) {
+    report_exception(context, "An EPT violation was reported to the guest.");
+    halt();
+}
+
+/// Control protection exception handler (Vector 21)
+pub fn control_protection_handler(context: &mut InterruptContext) {
+    report_exception(context, "A control flow transfer violated CET protections.");
+    halt();
+}
+
+/// Hypervisor injection exception handler (Vector 28)
+pub fn hypervisor_injection_handler(context: &mut InterruptContext) {
+    report_exception(context, "The hypervisor injected an exception.");
+    halt();
+}
+
+/// VMM communication exception handler (Vector 29)
+pub fn vmm_communication_handler(context: &mut InterruptContext) {
+    report_exception(context, "An SEV-ES guest requested VMM communication.");
+    halt();
+}
+
+/// Security exception handler (Vector 30)
+pub fn security_exception_handler(context: &mut InterruptContext) {
+    report_exception(context, "A security-sensitive event occurred.");
+    halt();
+}
+
+/// Handler for reserved exception vectors (15, 22-27, 31)
+pub fn reserved_exception_handler(context: &mut InterruptContext) {
+    report_exception(context, "The CPU raised an exception on a reserved vector.");
+    halt();
+}
+
+// === Crash Reporting ===
+
+/// Print a complete exception report
+/// 
+/// The report contains the exception name and class, a short description,
+/// the decoded error code, CR2 for page faults and the full register state.
+fn report_exception(context: &InterruptContext, description: &str) {
+    let vector = context.vector as usize;
+    let info = &EXCEPTIONS[vector];
+    
+    eprintln!();
+    eprintln!("========================================");
+    let severity = if info.kind == ExceptionKind::Abort { "CRITICAL" } else { "EXCEPTION" };
+    if info.mnemonic.is_empty() {
+        eprintln!("{}: {}", severity, info.name);
+    } else {
+        eprintln!("{}: {} ({})", severity, info.name, info.mnemonic);
+    }
+    eprintln!("========================================");
+    eprintln!("{}", description);
+    
+    eprintln!("  Vector: {}  Type: {}", vector, info.kind.as_str());
+    
+    match context.error_code() {
+        Some(code) => {
+            eprintln!("  Error Code: {:#06X}", code);
+            print_error_code(vector as u8, code);
+        }
+        None => eprintln!("  Error Code: None"),
+    }
+    
+    if vector == 14 {
+        eprintln!("  Faulting Address (CR2): {:#018X}", read_cr2());
+    }
+    
+    if let Some(index) = ist_index(vector as u8) {
+        eprintln!("  IST Stack: {} (top {:#018X})", index, ist_stack(index));
+    }
+    
+    dump_registers(&context.regs, &context.frame);
+    print_backtrace(context.regs.rbp);
+}
+
+/// IST index of the stack a vector's handler runs on, if it has one
+fn ist_index(vector: u8) -> Option<u8> {
+    match vector {
+        2 => Some(NMI_IST_INDEX),
+        8 => Some(DOUBLE_FAULT_IST_INDEX),
+        18 => Some(MACHINE_CHECK_IST_INDEX),
+        _ => None,
+    }
+}
+
+/// Decode an error code according to the exception that pushed it
+fn print_error_code(vector: u8, code: u64) {
+    match vector {
+        // Selector error codes
+        10..=13 => {
+            if code == 0 {
+                eprintln!("    (not segment related)");
+                return;
+            }
+            
+            let selector = SelectorErrorCode(code);
+            eprintln!(
+                "    Selector: {}[{}]  External: {}",
+                selector.table(),
+                selector.index(),
+                if selector.external() { "Yes" } else { "No" },
+            );
+        }
+        
+        // Page fault error code
+        14 => {
+            let code = PageFaultErrorCode(code);
+            eprintln!(
+                "    P:{} W:{} U:{}",
+                if code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) { "protection-violation" } else { "not-present" },
+                if code.contains(PageFaultErrorCode::WRITE) { "write" } else { "read" },
+                if code.contains(PageFaultErrorCode::USER) { "user" } else { "supervisor" },
+            );
+            
+            eprint!("    ");
+            print_flag("R:reserved-bit ", code.contains(PageFaultErrorCode::RESERVED_BIT));
+            print_flag("I:instruction-fetch ", code.contains(PageFaultErrorCode::INSTRUCTION_FETCH));
+            print_flag("PK:protection-key ", code.contains(PageFaultErrorCode::PROTECTION_KEY));
+            print_flag("SS:shadow-stack ", code.contains(PageFaultErrorCode::SHADOW_STACK));
+            print_flag("SGX ", code.contains(PageFaultErrorCode::SGX));
+            eprintln!();
+        }
+        
+        // Control protection error code
+        21 => {
+            eprintln!("    Cause: {}", match code & 0x7FFF {
+                1 => "NEAR-RET",
+                2 => "FAR-RET/IRET",
+                3 => "ENDBRANCH",
+                4 => "RSTORSSP",
+                5 => "SETSSBSY",
+                _ => "Unknown",
+            });
+        }
+        
+        // #DF and #AC always push 0; #VC and #SX codes are printed raw
+        _ => {}
+    }
+}
+
+/// Print a flag name if it is set
+fn print_flag(name: &str, set: bool) {
+    if set {
+        eprint!("{}", name);
+    }
+}
+
+/// Print the saved general purpose registers, the interrupt frame and the
+/// control registers
+pub fn dump_registers(regs: &SavedRegisters, frame: &InterruptStackFrame) {
+    eprintln!("Registers:");
+    print_register_row(&[("RAX", regs.rax), ("RBX", regs.rbx), ("RCX", regs.rcx)]);
+    print_register_row(&[("RDX", regs.rdx), ("RSI", regs.rsi), ("RDI", regs.rdi)]);
+    print_register_row(&[("RBP", regs.rbp), ("RSP", frame.rsp), ("R8 ", regs.r8)]);
+    print_register_row(&[("R9 ", regs.r9), ("R10", regs.r10), ("R11", regs.r11)]);
+    print_register_row(&[("R12", regs.r12), ("R13", regs.r13), ("R14", regs.r14)]);
+    print_register_row(&[("R15", regs.r15), ("RIP", frame.rip), ("RFL", frame.rflags)]);
+    
+    eprintln!("  CS={:#06X}  SS={:#06X}  RIP at {}", frame.cs, frame.ss, Symbolized(frame.rip));
+    
+    let (cr0, cr3, cr4) = read_control_registers();
+    print_register_row(&[("CR0", cr0), ("CR3", cr3), ("CR4", cr4)]);
+}
+
+/// Print a row of named 64-bit register values
+fn print_register_row(registers: &[(&str, u64)]) {
+    eprint!(" ");
+    for (name, value) in registers {
+        eprint!(" {}={:016X}", name, value);
+    }
+    eprintln!();
+}
+
+/// Read CR2, which holds the linear address that caused the last page fault
+fn read_cr2() -> u64 {
+    let value: u64;
+    unsafe {
+        core::arch::asm!("mov {}, cr2", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// Read CR0, CR3 and CR4
+fn read_control_registers() -> (u64, u64, u64) {
+    let (cr0, cr3, cr4): (u64, u64, u64);
+    unsafe {
+        core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov {}, cr3", out(reg) cr3, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov {}, cr4", out(reg) cr4, options(nomem, nostack, preserves_flags));
+    }
+    (cr0, cr3, cr4)
+}
+
+/// Halt the CPU after a fatal exception
+fn halt() -> ! {
+    eprintln!("System halted. Please reset to continue.");
+    eprintln!("========================================");
     
     loop {
         unsafe {
@@ -2,91 +2,101 @@
 /// 
 /// This module contains handlers for hardware interrupts (vectors 32-255).
 /// These are triggered by external hardware devices like timers, keyboards, etc.
+/// The timer interrupt is handled by the timekeeping module (`time.rs`).
+/// 
+/// IRQ handlers are registered with the IRQ dispatcher (`irq.rs`), which
+/// filters spurious IRQs and sends the end-of-interrupt command after the
+/// handler returns. The second handler argument is the context value given
+/// at registration.
 
-use crate::arch::drivers::vga::println;
+use crate::arch::println;
+use crate::arch::drivers::{i8042, keyboard, mouse, rtc, serial};
+use crate::eprintln;
+use super::apic;
+use super::entry::InterruptContext;
+use super::irq::IrqReturn;
 
-/// Timer interrupt handler (Vector 32, IRQ 0)
+/// Keyboard interrupt handler (Vector 33, IRQ 1)
 /// 
-/// Handles timer interrupts for system clock and scheduling.
-/// Currently just a placeholder that acknowledges the interrupt.
-pub extern "C" fn timer_interrupt_handler() {
-    println("Timer interrupt received");
-    
-    // TODO: Implement proper timer handling
-    // - Update system clock
-    // - Handle scheduling
-    // - Send EOI to interrupt controller
-    
-    loop {
-        unsafe {
-            core::arch::asm!("hlt");
-        }
+/// Reads the byte the keyboard sent and passes it to the keyboard driver,
+/// which decodes it and queues the key event.
+pub fn keyboard_interrupt_handler(_context: &mut InterruptContext, _data: usize) -> IrqReturn {
+    let status = i8042::status();
+    if status & i8042::STATUS_OUTPUT_FULL == 0 || status & i8042::STATUS_AUX_DATA != 0 {
+        return IrqReturn::NotHandled;
     }
+    keyboard::handle_byte(i8042::read_data());
+    IrqReturn::Handled
 }
 
-/// Keyboard interrupt handler (Vector 33, IRQ 1)
+/// Mouse interrupt handler (IRQ 12)
 /// 
-/// Handles keyboard input interrupts.
-/// Currently just a placeholder that acknowledges the interrupt.
-pub extern "C" fn keyboard_interrupt_handler() {
-    println("Keyboard interrupt received");
-    
-    // TODO: Implement proper keyboard handling
-    // - Read scan code from keyboard controller
-    // - Convert to key codes
-    // - Handle key press/release events
-    // - Send EOI to interrupt controller
-    
-    loop {
-        unsafe {
-            core::arch::asm!("hlt");
-        }
+/// Reads the byte the mouse sent and passes it to the mouse driver, which
+/// assembles packets and queues the mouse events. Bytes from the keyboard
+/// are left for the keyboard handler.
+pub fn mouse_interrupt_handler(_context: &mut InterruptContext, _data: usize) -> IrqReturn {
+    let status = i8042::status();
+    if status & i8042::STATUS_OUTPUT_FULL == 0 || status & i8042::STATUS_AUX_DATA == 0 {
+        return IrqReturn::NotHandled;
     }
+    mouse::handle_byte(i8042::read_data());
+    IrqReturn::Handled
 }
 
-/// Serial port interrupt handler (Vector 36, IRQ 4)
+/// Serial port interrupt handler (Vector 36, IRQ 4 and Vector 35, IRQ 3)
 /// 
-/// Handles serial port communication interrupts.
-/// Currently just a placeholder.
-pub extern "C" fn serial_interrupt_handler() {
-    println("Serial interrupt received");
-    
-    // TODO: Implement proper serial handling
-    // - Read/write serial data
-    // - Handle communication protocols
-    // - Send EOI to interrupt controller
-    
-    loop {
-        unsafe {
-            core::arch::asm!("hlt");
-        }
+/// Moves received bytes into the serial driver's receive buffer and
+/// refills the transmit FIFO of one port. COM1/COM3 share IRQ 4 and
+/// COM2/COM4 share IRQ 3, so each port registers its own shared handler
+/// with the port's index in `ComPort::ALL` as context.
+pub fn serial_interrupt_handler(_context: &mut InterruptContext, port: usize) -> IrqReturn {
+    match serial::ComPort::ALL.get(port) {
+        Some(&port) if serial::handle_interrupt(port) => IrqReturn::Handled,
+        _ => IrqReturn::NotHandled,
     }
 }
 
-/// Spurious interrupt handler
+/// RTC interrupt handler (Vector 40, IRQ 8)
 /// 
-/// Handles spurious interrupts that may be generated by the interrupt controller.
-pub extern "C" fn spurious_interrupt_handler() {
+/// Acknowledges periodic and alarm interrupts by reading status C. Only
+/// registered while one of them is enabled.
+pub fn rtc_interrupt_handler(_context: &mut InterruptContext, _data: usize) -> IrqReturn {
+    if rtc::handle_interrupt() {
+        IrqReturn::Handled
+    } else {
+        IrqReturn::NotHandled
+    }
+}
+
+/// Spurious interrupt handler (Vector 0xFF)
+/// 
+/// Handles spurious interrupts from the local APIC, raised when an
+/// interrupt is withdrawn after the APIC started delivering it.
+pub fn spurious_interrupt_handler(_context: &mut InterruptContext) {
     println("Spurious interrupt received");
     
     // Spurious interrupts don't require EOI
     // Just return normally
 }
 
+/// APIC error handler (Vector 0xFE)
+/// 
+/// The local APIC reports errors such as an illegal vector or a failed
+/// IPI through its error status register.
+pub fn apic_error_handler(_context: &mut InterruptContext) {
+    let status = apic::read_error_status();
+    eprintln!("APIC error, status {:#x}", status);
+    apic::eoi();
+}
+
 /// General purpose dummy handler for unimplemented interrupts
 /// 
 /// This handler is used for interrupt vectors that don't have specific handlers yet.
-pub extern "C" fn unhandled_interrupt_handler() {
+/// It reports the interrupt and returns to the interrupted code.
+pub fn unhandled_interrupt_handler(_context: &mut InterruptContext) {
     println("");
-    println("UNHANDLED HARDWARE INTERRUPT");
-    println("An unimplemented hardware interrupt occurred.");
+    println("UNHANDLED INTERRUPT");
+    println("An unimplemented interrupt occurred.");
     println("This interrupt vector needs a proper handler.");
     println("");
-    println("System halted for debugging.");
-    
-    loop {
-        unsafe {
-            core::arch::asm!("hlt");
-        }
-    }
 }
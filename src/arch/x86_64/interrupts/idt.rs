@@ -10,7 +10,7 @@ const IDT_ENTRIES: usize = 256;
 
 /// IDT gate types
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GateType {
     InterruptGate = 0b1110,
     TrapGate = 0b1111,
@@ -42,7 +42,7 @@ impl IdtEntry {
         Self {
             offset_low: (handler & 0xFFFF) as u16,
             selector,
-            ist: 0, // No IST for now
+            ist: 0, // Use the current stack (see set_stack_index)
             type_attributes: (gate_type as u8) | (1 << 7), // Present bit set
             offset_middle: ((handler >> 16) & 0xFFFF) as u16,
             offset_high: ((handler >> 32) & 0xFFFFFFFF) as u32,
@@ -50,6 +50,57 @@ impl IdtEntry {
         }
     }
 
+    /// Switch to an Interrupt Stack Table stack when this entry fires
+    /// 
+    /// `index` is the IST slot (1-7) in the TSS. An index of 0 disables the
+    /// stack switch and the handler runs on the interrupted stack.
+    pub fn set_stack_index(&mut self, index: u8) -> &mut Self {
+        assert!(index <= 7, "IST index must be 0-7");
+        self.ist = index;
+        self
+    }
+
+    /// Set the descriptor privilege level (DPL) of this entry
+    /// 
+    /// The DPL is the least privileged ring allowed to trigger the vector
+    /// with a software `int` instruction. Hardware interrupts and exceptions
+    /// ignore it. Kernel-only entries use 0, entries usable from user space
+    /// (such as `int3`) use 3.
+    pub fn set_privilege_level(&mut self, dpl: u8) -> &mut Self {
+        assert!(dpl <= 3, "DPL must be 0-3");
+        self.type_attributes = (self.type_attributes & !0x60) | (dpl << 5);
+        self
+    }
+
+    /// Whether the entry is present (has a handler)
+    pub fn is_present(&self) -> bool {
+        self.type_attributes & (1 << 7) != 0
+    }
+
+    /// Address of the handler the entry points at
+    pub fn handler_address(&self) -> u64 {
+        self.offset_low as u64 | (self.offset_middle as u64) << 16 | (self.offset_high as u64) << 32
+    }
+
+    /// Gate type of a present entry
+    pub fn gate_type(&self) -> Option<GateType> {
+        match self.type_attributes & 0x0F {
+            0b1110 => Some(GateType::InterruptGate),
+            0b1111 => Some(GateType::TrapGate),
+            _ => None,
+        }
+    }
+
+    /// Interrupt Stack Table index (0 if the stack is not switched)
+    pub fn stack_index(&self) -> u8 {
+        self.ist & 0x07
+    }
+
+    /// Descriptor privilege level
+    pub fn privilege_level(&self) -> u8 {
+        (self.type_attributes >> 5) & 0x03
+    }
+
     /// Create an empty IDT entry
     pub fn empty() -> Self {
         Self {
@@ -88,8 +139,16 @@ impl Idt {
     }
 
     /// Set an IDT entry
-    pub fn set_handler(&mut self, vector: u8, handler: u64, selector: u16, gate_type: GateType) {
+    /// 
+    /// Returns the new entry so that its IST index and DPL can be adjusted.
+    pub fn set_handler(&mut self, vector: u8, handler: u64, selector: u16, gate_type: GateType) -> &mut IdtEntry {
         self.entries[vector as usize] = IdtEntry::new(handler, selector, gate_type);
+        &mut self.entries[vector as usize]
+    }
+
+    /// Get the entry of a vector
+    pub fn entry(&self, vector: u8) -> &IdtEntry {
+        &self.entries[vector as usize]
     }
 
     /// Load the IDT using the LIDT instruction
@@ -0,0 +1,265 @@
+/// I/O APIC
+/// 
+/// I/O APICs collect device interrupts and forward them to the local
+/// APICs. Each has a number of inputs, identified system-wide by their
+/// Global System Interrupt (GSI) number, and a redirection table entry per
+/// input that selects the vector, destination CPU, polarity and trigger
+/// mode.
+/// 
+/// ## Registers
+/// 
+/// Only two registers are memory mapped: IOREGSEL (offset 0x00) selects an
+/// internal register and IOWIN (offset 0x10) reads or writes it.
+/// 
+/// ```text
+/// 0x00         I/O APIC ID
+/// 0x01         Version (bits 16-23: number of inputs - 1)
+/// 0x10 + 2n    Redirection entry n, low 32 bits
+/// 0x11 + 2n    Redirection entry n, high 32 bits (destination)
+/// ```
+/// 
+/// ## ISA IRQs
+/// 
+/// `init` routes the 16 legacy ISA IRQs to the same vectors the PICs used
+/// (32-47), applying the MADT interrupt source overrides. Under QEMU the
+/// PIT's IRQ 0 arrives on GSI 2, so without the override the timer would
+/// never be seen. All routes start masked, like the PIC lines.
+
+use crate::arch::x86_64::acpi::madt::{Madt, Polarity, TriggerMode, ISA_IRQ_COUNT};
+use crate::arch::x86_64::memory::{identity_map_region, PageTableFlags};
+use crate::arch::x86_64::sync::SpinLock;
+use super::pic;
+
+/// Register select offset
+const IOREGSEL: u64 = 0x00;
+/// Register window offset
+const IOWIN: u64 = 0x10;
+
+/// I/O APIC version register
+const REG_VERSION: u32 = 0x01;
+/// First redirection table register
+const REG_REDIRECTION_TABLE: u32 = 0x10;
+
+/// Redirection entry: interrupt masked
+const ENTRY_MASKED: u64 = 1 << 16;
+/// Redirection entry: level triggered
+const ENTRY_LEVEL: u64 = 1 << 15;
+/// Redirection entry: active low
+const ENTRY_ACTIVE_LOW: u64 = 1 << 13;
+
+/// Maximum number of I/O APICs supported
+pub const MAX_IO_APICS: usize = 8;
+
+/// Errors that can occur while setting up the I/O APICs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoApicError {
+    /// The MADT lists no I/O APIC
+    NotFound,
+    /// More I/O APICs than `MAX_IO_APICS`
+    RegistryFull,
+    /// The registers of the I/O APIC at this address could not be mapped
+    MapFailed(u32),
+    /// No I/O APIC handles the GSI
+    NoSuchGsi(u32),
+}
+
+/// A redirection table entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RedirectionEntry {
+    pub vector: u8,
+    pub polarity: Polarity,
+    pub trigger_mode: TriggerMode,
+    pub masked: bool,
+    /// APIC ID of the destination CPU (physical destination mode)
+    pub destination: u32,
+}
+
+impl RedirectionEntry {
+    /// Fixed delivery to one CPU, physical destination mode
+    fn to_bits(self) -> u64 {
+        let mut bits = self.vector as u64;
+        if self.polarity == Polarity::ActiveLow {
+            bits |= ENTRY_ACTIVE_LOW;
+        }
+        if self.trigger_mode == TriggerMode::Level {
+            bits |= ENTRY_LEVEL;
+        }
+        if self.masked {
+            bits |= ENTRY_MASKED;
+        }
+        bits | (self.destination as u64 & 0xFF) << 56
+    }
+    
+    fn from_bits(bits: u64) -> Self {
+        RedirectionEntry {
+            vector: bits as u8,
+            polarity: if bits & ENTRY_ACTIVE_LOW != 0 { Polarity::ActiveLow } else { Polarity::ActiveHigh },
+            trigger_mode: if bits & ENTRY_LEVEL != 0 { TriggerMode::Level } else { TriggerMode::Edge },
+            masked: bits & ENTRY_MASKED != 0,
+            destination: (bits >> 56) as u32,
+        }
+    }
+}
+
+/// An I/O APIC found in the MADT
+#[derive(Debug, Clone, Copy)]
+pub struct IoApic {
+    pub id: u8,
+    /// Address of the memory-mapped registers
+    pub address: u64,
+    /// GSI of input 0
+    pub gsi_base: u32,
+    /// Number of inputs
+    pub inputs: u32,
+}
+
+impl IoApic {
+    fn read(&self, reg: u32) -> u32 {
+        unsafe {
+            core::ptr::write_volatile((self.address + IOREGSEL) as *mut u32, reg);
+            core::ptr::read_volatile((self.address + IOWIN) as *const u32)
+        }
+    }
+    
+    fn write(&self, reg: u32, value: u32) {
+        unsafe {
+            core::ptr::write_volatile((self.address + IOREGSEL) as *mut u32, reg);
+            core::ptr::write_volatile((self.address + IOWIN) as *mut u32, value);
+        }
+    }
+    
+    /// Check whether this I/O APIC handles a GSI
+    pub fn handles(&self, gsi: u32) -> bool {
+        gsi >= self.gsi_base && gsi < self.gsi_base + self.inputs
+    }
+    
+    fn read_entry(&self, input: u32) -> u64 {
+        let reg = REG_REDIRECTION_TABLE + input * 2;
+        self.read(reg) as u64 | (self.read(reg + 1) as u64) << 32
+    }
+    
+    fn write_entry(&self, input: u32, bits: u64) {
+        let reg = REG_REDIRECTION_TABLE + input * 2;
+        // Keep the entry masked while it is half written
+        self.write(reg, ENTRY_MASKED as u32);
+        self.write(reg + 1, (bits >> 32) as u32);
+        self.write(reg, bits as u32);
+    }
+}
+
+/// I/O APICs found by `init`
+static IO_APICS: SpinLock<[Option<IoApic>; MAX_IO_APICS]> = SpinLock::new([None; MAX_IO_APICS]);
+
+/// GSI of each ISA IRQ, set by `init` (None if the IRQ has no route)
+static ISA_GSIS: SpinLock<[Option<u32>; ISA_IRQ_COUNT as usize]> = SpinLock::new([None; ISA_IRQ_COUNT as usize]);
+
+/// Find and mask every I/O APIC, then route the ISA IRQs to vectors 32-47
+/// 
+/// `destination` is the APIC ID of the CPU that receives the IRQs.
+pub fn init(madt: &Madt, destination: u32) -> Result<usize, IoApicError> {
+    let mut count = 0;
+    
+    {
+        let mut io_apics = IO_APICS.lock();
+        for (id, address, gsi_base) in madt.io_apics() {
+            let slot = io_apics.get_mut(count).ok_or(IoApicError::RegistryFull)?;
+            identity_map_region(address as u64, 0x1000, PageTableFlags::WRITABLE.union(PageTableFlags::NO_CACHE))
+                .map_err(|_| IoApicError::MapFailed(address))?;
+            
+            let mut io_apic = IoApic { id, address: address as u64, gsi_base, inputs: 0 };
+            io_apic.inputs = ((io_apic.read(REG_VERSION) >> 16) & 0xFF) + 1;
+            
+            for input in 0..io_apic.inputs {
+                io_apic.write_entry(input, ENTRY_MASKED);
+            }
+            
+            *slot = Some(io_apic);
+            count += 1;
+        }
+    }
+    
+    if count == 0 {
+        return Err(IoApicError::NotFound);
+    }
+    
+    for irq in 0..ISA_IRQ_COUNT {
+        // IRQ 2 is the PIC cascade and never raised by a device
+        if irq == pic::CASCADE_IRQ {
+            continue;
+        }
+        let Some(route) = madt.isa_route(irq) else {
+            continue;
+        };
+        
+        let entry = RedirectionEntry {
+            vector: pic::irq_vector(irq),
+            polarity: route.polarity,
+            trigger_mode: route.trigger_mode,
+            masked: true,
+            destination,
+        };
+        if set_entry(route.gsi, entry).is_ok() {
+            ISA_GSIS.lock()[irq as usize] = Some(route.gsi);
+        }
+    }
+    
+    Ok(count)
+}
+
+/// Run a closure on the I/O APIC handling a GSI, with the GSI's input number
+fn with_io_apic<R>(gsi: u32, f: impl FnOnce(&IoApic, u32) -> R) -> Result<R, IoApicError> {
+    let io_apics = IO_APICS.lock();
+    let io_apic = io_apics.iter().flatten().find(|io_apic| io_apic.handles(gsi)).ok_or(IoApicError::NoSuchGsi(gsi))?;
+    Ok(f(io_apic, gsi - io_apic.gsi_base))
+}
+
+/// Program the redirection entry of a GSI
+pub fn set_entry(gsi: u32, entry: RedirectionEntry) -> Result<(), IoApicError> {
+    with_io_apic(gsi, |io_apic, input| io_apic.write_entry(input, entry.to_bits()))
+}
+
+/// Read the redirection entry of a GSI
+pub fn entry(gsi: u32) -> Result<RedirectionEntry, IoApicError> {
+    with_io_apic(gsi, |io_apic, input| RedirectionEntry::from_bits(io_apic.read_entry(input)))
+}
+
+/// Mask or unmask a GSI
+pub fn set_masked(gsi: u32, masked: bool) -> Result<(), IoApicError> {
+    with_io_apic(gsi, |io_apic, input| {
+        let bits = io_apic.read_entry(input);
+        let bits = if masked { bits | ENTRY_MASKED } else { bits & !ENTRY_MASKED };
+        io_apic.write_entry(input, bits);
+    })
+}
+
+/// GSI an ISA IRQ is routed to
+pub fn isa_gsi(irq: u8) -> Option<u32> {
+    ISA_GSIS.lock().get(irq as usize).copied().flatten()
+}
+
+/// Stop an ISA IRQ from being delivered
+pub fn mask_irq(irq: u8) {
+    if let Some(gsi) = isa_gsi(irq) {
+        let _ = set_masked(gsi, true);
+    }
+}
+
+/// Allow an ISA IRQ to be delivered
+pub fn unmask_irq(irq: u8) {
+    if let Some(gsi) = isa_gsi(irq) {
+        let _ = set_masked(gsi, false);
+    }
+}
+
+/// Check whether an ISA IRQ is masked (or has no route)
+pub fn is_masked(irq: u8) -> bool {
+    match isa_gsi(irq) {
+        Some(gsi) => entry(gsi).map(|entry| entry.masked).unwrap_or(true),
+        None => true,
+    }
+}
+
+/// I/O APICs found by `init`
+pub fn io_apics() -> [Option<IoApic>; MAX_IO_APICS] {
+    *IO_APICS.lock()
+}
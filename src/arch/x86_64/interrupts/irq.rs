@@ -0,0 +1,415 @@
+/// IRQ Dispatch and Handler Registration
+/// 
+/// Every device vector (32-253) has a fixed IDT entry that leads to
+/// `irq_interrupt_handler`. Which code actually runs is decided at run time
+/// by a table of handlers that drivers fill in:
+/// 
+/// ```rust
+/// // ISA IRQ: vector 32 + irq, unmasked once the handler is in place
+/// irq::register_irq(4, "COM1", serial_handler, 0, IrqSharing::Shared)?;
+/// 
+/// // Any other device (MSI, IPIs): allocate a free vector first
+/// let vector = irq::allocate_vector()?;
+/// irq::register_vector(vector, "nic", nic_handler, nic as usize, IrqSharing::Exclusive)?;
+/// ```
+/// 
+/// For each interrupt the dispatcher:
+/// 
+/// 1. Drops spurious IRQ 7 and IRQ 15 interrupts (PIC only, see `pic.rs`)
+/// 2. Calls every handler registered on the vector; a shared line calls all
+///    of them, since the interrupt does not say which device raised it
+/// 3. Masks an ISA IRQ that has no handler, so it cannot keep firing
+/// 4. Sends the end-of-interrupt command to the active controller
+/// 
+/// Device handlers therefore only deal with their device and never talk
+/// to the interrupt controller themselves. Drivers mask and unmask their
+/// IRQ through `mask_irq`/`unmask_irq`, which go to the 8259 PICs or the
+/// I/O APIC depending on which one is active.
+/// 
+/// ## Vector layout
+/// 
+/// ```text
+/// 32-47    ISA IRQ 0-15 (register_irq)
+/// 48-253   Allocated on demand (allocate_vector, register_vector)
+/// 254-255  Local APIC error and spurious vectors (not dispatched here)
+/// ```
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use super::entry::{self, InterruptContext};
+use super::hardware;
+use super::{apic, ioapic, pic};
+use crate::arch::x86_64::sync::SpinLock;
+use crate::{eprintln, print, println};
+
+/// First vector dispatched through the handler table
+pub const FIRST_DEVICE_VECTOR: u8 = pic::PIC1_OFFSET;
+/// Last vector dispatched through the handler table
+pub const LAST_DEVICE_VECTOR: u8 = apic::ERROR_VECTOR - 1;
+/// First vector handed out by `allocate_vector`
+pub const FIRST_DYNAMIC_VECTOR: u8 = pic::irq_vector(pic::IRQ_COUNT);
+
+/// Number of vectors in the handler table
+const DEVICE_VECTOR_COUNT: usize = (LAST_DEVICE_VECTOR - FIRST_DEVICE_VECTOR) as usize + 1;
+
+/// Maximum number of handlers sharing one vector
+pub const MAX_SHARED_HANDLERS: usize = 4;
+
+/// Interrupt controller delivering the ISA IRQs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptController {
+    /// Legacy 8259 PICs
+    Pic,
+    /// Local APIC and I/O APIC
+    Apic,
+}
+
+/// What a handler did with an interrupt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqReturn {
+    /// The handler's device raised the interrupt and has been serviced
+    Handled,
+    /// The handler's device had nothing pending (another device on a
+    /// shared line raised the interrupt)
+    NotHandled,
+}
+
+/// Signature of a device interrupt handler
+/// 
+/// The second argument is the context value given at registration, such
+/// as a device index or a pointer to the driver's state.
+pub type IrqHandler = fn(&mut InterruptContext, usize) -> IrqReturn;
+
+/// Whether other handlers may be registered on the same vector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqSharing {
+    /// The handler must be the only one on the vector
+    Exclusive,
+    /// Other shared handlers may be added to the vector
+    Shared,
+}
+
+/// Errors returned by the registration API
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqError {
+    /// The IRQ number is not an ISA IRQ (0-15)
+    InvalidIrq(u8),
+    /// The vector is not a device vector, or has not been allocated
+    InvalidVector(u8),
+    /// The vector has an exclusive handler, or an exclusive handler was
+    /// requested for a vector that already has handlers
+    Busy,
+    /// The vector already has `MAX_SHARED_HANDLERS` handlers
+    TooManyHandlers,
+    /// A handler with the same name is already registered on the vector
+    AlreadyRegistered,
+    /// No handler with the given name is registered on the vector
+    NotRegistered,
+    /// Every dynamic vector is allocated
+    NoFreeVector,
+}
+
+impl core::fmt::Display for IrqError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            IrqError::InvalidIrq(irq) => write!(f, "invalid IRQ {}", irq),
+            IrqError::InvalidVector(vector) => write!(f, "vector {} is not available", vector),
+            IrqError::Busy => write!(f, "vector is in exclusive use"),
+            IrqError::TooManyHandlers => write!(f, "too many handlers on the vector"),
+            IrqError::AlreadyRegistered => write!(f, "handler already registered"),
+            IrqError::NotRegistered => write!(f, "handler not registered"),
+            IrqError::NoFreeVector => write!(f, "no free interrupt vector"),
+        }
+    }
+}
+
+/// A registered handler
+#[derive(Clone, Copy)]
+struct IrqAction {
+    name: &'static str,
+    handler: IrqHandler,
+    context: usize,
+}
+
+/// Handlers and ownership of one device vector
+#[derive(Clone, Copy)]
+struct VectorSlot {
+    /// Handed out by `allocate_vector` (ISA vectors need no allocation)
+    allocated: bool,
+    sharing: IrqSharing,
+    actions: [Option<IrqAction>; MAX_SHARED_HANDLERS],
+}
+
+impl VectorSlot {
+    const EMPTY: Self = VectorSlot { allocated: false, sharing: IrqSharing::Shared, actions: [None; MAX_SHARED_HANDLERS] };
+    
+    fn is_empty(&self) -> bool {
+        self.actions.iter().all(Option::is_none)
+    }
+}
+
+/// Handler table for vectors `FIRST_DEVICE_VECTOR..=LAST_DEVICE_VECTOR`
+static VECTORS: SpinLock<[VectorSlot; DEVICE_VECTOR_COUNT]> = SpinLock::new([VectorSlot::EMPTY; DEVICE_VECTOR_COUNT]);
+
+/// Number of interrupts that none of the registered handlers claimed
+static UNCLAIMED: AtomicU64 = AtomicU64::new(0);
+
+/// Set once the APICs have replaced the PICs
+static APIC_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Interrupt controller currently delivering the ISA IRQs
+pub fn controller() -> InterruptController {
+    if APIC_ACTIVE.load(Ordering::Acquire) {
+        InterruptController::Apic
+    } else {
+        InterruptController::Pic
+    }
+}
+
+/// Switch IRQ masking and EOI to a different controller
+pub(super) fn set_controller(controller: InterruptController) {
+    APIC_ACTIVE.store(controller == InterruptController::Apic, Ordering::Release);
+}
+
+/// Stop an IRQ from being delivered
+pub fn mask_irq(irq: u8) {
+    match controller() {
+        InterruptController::Pic => pic::mask_irq(irq),
+        InterruptController::Apic => ioapic::mask_irq(irq),
+    }
+}
+
+/// Allow an IRQ to be delivered
+pub fn unmask_irq(irq: u8) {
+    match controller() {
+        InterruptController::Pic => pic::unmask_irq(irq),
+        InterruptController::Apic => ioapic::unmask_irq(irq),
+    }
+}
+
+/// Check whether an IRQ is masked
+pub fn is_masked(irq: u8) -> bool {
+    match controller() {
+        InterruptController::Pic => pic::is_masked(irq),
+        InterruptController::Apic => ioapic::is_masked(irq),
+    }
+}
+
+/// Index of a vector in the handler table
+fn slot_index(vector: u8) -> Result<usize, IrqError> {
+    if (FIRST_DEVICE_VECTOR..=LAST_DEVICE_VECTOR).contains(&vector) {
+        Ok((vector - FIRST_DEVICE_VECTOR) as usize)
+    } else {
+        Err(IrqError::InvalidVector(vector))
+    }
+}
+
+/// Vector of an ISA IRQ
+fn isa_vector(irq: u8) -> Result<u8, IrqError> {
+    if irq < pic::IRQ_COUNT {
+        Ok(pic::irq_vector(irq))
+    } else {
+        Err(IrqError::InvalidIrq(irq))
+    }
+}
+
+/// Reserve a free vector for a device without a fixed IRQ line
+/// 
+/// Vectors are handed out from `FIRST_DYNAMIC_VECTOR` upwards.
+pub fn allocate_vector() -> Result<u8, IrqError> {
+    let mut vectors = VECTORS.lock();
+    let first = (FIRST_DYNAMIC_VECTOR - FIRST_DEVICE_VECTOR) as usize;
+    
+    let index = (first..DEVICE_VECTOR_COUNT)
+        .find(|&index| !vectors[index].allocated)
+        .ok_or(IrqError::NoFreeVector)?;
+    vectors[index].allocated = true;
+    Ok(FIRST_DEVICE_VECTOR + index as u8)
+}
+
+/// Return a vector obtained from `allocate_vector`
+/// 
+/// Fails with `Busy` while handlers are still registered on it.
+pub fn free_vector(vector: u8) -> Result<(), IrqError> {
+    if vector < FIRST_DYNAMIC_VECTOR {
+        return Err(IrqError::InvalidVector(vector));
+    }
+    let index = slot_index(vector)?;
+    
+    let mut vectors = VECTORS.lock();
+    if !vectors[index].is_empty() {
+        return Err(IrqError::Busy);
+    }
+    vectors[index] = VectorSlot::EMPTY;
+    Ok(())
+}
+
+/// Add a handler to a device vector
+/// 
+/// The vector must be an ISA vector or one returned by `allocate_vector`.
+/// Handler names must be unique per vector; they identify the handler in
+/// `unregister_vector` and in `print_vectors`.
+pub fn register_vector(
+    vector: u8,
+    name: &'static str,
+    handler: IrqHandler,
+    context: usize,
+    sharing: IrqSharing,
+) -> Result<(), IrqError> {
+    let index = slot_index(vector)?;
+    let mut vectors = VECTORS.lock();
+    let slot = &mut vectors[index];
+    
+    if vector >= FIRST_DYNAMIC_VECTOR && !slot.allocated {
+        return Err(IrqError::InvalidVector(vector));
+    }
+    if slot.actions.iter().flatten().any(|action| action.name == name) {
+        return Err(IrqError::AlreadyRegistered);
+    }
+    if !slot.is_empty() && (slot.sharing == IrqSharing::Exclusive || sharing == IrqSharing::Exclusive) {
+        return Err(IrqError::Busy);
+    }
+    
+    let free = slot.actions.iter_mut().find(|action| action.is_none()).ok_or(IrqError::TooManyHandlers)?;
+    *free = Some(IrqAction { name, handler, context });
+    slot.sharing = sharing;
+    Ok(())
+}
+
+/// Remove a handler from a device vector
+/// 
+/// Returns true if it was the last handler on the vector.
+pub fn unregister_vector(vector: u8, name: &str) -> Result<bool, IrqError> {
+    let index = slot_index(vector)?;
+    let mut vectors = VECTORS.lock();
+    let slot = &mut vectors[index];
+    
+    let action = slot.actions.iter_mut()
+        .find(|action| action.is_some_and(|action| action.name == name))
+        .ok_or(IrqError::NotRegistered)?;
+    *action = None;
+    
+    if slot.is_empty() {
+        slot.sharing = IrqSharing::Shared;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Add a handler for an ISA IRQ and unmask the IRQ
+/// 
+/// The handler is called on vector 32 + `irq` whichever interrupt
+/// controller is active.
+pub fn register_irq(
+    irq: u8,
+    name: &'static str,
+    handler: IrqHandler,
+    context: usize,
+    sharing: IrqSharing,
+) -> Result<(), IrqError> {
+    register_vector(isa_vector(irq)?, name, handler, context, sharing)?;
+    unmask_irq(irq);
+    Ok(())
+}
+
+/// Remove a handler from an ISA IRQ, masking the IRQ if it was the last one
+pub fn unregister_irq(irq: u8, name: &str) -> Result<(), IrqError> {
+    if unregister_vector(isa_vector(irq)?, name)? {
+        mask_irq(irq);
+    }
+    Ok(())
+}
+
+/// Number of interrupts that none of the registered handlers claimed
+pub fn unclaimed_count() -> u64 {
+    UNCLAIMED.load(Ordering::Relaxed)
+}
+
+/// Signal the end of an interrupt to the active controller
+fn end_of_interrupt(vector: u8) {
+    match (controller(), pic::vector_irq(vector)) {
+        (InterruptController::Pic, Some(irq)) => pic::end_of_interrupt(irq),
+        // Without the APIC, other vectors can only come from `int`
+        (InterruptController::Pic, None) => {}
+        (InterruptController::Apic, _) => apic::eoi(),
+    }
+}
+
+/// Common handler for all device vectors (32-253)
+pub fn irq_interrupt_handler(context: &mut InterruptContext) {
+    let vector = context.vector as u8;
+    let Ok(index) = slot_index(vector) else {
+        return hardware::unhandled_interrupt_handler(context);
+    };
+    let irq = pic::vector_irq(vector);
+    
+    if let Some(irq) = irq {
+        if controller() == InterruptController::Pic && pic::handle_spurious(irq) {
+            return;
+        }
+    }
+    
+    // Copy the handlers out so they run without the table locked
+    let actions = VECTORS.lock()[index].actions;
+    let mut registered = false;
+    let mut handled = false;
+    for action in actions.iter().flatten() {
+        registered = true;
+        if (action.handler)(context, action.context) == IrqReturn::Handled {
+            handled = true;
+        }
+    }
+    
+    match (registered, irq) {
+        (true, _) if !handled => {
+            UNCLAIMED.fetch_add(1, Ordering::Relaxed);
+        }
+        (true, _) => {}
+        (false, Some(irq)) => {
+            // Nothing will service the device, so keep the line quiet
+            eprintln!("Unexpected IRQ {}, masking it", irq);
+            mask_irq(irq);
+        }
+        (false, None) => hardware::unhandled_interrupt_handler(context),
+    }
+    
+    end_of_interrupt(vector);
+}
+
+/// Print every device vector that is allocated or has handlers
+/// 
+/// Shows the ISA IRQ, the number of interrupts delivered and the names of
+/// the handlers owning the vector.
+pub fn print_vectors() {
+    println!("Vector  IRQ  Count       Handlers");
+    
+    for index in 0..DEVICE_VECTOR_COUNT {
+        // Copy one slot at a time: the whole table is too large for the
+        // stack, and printing must not happen with the lock held
+        let slot = VECTORS.lock()[index];
+        if !slot.allocated && slot.is_empty() {
+            continue;
+        }
+        let vector = FIRST_DEVICE_VECTOR + index as u8;
+        let count = entry::interrupt_count(vector);
+        
+        match pic::vector_irq(vector) {
+            Some(irq) => print!("{:<7} {:<4} {:<11}", vector, irq, count),
+            None => print!("{:<7} -    {:<11}", vector, count),
+        }
+        
+        let mut names = slot.actions.iter().flatten().map(|action| action.name);
+        match names.next() {
+            Some(first) => print!(" {}", first),
+            None => print!(" (allocated)"),
+        }
+        for name in names {
+            print!(", {}", name);
+        }
+        if slot.sharing == IrqSharing::Exclusive && !slot.is_empty() {
+            print!(" (exclusive)");
+        }
+        println!();
+    }
+    
+    println!("Unclaimed interrupts: {}", unclaimed_count());
+}
@@ -4,8 +4,14 @@
 /// It's organized into separate submodules for better maintainability:
 /// 
 /// - `idt`: Core IDT data structures and management
+/// - `entry`: Assembly entry stubs and the Rust interrupt dispatcher
 /// - `exceptions`: CPU exception handlers (vectors 0-31)
 /// - `hardware`: Hardware interrupt handlers (vectors 32-255)
+/// - `pic`: 8259 PIC remapping, IRQ masking and end-of-interrupt
+/// - `apic`: Local APIC (xAPIC/x2APIC): EOI, spurious vector, timer, IPIs
+/// - `ioapic`: I/O APIC redirection table and ISA IRQ routing
+/// - `irq`: IRQ dispatch (spurious IRQ check, device handler, EOI) and
+///   masking through the active interrupt controller
 /// - `setup`: Interrupt system initialization and management
 /// 
 /// ## Usage
@@ -14,6 +20,10 @@
 /// // Initialize the interrupt system
 /// interrupts::setup_idt();
 /// 
+/// // Remap the PICs away from the exception vectors, then switch to the
+/// // APICs if available; all IRQs start masked
+/// interrupts::init_interrupt_controller();
+/// 
 /// // Enable interrupts (after setting up interrupt controllers)
 /// interrupts::enable_interrupts();
 /// 
@@ -27,9 +37,22 @@
 /// ```
 
 pub mod idt;
+pub mod entry;
 pub mod exceptions;
 pub mod hardware;
+pub mod irq;
+pub mod pic;
+pub mod apic;
+pub mod ioapic;
 pub mod setup;
 
+use crate::arch::x86_64::boot::cmdline::{BootOption, OptionKind};
+
 // Re-export the main public interface
-pub use setup::setup_idt;
+pub use setup::{setup_idt, init_interrupt_controller, enable_interrupts, print_idt};
+
+/// Boot options of the interrupt subsystem
+pub static BOOT_OPTIONS: [BootOption; 2] = [
+    BootOption::new("noapic", OptionKind::Flag, "Use the 8259 PICs instead of the APICs"),
+    BootOption::new("nox2apic", OptionKind::Flag, "Use the local APIC in xAPIC (memory-mapped) mode"),
+];
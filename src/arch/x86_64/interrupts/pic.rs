@@ -0,0 +1,227 @@
+/// 8259 Programmable Interrupt Controller
+/// 
+/// PCs have two 8259 PICs chained together: the master handles IRQs 0-7
+/// and the slave handles IRQs 8-15, cascaded through the master's IRQ 2.
+/// 
+/// The BIOS leaves the master on vectors 8-15, which overlap the CPU
+/// exceptions (a timer tick would look like a double fault). `init`
+/// reprograms both PICs so the IRQs arrive on vectors 32-47 instead:
+/// 
+/// ```text
+/// IRQ 0-7   (master) -> vectors 32-39
+/// IRQ 8-15  (slave)  -> vectors 40-47
+/// ```
+/// 
+/// ## End of interrupt
+/// 
+/// After delivering an IRQ the PIC blocks that line (and every lower
+/// priority line) until it receives an EOI command. IRQs from the slave
+/// need an EOI sent to both PICs.
+/// 
+/// ## Spurious interrupts
+/// 
+/// If an IRQ line drops before the PIC delivers its interrupt, the PIC
+/// delivers its lowest priority vector instead: IRQ 7 for the master and
+/// IRQ 15 for the slave. A spurious IRQ can be recognised by its bit not
+/// being set in the In-Service Register. It must not be acknowledged, except
+/// that a spurious IRQ 15 still needs an EOI for the cascade line on the
+/// master, which did deliver a real interrupt.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use crate::arch::x86_64::port::{inb, io_wait, outb};
+use crate::arch::x86_64::sync::SpinLock;
+
+/// Master PIC command port
+const PIC1_COMMAND: u16 = 0x20;
+/// Master PIC data port (interrupt mask)
+const PIC1_DATA: u16 = 0x21;
+/// Slave PIC command port
+const PIC2_COMMAND: u16 = 0xA0;
+/// Slave PIC data port (interrupt mask)
+const PIC2_DATA: u16 = 0xA1;
+
+/// ICW1: start initialization, ICW4 follows
+const ICW1_INIT: u8 = 0x11;
+/// ICW4: 8086 mode
+const ICW4_8086: u8 = 0x01;
+/// OCW2: non-specific end of interrupt
+const OCW2_EOI: u8 = 0x20;
+/// OCW3: read the In-Service Register on the next command port read
+const OCW3_READ_ISR: u8 = 0x0B;
+/// OCW3: read the Interrupt Request Register on the next command port read
+const OCW3_READ_IRR: u8 = 0x0A;
+
+/// Vector of IRQ 0 (first master IRQ)
+pub const PIC1_OFFSET: u8 = 32;
+/// Vector of IRQ 8 (first slave IRQ)
+pub const PIC2_OFFSET: u8 = PIC1_OFFSET + 8;
+
+/// Number of IRQ lines
+pub const IRQ_COUNT: u8 = 16;
+
+/// Master IRQ line the slave PIC is connected to
+pub const CASCADE_IRQ: u8 = 2;
+
+/// Number of spurious IRQs seen on IRQ 7 and IRQ 15
+static SPURIOUS_IRQS: [AtomicU64; 2] = [AtomicU64::new(0), AtomicU64::new(0)];
+
+/// Interrupt masks of both PICs (bit set = IRQ masked)
+/// 
+/// The masks are cached so that changing one IRQ does not need a port
+/// read, and the lock keeps the read-modify-write atomic.
+static MASKS: SpinLock<u16> = SpinLock::new(0xFFFF);
+
+/// Remap the PICs to vectors 32-47 and mask every IRQ
+/// 
+/// Only the cascade line stays unmasked; drivers unmask their IRQ with
+/// `unmask_irq` once their handler is ready.
+pub fn init() {
+    let mut masks = MASKS.lock();
+    
+    unsafe {
+        // ICW1: begin initialization in cascade mode
+        outb(PIC1_COMMAND, ICW1_INIT);
+        io_wait();
+        outb(PIC2_COMMAND, ICW1_INIT);
+        io_wait();
+        
+        // ICW2: vector offsets
+        outb(PIC1_DATA, PIC1_OFFSET);
+        io_wait();
+        outb(PIC2_DATA, PIC2_OFFSET);
+        io_wait();
+        
+        // ICW3: the slave is on the master's IRQ 2, and has cascade identity 2
+        outb(PIC1_DATA, 1 << CASCADE_IRQ);
+        io_wait();
+        outb(PIC2_DATA, CASCADE_IRQ);
+        io_wait();
+        
+        // ICW4: 8086 mode
+        outb(PIC1_DATA, ICW4_8086);
+        io_wait();
+        outb(PIC2_DATA, ICW4_8086);
+        io_wait();
+    }
+    
+    *masks = !(1 << CASCADE_IRQ);
+    write_masks(*masks);
+}
+
+/// Mask every IRQ on both PICs
+/// 
+/// Used when the PICs are replaced by another interrupt controller.
+pub fn disable() {
+    let mut masks = MASKS.lock();
+    *masks = 0xFFFF;
+    write_masks(*masks);
+}
+
+/// Write the interrupt masks of both PICs
+fn write_masks(masks: u16) {
+    unsafe {
+        outb(PIC1_DATA, masks as u8);
+        outb(PIC2_DATA, (masks >> 8) as u8);
+    }
+}
+
+/// Stop the PIC from delivering an IRQ
+pub fn mask_irq(irq: u8) {
+    assert!(irq < IRQ_COUNT, "invalid IRQ {}", irq);
+    
+    let mut masks = MASKS.lock();
+    *masks |= 1 << irq;
+    write_masks(*masks);
+}
+
+/// Allow the PIC to deliver an IRQ
+pub fn unmask_irq(irq: u8) {
+    assert!(irq < IRQ_COUNT, "invalid IRQ {}", irq);
+    
+    let mut masks = MASKS.lock();
+    *masks &= !(1 << irq);
+    write_masks(*masks);
+}
+
+/// Check whether an IRQ is masked
+pub fn is_masked(irq: u8) -> bool {
+    irq >= IRQ_COUNT || *MASKS.lock() & (1 << irq) != 0
+}
+
+/// Read the interrupt masks back from both PICs (bit set = IRQ masked)
+pub fn read_masks() -> u16 {
+    unsafe { inb(PIC1_DATA) as u16 | (inb(PIC2_DATA) as u16) << 8 }
+}
+
+/// Read a register of both PICs selected by an OCW3 command
+fn read_register(ocw3: u8) -> u16 {
+    unsafe {
+        outb(PIC1_COMMAND, ocw3);
+        outb(PIC2_COMMAND, ocw3);
+        inb(PIC1_COMMAND) as u16 | (inb(PIC2_COMMAND) as u16) << 8
+    }
+}
+
+/// In-Service Register of both PICs (IRQs being handled)
+pub fn in_service() -> u16 {
+    read_register(OCW3_READ_ISR)
+}
+
+/// Interrupt Request Register of both PICs (IRQs waiting to be delivered)
+pub fn requested() -> u16 {
+    read_register(OCW3_READ_IRR)
+}
+
+/// Vector an IRQ is delivered on
+pub const fn irq_vector(irq: u8) -> u8 {
+    PIC1_OFFSET + irq
+}
+
+/// IRQ delivered on a vector, if the vector belongs to the PICs
+pub const fn vector_irq(vector: u8) -> Option<u8> {
+    if vector >= PIC1_OFFSET && vector < PIC1_OFFSET + IRQ_COUNT {
+        Some(vector - PIC1_OFFSET)
+    } else {
+        None
+    }
+}
+
+/// Check for a spurious IRQ
+/// 
+/// Must be called at the start of handling IRQ 7 or IRQ 15. Returns true
+/// if the IRQ was spurious, in which case it has been dealt with and the
+/// handler must not run or send an EOI.
+pub fn handle_spurious(irq: u8) -> bool {
+    let index = match irq {
+        7 => 0,
+        15 => 1,
+        _ => return false,
+    };
+    
+    if in_service() & (1 << irq) != 0 {
+        return false;
+    }
+    
+    SPURIOUS_IRQS[index].fetch_add(1, Ordering::Relaxed);
+    
+    // The master did deliver the cascade interrupt for a spurious IRQ 15
+    if irq == 15 {
+        unsafe { outb(PIC1_COMMAND, OCW2_EOI) };
+    }
+    true
+}
+
+/// Number of spurious IRQs seen on IRQ 7 and IRQ 15
+pub fn spurious_counts() -> (u64, u64) {
+    (SPURIOUS_IRQS[0].load(Ordering::Relaxed), SPURIOUS_IRQS[1].load(Ordering::Relaxed))
+}
+
+/// Signal the end of an IRQ so the PIC can deliver it again
+pub fn end_of_interrupt(irq: u8) {
+    unsafe {
+        if irq >= 8 {
+            outb(PIC2_COMMAND, OCW2_EOI);
+        }
+        outb(PIC1_COMMAND, OCW2_EOI);
+    }
+}
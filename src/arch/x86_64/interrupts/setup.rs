@@ -3,13 +3,28 @@
 /// This module coordinates interrupt setup and provides the main
 /// interface for interrupt management.
 
-use super::idt::{Idt, GateType};
+use super::idt::{Idt, IdtEntry, GateType};
+use super::entry::{self, InterruptHandler};
 use super::exceptions;
 use super::hardware;
+use super::irq::{self, InterruptController};
+use super::{apic, ioapic, pic};
+use crate::arch::x86_64::{acpi, cpu};
+use crate::arch::x86_64::boot::cmdline;
+use crate::arch::x86_64::symbols::Symbolized;
+use crate::{print, println};
+use crate::arch::gdt::{
+    KERNEL_CODE_SELECTOR, DOUBLE_FAULT_IST_INDEX, NMI_IST_INDEX, MACHINE_CHECK_IST_INDEX,
+};
 
-/// Code segment selector for kernel code
-/// This assumes the GDT has kernel code segment at selector 0x08
-const KERNEL_CODE_SELECTOR: u16 = 0x08;
+/// Install a Rust handler for a vector
+/// 
+/// The IDT entry points at the assembly entry stub for the vector, which
+/// saves the CPU state and calls `handler` through the dispatcher.
+fn set_handler(idt: &mut Idt, vector: u8, handler: InterruptHandler, gate_type: GateType) -> &mut IdtEntry {
+    entry::set_handler(vector, handler);
+    idt.set_handler(vector, entry::stub_address(vector), KERNEL_CODE_SELECTOR, gate_type)
+}
 
 /// Initialize the IDT with all exception and interrupt handlers
 pub fn init_idt() -> Idt {
@@ -18,45 +33,107 @@ pub fn init_idt() -> Idt {
     // === CPU Exception Handlers (Vectors 0-31) ===
     
     // Vector 0: Divide by Zero Exception (#DE)
-    idt.set_handler(0, exceptions::divide_by_zero_handler as u64, KERNEL_CODE_SELECTOR, GateType::InterruptGate);
+    set_handler(&mut idt, 0, exceptions::divide_by_zero_handler, GateType::InterruptGate);
     
     // Vector 1: Debug Exception (#DB)
-    idt.set_handler(1, exceptions::debug_handler as u64, KERNEL_CODE_SELECTOR, GateType::InterruptGate);
+    set_handler(&mut idt, 1, exceptions::debug_handler, GateType::InterruptGate);
+    
+    // Vector 2: Non-Maskable Interrupt (NMI)
+    // Runs on its own stack: an NMI can arrive at any point, even mid-way
+    // through a stack switch
+    set_handler(&mut idt, 2, exceptions::nmi_handler, GateType::InterruptGate)
+        .set_stack_index(NMI_IST_INDEX);
     
     // Vector 3: Breakpoint Exception (#BP)
-    idt.set_handler(3, exceptions::breakpoint_handler as u64, KERNEL_CODE_SELECTOR, GateType::TrapGate);
+    // DPL 3 so that `int3` remains usable once user space exists
+    set_handler(&mut idt, 3, exceptions::breakpoint_handler, GateType::TrapGate)
+        .set_privilege_level(3);
+    
+    // Vector 4: Overflow Exception (#OF)
+    set_handler(&mut idt, 4, exceptions::overflow_handler, GateType::TrapGate);
+    
+    // Vector 5: Bound Range Exceeded Exception (#BR)
+    set_handler(&mut idt, 5, exceptions::bound_range_handler, GateType::InterruptGate);
     
     // Vector 6: Invalid Opcode Exception (#UD)
-    idt.set_handler(6, exceptions::invalid_opcode_handler as u64, KERNEL_CODE_SELECTOR, GateType::InterruptGate);
+    set_handler(&mut idt, 6, exceptions::invalid_opcode_handler, GateType::InterruptGate);
+    
+    // Vector 7: Device Not Available Exception (#NM)
+    set_handler(&mut idt, 7, exceptions::device_not_available_handler, GateType::InterruptGate);
     
     // Vector 8: Double Fault Exception (#DF)
-    idt.set_handler(8, exceptions::double_fault_handler as u64, KERNEL_CODE_SELECTOR, GateType::InterruptGate);
+    // Runs on its own stack so that a kernel stack overflow is reported
+    // instead of escalating to a triple fault
+    set_handler(&mut idt, 8, exceptions::double_fault_handler, GateType::InterruptGate)
+        .set_stack_index(DOUBLE_FAULT_IST_INDEX);
+    
+    // Vector 9: Coprocessor Segment Overrun (legacy)
+    set_handler(&mut idt, 9, exceptions::coprocessor_segment_overrun_handler, GateType::InterruptGate);
+    
+    // Vector 10: Invalid TSS Exception (#TS)
+    set_handler(&mut idt, 10, exceptions::invalid_tss_handler, GateType::InterruptGate);
+    
+    // Vector 11: Segment Not Present Exception (#NP)
+    set_handler(&mut idt, 11, exceptions::segment_not_present_handler, GateType::InterruptGate);
+    
+    // Vector 12: Stack-Segment Fault (#SS)
+    set_handler(&mut idt, 12, exceptions::stack_segment_fault_handler, GateType::InterruptGate);
     
     // Vector 13: General Protection Fault (#GP)
-    idt.set_handler(13, exceptions::general_protection_fault_handler as u64, KERNEL_CODE_SELECTOR, GateType::InterruptGate);
+    set_handler(&mut idt, 13, exceptions::general_protection_fault_handler, GateType::InterruptGate);
     
     // Vector 14: Page Fault Exception (#PF)
-    idt.set_handler(14, exceptions::page_fault_handler as u64, KERNEL_CODE_SELECTOR, GateType::InterruptGate);
+    set_handler(&mut idt, 14, exceptions::page_fault_handler, GateType::InterruptGate);
     
-    // === Hardware Interrupt Handlers (Vectors 32-255) ===
+    // Vector 16: x87 Floating-Point Exception (#MF)
+    set_handler(&mut idt, 16, exceptions::x87_floating_point_handler, GateType::InterruptGate);
     
-    // Vector 32: Timer (IRQ 0)
-    idt.set_handler(32, hardware::timer_interrupt_handler as u64, KERNEL_CODE_SELECTOR, GateType::InterruptGate);
+    // Vector 17: Alignment Check Exception (#AC)
+    set_handler(&mut idt, 17, exceptions::alignment_check_handler, GateType::InterruptGate);
     
-    // Vector 33: Keyboard (IRQ 1)
-    idt.set_handler(33, hardware::keyboard_interrupt_handler as u64, KERNEL_CODE_SELECTOR, GateType::InterruptGate);
+    // Vector 18: Machine Check Exception (#MC)
+    // Runs on its own stack since the interrupted state may be corrupt
+    set_handler(&mut idt, 18, exceptions::machine_check_handler, GateType::InterruptGate)
+        .set_stack_index(MACHINE_CHECK_IST_INDEX);
     
-    // Vector 36: Serial Port (IRQ 4)
-    idt.set_handler(36, hardware::serial_interrupt_handler as u64, KERNEL_CODE_SELECTOR, GateType::InterruptGate);
+    // Vector 19: SIMD Floating-Point Exception (#XM)
+    set_handler(&mut idt, 19, exceptions::simd_floating_point_handler, GateType::InterruptGate);
     
-    // Fill remaining vectors with unhandled interrupt handler
-    for vector in 32..=255_u8 {
-        // Skip vectors we've already set
-        if vector != 32 && vector != 33 && vector != 36 {
-            idt.set_handler(vector, hardware::unhandled_interrupt_handler as u64, KERNEL_CODE_SELECTOR, GateType::InterruptGate);
-        }
+    // Vector 20: Virtualization Exception (#VE)
+    set_handler(&mut idt, 20, exceptions::virtualization_handler, GateType::InterruptGate);
+    
+    // Vector 21: Control Protection Exception (#CP)
+    set_handler(&mut idt, 21, exceptions::control_protection_handler, GateType::InterruptGate);
+    
+    // Vector 28: Hypervisor Injection Exception (#HV)
+    set_handler(&mut idt, 28, exceptions::hypervisor_injection_handler, GateType::InterruptGate);
+    
+    // Vector 29: VMM Communication Exception (#VC)
+    set_handler(&mut idt, 29, exceptions::vmm_communication_handler, GateType::InterruptGate);
+    
+    // Vector 30: Security Exception (#SX)
+    set_handler(&mut idt, 30, exceptions::security_exception_handler, GateType::InterruptGate);
+    
+    // Reserved vectors: 15, 22-27 and 31
+    for vector in [15, 22, 23, 24, 25, 26, 27, 31] {
+        set_handler(&mut idt, vector, exceptions::reserved_exception_handler, GateType::InterruptGate);
+    }
+    
+    // === Hardware Interrupt Handlers (Vectors 32-255) ===
+    
+    // Vectors 32-253: ISA IRQs 0-15 and dynamically allocated vectors
+    // The IRQ dispatcher calls the handlers registered at run time through
+    // `irq::register_irq`/`irq::register_vector` and sends the EOI
+    for vector in irq::FIRST_DEVICE_VECTOR..=irq::LAST_DEVICE_VECTOR {
+        set_handler(&mut idt, vector, irq::irq_interrupt_handler, GateType::InterruptGate);
     }
     
+    // Vector 0xFE: local APIC internal errors
+    set_handler(&mut idt, apic::ERROR_VECTOR, hardware::apic_error_handler, GateType::InterruptGate);
+    
+    // Vector 0xFF: local APIC spurious interrupts (no EOI)
+    set_handler(&mut idt, apic::SPURIOUS_VECTOR, hardware::spurious_interrupt_handler, GateType::InterruptGate);
+    
     idt
 }
 
@@ -73,6 +150,110 @@ pub fn setup_idt() {
     }
 }
 
+/// Print the present IDT entries
+/// 
+/// Consecutive vectors with the same Rust handler and gate settings are
+/// shown as one range, with the interrupts delivered on all of them.
+pub fn print_idt() {
+    let Some(idt) = (unsafe { (*core::ptr::addr_of!(IDT)).as_ref() }) else {
+        println!("IDT not loaded");
+        return;
+    };
+    
+    // Settings that must match for vectors to share a line
+    let settings = |vector: usize| {
+        let entry = idt.entry(vector as u8);
+        let handler = entry::handler(vector as u8).map(|handler| handler as usize);
+        (entry.is_present(), entry.gate_type(), entry.stack_index(), entry.privilege_level(), handler)
+    };
+    
+    println!("Vector   Gate IST DPL Count       Handler");
+    let mut vector = 0;
+    while vector < 256 {
+        let first = vector;
+        let mut count = 0;
+        while vector < 256 && settings(vector) == settings(first) {
+            count += entry::interrupt_count(vector as u8);
+            vector += 1;
+        }
+        
+        let entry = idt.entry(first as u8);
+        if !entry.is_present() {
+            continue;
+        }
+        if vector - 1 > first {
+            print!("{:>3}-{:<4} ", first, vector - 1);
+        } else {
+            print!("{:>3}      ", first);
+        }
+        let gate = match entry.gate_type() {
+            Some(GateType::InterruptGate) => "int",
+            Some(GateType::TrapGate) => "trap",
+            None => "?",
+        };
+        print!("{:<4} {:<3} {:<3} {:<11} ", gate, entry.stack_index(), entry.privilege_level(), count);
+        match entry::handler(first as u8) {
+            Some(handler) => println!("{}", Symbolized(handler as usize as u64)),
+            None => println!("{:#018X}", entry.handler_address()),
+        }
+    }
+}
+
+/// Set up the interrupt controller that delivers the ISA IRQs
+/// 
+/// Unless the MADT says the machine has no 8259 PICs (its `PCAT_COMPAT`
+/// flag is clear), the PICs are remapped first, so that no IRQ can arrive
+/// on an exception vector. If the CPU has a local APIC, the MADT lists an
+/// I/O APIC and the `noapic` boot option is not set, the PICs are then
+/// masked and the APICs take over; the IRQs keep their vectors (32-47).
+/// 
+/// Must be called after `setup_idt`, after `acpi::init` and before
+/// `enable_interrupts`. All IRQs are masked on return.
+pub fn init_interrupt_controller() -> InterruptController {
+    let madt = acpi::madt();
+    // Without a MADT there is nothing to say the PICs are missing
+    let legacy_pics = madt.as_ref().is_none_or(|madt| madt.has_legacy_pics());
+    if legacy_pics {
+        pic::init();
+    }
+    
+    if cmdline::flag("noapic") || !cpu::has_apic() {
+        return InterruptController::Pic;
+    }
+    let Some(madt) = madt else {
+        return InterruptController::Pic;
+    };
+    if madt.io_apics().next().is_none() {
+        return InterruptController::Pic;
+    }
+    
+    // The PIC outputs are still wired to the I/O APIC, so mask them to get
+    // every IRQ exactly once
+    if legacy_pics {
+        pic::disable();
+    }
+    
+    // The I/O APIC goes first: if the local APIC then fails, it has not
+    // touched any register and the PICs still reach the CPU through LINT0
+    if let Err(error) = ioapic::init(&madt, cpu::initial_apic_id()) {
+        println!("Warning: could not set up the I/O APIC ({:?}), using the PICs", error);
+        if legacy_pics {
+            pic::init();
+        }
+        return InterruptController::Pic;
+    }
+    if let Err(error) = apic::init(&madt, !cmdline::flag("nox2apic")) {
+        println!("Warning: could not enable the local APIC ({:?}), using the PICs", error);
+        if legacy_pics {
+            pic::init();
+        }
+        return InterruptController::Pic;
+    }
+    
+    irq::set_controller(InterruptController::Apic);
+    InterruptController::Apic
+}
+
 /// Enable interrupts
 /// 
 /// This function enables hardware interrupts by setting the interrupt flag.
@@ -0,0 +1,460 @@
+/// Buddy allocator for physical memory
+/// 
+/// Free memory is kept as blocks of 2^order frames (order 0 to `MAX_ORDER`,
+/// so 4KB to 4MB), each aligned to its own size. An allocation takes a
+/// block of the smallest order that fits, splitting larger blocks in halves
+/// ("buddies") as needed; a freed block is merged with its buddy whenever
+/// that is free too, so memory coalesces back into large blocks.
+/// 
+/// ```text
+///     order 2   [           block           ]
+///     order 1   [    buddy    ][    buddy    ]
+///     order 0   [  0  ][  1  ][  2  ][  3  ]
+/// ```
+/// 
+/// Because blocks are aligned to their size, an allocation aligned to a
+/// power of two (for DMA or a 2MB huge page) only has to take a block of
+/// at least that order.
+/// 
+/// ## Metadata
+/// 
+/// Every order has a bitmap with a bit for every place a block of that
+/// order fits, set while a free block starts there: about two bits per
+/// frame over all orders, kept outside the frames themselves so memory that
+/// is not mapped can be managed too, and small enough for the identity
+/// mapping even with terabytes of memory. Above each bitmap are summary
+/// levels with a bit per non-zero word of the level below.
+/// 
+/// Bits are in address order, so the lowest free block of an order is
+/// found by following the lowest set bit from the top level down, one word
+/// per level: O(log n). Allocations take that block, handing out memory
+/// from the bottom up like the bitmap allocator does, so frames low in
+/// memory (reachable through the boot identity mapping) are used first and
+/// a limit on the address is easy to honour.
+
+use super::constants::PAGE_SIZE;
+use super::physical::{PhysicalAllocator, RegionList, FrameRegion, MAX_REGIONS};
+use core::mem;
+
+/// Largest block order (2^10 frames, 4MB)
+pub const MAX_ORDER: usize = 10;
+
+/// Number of block orders
+pub const ORDERS: usize = MAX_ORDER + 1;
+
+/// Most levels of an order's bitmap, enough for 2^42 bits
+const LEVELS: usize = 7;
+
+/// Bits in a bitmap word
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// Where the bitmap of one order lives in the metadata
+#[derive(Debug, Clone, Copy)]
+struct OrderBitmap {
+    /// Word offset of every level, level 0 having a bit per block
+    levels: [usize; LEVELS],
+    /// Number of levels, 0 if no block of the order fits anywhere
+    depth: usize,
+    /// Bit of the first block in every region
+    region_bits: [usize; MAX_REGIONS],
+}
+
+impl OrderBitmap {
+    const EMPTY: Self = OrderBitmap { levels: [0; LEVELS], depth: 0, region_bits: [0; MAX_REGIONS] };
+}
+
+/// Aligned blocks of `order` that fit in a region, as the first block's
+/// frame and the number of blocks
+fn region_blocks(region: &FrameRegion, order: usize) -> (usize, usize) {
+    let first = region.start_frame.next_multiple_of(1 << order);
+    let end = region.end_frame() & !((1 << order) - 1);
+    (first, end.saturating_sub(first) >> order)
+}
+
+/// Bitmaps of all orders for the regions, and the words they take
+fn bitmap_layout(regions: &RegionList) -> ([OrderBitmap; ORDERS], usize) {
+    let mut bitmaps = [OrderBitmap::EMPTY; ORDERS];
+    let mut words = 0;
+    for (order, bitmap) in bitmaps.iter_mut().enumerate() {
+        let mut bits = 0;
+        for (index, region) in regions.as_slice().iter().enumerate() {
+            bitmap.region_bits[index] = bits;
+            bits += region_blocks(region, order).1;
+        }
+        
+        // Each level has a bit per word of the one below, up to one word
+        while bits > 0 && bitmap.depth < LEVELS {
+            bitmap.levels[bitmap.depth] = words;
+            bitmap.depth += 1;
+            bits = bits.div_ceil(WORD_BITS);
+            words += bits;
+            if bits == 1 {
+                break;
+            }
+        }
+    }
+    (bitmaps, words)
+}
+
+/// Statistics of one block order
+#[derive(Debug, Clone, Copy)]
+pub struct OrderStats {
+    /// Free blocks
+    pub free_blocks: usize,
+    /// Allocations served by a block of this order
+    pub allocations: u64,
+    /// Blocks of this order split in two
+    pub splits: u64,
+    /// Blocks of this order merged with their buddy
+    pub merges: u64,
+}
+
+impl OrderStats {
+    const fn new() -> Self {
+        OrderStats { free_blocks: 0, allocations: 0, splits: 0, merges: 0 }
+    }
+}
+
+/// Buddy allocator for physical memory frames
+pub struct BuddyAllocator {
+    regions: RegionList,
+    /// Words of the bitmaps of all orders
+    words: &'static mut [u64],
+    bitmaps: [OrderBitmap; ORDERS],
+    stats: [OrderStats; ORDERS],
+    free_frames: usize,
+}
+
+impl BuddyAllocator {
+    /// Create a new uninitialized allocator
+    /// 
+    /// The allocator must be initialized with `init()` before use.
+    pub const fn new() -> Self {
+        Self {
+            regions: RegionList::new(),
+            words: &mut [],
+            bitmaps: [OrderBitmap::EMPTY; ORDERS],
+            stats: [OrderStats::new(); ORDERS],
+            free_frames: 0,
+        }
+    }
+    
+    /// Statistics of every order
+    pub fn order_stats(&self) -> [OrderStats; ORDERS] {
+        self.stats
+    }
+    
+    /// Smallest order whose blocks hold `count` frames
+    fn order_for(count: usize) -> usize {
+        count.next_power_of_two().trailing_zeros() as usize
+    }
+    
+    /// Bit of the block of `order` starting at `frame`, if such a block fits
+    /// in a region
+    fn block_bit(&self, frame: usize, order: usize) -> Option<usize> {
+        let regions = self.regions.as_slice();
+        let index = regions.iter().position(|region| region.contains(frame))?;
+        let (first, blocks) = region_blocks(&regions[index], order);
+        let block = frame.checked_sub(first)? >> order;
+        let aligned = frame.is_multiple_of(1 << order);
+        (aligned && block < blocks).then(|| self.bitmaps[order].region_bits[index] + block)
+    }
+    
+    /// First frame of the block of `order` with bit `bit`
+    fn block_frame(&self, order: usize, bit: usize) -> Option<usize> {
+        let bitmap = &self.bitmaps[order];
+        self.regions.as_slice().iter().enumerate().find_map(|(index, region)| {
+            let (first, blocks) = region_blocks(region, order);
+            let block = bit.checked_sub(bitmap.region_bits[index])?;
+            (block < blocks).then_some(first + (block << order))
+        })
+    }
+    
+    /// Set or clear a bit of an order's bitmap, keeping the summary levels
+    /// up to date
+    fn set_bit(&mut self, order: usize, mut bit: usize, value: bool) {
+        let bitmap = self.bitmaps[order];
+        for &level in &bitmap.levels[..bitmap.depth] {
+            let word = &mut self.words[level + bit / WORD_BITS];
+            let was_empty = *word == 0;
+            if value {
+                *word |= 1 << (bit % WORD_BITS);
+            } else {
+                *word &= !(1 << (bit % WORD_BITS));
+            }
+            // A summary bit only changes with the emptiness of its word
+            if was_empty == (*word == 0) {
+                break;
+            }
+            bit /= WORD_BITS;
+        }
+    }
+    
+    fn test_bit(&self, order: usize, bit: usize) -> bool {
+        let word = self.words[self.bitmaps[order].levels[0] + bit / WORD_BITS];
+        word & (1 << (bit % WORD_BITS)) != 0
+    }
+    
+    /// Lowest set bit of an order's bitmap
+    fn lowest_bit(&self, order: usize) -> Option<usize> {
+        let bitmap = &self.bitmaps[order];
+        let mut bit = 0;
+        for &level in bitmap.levels[..bitmap.depth].iter().rev() {
+            let word = self.words[level + bit];
+            if word == 0 {
+                return None;
+            }
+            bit = bit * WORD_BITS + word.trailing_zeros() as usize;
+        }
+        (bitmap.depth > 0).then_some(bit)
+    }
+    
+    /// Whether any bit in `[start, end)` of an order's bitmap is set
+    fn any_bit(&self, order: usize, start: usize, end: usize) -> bool {
+        let level = self.bitmaps[order].levels[0];
+        let mut bit = start;
+        while bit < end {
+            let offset = bit % WORD_BITS;
+            let width = (WORD_BITS - offset).min(end - bit);
+            let mask = (u64::MAX >> (WORD_BITS - width)) << offset;
+            if self.words[level + bit / WORD_BITS] & mask != 0 {
+                return true;
+            }
+            bit += width;
+        }
+        false
+    }
+    
+    /// Whether a free block of `order` starts at `frame`
+    fn is_free_block(&self, frame: usize, order: usize) -> bool {
+        self.block_bit(frame, order).is_some_and(|bit| self.test_bit(order, bit))
+    }
+    
+    /// Whether any of the frames `[frame, frame + count)`, which lie in one
+    /// region, is free
+    fn any_free(&self, frame: usize, count: usize) -> bool {
+        let regions = self.regions.as_slice();
+        let Some(index) = regions.iter().position(|region| region.contains(frame)) else {
+            return false;
+        };
+        
+        // Every free block starting between the frame's own block of an
+        // order and the end of the range overlaps the range
+        (0..ORDERS).any(|order| {
+            let (first, blocks) = region_blocks(&regions[index], order);
+            let from = (frame & !((1 << order) - 1)).saturating_sub(first).div_ceil(1 << order);
+            let to = (frame + count).saturating_sub(first).div_ceil(1 << order).min(blocks);
+            let base = self.bitmaps[order].region_bits[index];
+            from < to && self.any_bit(order, base + from, base + to)
+        })
+    }
+    
+    /// Mark the block of `order` at `frame` free
+    fn push(&mut self, frame: usize, order: usize) {
+        let Some(bit) = self.block_bit(frame, order) else {
+            return;
+        };
+        self.set_bit(order, bit, true);
+        self.stats[order].free_blocks += 1;
+    }
+    
+    /// Mark the free block of `order` at `frame` in use
+    fn unlink(&mut self, frame: usize, order: usize) {
+        let Some(bit) = self.block_bit(frame, order) else {
+            return;
+        };
+        self.set_bit(order, bit, false);
+        self.stats[order].free_blocks -= 1;
+    }
+    
+    /// Free the block of `order` at `frame`, merging it with its buddies
+    fn free_block(&mut self, mut frame: usize, mut order: usize) {
+        while order < MAX_ORDER {
+            let buddy = frame ^ (1 << order);
+            // The buddy must be free as a whole and in the same region
+            let same_region = self.regions.find(frame).is_some_and(|region| region.contains(buddy));
+            if !same_region || !self.is_free_block(buddy, order) {
+                break;
+            }
+            self.unlink(buddy, order);
+            self.stats[order].merges += 1;
+            frame = frame.min(buddy);
+            order += 1;
+        }
+        self.push(frame, order);
+    }
+    
+    /// Free block containing `frame`, as its first frame and order
+    fn containing_free_block(&self, frame: usize) -> Option<(usize, usize)> {
+        let region = self.regions.find(frame)?;
+        (0..ORDERS)
+            .map(|order| (frame & !((1 << order) - 1), order))
+            .take_while(|&(start, _)| start >= region.start_frame)
+            .find(|&(start, order)| self.is_free_block(start, order))
+    }
+    
+    /// Take the single frame `frame` out of the free block holding it
+    /// 
+    /// Returns false if the frame is not free.
+    fn take_frame(&mut self, frame: usize) -> bool {
+        let Some((mut start, mut order)) = self.containing_free_block(frame) else {
+            return false;
+        };
+        self.unlink(start, order);
+        
+        // Give back the halves that do not hold the frame
+        while order > 0 {
+            self.stats[order].splits += 1;
+            order -= 1;
+            let half = 1 << order;
+            if frame < start + half {
+                self.push(start + half, order);
+            } else {
+                self.push(start, order);
+                start += half;
+            }
+        }
+        self.free_frames -= 1;
+        true
+    }
+    
+    /// Free the frames `[frame, frame + count)` as the largest aligned blocks
+    /// that fit
+    fn free_range(&mut self, mut frame: usize, mut count: usize) {
+        while count > 0 {
+            let order = (frame.trailing_zeros() as usize)
+                .min(count.ilog2() as usize)
+                .min(MAX_ORDER);
+            self.free_block(frame, order);
+            frame += 1 << order;
+            count -= 1 << order;
+        }
+    }
+}
+
+impl PhysicalAllocator for BuddyAllocator {
+    fn regions(&self) -> &RegionList {
+        &self.regions
+    }
+    
+    fn regions_mut(&mut self) -> &mut RegionList {
+        &mut self.regions
+    }
+    
+    fn metadata_size(&self) -> usize {
+        // Room to align the words, which storage for tests may not be
+        bitmap_layout(&self.regions).1 * mem::size_of::<u64>() + mem::align_of::<u64>() - 1
+    }
+    
+    unsafe fn set_metadata(&mut self, metadata: &'static mut [u8]) {
+        let (bitmaps, total_words) = bitmap_layout(&self.regions);
+        let (_, words, _) = metadata.align_to_mut::<u64>();
+        words[..total_words].fill(0);
+        self.words = &mut words[..total_words];
+        self.bitmaps = bitmaps;
+        self.stats = [OrderStats::new(); ORDERS];
+        
+        // Cut every region into the largest aligned blocks that fit
+        for index in 0..self.regions.as_slice().len() {
+            let region = self.regions.as_slice()[index];
+            self.free_range(region.start_frame, region.frames);
+        }
+        // Setting up is not merging
+        for stats in self.stats.iter_mut() {
+            stats.merges = 0;
+        }
+        self.free_frames = self.regions.total_frames();
+    }
+    
+    fn metadata_range(&self) -> (usize, usize) {
+        (self.words.as_ptr() as usize, mem::size_of_val(self.words))
+    }
+    
+    fn mark_region_reserved(&mut self, start: usize, end: usize) {
+        let start_frame = start / PAGE_SIZE;
+        let end_frame = end.div_ceil(PAGE_SIZE);
+        
+        for index in 0..self.regions.as_slice().len() {
+            let region = self.regions.as_slice()[index];
+            let first = start_frame.max(region.start_frame);
+            let last = end_frame.min(region.end_frame());
+            for frame in first..last {
+                self.take_frame(frame);
+            }
+        }
+    }
+    
+    fn allocate_frame(&mut self) -> Option<usize> {
+        self.allocate_frames(1, PAGE_SIZE)
+    }
+    
+    fn allocate_frames_below(&mut self, count: usize, align: usize, limit: usize) -> Option<usize> {
+        if count == 0 {
+            return None;
+        }
+        
+        let order = Self::order_for(count).max(Self::order_for(align / PAGE_SIZE));
+        if order > MAX_ORDER {
+            return None;
+        }
+        
+        // Lowest block of the smallest order that is large enough, split
+        // down to the order. Only the first `count` frames of the block are
+        // kept, so only they have to lie below the limit; if the lowest
+        // block of an order does not, no block of that order does.
+        let (mut block_order, frame) = (order..ORDERS)
+            .filter_map(|order| Some((order, self.block_frame(order, self.lowest_bit(order)?)?)))
+            .find(|&(_, frame)| (frame + count) * PAGE_SIZE <= limit)?;
+        self.unlink(frame, block_order);
+        while block_order > order {
+            self.stats[block_order].splits += 1;
+            block_order -= 1;
+            self.push(frame + (1 << block_order), block_order);
+        }
+        self.stats[order].allocations += 1;
+        
+        // Give back the frames past the end of a request that is not a
+        // power of two
+        let size = 1 << order;
+        self.free_range(frame + count, size - count);
+        self.free_frames -= count;
+        Some(frame * PAGE_SIZE)
+    }
+    
+    unsafe fn free_frames(&mut self, phys_addr: usize, count: usize) {
+        let mut frame = phys_addr / PAGE_SIZE;
+        let end = frame + count;
+        
+        // Free the largest aligned blocks that fit. A block holding frames
+        // that are free already is split until its halves hold none, so a
+        // double free, even of part of a block, frees only the frames in use
+        while frame < end {
+            let Some(region_end) = self.regions.find(frame).map(|region| region.end_frame()) else {
+                frame += 1;
+                continue;
+            };
+            let mut order = (frame.trailing_zeros() as usize)
+                .min((end - frame).ilog2() as usize)
+                .min(MAX_ORDER);
+            while frame + (1 << order) > region_end {
+                order -= 1;
+            }
+            loop {
+                if !self.any_free(frame, 1 << order) {
+                    self.free_block(frame, order);
+                    self.free_frames += 1 << order;
+                    break;
+                }
+                if order == 0 {
+                    break;
+                }
+                order -= 1;
+            }
+            frame += 1 << order;
+        }
+    }
+    
+    fn get_free_frames(&self) -> usize {
+        self.free_frames
+    }
+}
@@ -3,8 +3,15 @@
 /// This trait provides an interface between the virtual memory mapper
 /// and the physical memory allocator, allowing the mapper to allocate
 /// physical frames for new page tables.
+/// 
+/// `PhysicalFrameAllocator` hands out frames from the kernel's physical
+/// allocator. `BitmapAllocator` and `BuddyAllocator` implement the trait
+/// themselves too, so separate instances of both can be compared.
 
+use super::buddy::BuddyAllocator;
+use super::constants::PAGE_SIZE;
 use super::paging::{PhysFrame, PhysAddr};
+use super::physical::{BitmapAllocator, PhysicalAllocator};
 
 /// Result type for frame allocation
 pub type FrameAllocResult = Result<PhysFrame, FrameAllocError>;
@@ -34,19 +41,57 @@ pub trait FrameAllocator {
     /// The frame must have been previously allocated by this allocator
     /// and must not be in use anymore.
     unsafe fn deallocate_frame(&mut self, frame: PhysFrame);
+
+    /// Allocate `count` physically contiguous frames, the first one aligned
+    /// to `align` bytes
+    /// 
+    /// Returns the first frame. The default implementation can only
+    /// allocate a single frame.
+    fn allocate_frames(&mut self, count: usize, align: usize) -> FrameAllocResult {
+        if count == 1 && align <= PAGE_SIZE {
+            self.allocate_frame()
+        } else {
+            Err(FrameAllocError::OutOfMemory)
+        }
+    }
+
+    /// Deallocate `count` contiguous frames starting at `first`
+    /// 
+    /// # Safety
+    /// The frames must have been previously allocated by this allocator
+    /// and must not be in use anymore.
+    unsafe fn deallocate_frames(&mut self, first: PhysFrame, count: usize) {
+        let start = first.start_address().as_u64();
+        for i in 0..count as u64 {
+            let addr = PhysAddr::new(start + i * PAGE_SIZE as u64);
+            self.deallocate_frame(PhysFrame::containing_address(addr));
+        }
+    }
 }
 
-/// Frame allocator implementation that wraps our bitmap allocator
-pub struct BitmapFrameAllocator;
+/// Frame at a physical address returned by a `PhysicalAllocator`
+fn to_frame(addr: Option<usize>) -> FrameAllocResult {
+    match addr {
+        Some(addr) => Ok(PhysFrame::containing_address(PhysAddr::new(addr as u64))),
+        None => Err(FrameAllocError::OutOfMemory),
+    }
+}
 
-impl BitmapFrameAllocator {
+/// Frame allocator implementation that wraps the kernel's physical
+/// allocator (bitmap or buddy, chosen at build time)
+/// 
+/// Its frames lie inside the boot identity mapping, where the mapper
+/// writes the page tables it allocates.
+pub struct PhysicalFrameAllocator;
+
+impl PhysicalFrameAllocator {
     /// Create a new bitmap frame allocator
     pub const fn new() -> Self {
         Self
     }
 }
 
-impl FrameAllocator for BitmapFrameAllocator {
+impl FrameAllocator for PhysicalFrameAllocator {
     fn allocate_frame(&mut self) -> FrameAllocResult {
         use super::physical;
         
@@ -66,6 +111,50 @@ impl FrameAllocator for BitmapFrameAllocator {
         let addr = frame.start_address().as_u64() as usize;
         physical::free_frame(addr);
     }
+
+    fn allocate_frames(&mut self, count: usize, align: usize) -> FrameAllocResult {
+        to_frame(super::physical::allocate_frames_aligned(count, align))
+    }
+
+    unsafe fn deallocate_frames(&mut self, first: PhysFrame, count: usize) {
+        super::physical::free_frames(first.start_address().as_u64() as usize, count);
+    }
+}
+
+impl FrameAllocator for BitmapAllocator {
+    fn allocate_frame(&mut self) -> FrameAllocResult {
+        to_frame(PhysicalAllocator::allocate_frame(self))
+    }
+
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.free_frame(frame.start_address().as_u64() as usize);
+    }
+
+    fn allocate_frames(&mut self, count: usize, align: usize) -> FrameAllocResult {
+        to_frame(PhysicalAllocator::allocate_frames(self, count, align))
+    }
+
+    unsafe fn deallocate_frames(&mut self, first: PhysFrame, count: usize) {
+        self.free_frames(first.start_address().as_u64() as usize, count);
+    }
+}
+
+impl FrameAllocator for BuddyAllocator {
+    fn allocate_frame(&mut self) -> FrameAllocResult {
+        to_frame(PhysicalAllocator::allocate_frame(self))
+    }
+
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.free_frame(frame.start_address().as_u64() as usize);
+    }
+
+    fn allocate_frames(&mut self, count: usize, align: usize) -> FrameAllocResult {
+        to_frame(PhysicalAllocator::allocate_frames(self, count, align))
+    }
+
+    unsafe fn deallocate_frames(&mut self, first: PhysFrame, count: usize) {
+        self.free_frames(first.start_address().as_u64() as usize, count);
+    }
 }
 
 /// Empty frame allocator for testing
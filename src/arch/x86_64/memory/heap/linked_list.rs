@@ -0,0 +1,153 @@
+/// Linked-list heap allocator
+/// 
+/// Free memory is kept in a list of blocks sorted by address. Allocation
+/// takes the first block that fits, and splits off what is left in front
+/// of and behind the allocation as new free blocks. Freed blocks are
+/// merged with their neighbours, so the heap does not fragment into ever
+/// smaller pieces.
+/// 
+/// Each free block stores its size and the next block in its first 16
+/// bytes, so block addresses and sizes are kept at multiples of 16 and
+/// every allocation takes at least 16 bytes.
+
+use core::alloc::Layout;
+use core::mem;
+use core::ptr::{self, NonNull};
+use super::HeapAllocator;
+
+/// Header at the start of every free block
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+/// Granularity of block addresses and sizes
+const BLOCK_ALIGN: usize = 16;
+
+const _: () = assert!(mem::size_of::<FreeBlock>() <= BLOCK_ALIGN);
+
+/// First-fit allocator over a sorted list of free blocks
+pub struct LinkedListAllocator {
+    head: *mut FreeBlock,
+}
+
+unsafe impl Send for LinkedListAllocator {}
+
+impl LinkedListAllocator {
+    pub const fn new() -> Self {
+        LinkedListAllocator { head: ptr::null_mut() }
+    }
+    
+    /// Size in bytes of the block an allocation of `layout` takes
+    fn block_size(layout: Layout) -> usize {
+        layout.size().max(1).next_multiple_of(BLOCK_ALIGN)
+    }
+    
+    /// Bytes in the free list
+    pub fn free_bytes(&self) -> usize {
+        let mut total = 0;
+        let mut block = self.head;
+        while !block.is_null() {
+            unsafe {
+                total += (*block).size;
+                block = (*block).next;
+            }
+        }
+        total
+    }
+    
+    /// Number of blocks in the free list
+    pub fn free_blocks(&self) -> usize {
+        let mut count = 0;
+        let mut block = self.head;
+        while !block.is_null() {
+            count += 1;
+            block = unsafe { (*block).next };
+        }
+        count
+    }
+    
+    /// Insert the free block `[addr, addr + size)` into the list, merging
+    /// it with the blocks on either side if they touch it
+    /// 
+    /// # Safety
+    /// The memory must be unused, writable and not overlap any free block.
+    /// `addr` and `size` must be multiples of `BLOCK_ALIGN`.
+    unsafe fn insert(&mut self, addr: usize, size: usize) {
+        // Find the blocks before and after the new one
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            next = (*next).next;
+        }
+        
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next });
+        
+        if !next.is_null() && addr + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+        
+        if prev.is_null() {
+            self.head = block;
+        } else if prev as usize + (*prev).size == addr {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+}
+
+impl HeapAllocator for LinkedListAllocator {
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let size = Self::block_size(layout);
+        let align = layout.align().max(BLOCK_ALIGN);
+        
+        let mut prev: *mut FreeBlock = ptr::null_mut();
+        let mut block = self.head;
+        while !block.is_null() {
+            let (start, block_size, next) = unsafe { (block as usize, (*block).size, (*block).next) };
+            let end = start + block_size;
+            let alloc_start = start.next_multiple_of(align);
+            
+            if alloc_start.checked_add(size).is_some_and(|alloc_end| alloc_end <= end) {
+                let alloc_end = alloc_start + size;
+                
+                // Unlink the block, then give back the space around the allocation
+                unsafe {
+                    if prev.is_null() {
+                        self.head = next;
+                    } else {
+                        (*prev).next = next;
+                    }
+                    if alloc_end < end {
+                        self.insert(alloc_end, end - alloc_end);
+                    }
+                    if alloc_start > start {
+                        self.insert(start, alloc_start - start);
+                    }
+                }
+                return NonNull::new(alloc_start as *mut u8);
+            }
+            
+            prev = block;
+            block = next;
+        }
+        None
+    }
+    
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        self.insert(ptr.as_ptr() as usize, Self::block_size(layout));
+    }
+    
+    unsafe fn add_region(&mut self, start: usize, size: usize) {
+        let aligned = start.next_multiple_of(BLOCK_ALIGN);
+        let end = (start + size) & !(BLOCK_ALIGN - 1);
+        if end > aligned {
+            self.insert(aligned, end - aligned);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+/// Kernel heap
+/// 
+/// The heap lives in its own virtual region (`KERNEL_HEAP_START` -
+/// `KERNEL_HEAP_END`). `init` maps the first `INITIAL_HEAP_SIZE` bytes;
+/// when an allocation does not fit, more pages are mapped at the end of
+/// the heap with `Mapper::map` and handed to the allocator, so the heap
+/// grows on demand until physical memory runs out.
+/// 
+/// ```text
+///     KERNEL_HEAP_START               heap end               KERNEL_HEAP_END
+///     +-------------------------------+----------------------------+
+///     |        mapped, in use         |         not mapped         |
+///     +-------------------------------+----------------------------+
+/// ```
+/// 
+/// `KernelHeap` is the `#[global_allocator]`, so `alloc::vec::Vec`,
+/// `Box`, `String` and the `alloc` collections work anywhere in the
+/// kernel. Allocations of up to 1 KB come from the `kmalloc-*` slab
+/// caches; the heap takes larger ones and those the caches cannot serve.
+/// The allocator behind the heap is chosen at build time:
+/// - `LinkedListAllocator`: first-fit free list (default)
+/// - `SizeClassAllocator`: free lists per size class (`heap-size-class` feature)
+/// 
+/// `#[alloc_error_handler]` is not available on stable Rust, so a failed
+/// allocation is reported here, with its layout and the heap usage, before
+/// null is returned; the standard handler then panics.
+
+pub mod linked_list;
+pub mod size_class;
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use crate::arch::x86_64::sync::SpinLock;
+use crate::arch::ByteSize;
+use crate::eprintln;
+use super::constants::{KERNEL_HEAP_END, KERNEL_HEAP_START, PAGE_SIZE};
+use super::mapper::{MapError, MapResult};
+use super::paging::{Page, PageTableFlags, VirtAddr};
+use super::slab;
+use super::{PhysicalFrameAllocator, FrameAllocator};
+
+pub use linked_list::LinkedListAllocator;
+pub use size_class::SizeClassAllocator;
+
+/// Heap mapped by `init`
+pub const INITIAL_HEAP_SIZE: usize = 256 * 1024;
+
+/// Smallest amount the heap grows by
+pub const HEAP_GROW_SIZE: usize = 64 * 1024;
+
+/// Interface between `KernelHeap` and the allocator managing its memory
+pub trait HeapAllocator {
+    /// Allocate a block for `layout`, or return None if no free block fits
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>>;
+    
+    /// Return a block to the allocator
+    /// 
+    /// # Safety
+    /// The block must have been returned by `allocate` with the same layout
+    /// and must not be used afterwards.
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout);
+    
+    /// Hand the allocator a region of free memory
+    /// 
+    /// # Safety
+    /// The region must be mapped, writable and used by nothing else.
+    unsafe fn add_region(&mut self, start: usize, size: usize);
+}
+
+#[cfg(not(feature = "heap-size-class"))]
+type Allocator = LinkedListAllocator;
+#[cfg(feature = "heap-size-class")]
+type Allocator = SizeClassAllocator;
+
+/// Name of the allocator selected at build time
+#[cfg(not(feature = "heap-size-class"))]
+pub const ALLOCATOR_NAME: &str = "linked list";
+#[cfg(feature = "heap-size-class")]
+pub const ALLOCATOR_NAME: &str = "size classes";
+
+/// Heap usage (allocations served by the slab caches are not included)
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    /// Bytes mapped for the heap
+    pub size: usize,
+    /// Bytes requested by live allocations
+    pub used: usize,
+    /// Number of live allocations
+    pub allocations: usize,
+    /// Allocations that failed because the heap could not grow
+    pub failures: usize,
+}
+
+struct Heap {
+    allocator: Allocator,
+    /// End of the mapped part of the heap region
+    end: u64,
+    stats: HeapStats,
+}
+
+impl Heap {
+    /// Map at least `min_size` more bytes at the end of the heap and add
+    /// them to the allocator
+    fn grow(&mut self, min_size: usize) -> MapResult<()> {
+        let size = min_size.max(HEAP_GROW_SIZE).next_multiple_of(PAGE_SIZE);
+        let start = self.end;
+        if (KERNEL_HEAP_END - start) < size as u64 {
+            // The region is exhausted; treat it like running out of memory
+            return Err(MapError::FrameAllocationFailed);
+        }
+        
+        let flags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE);
+        let mut mapper = unsafe { super::active_mapper() };
+        for offset in (0..size).step_by(PAGE_SIZE) {
+            let page = Page::containing_address(VirtAddr::new(start + offset as u64));
+            
+            if let Err(e) = mapper.map(page, flags) {
+                // Release the pages that were already mapped
+                for offset in (0..offset).step_by(PAGE_SIZE) {
+                    let page = Page::containing_address(VirtAddr::new(start + offset as u64));
+                    if let Ok(frame) = mapper.unmap(page) {
+                        unsafe { PhysicalFrameAllocator::new().deallocate_frame(frame); }
+                    }
+                }
+                return Err(e);
+            }
+        }
+        
+        unsafe {
+            self.allocator.add_region(start as usize, size);
+        }
+        self.end += size as u64;
+        self.stats.size += size;
+        Ok(())
+    }
+}
+
+/// The kernel's global allocator
+pub struct KernelHeap {
+    heap: SpinLock<Heap>,
+}
+
+impl KernelHeap {
+    const fn new() -> Self {
+        KernelHeap {
+            heap: SpinLock::new(Heap {
+                allocator: Allocator::new(),
+                end: KERNEL_HEAP_START,
+                stats: HeapStats { size: 0, used: 0, allocations: 0, failures: 0 },
+            }),
+        }
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if let Some(object) = slab::kmalloc_cache(layout).and_then(|cache| cache.alloc()) {
+            return object.as_ptr();
+        }
+        
+        let mut heap = self.heap.lock();
+        let block = heap.allocator.allocate(layout).or_else(|| {
+            // A fresh region always has room for the block and its alignment.
+            // If no frame is left, give back the empty slabs and try again.
+            let size = layout.size() + layout.align();
+            let grown = heap.grow(size).is_ok() || (slab::shrink_all() > 0 && heap.grow(size).is_ok());
+            if grown {
+                heap.allocator.allocate(layout)
+            } else {
+                None
+            }
+        });
+        
+        match block {
+            Some(block) => {
+                heap.stats.used += layout.size();
+                heap.stats.allocations += 1;
+                block.as_ptr()
+            }
+            None => {
+                heap.stats.failures += 1;
+                let stats = heap.stats;
+                drop(heap);
+                alloc_error(layout, &stats);
+                ptr::null_mut()
+            }
+        }
+    }
+    
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let Some(block) = NonNull::new(ptr) else {
+            return;
+        };
+        if !(KERNEL_HEAP_START..KERNEL_HEAP_END).contains(&(ptr as u64)) {
+            if let Some(cache) = slab::kmalloc_cache(layout) {
+                cache.free(block);
+            }
+            return;
+        }
+        
+        let mut heap = self.heap.lock();
+        heap.allocator.deallocate(block, layout);
+        heap.stats.used -= layout.size();
+        heap.stats.allocations -= 1;
+    }
+}
+
+#[global_allocator]
+static KERNEL_HEAP: KernelHeap = KernelHeap::new();
+
+/// Report an allocation the heap could not satisfy
+fn alloc_error(layout: Layout, stats: &HeapStats) {
+    eprintln!(
+        "Kernel heap: allocation of {} bytes (align {}) failed",
+        layout.size(),
+        layout.align()
+    );
+    eprintln!(
+        "  Heap size {}, {} in use by {} allocations",
+        ByteSize(stats.size as u64),
+        ByteSize(stats.used as u64),
+        stats.allocations
+    );
+}
+
+/// Map the initial heap
+/// 
+/// Requires the physical memory allocator. Allocating before `init` also
+/// works, since the heap grows on the first allocation.
+pub fn init() -> MapResult<()> {
+    let mut heap = KERNEL_HEAP.heap.lock();
+    if heap.stats.size >= INITIAL_HEAP_SIZE {
+        return Ok(());
+    }
+    let missing = INITIAL_HEAP_SIZE - heap.stats.size;
+    heap.grow(missing)
+}
+
+/// Current heap usage
+pub fn heap_stats() -> HeapStats {
+    KERNEL_HEAP.heap.lock().stats
+}
@@ -0,0 +1,103 @@
+/// Size-class heap allocator
+/// 
+/// Small allocations are rounded up to one of a few power-of-two size
+/// classes, and each class keeps its own list of free blocks. Allocating
+/// and freeing a small block only pops or pushes a list, so both take
+/// constant time. When a class runs out, a page is taken from a
+/// `LinkedListAllocator` and cut into blocks of that class.
+/// 
+/// Allocations larger than the biggest class go straight to the
+/// linked-list allocator. Blocks of a size class are never handed back to
+/// it, so memory used for one class is not reused for another.
+
+use core::alloc::Layout;
+use core::ptr::{self, NonNull};
+use super::linked_list::LinkedListAllocator;
+use super::HeapAllocator;
+use crate::arch::x86_64::memory::constants::PAGE_SIZE;
+
+/// Block sizes of the size classes
+const SIZE_CLASSES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+
+/// A free block of a size class, linked through its first word
+struct FreeBlock {
+    next: *mut FreeBlock,
+}
+
+/// Allocator with a free list per size class
+pub struct SizeClassAllocator {
+    lists: [*mut FreeBlock; SIZE_CLASSES.len()],
+    /// Pages for the size classes and large allocations
+    fallback: LinkedListAllocator,
+}
+
+unsafe impl Send for SizeClassAllocator {}
+
+impl SizeClassAllocator {
+    pub const fn new() -> Self {
+        SizeClassAllocator {
+            lists: [ptr::null_mut(); SIZE_CLASSES.len()],
+            fallback: LinkedListAllocator::new(),
+        }
+    }
+    
+    /// Index of the smallest class that holds `layout`
+    /// 
+    /// Blocks are aligned to their size, so the class must also be at
+    /// least as large as the alignment.
+    fn class_index(layout: Layout) -> Option<usize> {
+        let needed = layout.size().max(layout.align());
+        SIZE_CLASSES.iter().position(|&size| size >= needed)
+    }
+    
+    /// Cut a page from the fallback allocator into blocks of class `index`
+    fn refill(&mut self, index: usize) -> bool {
+        let Ok(layout) = Layout::from_size_align(PAGE_SIZE, PAGE_SIZE) else {
+            return false;
+        };
+        let Some(page) = self.fallback.allocate(layout) else {
+            return false;
+        };
+        
+        let size = SIZE_CLASSES[index];
+        let start = page.as_ptr() as usize;
+        for addr in (start..start + PAGE_SIZE).step_by(size).rev() {
+            let block = addr as *mut FreeBlock;
+            unsafe {
+                block.write(FreeBlock { next: self.lists[index] });
+            }
+            self.lists[index] = block;
+        }
+        true
+    }
+}
+
+impl HeapAllocator for SizeClassAllocator {
+    fn allocate(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+        let Some(index) = Self::class_index(layout) else {
+            return self.fallback.allocate(layout);
+        };
+        
+        if self.lists[index].is_null() && !self.refill(index) {
+            return None;
+        }
+        let block = self.lists[index];
+        self.lists[index] = unsafe { (*block).next };
+        NonNull::new(block as *mut u8)
+    }
+    
+    unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        match Self::class_index(layout) {
+            Some(index) => {
+                let block = ptr.as_ptr() as *mut FreeBlock;
+                block.write(FreeBlock { next: self.lists[index] });
+                self.lists[index] = block;
+            }
+            None => self.fallback.deallocate(ptr, layout),
+        }
+    }
+    
+    unsafe fn add_region(&mut self, start: usize, size: usize) {
+        self.fallback.add_region(start, size);
+    }
+}
@@ -10,6 +10,9 @@ use super::paging::{
 };
 use super::frame_alloc::{FrameAllocator, FrameAllocError};
 
+/// Size of a 2MB huge page
+const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
 /// Result type for mapping operations
 pub type MapResult<T> = Result<T, MapError>;
 
@@ -18,6 +21,8 @@ pub type MapResult<T> = Result<T, MapError>;
 pub enum MapError {
     /// The page is already mapped
     PageAlreadyMapped,
+    /// The page (or one of its parent tables) is not mapped
+    PageNotMapped,
     /// Frame allocation failed
     FrameAllocationFailed,
     /// The parent entry is a huge page
@@ -136,55 +141,34 @@ impl<'a, A: FrameAllocator> Mapper<'a, A> {
 
     /// Translate a virtual address to a physical address
     /// 
-    /// Returns None if the virtual address is not mapped.
+    /// Follows 2MB and 1GB huge pages as well as 4KB pages. Returns None if
+    /// the virtual address is not mapped.
     pub fn translate(&self, addr: VirtAddr) -> Option<PhysAddr> {
-        let page = Page::containing_address(addr);
-        let offset = addr.page_offset();
-        
-        // Get the page table entry for this page
-        match self.page_table_entry_readonly(page) {
-            Ok(entry) => {
-                if entry.is_unused() {
-                    None
-                } else {
-                    let frame_addr = entry.addr().as_u64();
-                    Some(PhysAddr::new(frame_addr + offset as u64))
-                }
-            }
-            Err(_) => None,
-        }
-    }
-
-    /// Get the page table entry for a virtual page (read-only)
-    /// 
-    /// This traverses the page table hierarchy without creating tables.
-    fn page_table_entry_readonly(&self, page: Page) -> MapResult<&PageTableEntry> {
-        let addr = page.start_address();
-        
-        // Start at PML4
         let mut table = self.pml4 as *const PageTable;
         
-        // Traverse through levels 4, 3, and 2
-        for level in [PageTableLevel::Four, PageTableLevel::Three, PageTableLevel::Two] {
-            let index = addr.page_table_index(level);
+        for level in [PageTableLevel::Four, PageTableLevel::Three, PageTableLevel::Two, PageTableLevel::One] {
             let table_ref = unsafe { &*table };
-            let entry = &table_ref[index];
+            let entry = &table_ref[addr.page_table_index(level)];
             
             if !entry.flags().contains(PageTableFlags::PRESENT) {
-                return Err(MapError::PageAlreadyMapped);
-            }
-            
-            if entry.flags().contains(PageTableFlags::HUGE_PAGE) {
-                return Err(MapError::ParentEntryHugePage);
+                return None;
             }
             
-            table = entry.addr().as_u64() as *const PageTable;
+            // Size of the page this entry maps, if it maps one
+            let page_size: u64 = match level {
+                PageTableLevel::One => 4096,
+                PageTableLevel::Two if entry.flags().contains(PageTableFlags::HUGE_PAGE) => 2 * 1024 * 1024,
+                PageTableLevel::Three if entry.flags().contains(PageTableFlags::HUGE_PAGE) => 1024 * 1024 * 1024,
+                _ => {
+                    table = entry.addr().as_u64() as *const PageTable;
+                    continue;
+                }
+            };
+            let base = entry.addr().as_u64() & !(page_size - 1);
+            return Some(PhysAddr::new(base + addr.as_u64() % page_size));
         }
         
-        // Get the entry from the final page table (level 1)
-        let index = addr.page_table_index(PageTableLevel::One);
-        let table_ref = unsafe { &*table };
-        Ok(&table_ref[index])
+        None
     }
 
     /// Get a mutable reference to the page table entry for a virtual page
@@ -288,6 +272,76 @@ impl<'a, A: FrameAllocator> Mapper<'a, A> {
         Ok(())
     }
 
+    /// Split the 2MB huge page containing `page` into 512 4KB pages
+    /// 
+    /// The new page table maps exactly the same physical memory with the
+    /// same flags, so the split is invisible to running code. Afterwards
+    /// individual 4KB pages of the region can be unmapped or remapped.
+    /// 
+    /// Does nothing if the region is already mapped with 4KB pages.
+    /// Returns an error for 1GB huge pages, which are not supported.
+    pub fn split_huge_page(&mut self, page: Page) -> MapResult<()> {
+        let addr = page.start_address();
+        
+        // Start at PML4
+        let mut table = self.pml4 as *mut PageTable;
+        
+        // Traverse through levels 4 and 3 to reach the page directory
+        for level in [PageTableLevel::Four, PageTableLevel::Three] {
+            let index = addr.page_table_index(level);
+            let table_ref = unsafe { &mut *table };
+            let entry = &table_ref[index];
+            
+            if !entry.flags().contains(PageTableFlags::PRESENT) {
+                return Err(MapError::PageNotMapped);
+            }
+            
+            if entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+                return Err(MapError::ParentEntryHugePage);
+            }
+            
+            table = entry.addr().as_u64() as *mut PageTable;
+        }
+        
+        let index = addr.page_table_index(PageTableLevel::Two);
+        let table_ref = unsafe { &mut *table };
+        let pd_entry = &mut table_ref[index];
+        
+        if !pd_entry.flags().contains(PageTableFlags::PRESENT) {
+            return Err(MapError::PageNotMapped);
+        }
+        
+        if !pd_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            // Already mapped with a page table
+            return Ok(());
+        }
+        
+        // Bit 12 of a huge page entry is the PAT bit, so the base address
+        // must be aligned down to 2MB rather than taken as-is
+        let base = pd_entry.addr().align_down(HUGE_PAGE_SIZE).as_u64();
+        let mut flags = pd_entry.flags();
+        flags.remove(PageTableFlags::HUGE_PAGE);
+        
+        // Allocate and fill the new page table
+        let frame = self.allocator.allocate_frame()?;
+        let new_table = unsafe { &mut *(frame.start_address().as_u64() as *mut PageTable) };
+        for (i, entry) in new_table.iter_mut().enumerate() {
+            entry.set_addr(PhysAddr::new(base + (i * 4096) as u64), flags);
+        }
+        
+        // Point the page directory entry at the new table
+        let mut table_flags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE);
+        if flags.contains(PageTableFlags::USER_ACCESSIBLE) {
+            table_flags.insert(PageTableFlags::USER_ACCESSIBLE);
+        }
+        pd_entry.set_addr(frame.start_address(), table_flags);
+        
+        // The whole 2MB translation is cached, not just one page
+        flush_all();
+        
+        Ok(())
+    }
+
     /// Identity map a physical frame (virtual address = physical address)
     /// 
     /// This is useful for memory-mapped I/O and during early boot.
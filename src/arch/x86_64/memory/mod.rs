@@ -7,13 +7,22 @@
 /// - Memory mapping and protection
 /// - Heap management
 
-use crate::arch::println;
-use super::boot::{BootInfo, MULTIBOOT2_MAGIC};
+use crate::arch::ByteSize;
+use crate::println;
+use super::boot::BootInfo;
+use super::boot::cmdline::{self, BootOption, OptionKind};
+use super::drivers::console::{log_enabled, LogLevel};
 
+pub mod reserve;
 pub mod physical;
+pub mod buddy;
+pub mod zone;
 pub mod paging;
 pub mod frame_alloc;
 pub mod mapper;
+pub mod stack;
+pub mod heap;
+pub mod slab;
 pub mod examples;
 pub mod tests;
 
@@ -22,8 +31,31 @@ pub use paging::{
     Page, PageTable, PageTableEntry, PageTableFlags, PageTableLevel,
     PhysAddr, PhysFrame, VirtAddr,
 };
-pub use frame_alloc::{FrameAllocator, BitmapFrameAllocator};
+pub use frame_alloc::{FrameAllocator, PhysicalFrameAllocator};
 pub use mapper::{Mapper, MapError, flush_page, flush_all, read_cr3, write_cr3};
+pub use stack::allocate_kernel_stack;
+pub use zone::Zone;
+
+/// Boot options read by the memory subsystem
+pub static BOOT_OPTIONS: [BootOption; 2] = [
+    BootOption::new("mem", OptionKind::Size, "Limit usable physical memory (e.g. mem=512M)"),
+    BootOption::new("memmap", OptionKind::List, "Never use physical memory (e.g. memmap=64K$0x9F000,...)"),
+];
+
+/// Most ranges the `memmap` boot option can exclude
+const MAX_EXCLUDED_RANGES: usize = 16;
+
+/// Parse a `memmap` range, `<size>$<start>`, into `[start, end)`
+/// 
+/// Both are sizes, so `64K$16M` excludes the 64KB at 16MB. GRUB expands
+/// `$`, so it must be written `\$` in `grub.cfg`.
+pub fn parse_memmap(range: &str) -> Option<(u64, u64)> {
+    let (size, start) = range.split_once('$')?;
+    let size = cmdline::parse_size(size)?;
+    let start = cmdline::parse_size(start)?;
+    let end = start.checked_add(size)?;
+    (size > 0).then_some((start, end))
+}
 
 /// Basic memory constants for x86_64
 pub mod constants {
@@ -33,6 +65,52 @@ pub mod constants {
     /// Canonical address space limits
     pub const CANONICAL_LOWER_LIMIT: u64 = 0x0000_7FFF_FFFF_FFFF;
     pub const CANONICAL_UPPER_LIMIT: u64 = 0xFFFF_8000_0000_0000;
+    
+    /// End of the physical memory the boot code identity maps (1GB)
+    pub const IDENTITY_MAPPED_END: u64 = 0x4000_0000;
+    
+    /// Virtual region reserved for the kernel heap (PML4 entry 509)
+    pub const KERNEL_HEAP_START: u64 = 0xFFFF_FE80_0000_0000;
+    pub const KERNEL_HEAP_END: u64 = 0xFFFF_FF00_0000_0000;
+    
+    /// Virtual region reserved for kernel stacks (PML4 entry 510)
+    pub const KERNEL_STACKS_START: u64 = 0xFFFF_FF00_0000_0000;
+    pub const KERNEL_STACKS_END: u64 = 0xFFFF_FF80_0000_0000;
+}
+
+/// Create a mapper for the active page tables
+/// 
+/// The returned mapper allocates page table frames from the physical
+/// allocator, so `init_memory` must have run for mapping to succeed.
+/// 
+/// # Safety
+/// The caller must ensure no other mapper is modifying the active page
+/// tables at the same time.
+pub unsafe fn active_mapper() -> Mapper<'static, PhysicalFrameAllocator> {
+    let pml4 = &mut *(read_cr3().as_u64() as *mut PageTable);
+    Mapper::new(pml4, PhysicalFrameAllocator::new())
+}
+
+/// Identity map a range of physical memory (virtual address = physical address)
+/// 
+/// Used for firmware tables and memory-mapped devices, which may lie above
+/// the first 1GB that the boot code maps. Pages that are already mapped
+/// (including those inside the boot code's 2MB pages) are left alone.
+/// Device registers should be mapped with `PageTableFlags::NO_CACHE`.
+pub fn identity_map_region(start: u64, size: u64, flags: PageTableFlags) -> Result<(), MapError> {
+    let mut mapper = unsafe { active_mapper() };
+    let first = start / constants::PAGE_SIZE as u64;
+    let last = (start + size.max(1) - 1) / constants::PAGE_SIZE as u64;
+    
+    for number in first..=last {
+        let frame = PhysFrame::containing_address(PhysAddr::new(number * constants::PAGE_SIZE as u64));
+        match mapper.identity_map(frame, flags) {
+            Ok(()) | Err(MapError::PageAlreadyMapped) | Err(MapError::ParentEntryHugePage) => {}
+            Err(error) => return Err(error),
+        }
+    }
+    
+    Ok(())
 }
 
 // Kernel boundaries (defined in linker script)
@@ -43,111 +121,103 @@ extern "C" {
 
 /// Initialize memory subsystem from multiboot information
 /// 
-/// Validates the multiboot magic number, parses boot info,
-/// displays the memory map, and initializes the physical memory allocator.
-pub fn init_memory(multiboot_info_addr: usize, multiboot_magic: usize) {
-    if multiboot_magic != MULTIBOOT2_MAGIC as usize {
-        println("Invalid multiboot magic number!");
-        return;
-    }
-    
-    if let Some(boot_info) = unsafe { BootInfo::new(multiboot_info_addr) } {
+/// Displays the memory map, records the memory holding boot data,
+/// initializes the physical memory allocator and maps the kernel heap.
+pub fn init_memory(boot_info: &BootInfo) {
+    if log_enabled(LogLevel::Info) {
         boot_info.print_memory_map();
-        
-        // Initialize physical memory allocator
-        let kernel_start = unsafe { &__kernel_start as *const u8 as usize };
-        let kernel_end = unsafe { &__kernel_end as *const u8 as usize };
-        
-        println("Initializing physical memory allocator...");
-        crate::arch::print("  Kernel: 0x");
-        print_hex(kernel_start as u64);
-        crate::arch::print(" - 0x");
-        print_hex(kernel_end as u64);
-        println("");
-        
-        unsafe {
-            physical::init_physical_allocator(&boot_info, kernel_start, kernel_end);
-        }
-        
-        // Print memory statistics
-        let (total, free, allocated) = physical::memory_stats();
-        crate::arch::print("  Total frames:     ");
-        print_decimal(total as u64);
-        println("");
-        crate::arch::print("  Free frames:      ");
-        print_decimal(free as u64);
-        crate::arch::print(" (");
-        print_size((free * constants::PAGE_SIZE) as u64);
-        println(")");
-        crate::arch::print("  Allocated frames: ");
-        print_decimal(allocated as u64);
-        crate::arch::print(" (");
-        print_size((allocated * constants::PAGE_SIZE) as u64);
-        println(")");
-        println("");
-    } else {
-        println("Failed to parse multiboot info!");
-    }
-}
-
-/// Helper function to print a hex value
-fn print_hex(value: u64) {
-    use crate::arch::print;
-    
-    const HEX_CHARS: &[u8; 16] = b"0123456789ABCDEF";
-    let mut buffer = [0u8; 16];
-    
-    for i in 0..16 {
-        let nibble = ((value >> (60 - i * 4)) & 0xF) as usize;
-        buffer[i] = HEX_CHARS[nibble];
     }
     
-    let s = unsafe { core::str::from_utf8_unchecked(&buffer) };
-    print(s);
-}
-
-/// Helper function to print size in human-readable format
-fn print_size(bytes: u64) {
-    use crate::arch::print;
+    // Initialize physical memory allocator
+    let kernel_start = unsafe { &__kernel_start as *const u8 as usize };
+    let kernel_end = unsafe { &__kernel_end as *const u8 as usize };
     
-    let kb = bytes / 1024;
-    let mb = kb / 1024;
+    println!("Initializing physical memory allocator...");
+    println!("  Kernel: {:#018X} - {:#018X}", kernel_start, kernel_end);
     
-    if mb > 0 {
-        print_decimal(mb);
-        print(" MB");
-    } else if kb > 0 {
-        print_decimal(kb);
-        print(" KB");
-    } else {
-        print_decimal(bytes);
-        print(" bytes");
+    // Every range that could not be recorded has been logged already
+    if reserve::reserve_boot_memory(boot_info, kernel_start, kernel_end).is_err() {
+        println!("  Warning: boot memory not fully reserved");
     }
-}
-
-/// Helper function to print a decimal number
-fn print_decimal(mut value: u64) {
-    use crate::arch::print;
+    let reservations = reserve::reservations();
+    println!(
+        "  Boot reservations: {} in {} ranges",
+        ByteSize(reservations.total_size()),
+        reservations.as_slice().len()
+    );
     
-    if value == 0 {
-        print("0");
-        return;
+    let memory_limit = cmdline::size("mem");
+    if let Some(limit) = memory_limit {
+        println!("  Memory limited to {} by the mem boot option", ByteSize(limit));
     }
     
-    let mut buffer = [0u8; 20];
-    let mut i = 0;
+    let mut excluded = [(0, 0); MAX_EXCLUDED_RANGES];
+    let mut excluded_count = 0;
+    for range in cmdline::list("memmap").into_iter().flatten() {
+        match parse_memmap(range) {
+            Some((start, end)) if excluded_count < MAX_EXCLUDED_RANGES => {
+                println!("  Excluded by memmap: {:#018X} - {:#018X}", start, end);
+                excluded[excluded_count] = (start, end);
+                excluded_count += 1;
+            }
+            Some(_) => println!("  Warning: too many memmap ranges, ignoring {}", range),
+            None => println!("  Warning: invalid memmap range '{}' (expected <size>$<start>)", range),
+        }
+    }
     
-    while value > 0 {
-        buffer[i] = b'0' + (value % 10) as u8;
-        value /= 10;
-        i += 1;
+    unsafe {
+        physical::init_physical_allocator(boot_info, memory_limit, &excluded[..excluded_count]);
     }
     
-    // Reverse the buffer
-    for j in 0..i/2 {
-        buffer.swap(j, i - 1 - j);
+    // Print memory statistics
+    let stats = physical::memory_stats();
+    for zone in Zone::ALL {
+        let frames = stats.zones[zone.index()].total_frames;
+        if frames == 0 {
+            continue;
+        }
+        let layout = physical::zone_layout(zone);
+        println!(
+            "  Zone {:<6}       {} in {} regions, metadata at {:#018X} ({})",
+            zone.name(),
+            ByteSize((frames * constants::PAGE_SIZE) as u64),
+            layout.regions,
+            layout.metadata_start,
+            ByteSize(layout.metadata_size as u64)
+        );
     }
+    println!("  Frame allocator:  {}", physical::ALLOCATOR_NAME);
+    println!("  Total frames:     {}", stats.total_frames);
+    println!("  Free frames:      {} ({})", stats.free_frames, ByteSize((stats.free_frames * constants::PAGE_SIZE) as u64));
+    println!(
+        "  Allocated frames: {} ({})",
+        stats.allocated_frames,
+        ByteSize((stats.allocated_frames * constants::PAGE_SIZE) as u64)
+    );
     
-    let s = unsafe { core::str::from_utf8_unchecked(&buffer[..i]) };
-    print(s);
+    match heap::init() {
+        Ok(()) => println!(
+            "  Kernel heap:      {} at {:#018X} ({})",
+            ByteSize(heap::heap_stats().size as u64),
+            constants::KERNEL_HEAP_START,
+            heap::ALLOCATOR_NAME
+        ),
+        Err(error) => println!("  Warning: kernel heap not mapped: {:?}", error),
+    }
+    println!();
+}
+
+/// Give the memory holding the ACPI tables to the frame allocator
+/// 
+/// Returns the number of frames reclaimed.
+/// 
+/// # Safety
+/// Nothing may read the ACPI tables in place any more (see
+/// `acpi::copy_tables`).
+pub unsafe fn reclaim_acpi_memory(boot_info: &BootInfo) -> usize {
+    let frames = physical::reclaim_acpi_memory(boot_info);
+    if frames > 0 {
+        println!("Reclaimed {} of ACPI memory", ByteSize((frames * constants::PAGE_SIZE) as u64));
+    }
+    frames
 }
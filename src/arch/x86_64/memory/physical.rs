@@ -1,158 +1,420 @@
-/// Physical memory allocator using a bitmap approach
+/// Physical memory allocators
 /// 
-/// This allocator tracks physical memory frames using a bitmap where each bit
-/// represents one 4KB frame. This is a simple and efficient approach that can
-/// be extended or replaced with more sophisticated allocators later.
+/// The usable memory from the memory map is kept as a sorted list of
+/// regions, so frames are tracked by their physical address however sparse
+/// the memory map is: holes between regions cost no metadata and can never
+/// be handed out.
+/// 
+/// ```text
+///     physical memory   [ region 0 ]   hole   [  region 1  ]     hole     [ region 2 ]
+///     metadata          [ frames 0 ][     frames 1     ][ frames 2 ]
+/// ```
+/// 
+/// The regions are split among the zones (see `zone.rs`), and each zone
+/// is managed by its own allocator, behind the `PhysicalAllocator` trait:
+/// - `BitmapAllocator`: one bit per frame, first-fit search (default)
+/// - `BuddyAllocator`: power-of-two blocks in per-order bitmaps (`frame-buddy` feature)
+/// 
+/// Their metadata is stored in blocks taken from usable memory that the
+/// memory map reports as available and that holds nothing recorded in the
+/// boot reservations (see `reserve.rs`), above the DMA zone when there is
+/// room.
 
-use super::constants::PAGE_SIZE;
-use crate::arch::boot::multiboot2::{BootInfo, MemoryType};
+use super::buddy::{OrderStats, ORDERS};
+use super::constants::{IDENTITY_MAPPED_END, PAGE_SIZE};
+use super::reserve;
+use super::zone::{self, Fallback, Zone, ZoneStats, ZONE_COUNT};
+use crate::arch::boot::multiboot2::{BootInfo, EfiMemoryType, MemoryType};
+use core::ptr::{addr_of, addr_of_mut};
 use core::sync::atomic::{AtomicUsize, Ordering};
 
-/// Maximum physical memory we can manage (16 GB)
-/// This limits bitmap size to a reasonable amount (512 KB for 16 GB)
-const MAX_PHYSICAL_MEMORY: usize = 16 * 1024 * 1024 * 1024;
+/// Maximum number of separate usable regions tracked
+/// 
+/// Adjacent and overlapping memory map entries are merged into one region;
+/// regions beyond this many are left unused.
+pub const MAX_REGIONS: usize = 32;
 
-/// Number of frames we can track
-const MAX_FRAMES: usize = MAX_PHYSICAL_MEMORY / PAGE_SIZE;
+/// Lowest address the metadata is placed at, leaving the first megabyte to
+/// firmware data and real-mode code
+const METADATA_MIN_ADDRESS: usize = 0x10_0000;
 
-/// Size of the bitmap in bytes
-const BITMAP_SIZE: usize = MAX_FRAMES / 8;
+/// A run of usable physical memory
+#[derive(Debug, Clone, Copy)]
+pub struct FrameRegion {
+    /// First frame number
+    pub start_frame: usize,
+    /// Number of frames
+    pub frames: usize,
+    /// Index of the first frame among the frames of all regions, which
+    /// is where its metadata starts
+    pub first_index: usize,
+}
 
-/// Bitmap allocator for physical memory frames
-/// 
-/// Each bit in the bitmap represents one 4KB physical frame:
-/// - 0 = frame is free
-/// - 1 = frame is allocated/reserved
-pub struct BitmapAllocator {
-    bitmap: &'static mut [u8],
-    total_frames: usize,
-    free_frames: AtomicUsize,
-    start_frame: usize,
+impl FrameRegion {
+    const EMPTY: Self = FrameRegion { start_frame: 0, frames: 0, first_index: 0 };
+    
+    pub fn end_frame(&self) -> usize {
+        self.start_frame + self.frames
+    }
+    
+    pub fn contains(&self, frame: usize) -> bool {
+        self.start_frame <= frame && frame < self.end_frame()
+    }
 }
 
-impl BitmapAllocator {
-    /// Create a new uninitialized allocator
-    /// 
-    /// The allocator must be initialized with `init()` before use.
+/// Sorted list of usable memory regions
+pub struct RegionList {
+    regions: [FrameRegion; MAX_REGIONS],
+    count: usize,
+}
+
+impl RegionList {
     pub const fn new() -> Self {
-        Self {
-            bitmap: &mut [],
-            total_frames: 0,
-            free_frames: AtomicUsize::new(0),
-            start_frame: 0,
-        }
+        RegionList { regions: [FrameRegion::EMPTY; MAX_REGIONS], count: 0 }
     }
     
-    /// Initialize the allocator using multiboot memory map
+    /// Add the usable memory `[start, end)`
     /// 
-    /// This function:
-    /// 1. Finds available memory to store the bitmap
-    /// 2. Marks all memory as reserved by default
-    /// 3. Marks available regions from the memory map as free
-    /// 4. Protects kernel memory and bitmap itself
-    /// 
-    /// # Safety
-    /// Must be called exactly once during kernel initialization
-    pub unsafe fn init(&mut self, boot_info: &BootInfo, kernel_start: usize, kernel_end: usize) {
-        // Calculate total available memory (not highest address!)
-        // This represents actual usable RAM, not address space holes
-        let mut total_available = 0u64;
-        
-        if let Some(mmap) = boot_info.memory_map() {
-            for entry in mmap {
-                if MemoryType::from_u32(entry.mem_type) == Some(MemoryType::Available) {
-                    total_available += entry.length;
-                }
-            }
+    /// Partial frames at either end are dropped. The list stays sorted by
+    /// address, and regions that overlap or touch are merged.
+    pub fn add(&mut self, start: u64, end: u64) {
+        let start_frame = (start as usize).div_ceil(PAGE_SIZE);
+        let end_frame = end as usize / PAGE_SIZE;
+        if end_frame <= start_frame {
+            return;
         }
         
-        // Cap at MAX_PHYSICAL_MEMORY
-        if total_available > MAX_PHYSICAL_MEMORY as u64 {
-            total_available = MAX_PHYSICAL_MEMORY as u64;
+        // Merge with every region that overlaps or touches the new one
+        let mut merged = FrameRegion { start_frame, frames: end_frame - start_frame, first_index: 0 };
+        let mut index = 0;
+        while index < self.count {
+            let region = self.regions[index];
+            if region.start_frame <= merged.end_frame() && merged.start_frame <= region.end_frame() {
+                let new_start = region.start_frame.min(merged.start_frame);
+                let new_end = region.end_frame().max(merged.end_frame());
+                merged = FrameRegion { start_frame: new_start, frames: new_end - new_start, first_index: 0 };
+                self.regions.copy_within(index + 1..self.count, index);
+                self.count -= 1;
+            } else {
+                index += 1;
+            }
         }
         
-        self.total_frames = (total_available as usize) / PAGE_SIZE;
-        let bitmap_bytes = (self.total_frames + 7) / 8;
-        
-        // Find a suitable location for the bitmap
-        // We'll place it right after the kernel
-        let bitmap_start = align_up(kernel_end, PAGE_SIZE);
-        let bitmap_end = bitmap_start + bitmap_bytes;
-        
-        // Create the bitmap slice
-        self.bitmap = core::slice::from_raw_parts_mut(
-            bitmap_start as *mut u8,
-            bitmap_bytes
-        );
-        
-        // Initially mark all memory as reserved (set all bits to 1)
-        for byte in self.bitmap.iter_mut() {
-            *byte = 0xFF;
+        if self.count == MAX_REGIONS {
+            return;
         }
+        let position = self.regions[..self.count]
+            .iter()
+            .position(|region| region.start_frame > merged.start_frame)
+            .unwrap_or(self.count);
+        self.regions.copy_within(position..self.count, position + 1);
+        self.regions[position] = merged;
+        self.count += 1;
+        self.number();
+    }
+    
+    /// Remove `[start, end)` from the regions
+    /// 
+    /// Frames the range only partly covers are removed too. A region split
+    /// in two when the list is full loses its upper part.
+    pub fn remove(&mut self, start: u64, end: u64) {
+        let start_frame = start as usize / PAGE_SIZE;
+        let end_frame = (end as usize).div_ceil(PAGE_SIZE);
         
-        // Now mark available regions as free based on memory map
-        let mut free_count = 0;
-        
-        if let Some(mmap) = boot_info.memory_map() {
-            for entry in mmap {
-                if MemoryType::from_u32(entry.mem_type) == Some(MemoryType::Available) {
-                    let start = entry.base_addr as usize;
-                    let end = (entry.base_addr + entry.length) as usize;
-                    free_count += self.mark_region_free(start, end);
+        let mut kept = RegionList::new();
+        for region in self.as_slice() {
+            let below = (region.start_frame, region.end_frame().min(start_frame));
+            let above = (region.start_frame.max(end_frame), region.end_frame());
+            for (first, last) in [below, above] {
+                if first < last && kept.count < MAX_REGIONS {
+                    kept.regions[kept.count] = FrameRegion { start_frame: first, frames: last - first, first_index: 0 };
+                    kept.count += 1;
                 }
             }
         }
-        
-        // Mark kernel memory as reserved
-        let kernel_frames = ((kernel_end - kernel_start) + PAGE_SIZE - 1) / PAGE_SIZE;
-        free_count = free_count.saturating_sub(kernel_frames);
-        self.mark_region_reserved(kernel_start, kernel_end);
-        
-        // Mark bitmap memory as reserved  
-        let bitmap_frames = ((bitmap_end - bitmap_start) + PAGE_SIZE - 1) / PAGE_SIZE;
-        free_count = free_count.saturating_sub(bitmap_frames);
-        self.mark_region_reserved(bitmap_start, bitmap_end);
-        
-        // Update free frame counter with the tracked count instead of recalculating
-        self.free_frames.store(free_count, Ordering::Relaxed);
+        kept.number();
+        *self = kept;
     }
     
-    /// Mark a memory region as free (available for allocation)
-    /// Returns the number of frames marked as free
-    fn mark_region_free(&mut self, start: usize, end: usize) -> usize {
-        let start_frame = start / PAGE_SIZE;
-        let end_frame = (end + PAGE_SIZE - 1) / PAGE_SIZE;
-        let mut count = 0;
-        
-        for frame in start_frame..end_frame.min(self.total_frames) {
-            if self.mark_frame_free(frame) {
-                count += 1;
+    /// Keep only the lowest `limit` bytes of usable memory
+    pub fn limit(&mut self, limit: u64) {
+        let mut remaining = limit as usize / PAGE_SIZE;
+        let mut kept = 0;
+        for region in self.regions[..self.count].iter_mut() {
+            if remaining == 0 {
+                break;
             }
+            region.frames = region.frames.min(remaining);
+            remaining -= region.frames;
+            kept += 1;
         }
-        
-        count
+        self.count = kept;
+        self.number();
+    }
+    
+    /// Recompute the index of every region's first frame
+    fn number(&mut self) {
+        let mut index = 0;
+        for region in self.regions[..self.count].iter_mut() {
+            region.first_index = index;
+            index += region.frames;
+        }
+    }
+    
+    pub fn clear(&mut self) {
+        self.count = 0;
     }
     
+    pub fn as_slice(&self) -> &[FrameRegion] {
+        &self.regions[..self.count]
+    }
+    
+    /// Frames in all regions
+    pub fn total_frames(&self) -> usize {
+        self.as_slice().iter().map(|region| region.frames).sum()
+    }
+    
+    /// End of the highest region
+    pub fn highest_address(&self) -> usize {
+        self.as_slice().last().map_or(0, |region| region.end_frame() * PAGE_SIZE)
+    }
+    
+    /// Region holding a frame
+    pub fn find(&self, frame: usize) -> Option<&FrameRegion> {
+        self.as_slice().iter().find(|region| region.contains(frame))
+    }
+    
+    /// Index of a frame among the frames of all regions
+    pub fn index_of(&self, frame: usize) -> Option<usize> {
+        self.find(frame).map(|region| region.first_index + frame - region.start_frame)
+    }
+    
+    /// The parts of the regions between two frames
+    pub fn clip(&self, start_frame: usize, end_frame: usize) -> RegionList {
+        let mut clipped = RegionList::new();
+        for region in self.as_slice() {
+            let start = region.start_frame.max(start_frame);
+            let end = region.end_frame().min(end_frame);
+            if start < end {
+                clipped.regions[clipped.count] = FrameRegion { start_frame: start, frames: end - start, first_index: 0 };
+                clipped.count += 1;
+            }
+        }
+        clipped.number();
+        clipped
+    }
+    
+    /// Find `size` bytes of usable memory at or above `from`, page aligned
+    /// and inside the identity mapping, that do not overlap an occupied range
+    /// 
+    /// `occupied(start, end)` returns a range overlapping `[start, end)`.
+    fn find_free_area(
+        &self,
+        size: usize,
+        from: usize,
+        occupied: impl Fn(usize, usize) -> Option<(usize, usize)>,
+    ) -> Option<usize> {
+        for region in self.as_slice() {
+            let region_end = (region.end_frame() * PAGE_SIZE).min(IDENTITY_MAPPED_END as usize);
+            let mut start = (region.start_frame * PAGE_SIZE).max(from);
+            
+            while start + size <= region_end {
+                match occupied(start, start + size) {
+                    Some((_, used_end)) => start = used_end.next_multiple_of(PAGE_SIZE),
+                    None => return Some(start),
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Layout of the managed physical memory
+#[derive(Debug, Clone, Copy)]
+pub struct PhysicalLayout {
+    /// Number of separate usable regions
+    pub regions: usize,
+    /// End of the highest usable region
+    pub highest_address: usize,
+    /// Where the allocator's metadata is stored
+    pub metadata_start: usize,
+    pub metadata_size: usize,
+}
+
+/// Interface of the allocators managing physical frames
+/// 
+/// `init_physical_allocator` builds the region lists from the memory map,
+/// places the metadata and reserves what is in use; the allocator itself
+/// only has to manage the frames of its regions.
+pub trait PhysicalAllocator {
+    fn regions(&self) -> &RegionList;
+    
+    fn regions_mut(&mut self) -> &mut RegionList;
+    
+    /// Bytes of metadata needed for the current regions
+    fn metadata_size(&self) -> usize;
+    
+    /// Take `metadata` as storage and mark every frame of every region free
+    /// 
+    /// # Safety
+    /// `metadata` must be at least `metadata_size()` bytes and used by
+    /// nothing else.
+    unsafe fn set_metadata(&mut self, metadata: &'static mut [u8]);
+    
+    /// Address and size of the metadata
+    fn metadata_range(&self) -> (usize, usize);
+    
     /// Mark a memory region as reserved (not available for allocation)
-    fn mark_region_reserved(&mut self, start: usize, end: usize) {
-        let start_frame = start / PAGE_SIZE;
-        let end_frame = (end + PAGE_SIZE - 1) / PAGE_SIZE;
-        
-        for frame in start_frame..end_frame.min(self.total_frames) {
-            self.mark_frame_allocated(frame);
+    fn mark_region_reserved(&mut self, start: usize, end: usize);
+    
+    /// Allocate a single physical frame
+    /// 
+    /// Returns the physical address of the allocated frame, or None if
+    /// no free frames are available.
+    fn allocate_frame(&mut self) -> Option<usize>;
+    
+    /// Allocate `count` contiguous physical frames, with the first one
+    /// aligned to `align` bytes (a power of two)
+    /// 
+    /// Returns the physical address of the first frame, or None if the
+    /// frames cannot be allocated. The frames always lie in one region,
+    /// so never span a hole.
+    fn allocate_frames(&mut self, count: usize, align: usize) -> Option<usize> {
+        self.allocate_frames_below(count, align, usize::MAX)
+    }
+    
+    /// Allocate like `allocate_frames`, but only frames that end at or
+    /// below the physical address `limit`
+    fn allocate_frames_below(&mut self, count: usize, align: usize, limit: usize) -> Option<usize>;
+    
+    /// Free contiguous frames
+    /// 
+    /// Frames outside all regions are ignored, and so are frames that are
+    /// free already.
+    /// 
+    /// # Safety
+    /// The caller must ensure that the frames are no longer in use.
+    unsafe fn free_frames(&mut self, phys_addr: usize, count: usize);
+    
+    /// Free a previously allocated frame
+    /// 
+    /// # Safety
+    /// The caller must ensure that the frame is no longer in use.
+    unsafe fn free_frame(&mut self, phys_addr: usize) {
+        self.free_frames(phys_addr, 1);
+    }
+    
+    /// Get the number of free frames
+    fn get_free_frames(&self) -> usize;
+    
+    /// Get the total number of frames
+    fn total_frames(&self) -> usize {
+        self.regions().total_frames()
+    }
+    
+    /// Get the number of allocated frames
+    fn allocated_frames(&self) -> usize {
+        self.total_frames().saturating_sub(self.get_free_frames())
+    }
+    
+    /// Regions and metadata placement
+    fn layout(&self) -> PhysicalLayout {
+        let (metadata_start, metadata_size) = self.metadata_range();
+        PhysicalLayout {
+            regions: self.regions().as_slice().len(),
+            highest_address: self.regions().highest_address(),
+            metadata_start,
+            metadata_size,
+        }
+    }
+}
+
+/// How the allocator treats a range of the memory maps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeKind {
+    /// Free for use
+    Available,
+    /// Holds the ACPI tables; managed, but reserved until
+    /// `reclaim_acpi_memory`
+    Reclaimable,
+    /// Never managed, even where an available entry overlaps it
+    Fenced,
+    /// Not managed; frames of available entries it overlaps stay reserved
+    Reserved,
+}
+
+/// Ranges of the Multiboot2 memory map and the EFI memory map, as
+/// `(start, end, kind)`
+/// 
+/// The EFI map only adds what the Multiboot2 map may not tell: unusable
+/// (bad) memory, ACPI tables, firmware memory, and memory the boot services
+/// still use if they are running.
+pub fn memory_ranges(boot_info: &BootInfo) -> impl Iterator<Item = (u64, u64, RangeKind)> {
+    let multiboot = boot_info.memory_map().into_iter().flatten().map(|entry| {
+        let kind = match MemoryType::from_u32(entry.mem_type) {
+            Some(MemoryType::Available) => RangeKind::Available,
+            Some(MemoryType::AcpiReclaimable) => RangeKind::Reclaimable,
+            Some(MemoryType::BadRam) => RangeKind::Fenced,
+            _ => RangeKind::Reserved,
+        };
+        (entry.base_addr, entry.base_addr.saturating_add(entry.length), kind)
+    });
+    
+    let boot_services_active = boot_info.efi_boot_services_active();
+    let efi = boot_info.efi_memory_map().into_iter().flatten().filter_map(move |descriptor| {
+        let kind = match EfiMemoryType::from_u32(descriptor.mem_type) {
+            Some(EfiMemoryType::LoaderCode | EfiMemoryType::LoaderData | EfiMemoryType::Conventional) => return None,
+            Some(EfiMemoryType::BootServicesCode | EfiMemoryType::BootServicesData) if !boot_services_active => return None,
+            Some(EfiMemoryType::Unusable) => RangeKind::Fenced,
+            Some(EfiMemoryType::AcpiReclaim) => RangeKind::Reclaimable,
+            _ => RangeKind::Reserved,
+        };
+        let size = descriptor.page_count.saturating_mul(PAGE_SIZE as u64);
+        Some((descriptor.physical_start, descriptor.physical_start.saturating_add(size), kind))
+    });
+    
+    multiboot.chain(efi)
+}
+
+/// Bitmap allocator for physical memory frames
+/// 
+/// Each bit in the bitmap represents one 4KB physical frame, in the order
+/// of the regions:
+/// - 0 = frame is free
+/// - 1 = frame is allocated/reserved
+pub struct BitmapAllocator {
+    regions: RegionList,
+    bitmap: &'static mut [u8],
+    free_frames: AtomicUsize,
+    /// Frame to start the next search at
+    start_frame: usize,
+}
+
+impl BitmapAllocator {
+    /// Create a new uninitialized allocator
+    /// 
+    /// The allocator must be initialized with `init()` before use.
+    pub const fn new() -> Self {
+        Self {
+            regions: RegionList::new(),
+            bitmap: &mut [],
+            free_frames: AtomicUsize::new(0),
+            start_frame: 0,
         }
     }
     
+    /// Byte index and bit mask of a frame in the bitmap
+    fn bit(&self, frame: usize) -> Option<(usize, u8)> {
+        let index = self.regions.index_of(frame)?;
+        Some((index / 8, 1u8 << (index % 8)))
+    }
+    
     /// Mark a single frame as free
     /// Returns true if the frame was previously allocated
     fn mark_frame_free(&mut self, frame: usize) -> bool {
-        if frame >= self.total_frames {
+        let Some((byte_index, mask)) = self.bit(frame) else {
             return false;
-        }
-        
-        let byte_index = frame / 8;
-        let bit_index = frame % 8;
-        let mask = 1u8 << bit_index;
+        };
         
         let was_allocated = (self.bitmap[byte_index] & mask) != 0;
         self.bitmap[byte_index] &= !mask;
@@ -161,195 +423,422 @@ impl BitmapAllocator {
     
     /// Mark a single frame as allocated
     fn mark_frame_allocated(&mut self, frame: usize) {
-        if frame >= self.total_frames {
-            return;
+        if let Some((byte_index, mask)) = self.bit(frame) {
+            self.bitmap[byte_index] |= mask;
         }
-        
-        let byte_index = frame / 8;
-        let bit_index = frame % 8;
-        let mask = 1u8 << bit_index;
-        
-        self.bitmap[byte_index] |= mask;
     }
     
     /// Check if a frame is free
     fn is_frame_free(&self, frame: usize) -> bool {
-        if frame >= self.total_frames {
-            return false;
+        match self.bit(frame) {
+            Some((byte_index, mask)) => (self.bitmap[byte_index] & mask) == 0,
+            None => false,
         }
-        
-        let byte_index = frame / 8;
-        let bit_index = frame % 8;
-        let mask = 1u8 << bit_index;
-        
-        (self.bitmap[byte_index] & mask) == 0
     }
     
-    /// Count total free frames (used during initialization)
-    fn count_free_frames(&self) -> usize {
-        let mut count = 0;
-        
-        for frame in 0..self.total_frames {
-            if self.is_frame_free(frame) {
-                count += 1;
+    /// First free frame of a region at or after `from` and below `end_frame`
+    fn find_free_in(&self, region: &FrameRegion, from: usize, end_frame: usize) -> Option<usize> {
+        let mut bit = region.first_index + from.max(region.start_frame) - region.start_frame;
+        let end = region.first_index + region.frames.min(end_frame.saturating_sub(region.start_frame));
+        while bit < end {
+            let byte = self.bitmap[bit / 8];
+            if byte == 0xFF {
+                // Skip whole bytes of allocated frames
+                bit = (bit / 8 + 1) * 8;
+                continue;
+            }
+            if byte & (1 << (bit % 8)) == 0 {
+                return Some(region.start_frame + bit - region.first_index);
             }
+            bit += 1;
         }
-        
-        count
+        None
+    }
+}
+
+impl PhysicalAllocator for BitmapAllocator {
+    fn regions(&self) -> &RegionList {
+        &self.regions
     }
     
-    /// Allocate a single physical frame
-    /// 
-    /// Returns the physical address of the allocated frame, or None if
-    /// no free frames are available.
-    pub fn allocate_frame(&mut self) -> Option<usize> {
-        // Simple first-fit algorithm: scan for the first free frame
-        for frame in self.start_frame..self.total_frames {
-            if self.is_frame_free(frame) {
-                self.mark_frame_allocated(frame);
-                self.free_frames.fetch_sub(1, Ordering::Relaxed);
-                self.start_frame = frame + 1; // Hint for next allocation
-                return Some(frame * PAGE_SIZE);
-            }
+    fn regions_mut(&mut self) -> &mut RegionList {
+        &mut self.regions
+    }
+    
+    fn metadata_size(&self) -> usize {
+        self.regions.total_frames().div_ceil(8)
+    }
+    
+    unsafe fn set_metadata(&mut self, metadata: &'static mut [u8]) {
+        let total = self.regions.total_frames();
+        self.bitmap = metadata;
+        self.bitmap.fill(0);
+        self.start_frame = 0;
+        
+        // Bits past the last frame are never free
+        for index in total..self.bitmap.len() * 8 {
+            self.bitmap[index / 8] |= 1 << (index % 8);
         }
         
-        // If we didn't find anything, wrap around and search from the beginning
-        for frame in 0..self.start_frame {
-            if self.is_frame_free(frame) {
-                self.mark_frame_allocated(frame);
-                self.free_frames.fetch_sub(1, Ordering::Relaxed);
-                self.start_frame = frame + 1;
-                return Some(frame * PAGE_SIZE);
+        self.free_frames.store(total, Ordering::Relaxed);
+    }
+    
+    fn metadata_range(&self) -> (usize, usize) {
+        (self.bitmap.as_ptr() as usize, self.bitmap.len())
+    }
+    
+    fn mark_region_reserved(&mut self, start: usize, end: usize) {
+        let start_frame = start / PAGE_SIZE;
+        let end_frame = end.div_ceil(PAGE_SIZE);
+        
+        for index in 0..self.regions.as_slice().len() {
+            let region = self.regions.as_slice()[index];
+            let first = start_frame.max(region.start_frame);
+            let last = end_frame.min(region.end_frame());
+            for frame in first..last {
+                if self.is_frame_free(frame) {
+                    self.mark_frame_allocated(frame);
+                    self.free_frames.fetch_sub(1, Ordering::Relaxed);
+                }
             }
         }
-        
-        // No free frames available
-        None
     }
     
-    /// Allocate multiple contiguous physical frames
-    /// 
-    /// Returns the physical address of the first frame, or None if
-    /// the requested number of contiguous frames cannot be allocated.
-    pub fn allocate_frames(&mut self, count: usize) -> Option<usize> {
+    fn allocate_frame(&mut self) -> Option<usize> {
+        self.allocate_frames_below(1, PAGE_SIZE, usize::MAX)
+    }
+    
+    fn allocate_frames_below(&mut self, count: usize, align: usize, limit: usize) -> Option<usize> {
         if count == 0 {
             return None;
         }
         
-        if count == 1 {
-            return self.allocate_frame();
+        let end_frame = limit / PAGE_SIZE;
+        let align_frames = (align / PAGE_SIZE).max(1);
+        if count == 1 && align_frames == 1 {
+            // First fit from the hint, then wrap around to the lowest region
+            let hint = self.start_frame;
+            let regions = self.regions.as_slice();
+            let found = regions
+                .iter()
+                .filter(|region| region.end_frame() > hint)
+                .find_map(|region| self.find_free_in(region, hint, end_frame))
+                .or_else(|| regions.iter().find_map(|region| self.find_free_in(region, 0, end_frame)));
+            
+            let frame = found?;
+            self.mark_frame_allocated(frame);
+            self.free_frames.fetch_sub(1, Ordering::Relaxed);
+            self.start_frame = frame + 1; // Hint for next allocation
+            return Some(frame * PAGE_SIZE);
         }
         
-        // Search for contiguous free frames
-        let mut consecutive = 0;
-        let mut start_frame = 0;
-        
-        for frame in 0..self.total_frames {
-            if self.is_frame_free(frame) {
-                if consecutive == 0 {
-                    start_frame = frame;
+        // Search each region for contiguous free frames
+        for index in 0..self.regions.as_slice().len() {
+            let region = self.regions.as_slice()[index];
+            let mut consecutive = 0;
+            let mut start_frame = 0;
+            
+            for frame in region.start_frame..region.end_frame().min(end_frame) {
+                if consecutive == 0 && !frame.is_multiple_of(align_frames) {
+                    continue;
                 }
-                consecutive += 1;
-                
-                if consecutive == count {
-                    // Found enough contiguous frames
-                    for f in start_frame..(start_frame + count) {
-                        self.mark_frame_allocated(f);
+                if self.is_frame_free(frame) {
+                    if consecutive == 0 {
+                        start_frame = frame;
+                    }
+                    consecutive += 1;
+                    
+                    if consecutive == count {
+                        // Found enough contiguous frames
+                        for f in start_frame..(start_frame + count) {
+                            self.mark_frame_allocated(f);
+                        }
+                        self.free_frames.fetch_sub(count, Ordering::Relaxed);
+                        self.start_frame = start_frame + count;
+                        return Some(start_frame * PAGE_SIZE);
                     }
-                    self.free_frames.fetch_sub(count, Ordering::Relaxed);
-                    self.start_frame = start_frame + count;
-                    return Some(start_frame * PAGE_SIZE);
+                } else {
+                    consecutive = 0;
                 }
-            } else {
-                consecutive = 0;
             }
         }
         
         None
     }
     
-    /// Free a previously allocated frame
-    /// 
-    /// # Safety
-    /// The caller must ensure that the frame is no longer in use.
-    pub unsafe fn free_frame(&mut self, phys_addr: usize) {
-        let frame = phys_addr / PAGE_SIZE;
-        
-        if frame < self.total_frames && !self.is_frame_free(frame) {
-            self.mark_frame_free(frame);
-            self.free_frames.fetch_add(1, Ordering::Relaxed);
-            
-            // Update hint for next allocation
-            if frame < self.start_frame {
-                self.start_frame = frame;
-            }
-        }
-    }
-    
-    /// Free multiple contiguous frames
-    /// 
-    /// # Safety
-    /// The caller must ensure that all frames are no longer in use.
-    pub unsafe fn free_frames(&mut self, phys_addr: usize, count: usize) {
+    unsafe fn free_frames(&mut self, phys_addr: usize, count: usize) {
         let start_frame = phys_addr / PAGE_SIZE;
         
-        for i in 0..count {
-            let frame = start_frame + i;
-            if frame < self.total_frames && !self.is_frame_free(frame) {
-                self.mark_frame_free(frame);
+        for frame in start_frame..start_frame + count {
+            if self.mark_frame_free(frame) {
                 self.free_frames.fetch_add(1, Ordering::Relaxed);
             }
         }
         
+        // Update hint for next allocation
         if start_frame < self.start_frame {
             self.start_frame = start_frame;
         }
     }
     
-    /// Get the number of free frames
-    pub fn get_free_frames(&self) -> usize {
+    fn get_free_frames(&self) -> usize {
         self.free_frames.load(Ordering::Relaxed)
     }
-    
-    /// Get the total number of frames
-    pub fn total_frames(&self) -> usize {
-        self.total_frames
-    }
-    
-    /// Get the number of allocated frames
-    pub fn allocated_frames(&self) -> usize {
-        self.total_frames.saturating_sub(self.get_free_frames())
-    }
 }
 
-/// Round up to the nearest multiple of alignment
-fn align_up(addr: usize, align: usize) -> usize {
-    (addr + align - 1) & !(align - 1)
+#[cfg(not(feature = "frame-buddy"))]
+type Allocator = BitmapAllocator;
+#[cfg(feature = "frame-buddy")]
+type Allocator = super::buddy::BuddyAllocator;
+
+/// Name of the allocator selected at build time
+#[cfg(not(feature = "frame-buddy"))]
+pub const ALLOCATOR_NAME: &str = "bitmap";
+#[cfg(feature = "frame-buddy")]
+pub const ALLOCATOR_NAME: &str = "buddy";
+
+/// A zone's allocator and statistics
+struct ZoneState {
+    allocator: Allocator,
+    allocations: u64,
+    fallback_allocations: u64,
+    failures: u64,
 }
 
-// Global physical memory allocator
-static mut PHYSICAL_ALLOCATOR: BitmapAllocator = BitmapAllocator::new();
+impl ZoneState {
+    const fn new() -> Self {
+        ZoneState { allocator: Allocator::new(), allocations: 0, fallback_allocations: 0, failures: 0 }
+    }
+}
+
+// Global physical memory allocators, one per zone
+static mut ZONES: [ZoneState; ZONE_COUNT] = [ZoneState::new(), ZoneState::new(), ZoneState::new()];
+
+/// Zone for allocations that do not ask for one
+/// 
+/// Page tables and slabs are written through the boot identity mapping,
+/// which only covers the first `IDENTITY_MAPPED_END` bytes (1GB), so these
+/// allocations are also kept below that limit. Memory above it is only
+/// handed to callers that ask for a zone.
+pub const DEFAULT_ZONE: Zone = Zone::Dma32;
 
 /// Initialize the physical memory allocator
 /// 
+/// This function:
+/// 1. Collects the available and reclaimable regions from the memory maps,
+///    leaves out fenced and excluded ranges and splits the rest among the
+///    zones
+/// 2. Finds free memory to store each zone's metadata
+/// 3. Marks the available regions as free
+/// 4. Protects the boot reservations, reserved and reclaimable memory and
+///    the metadata itself
+/// 
+/// `memory_limit` caps the amount of memory managed (the `mem` boot
+/// option); regions are used from the lowest address up. `excluded` lists
+/// ranges that are never used (the `memmap` boot option).
+/// 
 /// # Safety
-/// Must be called exactly once during kernel initialization
-pub unsafe fn init_physical_allocator(
-    boot_info: &BootInfo,
-    kernel_start: usize,
-    kernel_end: usize
-) {
-    PHYSICAL_ALLOCATOR.init(boot_info, kernel_start, kernel_end);
+/// Must be called exactly once during kernel initialization, after the
+/// memory the kernel still needs has been recorded with `reserve::reserve`
+pub unsafe fn init_physical_allocator(boot_info: &BootInfo, memory_limit: Option<u64>, excluded: &[(u64, u64)]) {
+    let mut regions = RegionList::new();
+    for (start, end, kind) in memory_ranges(boot_info) {
+        if matches!(kind, RangeKind::Available | RangeKind::Reclaimable) {
+            regions.add(start, end);
+        }
+    }
+    
+    // Bad memory and excluded ranges are left out of the regions, so their
+    // frames can never be handed out, not even by freeing them
+    for (start, end, kind) in memory_ranges(boot_info) {
+        if kind == RangeKind::Fenced {
+            regions.remove(start, end);
+        }
+    }
+    for &(start, end) in excluded {
+        regions.remove(start, end);
+    }
+    if let Some(limit) = memory_limit {
+        regions.limit(limit);
+    }
+    
+    // Store each zone's metadata in available memory that holds nothing the
+    // kernel still needs, keeping the DMA zone for devices if possible
+    let reservations = reserve::close();
+    let zones = &mut *addr_of_mut!(ZONES);
+    let mut metadata_ranges = [(0, 0); ZONE_COUNT];
+    let dma_end = Zone::Dma.range().1 as usize;
+    for zone in Zone::ALL {
+        let (start, end) = zone.range();
+        let allocator = &mut zones[zone.index()].allocator;
+        *allocator.regions_mut() = regions.clip(start as usize / PAGE_SIZE, (end / PAGE_SIZE as u64) as usize);
+        if allocator.total_frames() == 0 {
+            continue;
+        }
+        
+        let size = allocator.metadata_size();
+        let occupied = |start: usize, end: usize| {
+            let unavailable = memory_ranges(boot_info)
+                .filter(|&(_, _, kind)| kind != RangeKind::Available)
+                .map(|(start, end, _)| (start as usize, end as usize));
+            let reserved = reservations.as_slice().iter().map(|entry| (entry.start as usize, entry.end as usize));
+            reserved
+                .chain(metadata_ranges.iter().copied())
+                .chain(unavailable)
+                .find(|&(used_start, used_end)| used_start < end && start < used_end)
+        };
+        let placed = regions
+            .find_free_area(size, dma_end, occupied)
+            .or_else(|| regions.find_free_area(size, METADATA_MIN_ADDRESS, occupied));
+        let Some(metadata_start) = placed else {
+            // Nothing can be managed without somewhere to keep the metadata
+            allocator.regions_mut().clear();
+            continue;
+        };
+        metadata_ranges[zone.index()] = (metadata_start, metadata_start + size);
+        allocator.set_metadata(core::slice::from_raw_parts_mut(metadata_start as *mut u8, size));
+    }
+    
+    for state in zones.iter_mut() {
+        let allocator = &mut state.allocator;
+        
+        // Memory map entries of other types can overlap available ones;
+        // the frames they share are not handed out. Reclaimable memory is
+        // freed by `reclaim_acpi_memory` once the ACPI tables are copied.
+        for (start, end, kind) in memory_ranges(boot_info) {
+            if matches!(kind, RangeKind::Reserved | RangeKind::Reclaimable) {
+                allocator.mark_region_reserved(start as usize, end as usize);
+            }
+        }
+        
+        // Mark the boot reservations (the kernel, boot data, low memory) as
+        // reserved until their owners release them
+        for entry in reservations.as_slice() {
+            allocator.mark_region_reserved(entry.start as usize, entry.end as usize);
+        }
+        
+        // Mark metadata memory as reserved
+        for &(start, end) in &metadata_ranges {
+            allocator.mark_region_reserved(start, end);
+        }
+    }
 }
 
-/// Allocate a single physical frame
+/// Return the frames of ACPI-reclaimable memory to the allocator
+/// 
+/// Frames that another memory map entry, a boot reservation, the
+/// allocator metadata or a table the FADT points at (see
+/// `acpi::fadt_tables`) overlaps stay reserved. Returns the number of
+/// frames freed.
+/// 
+/// # Safety
+/// Nothing may read the ACPI tables in place any more (see
+/// `acpi::copy_tables`).
+pub unsafe fn reclaim_acpi_memory(boot_info: &BootInfo) -> usize {
+    let mut reclaimable = RegionList::new();
+    for (start, end, kind) in memory_ranges(boot_info) {
+        if kind == RangeKind::Reclaimable {
+            reclaimable.add(start, end);
+        }
+    }
+    for (start, end, kind) in memory_ranges(boot_info) {
+        if matches!(kind, RangeKind::Reserved | RangeKind::Fenced) {
+            reclaimable.remove(start, end);
+        }
+    }
+    let zones = &*addr_of!(ZONES);
+    let metadata = zones.iter().map(|state| {
+        let (start, size) = state.allocator.metadata_range();
+        (start, start + size)
+    });
+    for (start, end) in metadata {
+        reclaimable.remove(start as u64, end as u64);
+    }
+    for entry in reserve::reservations().as_slice() {
+        reclaimable.remove(entry.start, entry.end);
+    }
+    // The copied FADT still points at the DSDT and FACS in place
+    for (start, end) in crate::arch::acpi::fadt_tables() {
+        reclaimable.remove(start, end);
+    }
+    
+    let free_before = memory_stats().free_frames;
+    for region in reclaimable.as_slice() {
+        free_frames(region.start_frame * PAGE_SIZE, region.frames);
+    }
+    memory_stats().free_frames - free_before
+}
+
+/// Allocate a single physical frame inside the identity mapping
 pub fn allocate_frame() -> Option<usize> {
-    unsafe { PHYSICAL_ALLOCATOR.allocate_frame() }
+    allocate_frames_aligned(1, PAGE_SIZE)
 }
 
-/// Allocate multiple contiguous physical frames
+/// Allocate multiple contiguous physical frames inside the identity mapping
 pub fn allocate_frames(count: usize) -> Option<usize> {
-    unsafe { PHYSICAL_ALLOCATOR.allocate_frames(count) }
+    allocate_frames_aligned(count, PAGE_SIZE)
+}
+
+/// Allocate multiple contiguous physical frames inside the identity
+/// mapping, starting at a multiple of `align` bytes (a power of two, e.g.
+/// 2MB for a huge page)
+pub fn allocate_frames_aligned(count: usize, align: usize) -> Option<usize> {
+    allocate_frames_below(DEFAULT_ZONE, count, align, Fallback::LowerZones, IDENTITY_MAPPED_END as usize)
+}
+
+/// Allocate contiguous frames from a zone, aligned to `align` bytes
+/// 
+/// Falls back to the lower zones, whose memory is reachable by the same
+/// devices, if the zone has no room.
+pub fn allocate_frames_in(zone: Zone, count: usize, align: usize) -> Option<usize> {
+    allocate_frames_with(zone, count, align, Fallback::LowerZones)
+}
+
+/// Allocate contiguous frames from a zone with an explicit fallback policy
+pub fn allocate_frames_with(zone: Zone, count: usize, align: usize, fallback: Fallback) -> Option<usize> {
+    allocate_frames_below(zone, count, align, fallback, usize::MAX)
+}
+
+/// Allocate contiguous frames from a zone that end at or below the
+/// physical address `limit`
+/// 
+/// Zones starting at or above the limit are skipped.
+pub fn allocate_frames_below(zone: Zone, count: usize, align: usize, fallback: Fallback, limit: usize) -> Option<usize> {
+    let zones = unsafe { &mut *addr_of_mut!(ZONES) };
+    let candidates = match fallback {
+        Fallback::None => &zone.fallback_order()[..1],
+        Fallback::LowerZones => zone.fallback_order(),
+    };
+    let totals = zone_totals();
+    
+    for &candidate in candidates {
+        if candidate.range().0 >= limit as u64 {
+            continue;
+        }
+        let state = &mut zones[candidate.index()];
+        if candidate != zone {
+            let reserve = zone::lowmem_reserve(&totals, candidate, zone);
+            if state.allocator.get_free_frames() < count + reserve {
+                continue;
+            }
+        }
+        if let Some(addr) = state.allocator.allocate_frames_below(count, align, limit) {
+            state.allocations += 1;
+            if candidate != zone {
+                state.fallback_allocations += 1;
+            }
+            return Some(addr);
+        }
+    }
+    
+    zones[zone.index()].failures += 1;
+    None
+}
+
+/// Frames of every zone
+fn zone_totals() -> [usize; ZONE_COUNT] {
+    let zones = unsafe { &*addr_of!(ZONES) };
+    Zone::ALL.map(|zone| zones[zone.index()].allocator.total_frames())
 }
 
 /// Free a physical frame
@@ -357,7 +846,7 @@ pub fn allocate_frames(count: usize) -> Option<usize> {
 /// # Safety
 /// The frame must have been allocated and must no longer be in use
 pub unsafe fn free_frame(phys_addr: usize) {
-    PHYSICAL_ALLOCATOR.free_frame(phys_addr);
+    free_frames(phys_addr, 1);
 }
 
 /// Free multiple contiguous physical frames
@@ -365,16 +854,65 @@ pub unsafe fn free_frame(phys_addr: usize) {
 /// # Safety
 /// The frames must have been allocated and must no longer be in use
 pub unsafe fn free_frames(phys_addr: usize, count: usize) {
-    PHYSICAL_ALLOCATOR.free_frames(phys_addr, count);
+    let zones = &mut *addr_of_mut!(ZONES);
+    let end = phys_addr + count * PAGE_SIZE;
+    let mut start = phys_addr;
+    
+    // Each zone frees its part of the range
+    while start < end {
+        let zone = Zone::containing(start as u64);
+        let zone_end = zone.range().1.min(end as u64) as usize;
+        zones[zone.index()].allocator.free_frames(start, (zone_end - start) / PAGE_SIZE);
+        start = zone_end;
+    }
+}
+
+/// Statistics about physical memory
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryStats {
+    pub total_frames: usize,
+    pub free_frames: usize,
+    pub allocated_frames: usize,
+    /// Statistics of every zone, lowest first
+    pub zones: [ZoneStats; ZONE_COUNT],
 }
 
 /// Get statistics about physical memory
-pub fn memory_stats() -> (usize, usize, usize) {
-    unsafe {
-        (
-            PHYSICAL_ALLOCATOR.total_frames(),
-            PHYSICAL_ALLOCATOR.get_free_frames(),
-            PHYSICAL_ALLOCATOR.allocated_frames(),
-        )
+pub fn memory_stats() -> MemoryStats {
+    let zones = unsafe { &*addr_of!(ZONES) };
+    let stats = Zone::ALL.map(|zone| {
+        let state = &zones[zone.index()];
+        ZoneStats {
+            total_frames: state.allocator.total_frames(),
+            free_frames: state.allocator.get_free_frames(),
+            allocations: state.allocations,
+            fallback_allocations: state.fallback_allocations,
+            failures: state.failures,
+        }
+    });
+    
+    MemoryStats {
+        total_frames: stats.iter().map(|zone| zone.total_frames).sum(),
+        free_frames: stats.iter().map(|zone| zone.free_frames).sum(),
+        allocated_frames: zones.iter().map(|state| state.allocator.allocated_frames()).sum(),
+        zones: stats,
     }
 }
+
+/// Regions of usable memory in a zone and where its allocator's metadata
+/// is stored
+pub fn zone_layout(zone: Zone) -> PhysicalLayout {
+    unsafe { (*addr_of!(ZONES))[zone.index()].allocator.layout() }
+}
+
+/// Per-order statistics of a zone's buddy allocator, if it is the one in use
+#[cfg(not(feature = "frame-buddy"))]
+pub fn order_stats(_zone: Zone) -> Option<[OrderStats; ORDERS]> {
+    None
+}
+
+/// Per-order statistics of a zone's buddy allocator, if it is the one in use
+#[cfg(feature = "frame-buddy")]
+pub fn order_stats(zone: Zone) -> Option<[OrderStats; ORDERS]> {
+    unsafe { Some((*addr_of!(ZONES))[zone.index()].allocator.order_stats()) }
+}
@@ -0,0 +1,309 @@
+/// Boot memory reservations
+/// 
+/// Until the kernel is done with it, memory holding boot data must not be
+/// handed out by the frame allocator: the Multiboot2 information, the
+/// modules, the sections GRUB loaded, the boot page tables and the low
+/// memory firmware uses. Like Linux's memblock, these ranges are recorded
+/// here before the allocator comes up:
+/// 
+/// ```text
+///     reserve_boot_memory()      records the boot data
+///     init_physical_allocator()  closes the registry, places its metadata
+///                                around the records and reserves every
+///                                recorded range
+///     release(start, end)        returns a range to the allocator once
+///                                its owner is done with it
+/// ```
+/// 
+/// A released range only frees the frames no other reservation still
+/// covers, so boot data sharing a frame stays intact.
+
+use crate::arch::boot::multiboot2::{self, BootInfo, ElfSectionHeader};
+use crate::arch::x86_64::sync::SpinLock;
+use crate::arch::ByteSize;
+use crate::println;
+use super::constants::PAGE_SIZE;
+use super::mapper::read_cr3;
+use super::paging::PageTable;
+use super::physical::{self, RangeKind, RegionList};
+
+/// Most reservations that can be recorded
+/// 
+/// Touching and overlapping ranges with the same name share an entry.
+pub const MAX_RESERVATIONS: usize = 32;
+
+/// End of the low memory kept from the allocator: the real-mode interrupt
+/// vector table, the BIOS data area and the first pages some BIOSes
+/// scribble on (the 64KB Linux reserves by default)
+const LOW_MEMORY_END: u64 = 0x1_0000;
+
+/// Where the BIOS data area holds the segment of the extended BIOS data area
+const EBDA_SEGMENT_ADDRESS: usize = 0x40E;
+
+/// The EBDA lies below the video memory and is at most 128KB large
+const EBDA_LOWEST: u64 = 0x8_0000;
+const EBDA_END: u64 = 0xA_0000;
+
+/// A recorded range of physical memory
+#[derive(Debug, Clone, Copy)]
+pub struct Reservation {
+    pub start: u64,
+    pub end: u64,
+    /// What the range holds
+    pub name: &'static str,
+}
+
+impl Reservation {
+    const EMPTY: Self = Reservation { start: 0, end: 0, name: "" };
+    
+    pub fn size(&self) -> u64 {
+        self.end - self.start
+    }
+    
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+}
+
+/// Reasons a range cannot be reserved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReserveError {
+    /// The range holds no bytes
+    EmptyRange,
+    /// `MAX_RESERVATIONS` are recorded already
+    RegistryFull,
+    /// The frame allocator is running; its frames are allocated instead
+    AllocatorRunning,
+}
+
+/// Reservations sorted by start address
+#[derive(Clone, Copy)]
+pub struct ReservationList {
+    entries: [Reservation; MAX_RESERVATIONS],
+    count: usize,
+}
+
+impl ReservationList {
+    pub const fn new() -> Self {
+        ReservationList { entries: [Reservation::EMPTY; MAX_RESERVATIONS], count: 0 }
+    }
+    
+    /// Record `[start, end)` as holding `name`
+    /// 
+    /// Reservations with the same name that overlap or touch the range are
+    /// merged with it.
+    pub fn add(&mut self, start: u64, end: u64, name: &'static str) -> Result<(), ReserveError> {
+        if end <= start {
+            return Err(ReserveError::EmptyRange);
+        }
+        
+        let mut merged = Reservation { start, end, name };
+        let mut index = 0;
+        while index < self.count {
+            let entry = self.entries[index];
+            if entry.name == name && entry.start <= merged.end && merged.start <= entry.end {
+                merged.start = merged.start.min(entry.start);
+                merged.end = merged.end.max(entry.end);
+                self.entries.copy_within(index + 1..self.count, index);
+                self.count -= 1;
+            } else {
+                index += 1;
+            }
+        }
+        
+        self.push(merged)?;
+        self.sort();
+        Ok(())
+    }
+    
+    /// Remove `[start, end)` from every reservation
+    /// 
+    /// Returns the frames that are no longer reserved: the whole frames of
+    /// the parts removed, less those another reservation still touches. A
+    /// reservation that would have to be split while the list is full is
+    /// kept as it is.
+    pub fn remove(&mut self, start: u64, end: u64) -> RegionList {
+        let mut released = RegionList::new();
+        let mut kept = ReservationList::new();
+        
+        // Every entry is kept whole or as its pieces, so pushing cannot fail
+        for (index, &entry) in self.as_slice().iter().enumerate() {
+            let below = Reservation { end: start, ..entry };
+            let above = Reservation { start: end, ..entry };
+            let pieces = [below, above].into_iter().filter(|piece| piece.start < piece.end);
+            let later = self.count - index - 1;
+            if !entry.overlaps(start, end) || kept.count + pieces.clone().count() + later > MAX_RESERVATIONS {
+                let _ = kept.push(entry);
+                continue;
+            }
+            for piece in pieces {
+                let _ = kept.push(piece);
+            }
+            released.add(entry.start.max(start), entry.end.min(end));
+        }
+        
+        for entry in kept.as_slice() {
+            released.remove(entry.start, entry.end);
+        }
+        kept.sort();
+        *self = kept;
+        released
+    }
+    
+    /// A reservation overlapping `[start, end)`
+    pub fn overlapping(&self, start: u64, end: u64) -> Option<Reservation> {
+        self.as_slice().iter().copied().find(|entry| entry.overlaps(start, end))
+    }
+    
+    pub fn as_slice(&self) -> &[Reservation] {
+        &self.entries[..self.count]
+    }
+    
+    /// Bytes of all reservations (a range two of them overlap counts twice)
+    pub fn total_size(&self) -> u64 {
+        self.as_slice().iter().map(Reservation::size).sum()
+    }
+    
+    fn push(&mut self, entry: Reservation) -> Result<(), ReserveError> {
+        if self.count == MAX_RESERVATIONS {
+            return Err(ReserveError::RegistryFull);
+        }
+        self.entries[self.count] = entry;
+        self.count += 1;
+        Ok(())
+    }
+    
+    fn sort(&mut self) {
+        self.entries[..self.count].sort_unstable_by_key(|entry| entry.start);
+    }
+}
+
+/// The registry and whether the frame allocator has taken it over
+struct Registry {
+    list: ReservationList,
+    closed: bool,
+}
+
+static REGISTRY: SpinLock<Registry> = SpinLock::new(Registry { list: ReservationList::new(), closed: false });
+
+/// Keep `[start, end)` from the frame allocator
+/// 
+/// Only possible before `init_physical_allocator`; afterwards frames are
+/// taken by allocating them.
+pub fn reserve(start: u64, end: u64, name: &'static str) -> Result<(), ReserveError> {
+    let mut registry = REGISTRY.lock();
+    if registry.closed {
+        return Err(ReserveError::AllocatorRunning);
+    }
+    registry.list.add(start, end, name)
+}
+
+/// Stop accepting reservations and return the recorded ones
+/// 
+/// Called by `init_physical_allocator`, which reserves them.
+pub fn close() -> ReservationList {
+    let mut registry = REGISTRY.lock();
+    registry.closed = true;
+    registry.list
+}
+
+/// A copy of the recorded reservations
+pub fn reservations() -> ReservationList {
+    REGISTRY.lock().list
+}
+
+/// Return `[start, end)` to the frame allocator
+/// 
+/// Only frames that were reserved are freed, and only those no other
+/// reservation touches and the memory map lists as available. Returns
+/// the number of frames freed.
+/// 
+/// # Safety
+/// Nothing may use the memory of the range any more.
+pub unsafe fn release(start: u64, end: u64) -> usize {
+    let mut frames = REGISTRY.lock().list.remove(start, end);
+    if let Some(boot_info) = multiboot2::boot_info() {
+        for (start, end, kind) in physical::memory_ranges(&boot_info) {
+            if kind != RangeKind::Available {
+                frames.remove(start, end);
+            }
+        }
+    }
+    
+    let free_before = physical::memory_stats().free_frames;
+    for region in frames.as_slice() {
+        physical::free_frames(region.start_frame * PAGE_SIZE, region.frames);
+    }
+    physical::memory_stats().free_frames - free_before
+}
+
+/// Record the memory holding boot data
+/// 
+/// Every range is recorded on its own: one that cannot be recorded is
+/// logged and the others are still tried. The most important ranges are
+/// recorded first, so a full registry loses the least important ones (the
+/// modules go last). Returns the first error.
+pub fn reserve_boot_memory(boot_info: &BootInfo, kernel_start: usize, kernel_end: usize) -> Result<(), ReserveError> {
+    let mut first_error = None;
+    let mut record = |start: u64, end: u64, name: &'static str| {
+        if let Err(error) = reserve(start, end, name) {
+            println!("  Warning: could not reserve {} at {:#018X} - {:#018X}: {:?}", name, start, end, error);
+            first_error.get_or_insert(error);
+        }
+    };
+    
+    record(0, LOW_MEMORY_END, "low memory");
+    if let Some(ebda_start) = ebda_start() {
+        record(ebda_start, EBDA_END, "EBDA");
+    }
+    record(kernel_start as u64, kernel_end as u64, "kernel");
+    
+    let boot_info_start = boot_info.addr() as u64;
+    record(boot_info_start, boot_info_start + boot_info.total_size() as u64, "boot information");
+    
+    // The tables live in the kernel image today, but nothing should rely on
+    // where the boot code put them
+    let pml4_addr = read_cr3().as_u64();
+    reserve_page_tables(unsafe { &*(pml4_addr as *const PageTable) }, pml4_addr, 4, &mut record);
+    
+    // The symbol table stays intact for crash reports
+    for section in boot_info.elf_sections().into_iter().flatten().filter(ElfSectionHeader::is_loaded_by_bootloader) {
+        record(section.addr, section.addr + section.size, "kernel symbols");
+    }
+    for module in boot_info.modules() {
+        record(module.start as u64, module.end as u64, "module");
+    }
+    
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Start of the extended BIOS data area, if the BIOS data area points at
+/// a plausible one (it does not exist on UEFI machines)
+fn ebda_start() -> Option<u64> {
+    let segment = unsafe { core::ptr::read_volatile(EBDA_SEGMENT_ADDRESS as *const u16) };
+    let start = (segment as u64) << 4;
+    (EBDA_LOWEST..EBDA_END).contains(&start).then_some(start)
+}
+
+/// Record the frame of a page table at `level` (4 for the PML4) and of
+/// every table below it with `record`
+fn reserve_page_tables(table: &PageTable, addr: u64, level: u8, record: &mut impl FnMut(u64, u64, &'static str)) {
+    record(addr, addr + PAGE_SIZE as u64, "boot page tables");
+    if level == 1 {
+        return;
+    }
+    for index in 0..512 {
+        if let Some(next) = table.next_table(index) {
+            reserve_page_tables(next, table[index].addr().as_u64(), level - 1, record);
+        }
+    }
+}
+
+/// Print the recorded reservations
+pub fn print_reservations() {
+    let list = reservations();
+    println!("Start               End                 Holds               Size");
+    for entry in list.as_slice() {
+        println!("{:#018X}  {:#018X}  {:<18}  {}", entry.start, entry.end, entry.name, ByteSize(entry.size()));
+    }
+}
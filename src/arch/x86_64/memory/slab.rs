@@ -0,0 +1,377 @@
+/// Slab allocator
+/// 
+/// A `SlabCache` hands out objects of one size. It takes whole frames from
+/// the physical allocator ("slabs") and cuts each into as many objects as
+/// fit, so allocating and freeing an object only updates the slab's free
+/// list. Frames are used through the identity mapping set up at boot.
+/// 
+/// ```text
+///     frame start                                              frame end
+///     +--------+--------------+-----+----------+----------+----------+
+///     | header | free indices | pad | object 0 | object 1 |   ...    |
+///     +--------+--------------+-----+----------+----------+----------+
+/// ```
+/// 
+/// The free list is an array of object indices after the header, so free
+/// objects are never written to. An optional constructor runs once for
+/// every object when its slab is created; objects must be freed in their
+/// constructed state and are handed out again as they were left.
+/// 
+/// Each cache keeps its slabs on three lists (partial, full and empty).
+/// Empty slabs are kept for reuse until `shrink` returns them to the
+/// frame allocator; `shrink_all` does this for every cache, and runs
+/// automatically when a cache or the heap cannot get a frame.
+/// 
+/// The `kmalloc-*` caches serve the small allocations of the global
+/// allocator.
+
+use core::mem;
+use core::ptr::{self, NonNull};
+use core::alloc::Layout;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::arch::x86_64::sync::SpinLock;
+use crate::println;
+use super::constants::PAGE_SIZE;
+use super::physical;
+
+/// Marks the end of a slab's free list
+const NO_OBJECT: u16 = u16::MAX;
+
+/// Most caches that can be listed by `print_caches`
+const MAX_CACHES: usize = 32;
+
+/// Header at the start of every slab
+#[repr(C)]
+struct Slab {
+    next: *mut Slab,
+    prev: *mut Slab,
+    /// Index of the first free object
+    free: u16,
+    /// Objects handed out
+    in_use: u16,
+}
+
+/// Offset of the free index array in a slab
+const INDEX_OFFSET: usize = mem::size_of::<Slab>();
+
+/// Per-cache statistics
+#[derive(Debug, Clone, Copy)]
+pub struct CacheStats {
+    /// Slabs owned by the cache (one frame each)
+    pub slabs: usize,
+    /// Slabs without any object in use
+    pub empty_slabs: usize,
+    /// Objects handed out
+    pub objects_in_use: usize,
+    /// Total allocations and frees
+    pub allocations: u64,
+    pub frees: u64,
+    /// Slabs created and released over the cache's lifetime
+    pub slabs_created: u64,
+    pub slabs_released: u64,
+}
+
+impl CacheStats {
+    const fn new() -> Self {
+        CacheStats {
+            slabs: 0,
+            empty_slabs: 0,
+            objects_in_use: 0,
+            allocations: 0,
+            frees: 0,
+            slabs_created: 0,
+            slabs_released: 0,
+        }
+    }
+}
+
+/// Slab lists of a cache
+struct CacheState {
+    partial: *mut Slab,
+    full: *mut Slab,
+    empty: *mut Slab,
+    stats: CacheStats,
+}
+
+unsafe impl Send for CacheState {}
+
+/// A cache of fixed-size objects
+pub struct SlabCache {
+    name: &'static str,
+    object_size: usize,
+    /// Distance between objects (the size rounded up to the alignment)
+    stride: usize,
+    /// Objects per slab
+    capacity: usize,
+    /// Offset of object 0 in a slab
+    first_object: usize,
+    constructor: Option<fn(NonNull<u8>)>,
+    registered: AtomicBool,
+    state: SpinLock<CacheState>,
+}
+
+impl SlabCache {
+    /// Create a cache for objects of `size` bytes aligned to `align`
+    /// 
+    /// The constructor, if any, runs for every object of a new slab with
+    /// the cache locked, so it must not allocate from the same cache.
+    /// Panics (at compile time for a `static`) if `align` is not a power of
+    /// two up to the page size or an object does not fit in a slab.
+    pub const fn new(
+        name: &'static str,
+        size: usize,
+        align: usize,
+        constructor: Option<fn(NonNull<u8>)>,
+    ) -> Self {
+        assert!(align.is_power_of_two() && align <= PAGE_SIZE, "invalid slab alignment");
+        let stride = if size == 0 { align } else { size.next_multiple_of(align) };
+        let capacity = (PAGE_SIZE - INDEX_OFFSET) / (stride + mem::size_of::<u16>());
+        assert!(capacity > 0, "slab object too large");
+        
+        SlabCache {
+            name,
+            object_size: size,
+            stride,
+            capacity,
+            // Objects are packed at the end of the frame, which keeps them
+            // aligned because the stride is a multiple of the alignment
+            first_object: PAGE_SIZE - capacity * stride,
+            constructor,
+            registered: AtomicBool::new(false),
+            state: SpinLock::new(CacheState {
+                partial: ptr::null_mut(),
+                full: ptr::null_mut(),
+                empty: ptr::null_mut(),
+                stats: CacheStats::new(),
+            }),
+        }
+    }
+    
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    
+    pub fn objects_per_slab(&self) -> usize {
+        self.capacity
+    }
+    
+    pub fn stats(&self) -> CacheStats {
+        self.state.lock().stats
+    }
+    
+    /// Allocate an object
+    /// 
+    /// Takes a new frame when every slab is full. If no frame is left, the
+    /// empty slabs of all caches are released and the allocation retried.
+    pub fn alloc(&'static self) -> Option<NonNull<u8>> {
+        self.register();
+        if let Some(object) = self.try_alloc() {
+            return Some(object);
+        }
+        if shrink_all() == 0 {
+            return None;
+        }
+        self.try_alloc()
+    }
+    
+    fn try_alloc(&self) -> Option<NonNull<u8>> {
+        let mut state = self.state.lock();
+        
+        let slab = if !state.partial.is_null() {
+            state.partial
+        } else if !state.empty.is_null() {
+            let slab = state.empty;
+            unsafe {
+                remove(&mut state.empty, slab);
+                push(&mut state.partial, slab);
+            }
+            state.stats.empty_slabs -= 1;
+            slab
+        } else {
+            let slab = self.create_slab()?;
+            unsafe {
+                push(&mut state.partial, slab);
+            }
+            state.stats.slabs += 1;
+            state.stats.slabs_created += 1;
+            slab
+        };
+        
+        unsafe {
+            let index = (*slab).free;
+            (*slab).free = *self.index_slot(slab, index);
+            (*slab).in_use += 1;
+            if (*slab).in_use as usize == self.capacity {
+                remove(&mut state.partial, slab);
+                push(&mut state.full, slab);
+            }
+            state.stats.objects_in_use += 1;
+            state.stats.allocations += 1;
+            NonNull::new(self.object(slab, index))
+        }
+    }
+    
+    /// Return an object to the cache
+    /// 
+    /// # Safety
+    /// The object must have been allocated from this cache, must not be
+    /// used afterwards, and must be in its constructed state if the cache
+    /// has a constructor.
+    pub unsafe fn free(&self, object: NonNull<u8>) {
+        let addr = object.as_ptr() as usize;
+        let slab = (addr & !(PAGE_SIZE - 1)) as *mut Slab;
+        let offset = addr - slab as usize - self.first_object;
+        debug_assert!(offset.is_multiple_of(self.stride), "object freed to the wrong slab cache");
+        let index = (offset / self.stride) as u16;
+        
+        let mut state = self.state.lock();
+        let was_full = (*slab).in_use as usize == self.capacity;
+        *self.index_slot(slab, index) = (*slab).free;
+        (*slab).free = index;
+        (*slab).in_use -= 1;
+        
+        if was_full {
+            remove(&mut state.full, slab);
+            push(&mut state.partial, slab);
+        }
+        if (*slab).in_use == 0 {
+            remove(&mut state.partial, slab);
+            push(&mut state.empty, slab);
+            state.stats.empty_slabs += 1;
+        }
+        state.stats.objects_in_use -= 1;
+        state.stats.frees += 1;
+    }
+    
+    /// Return the empty slabs to the frame allocator
+    /// 
+    /// Returns the number of frames released.
+    pub fn shrink(&self) -> usize {
+        let mut state = self.state.lock();
+        let mut released = 0;
+        while !state.empty.is_null() {
+            let slab = state.empty;
+            unsafe {
+                remove(&mut state.empty, slab);
+                physical::free_frame(slab as usize);
+            }
+            released += 1;
+        }
+        state.stats.slabs -= released;
+        state.stats.empty_slabs = 0;
+        state.stats.slabs_released += released as u64;
+        released
+    }
+    
+    /// Take a frame and set it up as a slab with every object free
+    fn create_slab(&self) -> Option<*mut Slab> {
+        // The frame lies inside the identity mapping, so its physical
+        // address can be used as a pointer
+        let slab = physical::allocate_frame()? as *mut Slab;
+        unsafe {
+            slab.write(Slab { next: ptr::null_mut(), prev: ptr::null_mut(), free: 0, in_use: 0 });
+            for index in 0..self.capacity as u16 {
+                let next = if index as usize + 1 == self.capacity { NO_OBJECT } else { index + 1 };
+                *self.index_slot(slab, index) = next;
+                if let Some(constructor) = self.constructor {
+                    constructor(NonNull::new_unchecked(self.object(slab, index)));
+                }
+            }
+        }
+        Some(slab)
+    }
+    
+    /// Free list entry of object `index`
+    unsafe fn index_slot(&self, slab: *mut Slab, index: u16) -> *mut u16 {
+        (slab as *mut u8).add(INDEX_OFFSET).cast::<u16>().add(index as usize)
+    }
+    
+    /// Address of object `index`
+    unsafe fn object(&self, slab: *mut Slab, index: u16) -> *mut u8 {
+        (slab as *mut u8).add(self.first_object + index as usize * self.stride)
+    }
+    
+    /// Add the cache to the list used by `shrink_all` and `print_caches`
+    fn register(&'static self) {
+        if self.registered.load(Ordering::Acquire) || self.registered.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let mut caches = CACHES.lock();
+        if let Some(slot) = caches.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(self);
+        }
+    }
+}
+
+/// Add a slab to the front of a list
+unsafe fn push(list: &mut *mut Slab, slab: *mut Slab) {
+    (*slab).prev = ptr::null_mut();
+    (*slab).next = *list;
+    if !list.is_null() {
+        (**list).prev = slab;
+    }
+    *list = slab;
+}
+
+/// Unlink a slab from a list
+unsafe fn remove(list: &mut *mut Slab, slab: *mut Slab) {
+    if (*slab).prev.is_null() {
+        *list = (*slab).next;
+    } else {
+        (*(*slab).prev).next = (*slab).next;
+    }
+    if !(*slab).next.is_null() {
+        (*(*slab).next).prev = (*slab).prev;
+    }
+}
+
+/// Caches that have been used
+static CACHES: SpinLock<[Option<&'static SlabCache>; MAX_CACHES]> = SpinLock::new([None; MAX_CACHES]);
+
+/// Caches for small allocations of the global allocator
+static KMALLOC_CACHES: [SlabCache; 7] = [
+    SlabCache::new("kmalloc-16", 16, 16, None),
+    SlabCache::new("kmalloc-32", 32, 32, None),
+    SlabCache::new("kmalloc-64", 64, 64, None),
+    SlabCache::new("kmalloc-128", 128, 128, None),
+    SlabCache::new("kmalloc-256", 256, 256, None),
+    SlabCache::new("kmalloc-512", 512, 512, None),
+    SlabCache::new("kmalloc-1024", 1024, 1024, None),
+];
+
+/// The `kmalloc-*` cache for a layout, if it is small enough
+/// 
+/// Objects of these caches are aligned to their size, so the alignment
+/// counts towards the size.
+pub fn kmalloc_cache(layout: Layout) -> Option<&'static SlabCache> {
+    let needed = layout.size().max(layout.align());
+    KMALLOC_CACHES.iter().find(|cache| cache.object_size >= needed)
+}
+
+/// Release the empty slabs of every cache
+/// 
+/// Returns the number of frames released.
+pub fn shrink_all() -> usize {
+    let caches = *CACHES.lock();
+    caches.iter().flatten().map(|cache| cache.shrink()).sum()
+}
+
+/// Print the statistics of every cache in use
+pub fn print_caches() {
+    let caches = *CACHES.lock();
+    println!("Cache            Size  Per slab  Slabs  Empty   In use    Allocs     Frees");
+    for cache in caches.iter().flatten() {
+        let stats = cache.stats();
+        println!(
+            "{:<16} {:>4}  {:>8}  {:>5}  {:>5}  {:>7}  {:>8}  {:>8}",
+            cache.name,
+            cache.object_size,
+            cache.capacity,
+            stats.slabs,
+            stats.empty_slabs,
+            stats.objects_in_use,
+            stats.allocations,
+            stats.frees
+        );
+    }
+}
@@ -0,0 +1,104 @@
+/// Kernel stack allocation
+/// 
+/// Kernel stacks are mapped in a dedicated virtual region
+/// (`KERNEL_STACKS_START` - `KERNEL_STACKS_END`). Each stack is preceded by
+/// an unmapped guard page, so running off the bottom of a stack causes a
+/// page fault instead of silently overwriting whatever lies below it.
+/// 
+/// ```text
+///     +------------------+ <- top (initial RSP)
+///     |   stack pages    |
+///     +------------------+ <- bottom
+///     |    guard page    |    (never mapped)
+///     +------------------+
+/// ```
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use super::constants::{KERNEL_STACKS_END, KERNEL_STACKS_START, PAGE_SIZE};
+use super::mapper::{MapError, MapResult};
+use super::paging::{Page, PageTableFlags, VirtAddr};
+use super::FrameAllocator;
+
+/// Next unused address in the kernel stack region
+static NEXT_STACK_ADDR: AtomicU64 = AtomicU64::new(KERNEL_STACKS_START);
+
+/// A mapped kernel stack with a guard page below it
+#[derive(Debug, Clone, Copy)]
+pub struct KernelStack {
+    bottom: VirtAddr,
+    top: VirtAddr,
+}
+
+impl KernelStack {
+    /// Lowest mapped address of the stack
+    pub const fn bottom(&self) -> VirtAddr {
+        self.bottom
+    }
+    
+    /// Address just past the highest byte of the stack (the initial RSP)
+    pub const fn top(&self) -> VirtAddr {
+        self.top
+    }
+}
+
+/// Allocate and map a kernel stack of `pages` 4KB pages
+/// 
+/// Virtual space for the stack is never reused, so a stale pointer into a
+/// freed stack can never alias a newer one. Requires the physical memory
+/// allocator to be initialized.
+pub fn allocate_kernel_stack(pages: usize) -> MapResult<KernelStack> {
+    // Reserve the guard page plus the stack pages
+    let span = ((pages + 1) * PAGE_SIZE) as u64;
+    let guard = NEXT_STACK_ADDR.fetch_add(span, Ordering::Relaxed);
+    if guard + span > KERNEL_STACKS_END {
+        // The 512GB region is exhausted; treat it like running out of memory
+        return Err(MapError::FrameAllocationFailed);
+    }
+    
+    let bottom = guard + PAGE_SIZE as u64;
+    let flags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE);
+    let mut mapper = unsafe { super::active_mapper() };
+    
+    for i in 0..pages {
+        let page = Page::containing_address(VirtAddr::new(bottom + (i * PAGE_SIZE) as u64));
+        
+        if let Err(e) = mapper.map(page, flags) {
+            // Release the pages that were already mapped
+            for j in 0..i {
+                let page = Page::containing_address(VirtAddr::new(bottom + (j * PAGE_SIZE) as u64));
+                if let Ok(frame) = mapper.unmap(page) {
+                    unsafe { super::PhysicalFrameAllocator::new().deallocate_frame(frame); }
+                }
+            }
+            return Err(e);
+        }
+    }
+    
+    Ok(KernelStack {
+        bottom: VirtAddr::new(bottom),
+        top: VirtAddr::new(guard + span),
+    })
+}
+
+// Boot stack (defined in boot.s)
+extern "C" {
+    static stack_bottom: u8;
+}
+
+/// Turn the lowest page of the boot stack into a guard page
+/// 
+/// The boot stack lives in the kernel's `.bss`, which is identity mapped
+/// with 2MB huge pages, so the huge page containing it is split first.
+/// The usable boot stack shrinks by one page.
+pub fn protect_boot_stack() -> MapResult<()> {
+    let guard = unsafe { &stack_bottom as *const u8 as u64 };
+    let page = Page::containing_address(VirtAddr::new(guard));
+    let mut mapper = unsafe { super::active_mapper() };
+    
+    mapper.split_huge_page(page)?;
+    
+    // The frame belongs to the kernel image, so it is not freed
+    mapper.unmap(page)?;
+    
+    Ok(())
+}
@@ -1,99 +1,123 @@
 /// Tests for memory management (physical and virtual)
 
-use super::physical::{allocate_frame, allocate_frames, free_frame, free_frames, memory_stats};
+use super::physical::{
+    allocate_frame, allocate_frames, allocate_frames_in, allocate_frames_with, free_frame,
+    free_frames, memory_ranges, memory_stats, zone_layout, BitmapAllocator, MemoryStats,
+    PhysicalAllocator, RangeKind, RegionList,
+};
+use super::buddy::{BuddyAllocator, MAX_ORDER};
+use super::reserve::{self, ReservationList, ReserveError, MAX_RESERVATIONS};
+use super::zone::{self, Fallback, Zone};
 use super::paging::{
     Page, PageTable, PageTableEntry, PageTableFlags, PageTableLevel,
     PhysAddr, PhysFrame, VirtAddr,
 };
-use super::frame_alloc::{BitmapFrameAllocator, FrameAllocator};
+use super::frame_alloc::PhysicalFrameAllocator;
 use super::mapper::{Mapper, read_cr3};
-use crate::arch::{println, print};
+use super::stack::allocate_kernel_stack;
+use super::heap::{self, HeapAllocator, LinkedListAllocator, SizeClassAllocator};
+use super::slab::{self, SlabCache};
+use super::constants::{KERNEL_HEAP_START, PAGE_SIZE};
+use crate::arch::x86_64::acpi;
+use crate::arch::x86_64::boot::multiboot2::boot_info;
+use core::alloc::Layout;
+use core::ops::{Deref, DerefMut};
+use core::ptr::{addr_of_mut, NonNull};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::{print, println};
 
 /// Run basic physical memory allocator tests
 pub fn test_physical_allocator() {
-    println("=== Testing Physical Memory Allocator ===");
+    println!("=== Testing Physical Memory Allocator ===");
     
     // Test 1: Check initial state
-    let (total, free_before, allocated_before) = memory_stats();
-    print("Initial state: ");
-    print_decimal(free_before);
-    print(" free frames, ");
-    print_decimal(allocated_before);
-    println(" allocated frames");
+    let stats = memory_stats();
+    let (total, free_before, allocated_before) = (stats.total_frames, stats.free_frames, stats.allocated_frames);
+    println!("Initial state: {} free frames, {} allocated frames", free_before, allocated_before);
     
     // Test 2: Allocate a single frame
-    print("Test 1: Allocating single frame... ");
+    print!("Test 1: Allocating single frame... ");
     if let Some(frame1) = allocate_frame() {
-        print("OK (0x");
-        print_hex(frame1 as u64);
-        println(")");
+        println!("OK ({:#018X})", frame1 as u64);
     } else {
-        println("FAILED - no memory available");
+        println!("FAILED - no memory available");
         return;
     }
     
     // Test 3: Allocate another single frame
-    print("Test 2: Allocating another frame... ");
+    print!("Test 2: Allocating another frame... ");
     if let Some(frame2) = allocate_frame() {
-        print("OK (0x");
-        print_hex(frame2 as u64);
-        println(")");
+        println!("OK ({:#018X})", frame2 as u64);
     } else {
-        println("FAILED");
+        println!("FAILED");
         return;
     }
     
     // Test 4: Check that free count decreased
-    let (_, free_after_alloc, _) = memory_stats();
-    print("Test 3: Verifying free count decreased... ");
+    let free_after_alloc = memory_stats().free_frames;
+    print!("Test 3: Verifying free count decreased... ");
     if free_after_alloc == free_before - 2 {
-        println("OK");
+        println!("OK");
     } else {
-        print("FAILED (expected ");
-        print_decimal(free_before - 2);
-        print(", got ");
-        print_decimal(free_after_alloc);
-        println(")");
+        println!("FAILED (expected {}, got {})", free_before - 2, free_after_alloc);
     }
     
     // Test 5: Allocate multiple contiguous frames
-    print("Test 4: Allocating 4 contiguous frames... ");
+    print!("Test 4: Allocating 4 contiguous frames... ");
     if let Some(frames_base) = allocate_frames(4) {
-        print("OK (0x");
-        print_hex(frames_base as u64);
-        println(")");
+        println!("OK ({:#018X})", frames_base as u64);
         
         // Free them immediately
         unsafe {
             free_frames(frames_base, 4);
         }
-        println("Test 5: Freed 4 contiguous frames... OK");
+        println!("Test 5: Freed 4 contiguous frames... OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
     // Test 6: Free the first two frames we allocated
-    print("Test 6: Freeing first allocated frame... ");
+    print!("Test 6: Freeing first allocated frame... ");
     unsafe {
         free_frame(allocate_frame().unwrap());
     }
-    println("OK");
+    println!("OK");
+    
+    // Test 7: Frames for page tables and slabs are written through the
+    // boot identity mapping, so they must lie inside it
+    print!("Test 7: Default-zone frame is identity-mapped... ");
+    match allocate_frame() {
+        Some(frame) => {
+            let mapper = unsafe { super::active_mapper() };
+            let translated = mapper.translate(VirtAddr::new(frame as u64));
+            if translated == Some(PhysAddr::new(frame as u64)) {
+                println!("OK ({:#018X})", frame as u64);
+            } else {
+                println!("FAILED ({:#018X} maps to {:?})", frame as u64, translated);
+            }
+            unsafe {
+                free_frame(frame);
+            }
+        }
+        None => println!("FAILED - no memory available"),
+    }
     
-    // Test 7: Check final state
-    let (_, free_final, allocated_final) = memory_stats();
-    print("Final state: ");
-    print_decimal(free_final);
-    print(" free frames, ");
-    print_decimal(allocated_final);
-    println(" allocated frames");
+    // Test 8: Check final state
+    let stats = memory_stats();
+    let (free_final, allocated_final) = (stats.free_frames, stats.allocated_frames);
+    println!("Final state: {} free frames, {} allocated frames", free_final, allocated_final);
     
-    println("=== Physical Allocator Tests Complete ===");
-    println("");
+    println!("=== Physical Allocator Tests Complete ===");
+    println!();
 }
 
 /// Run virtual memory tests
 pub fn test_virtual_memory() {
-    println("=== Testing Virtual Memory System ===");
+    println!("=== Testing Virtual Memory System ===");
     
     test_virtual_addresses();
     test_physical_addresses();
@@ -103,191 +127,191 @@ pub fn test_virtual_memory() {
     test_page_table_structure();
     test_address_translation_indices();
     
-    println("=== Virtual Memory Tests Complete ===");
-    println("");
+    println!("=== Virtual Memory Tests Complete ===");
+    println!();
 }
 
 /// Test 1: Virtual address operations
 fn test_virtual_addresses() {
-    println("Test 1: Virtual Address Operations");
+    println!("Test 1: Virtual Address Operations");
     
     // Test canonical addresses
-    print("  1a. Lower canonical address... ");
+    print!("  1a. Lower canonical address... ");
     let lower = VirtAddr::new_unchecked(0x0000_7FFF_FFFF_F000);
     if lower.as_u64() == 0x0000_7FFF_FFFF_F000 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  1b. Upper canonical address... ");
+    print!("  1b. Upper canonical address... ");
     let upper = VirtAddr::new_unchecked(0xFFFF_8000_0000_0000);
     if upper.as_u64() == 0xFFFF_8000_0000_0000 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
     // Test alignment operations
-    print("  1c. Address alignment down... ");
+    print!("  1c. Address alignment down... ");
     let addr = VirtAddr::new_unchecked(0xFFFF_8000_0000_1234);
     let aligned = addr.align_down(4096);
     if aligned.as_u64() == 0xFFFF_8000_0000_1000 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  1d. Address alignment up... ");
+    print!("  1d. Address alignment up... ");
     let addr = VirtAddr::new_unchecked(0xFFFF_8000_0000_1234);
     let aligned = addr.align_up(4096);
     if aligned.as_u64() == 0xFFFF_8000_0000_2000 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  1e. Page offset extraction... ");
+    print!("  1e. Page offset extraction... ");
     let addr = VirtAddr::new_unchecked(0xFFFF_8000_0000_1ABC);
     if addr.page_offset() == 0xABC {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    println("");
+    println!();
 }
 
 /// Test 2: Physical address operations
 fn test_physical_addresses() {
-    println("Test 2: Physical Address Operations");
+    println!("Test 2: Physical Address Operations");
     
-    print("  2a. Physical address creation... ");
+    print!("  2a. Physical address creation... ");
     let phys = PhysAddr::new(0x1234_5678);
     if phys.as_u64() == 0x1234_5678 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  2b. Physical address alignment... ");
+    print!("  2b. Physical address alignment... ");
     let phys = PhysAddr::new(0x1000_1234);
     let aligned = phys.align_down(4096);
     if aligned.as_u64() == 0x1000_1000 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  2c. Check 4KB alignment... ");
+    print!("  2c. Check 4KB alignment... ");
     let aligned_addr = PhysAddr::new(0x2000);
     let unaligned_addr = PhysAddr::new(0x2001);
     if aligned_addr.is_aligned(4096) && !unaligned_addr.is_aligned(4096) {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    println("");
+    println!();
 }
 
 /// Test 3: Page and Frame operations
 fn test_page_and_frame() {
-    println("Test 3: Page and Frame Operations");
+    println!("Test 3: Page and Frame Operations");
     
-    print("  3a. Page from virtual address... ");
+    print!("  3a. Page from virtual address... ");
     let addr = VirtAddr::new_unchecked(0xFFFF_8000_0000_1234);
     let page = Page::containing_address(addr);
     if page.start_address().as_u64() == 0xFFFF_8000_0000_1000 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  3b. Frame from physical address... ");
+    print!("  3b. Frame from physical address... ");
     let addr = PhysAddr::new(0x20_0000 + 0x234);
     let frame = PhysFrame::containing_address(addr);
     if frame.start_address().as_u64() == 0x20_0000 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  3c. Page number calculation... ");
+    print!("  3c. Page number calculation... ");
     let page = Page::containing_address(VirtAddr::new_unchecked(0x5000));
     if page.number() == 5 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  3d. Frame number calculation... ");
+    print!("  3d. Frame number calculation... ");
     let frame = PhysFrame::containing_address(PhysAddr::new(0x3000));
     if frame.number() == 3 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    println("");
+    println!();
 }
 
 /// Test 4: Page table flags
 fn test_page_table_flags() {
-    println("Test 4: Page Table Flags");
+    println!("Test 4: Page Table Flags");
     
-    print("  4a. Flag creation and testing... ");
+    print!("  4a. Flag creation and testing... ");
     let flags = PageTableFlags::PRESENT;
     if flags.contains(PageTableFlags::PRESENT) {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  4b. Flag combination with union... ");
+    print!("  4b. Flag combination with union... ");
     let flags = PageTableFlags::PRESENT
         .union(PageTableFlags::WRITABLE)
         .union(PageTableFlags::NO_EXECUTE);
     if flags.contains(PageTableFlags::PRESENT) 
         && flags.contains(PageTableFlags::WRITABLE)
         && flags.contains(PageTableFlags::NO_EXECUTE) {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  4c. Flag bits extraction... ");
+    print!("  4c. Flag bits extraction... ");
     let flags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE);
     if flags.bits() == 0b11 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  4d. Empty flags... ");
+    print!("  4d. Empty flags... ");
     let flags = PageTableFlags::empty();
     if !flags.contains(PageTableFlags::PRESENT) {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    println("");
+    println!();
 }
 
 /// Test 5: Page table entries
 fn test_page_table_entry() {
-    println("Test 5: Page Table Entries");
+    println!("Test 5: Page Table Entries");
     
-    print("  5a. New entry is unused... ");
+    print!("  5a. New entry is unused... ");
     let entry = PageTableEntry::new();
     if entry.is_unused() {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  5b. Set entry address and flags... ");
+    print!("  5b. Set entry address and flags... ");
     let mut entry = PageTableEntry::new();
     let addr = PhysAddr::new(0x1000);
     let flags = PageTableFlags::PRESENT.union(PageTableFlags::WRITABLE);
@@ -295,71 +319,71 @@ fn test_page_table_entry() {
     if entry.addr().as_u64() == 0x1000 
         && entry.flags().contains(PageTableFlags::PRESENT)
         && entry.flags().contains(PageTableFlags::WRITABLE) {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  5c. Entry frame extraction... ");
+    print!("  5c. Entry frame extraction... ");
     let mut entry = PageTableEntry::new();
     entry.set_addr(PhysAddr::new(0x2000), PageTableFlags::PRESENT);
     let frame = entry.frame();
     if frame.start_address().as_u64() == 0x2000 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  5d. Clear entry... ");
+    print!("  5d. Clear entry... ");
     let mut entry = PageTableEntry::new();
     entry.set_addr(PhysAddr::new(0x3000), PageTableFlags::PRESENT);
     entry.set_unused();
     if entry.is_unused() {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    println("");
+    println!();
 }
 
 /// Test 6: Page table structure
 fn test_page_table_structure() {
-    println("Test 6: Page Table Structure");
+    println!("Test 6: Page Table Structure");
     
-    print("  6a. New page table is zeroed... ");
+    print!("  6a. New page table is zeroed... ");
     let table = PageTable::new();
     let all_unused = table.iter().all(|entry| entry.is_unused());
     if all_unused {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  6b. Page table indexing... ");
+    print!("  6b. Page table indexing... ");
     let mut table = PageTable::new();
     table[0].set_addr(PhysAddr::new(0x1000), PageTableFlags::PRESENT);
     if table[0].addr().as_u64() == 0x1000 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    print("  6c. Page table iteration... ");
+    print!("  6c. Page table iteration... ");
     let table = PageTable::new();
     let count = table.iter().count();
     if count == 512 {
-        println("OK");
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    println("");
+    println!();
 }
 
 /// Test 7: Address translation indices
 fn test_address_translation_indices() {
-    println("Test 7: Address Translation Indices");
+    println!("Test 7: Address Translation Indices");
     
     // Test address: 0xFFFF_8000_1234_5678
     // Binary breakdown of bits 47-12:
@@ -369,102 +393,1015 @@ fn test_address_translation_indices() {
     // Bits 20-12 (PT):   69 (0x45)
     let addr = VirtAddr::new_unchecked(0xFFFF_8000_1234_5678);
     
-    print("  7a. PML4 index extraction... ");
+    print!("  7a. PML4 index extraction... ");
     let pml4_idx = addr.page_table_index(PageTableLevel::Four);
     if pml4_idx == 256 {
-        println("OK");
+        println!("OK");
     } else {
-        print("FAILED (got ");
-        print_decimal(pml4_idx);
-        println(")");
+        println!("FAILED (got {})", pml4_idx);
     }
     
-    print("  7b. PDPT index extraction... ");
+    print!("  7b. PDPT index extraction... ");
     let pdpt_idx = addr.page_table_index(PageTableLevel::Three);
     if pdpt_idx == 0 {
-        println("OK");
+        println!("OK");
     } else {
-        print("FAILED (got ");
-        print_decimal(pdpt_idx);
-        println(")");
+        println!("FAILED (got {})", pdpt_idx);
     }
     
-    print("  7c. PD index extraction... ");
+    print!("  7c. PD index extraction... ");
     let pd_idx = addr.page_table_index(PageTableLevel::Two);
     if pd_idx == 145 {
-        println("OK");
+        println!("OK");
     } else {
-        print("FAILED (got ");
-        print_decimal(pd_idx);
-        println(")");
+        println!("FAILED (got {})", pd_idx);
     }
     
-    print("  7d. PT index extraction... ");
+    print!("  7d. PT index extraction... ");
     let pt_idx = addr.page_table_index(PageTableLevel::One);
     if pt_idx == 69 {
-        println("OK");
+        println!("OK");
     } else {
-        print("FAILED (got ");
-        print_decimal(pt_idx);
-        println(")");
+        println!("FAILED (got {})", pt_idx);
     }
     
-    println("");
+    println!();
 }
 
 /// Test 8: CR3 register reading (read-only test)
 pub fn test_cr3_access() {
-    println("Test 8: CR3 Register Access");
+    println!("Test 8: CR3 Register Access");
     
-    print("  8a. Read CR3 register... ");
+    print!("  8a. Read CR3 register... ");
     let cr3_addr = read_cr3();
     // CR3 should be page-aligned and non-zero
     if cr3_addr.as_u64() != 0 && cr3_addr.is_aligned(4096) {
-        print("OK (PML4 at 0x");
-        print_hex(cr3_addr.as_u64());
-        println(")");
+        println!("OK (PML4 at {:#018X})", cr3_addr.as_u64());
+    } else {
+        println!("FAILED");
+    }
+    
+    println!();
+}
+
+/// Test 9: Guarded kernel stack allocation
+pub fn test_kernel_stack() {
+    println!("Test 9: Guarded Kernel Stacks");
+    
+    print!("  9a. Allocate 2-page kernel stack... ");
+    let stack = match allocate_kernel_stack(2) {
+        Ok(stack) => stack,
+        Err(_) => {
+            println!("FAILED");
+            println!();
+            return;
+        }
+    };
+    println!("OK (top at {:#018X})", stack.top().as_u64());
+    
+    let mapper = unsafe { super::active_mapper() };
+    
+    print!("  9b. Stack pages mapped... ");
+    let top_mapped = mapper.translate(VirtAddr::new(stack.top().as_u64() - 8)).is_some();
+    let bottom_mapped = mapper.translate(stack.bottom()).is_some();
+    if top_mapped && bottom_mapped {
+        println!("OK");
+    } else {
+        println!("FAILED");
+    }
+    
+    print!("  9c. Guard page unmapped... ");
+    if mapper.translate(VirtAddr::new(stack.bottom().as_u64() - 8)).is_none() {
+        println!("OK");
     } else {
-        println("FAILED");
+        println!("FAILED");
     }
     
-    println("");
+    print!("  9d. Stacks do not overlap... ");
+    match allocate_kernel_stack(1) {
+        Ok(next) if next.bottom().as_u64() > stack.top().as_u64() => println!("OK"),
+        _ => println!("FAILED"),
+    }
+    
+    println!();
 }
 
-/// Helper to print hex value
-fn print_hex(value: u64) {
-    const HEX_CHARS: &[u8; 16] = b"0123456789ABCDEF";
-    let mut buffer = [0u8; 16];
+/// Memory handed to the heap allocators under test
+#[repr(C, align(4096))]
+struct Arena([u8; 4 * PAGE_SIZE]);
+
+static mut ARENA: Arena = Arena([0; 4 * PAGE_SIZE]);
+
+/// Test 10: Kernel heap and the allocators behind it
+pub fn test_heap() {
+    println!("Test 10: Kernel Heap ({})", heap::ALLOCATOR_NAME);
+    
+    let before = heap::heap_stats();
+    
+    print!("  10a. Box and Vec... ");
+    {
+        let boxed = Box::new(0x1234_5678u64);
+        let mut numbers = Vec::new();
+        for i in 0..100u32 {
+            numbers.push(i * i);
+        }
+        // Small allocations come from the slab caches, large ones from the heap
+        let large: Vec<u8> = Vec::with_capacity(2 * PAGE_SIZE);
+        let in_heap = large.as_ptr() as u64 >= KERNEL_HEAP_START;
+        if *boxed == 0x1234_5678 && numbers.iter().sum::<u32>() == 328350 && in_heap {
+            println!("OK");
+        } else {
+            println!("FAILED");
+        }
+    }
+    
+    print!("  10b. String and BTreeMap... ");
+    {
+        let mut text = String::from("noodle");
+        text.push_str("os");
+        let mut map = BTreeMap::new();
+        for (i, word) in ["zero", "one", "two", "three"].iter().enumerate() {
+            map.insert(*word, i);
+        }
+        if text == "noodleos" && map.get("two") == Some(&2) && map.keys().next() == Some(&"one") {
+            println!("OK");
+        } else {
+            println!("FAILED");
+        }
+    }
+    
+    print!("  10c. Freed allocations are returned... ");
+    let after = heap::heap_stats();
+    if after.allocations == before.allocations && after.used == before.used {
+        println!("OK");
+    } else {
+        println!("FAILED ({} allocations, {} bytes still in use)", after.allocations - before.allocations, after.used - before.used);
+    }
     
-    for i in 0..16 {
-        let nibble = ((value >> (60 - i * 4)) & 0xF) as usize;
-        buffer[i] = HEX_CHARS[nibble];
+    print!("  10d. Heap grows on demand... ");
+    {
+        let size = before.size + heap::HEAP_GROW_SIZE;
+        let mut large: Vec<u8> = Vec::with_capacity(size);
+        large.resize(size, 0xAB);
+        let grown = heap::heap_stats().size;
+        if grown > before.size && large[size - 1] == 0xAB {
+            println!("OK ({} KB mapped)", grown / 1024);
+        } else {
+            println!("FAILED");
+        }
+    }
+    
+    let arena = addr_of_mut!(ARENA) as usize;
+    let layout = |size, align| Layout::from_size_align(size, align).unwrap();
+    
+    print!("  10e. Linked list merges freed blocks... ");
+    let mut list = LinkedListAllocator::new();
+    unsafe { list.add_region(arena, 4 * PAGE_SIZE); }
+    let blocks = [list.allocate(layout(100, 8)), list.allocate(layout(200, 8)), list.allocate(layout(300, 8))];
+    if let [Some(a), Some(b), Some(c)] = blocks {
+        let disjoint = a.as_ptr() as usize + 100 <= b.as_ptr() as usize && b.as_ptr() as usize + 200 <= c.as_ptr() as usize;
+        unsafe {
+            list.deallocate(b, layout(200, 8));
+            list.deallocate(a, layout(100, 8));
+            list.deallocate(c, layout(300, 8));
+        }
+        if disjoint && list.free_blocks() == 1 && list.free_bytes() == 4 * PAGE_SIZE {
+            println!("OK");
+        } else {
+            println!("FAILED ({} free blocks, {} bytes)", list.free_blocks(), list.free_bytes());
+        }
+    } else {
+        println!("FAILED");
+    }
+    
+    print!("  10f. Linked list alignment... ");
+    let small = list.allocate(layout(16, 16));
+    let aligned = list.allocate(layout(64, PAGE_SIZE));
+    let too_large = list.allocate(layout(4 * PAGE_SIZE, 8));
+    match (small, aligned, too_large) {
+        (Some(small), Some(aligned), None) if (aligned.as_ptr() as usize).is_multiple_of(PAGE_SIZE) => {
+            unsafe {
+                list.deallocate(aligned, layout(64, PAGE_SIZE));
+                list.deallocate(small, layout(16, 16));
+            }
+            if list.free_blocks() == 1 {
+                println!("OK");
+            } else {
+                println!("FAILED (free list not merged)");
+            }
+        }
+        _ => println!("FAILED"),
+    }
+    
+    print!("  10g. Size classes reuse freed blocks... ");
+    let mut classes = SizeClassAllocator::new();
+    unsafe { classes.add_region(arena, 4 * PAGE_SIZE); }
+    match (classes.allocate(layout(24, 8)), classes.allocate(layout(24, 8))) {
+        (Some(a), Some(b)) => {
+            let distance = (b.as_ptr() as usize).abs_diff(a.as_ptr() as usize);
+            unsafe { classes.deallocate(a, layout(24, 8)); }
+            let reused = classes.allocate(layout(32, 32)) == Some(a);
+            if distance == 32 && reused {
+                println!("OK");
+            } else {
+                println!("FAILED (blocks {} bytes apart)", distance);
+            }
+        }
+        _ => println!("FAILED"),
+    }
+    
+    print!("  10h. Size classes pass large blocks through... ");
+    match classes.allocate(layout(PAGE_SIZE, 8)) {
+        Some(block) if (arena..arena + 4 * PAGE_SIZE).contains(&(block.as_ptr() as usize)) => println!("OK"),
+        _ => println!("FAILED"),
     }
     
-    let s = unsafe { core::str::from_utf8_unchecked(&buffer) };
-    print(s);
+    println!();
 }
 
-/// Helper to print decimal
-fn print_decimal(mut value: usize) {
-    if value == 0 {
-        print("0");
+/// Objects constructed by `TEST_CACHE`
+static CONSTRUCTED: AtomicUsize = AtomicUsize::new(0);
+
+/// Value the constructor leaves in every test object
+const CONSTRUCTED_MARK: u64 = 0x5AB5_5AB5_5AB5_5AB5;
+
+fn construct_test_object(object: NonNull<u8>) {
+    unsafe { object.cast::<u64>().write(CONSTRUCTED_MARK); }
+    CONSTRUCTED.fetch_add(1, Ordering::Relaxed);
+}
+
+static TEST_CACHE: SlabCache = SlabCache::new("test-object", 200, 64, Some(construct_test_object));
+
+/// Test 11: Slab caches
+pub fn test_slab() {
+    println!("Test 11: Slab Allocator");
+    
+    let per_slab = TEST_CACHE.objects_per_slab();
+    let constructed_before = CONSTRUCTED.load(Ordering::Relaxed);
+    
+    print!("  11a. Allocate constructed, aligned objects... ");
+    let (Some(first), Some(second)) = (TEST_CACHE.alloc(), TEST_CACHE.alloc()) else {
+        println!("FAILED");
+        println!();
         return;
+    };
+    let constructed = CONSTRUCTED.load(Ordering::Relaxed) - constructed_before;
+    let aligned = (first.as_ptr() as usize).is_multiple_of(64) && (second.as_ptr() as usize).is_multiple_of(64);
+    let marked = unsafe { *first.cast::<u64>().as_ptr() == CONSTRUCTED_MARK };
+    if aligned && marked && first != second && (constructed == 0 || constructed == per_slab) {
+        println!("OK ({} objects per slab)", per_slab);
+    } else {
+        println!("FAILED");
+    }
+    
+    print!("  11b. Freed object is reused without reconstruction... ");
+    unsafe { TEST_CACHE.free(second); }
+    let constructed = CONSTRUCTED.load(Ordering::Relaxed);
+    match TEST_CACHE.alloc() {
+        Some(again) if again == second && CONSTRUCTED.load(Ordering::Relaxed) == constructed => println!("OK"),
+        _ => println!("FAILED"),
+    }
+    
+    print!("  11c. Full slab adds another slab... ");
+    let stats = TEST_CACHE.stats();
+    let mut objects = Vec::new();
+    for _ in stats.objects_in_use..per_slab + 1 {
+        match TEST_CACHE.alloc() {
+            Some(object) => objects.push(object),
+            None => break,
+        }
+    }
+    let grown = TEST_CACHE.stats();
+    if grown.slabs > stats.slabs && grown.objects_in_use == per_slab + 1 + stats.objects_in_use - 2 {
+        println!("OK ({} slabs)", grown.slabs);
+    } else {
+        println!("FAILED ({} slabs, {} objects)", grown.slabs, grown.objects_in_use);
+    }
+    
+    print!("  11d. Shrink returns empty slabs... ");
+    for object in objects.drain(..) {
+        unsafe { TEST_CACHE.free(object); }
+    }
+    unsafe {
+        TEST_CACHE.free(first);
+        TEST_CACHE.free(second);
+    }
+    let free_before = memory_stats().free_frames;
+    let empty = TEST_CACHE.stats().empty_slabs;
+    let released = TEST_CACHE.shrink();
+    let free_after = memory_stats().free_frames;
+    let stats = TEST_CACHE.stats();
+    if released == empty && released > 0 && free_after == free_before + released && stats.slabs == 0 {
+        println!("OK ({} frames released)", released);
+    } else {
+        println!("FAILED ({} of {} released)", released, empty);
+    }
+    
+    print!("  11e. Small allocations use the kmalloc caches... ");
+    let cache = slab::kmalloc_cache(Layout::new::<[u64; 3]>());
+    let before = cache.map(|cache| cache.stats().objects_in_use);
+    let boxed = Box::new([1u64, 2, 3]);
+    let during = cache.map(|cache| cache.stats().objects_in_use);
+    let outside_heap = (&*boxed as *const [u64; 3] as u64) < KERNEL_HEAP_START;
+    drop(boxed);
+    let after = cache.map(|cache| cache.stats().objects_in_use);
+    match (cache, before, during, after) {
+        (Some(cache), Some(before), Some(during), Some(after)) if during == before + 1 && after == before && outside_heap => {
+            println!("OK ({})", cache.name());
+        }
+        _ => println!("FAILED"),
+    }
+    
+    println!();
+}
+
+/// Test 12: Frame allocator over a memory map with a hole
+/// 
+/// Uses its own allocator over made-up regions; only the bitmap is ever
+/// written, never the frames themselves.
+pub fn test_sparse_physical_memory() {
+    println!("Test 12: Sparse Physical Memory");
+    
+    const LOW: u64 = 0x10_0000;
+    const HIGH: u64 = 0x1_0000_0000;
+    let in_region = |addr: usize| {
+        let addr = addr as u64;
+        (LOW..LOW + 0x8_0000).contains(&addr) || (HIGH..HIGH + 0x4_0000).contains(&addr)
+    };
+    
+    print!("  12a. Regions merged and sorted... ");
+    let mut allocator = TestAllocator::new(BitmapAllocator::new(), &[
+        (HIGH, HIGH + 0x4_0000),
+        (LOW + 0x4_0000, LOW + 0x8_0000),
+        (LOW, LOW + 0x4_0000),
+        // Less than a whole frame, so nothing is added
+        (HIGH + 0x4_0100, HIGH + 0x4_1100),
+    ]);
+    let layout = allocator.layout();
+    if layout.regions == 2
+        && layout.highest_address as u64 == HIGH + 0x4_0000
+        && allocator.metadata_size() == 16 + 8
+        && allocator.total_frames() == 192
+        && allocator.get_free_frames() == 192
+    {
+        println!("OK ({} bytes of bitmap)", allocator.metadata_size());
+    } else {
+        println!("FAILED ({} regions, {} frames)", layout.regions, allocator.total_frames());
+    }
+    
+    print!("  12b. Reserved ranges are not free... ");
+    allocator.mark_region_reserved(LOW as usize, LOW as usize + 2 * PAGE_SIZE);
+    // Lies in the hole, so changes nothing
+    allocator.mark_region_reserved(0x20_0000, 0x30_0000);
+    if allocator.get_free_frames() == 190 {
+        println!("OK");
+    } else {
+        println!("FAILED ({} free)", allocator.get_free_frames());
+    }
+    
+    print!("  12c. Contiguous runs never span the hole... ");
+    let low_run = allocator.allocate_frames(120, PAGE_SIZE);
+    let high_run = allocator.allocate_frames(10, PAGE_SIZE);
+    match (low_run, high_run) {
+        (Some(low), Some(high)) if low as u64 == LOW + 2 * PAGE_SIZE as u64 && high as u64 == HIGH => {
+            println!("OK (second run at {:#X})", high);
+        }
+        _ => println!("FAILED ({:?}, {:?})", low_run, high_run),
+    }
+    
+    print!("  12d. Single frames come from both regions only... ");
+    let mut frames = Vec::new();
+    while let Some(frame) = allocator.allocate_frame() {
+        frames.push(frame);
+    }
+    let above_4g = frames.iter().filter(|&&frame| frame as u64 >= HIGH).count();
+    if frames.len() == 60 && frames.iter().all(|&frame| in_region(frame)) && above_4g == 54 {
+        println!("OK ({} above 4GB)", above_4g);
+    } else {
+        println!("FAILED ({} frames, {} above 4GB)", frames.len(), above_4g);
+    }
+    
+    print!("  12e. Freed frames are reused... ");
+    unsafe {
+        for &frame in &frames {
+            allocator.free_frame(frame);
+        }
+        if let Some(low) = low_run {
+            allocator.free_frames(low, 120);
+        }
+        if let Some(high) = high_run {
+            allocator.free_frames(high, 10);
+        }
+    }
+    let free = allocator.get_free_frames();
+    let again = allocator.allocate_frames(126, PAGE_SIZE);
+    if free == 190 && again.is_some_and(|addr| addr as u64 == LOW + 2 * PAGE_SIZE as u64) {
+        println!("OK");
+    } else {
+        println!("FAILED ({} free)", free);
+    }
+    
+    println!();
+}
+
+/// An allocator over made-up regions, for tests 12 to 16
+/// 
+/// Its metadata lives on the heap and is freed when the fixture is
+/// dropped. Only the metadata is ever written, never the frames.
+struct TestAllocator<A: PhysicalAllocator> {
+    allocator: A,
+    metadata: *mut [u8],
+}
+
+impl<A: PhysicalAllocator> TestAllocator<A> {
+    /// Give `allocator` the ranges `[start, end)` as its regions
+    fn new(allocator: A, ranges: &[(u64, u64)]) -> Self {
+        let mut regions = RegionList::new();
+        for &(start, end) in ranges {
+            regions.add(start, end);
+        }
+        Self::with_regions(allocator, regions)
+    }
+    
+    fn with_regions(mut allocator: A, regions: RegionList) -> Self {
+        *allocator.regions_mut() = regions;
+        let metadata = Box::leak(alloc::vec![0u8; allocator.metadata_size()].into_boxed_slice());
+        let ptr = metadata as *mut [u8];
+        unsafe {
+            allocator.set_metadata(metadata);
+        }
+        TestAllocator { allocator, metadata: ptr }
+    }
+}
+
+impl<A: PhysicalAllocator> Deref for TestAllocator<A> {
+    type Target = A;
+    
+    fn deref(&self) -> &A {
+        &self.allocator
+    }
+}
+
+impl<A: PhysicalAllocator> DerefMut for TestAllocator<A> {
+    fn deref_mut(&mut self) -> &mut A {
+        &mut self.allocator
+    }
+}
+
+impl<A: PhysicalAllocator> Drop for TestAllocator<A> {
+    fn drop(&mut self) {
+        // The allocator is not used after this
+        unsafe { drop(Box::from_raw(self.metadata)) };
+    }
+}
+
+/// Test 13: Buddy allocator
+/// 
+/// Like test 12 it works on made-up regions: an 8MB one at 4MB and one of
+/// 15 frames after a single-frame hole, which is not aligned.
+pub fn test_buddy_allocator() {
+    println!("Test 13: Buddy Allocator");
+    
+    const BIG: u64 = 0x40_0000;
+    const SMALL: u64 = 0xC0_1000;
+    let mut allocator = TestAllocator::new(BuddyAllocator::new(), &[
+        (BIG, BIG + 0x80_0000),
+        (SMALL, SMALL + 15 * PAGE_SIZE as u64),
+    ]);
+    let free_blocks = |allocator: &BuddyAllocator, order: usize| allocator.order_stats()[order].free_blocks;
+    
+    print!("  13a. Regions cut into aligned blocks... ");
+    let stats = allocator.order_stats();
+    let small_blocks = (0..4).all(|order| stats[order].free_blocks == 1);
+    let no_others = (4..MAX_ORDER).all(|order| stats[order].free_blocks == 0);
+    if allocator.get_free_frames() == 2063 && stats[MAX_ORDER].free_blocks == 2 && small_blocks && no_others {
+        println!("OK ({} frames)", allocator.total_frames());
+    } else {
+        println!("FAILED ({} free)", allocator.get_free_frames());
+    }
+    
+    print!("  13b. Allocations are aligned and split larger blocks... ");
+    let huge = allocator.allocate_frames(512, 0x20_0000);
+    let odd = allocator.allocate_frames(3, PAGE_SIZE);
+    let split = allocator.order_stats()[MAX_ORDER].splits == 1 && free_blocks(&allocator, 9) == 1;
+    match (huge, odd) {
+        (Some(huge), Some(odd))
+            if huge.is_multiple_of(0x20_0000)
+                && odd.is_multiple_of(4 * PAGE_SIZE)
+                && split
+                && allocator.get_free_frames() == 2063 - 515 =>
+        {
+            println!("OK ({:#X}, {:#X})", huge, odd);
+        }
+        _ => println!("FAILED ({:?}, {:?})", huge, odd),
     }
     
-    let mut buffer = [0u8; 20];
-    let mut i = 0;
+    print!("  13c. Freed blocks merge with their buddies... ");
+    unsafe {
+        if let Some(huge) = huge {
+            allocator.free_frames(huge, 512);
+        }
+        if let Some(odd) = odd {
+            allocator.free_frames(odd, 3);
+        }
+    }
+    let stats = allocator.order_stats();
+    let merged = stats[MAX_ORDER].free_blocks == 2 && (0..4).all(|order| stats[order].free_blocks == 1);
+    if merged && allocator.get_free_frames() == 2063 && stats[9].merges > 0 {
+        println!("OK");
+    } else {
+        println!("FAILED ({} free)", allocator.get_free_frames());
+    }
+    
+    print!("  13d. Reserving a frame splits its block... ");
+    allocator.mark_region_reserved(BIG as usize, BIG as usize + PAGE_SIZE);
+    let first = allocator.allocate_frames(1 << MAX_ORDER, PAGE_SIZE);
+    let second = allocator.allocate_frames(1 << MAX_ORDER, PAGE_SIZE);
+    if first.is_some() && second.is_none() && free_blocks(&allocator, MAX_ORDER - 1) == 1 {
+        println!("OK");
+    } else {
+        println!("FAILED ({:?}, {:?})", first, second);
+    }
+    
+    print!("  13e. Oversized requests and double frees are refused... ");
+    let oversized = allocator.allocate_frames(2 << MAX_ORDER, PAGE_SIZE);
+    unsafe {
+        if let Some(first) = first {
+            allocator.free_frames(first, 1 << MAX_ORDER);
+        }
+        let free = allocator.get_free_frames();
+        allocator.free_frame(SMALL as usize);
+        allocator.free_frame(BIG as usize + PAGE_SIZE);
+        if oversized.is_none() && free == 2062 && allocator.get_free_frames() == free {
+            println!("OK");
+        } else {
+            println!("FAILED ({} free)", allocator.get_free_frames());
+        }
+    }
+    
+    print!("  13f. Allocations take the lowest free block... ");
+    let low = allocator.allocate_frame();
+    let high = allocator.allocate_frame();
+    unsafe {
+        // The high frame is freed last
+        for frame in [low, high].into_iter().flatten() {
+            allocator.free_frame(frame);
+        }
+    }
+    let next = allocator.allocate_frame();
+    if low == Some(BIG as usize + PAGE_SIZE) && high == Some(SMALL as usize) && next == low {
+        println!("OK");
+    } else {
+        println!("FAILED ({:?}, {:?}, {:?})", low, high, next);
+    }
+    
+    print!("  13g. Overlapping double frees only free frames in use... ");
+    let free = allocator.get_free_frames();
+    let block = allocator.allocate_frames(8, 8 * PAGE_SIZE);
+    unsafe {
+        if let Some(block) = block {
+            // Frames 2 and 3 first, then the whole block again
+            allocator.free_frames(block + 2 * PAGE_SIZE, 2);
+            allocator.free_frames(block, 8);
+        }
+    }
+    let again = allocator.allocate_frames(8, 8 * PAGE_SIZE);
+    let taken = allocator.get_free_frames();
+    unsafe {
+        if let Some(again) = again {
+            allocator.free_frames(again, 8);
+        }
+    }
+    if block.is_some() && again == block && taken == free - 8 && allocator.get_free_frames() == free {
+        println!("OK");
+    } else {
+        println!("FAILED ({:?}, {:?}, {} free)", block, again, allocator.get_free_frames());
+    }
+    
+    println!();
+}
+
+/// Test 14: Frame allocator benchmark
+/// 
+/// A bitmap and a buddy allocator each manage the same made-up 64MB region
+/// with a few frames reserved, and run the same sequence of requests
+/// through the `FrameAllocator` trait. Every request must be served and
+/// every frame freed again; the cycles per operation are printed.
+pub fn bench_frame_allocators() {
+    println!("Test 14: Frame Allocator Benchmark");
     
-    while value > 0 {
-        buffer[i] = b'0' + (value % 10) as u8;
-        value /= 10;
-        i += 1;
+    let mut bitmap = bench_allocator(BitmapAllocator::new());
+    let mut buddy = bench_allocator(BuddyAllocator::new());
+    let free = (bitmap.get_free_frames(), buddy.get_free_frames());
+    
+    print!("  14a. Both allocators serve every request... ");
+    let bitmap_results = run_frame_benchmark(&mut *bitmap);
+    let buddy_results = run_frame_benchmark(&mut *buddy);
+    match (bitmap_results, buddy_results) {
+        (Some(_), Some(_)) => println!("OK"),
+        _ => println!("FAILED (bitmap {}, buddy {})", bitmap_results.is_some(), buddy_results.is_some()),
+    }
+    if let (Some(bitmap_results), Some(buddy_results)) = (bitmap_results, buddy_results) {
+        println!("  Cycles per operation      bitmap       buddy");
+        for (index, name) in ["4096 single frames", "256 runs of 16 frames", "16 aligned 2MB runs"].iter().enumerate() {
+            println!("  {:<22} {:>9}   {:>9}", name, bitmap_results[index], buddy_results[index]);
+        }
+    }
+    
+    print!("  14b. Both allocators end with all frames free... ");
+    let after = (bitmap.get_free_frames(), buddy.get_free_frames());
+    if after == free && free.0 == free.1 {
+        println!("OK ({} frames)", free.0);
+    } else {
+        println!("FAILED ({} and {} free, {} and {} before)", after.0, after.1, free.0, free.1);
+    }
+    
+    println!();
+}
+
+/// An allocator with the benchmark's region and reservations
+fn bench_allocator<A: PhysicalAllocator>(allocator: A) -> TestAllocator<A> {
+    const START: u64 = 0x100_0000;
+    let mut allocator = TestAllocator::new(allocator, &[(START, START + 0x400_0000)]);
+    for offset in [0x3000, 0x81_0000, 0x200_5000] {
+        let start = START as usize + offset;
+        allocator.mark_region_reserved(start, start + PAGE_SIZE);
+    }
+    allocator
+}
+
+/// Average cycles per allocation and free of the benchmark's steps
+/// 
+/// Returns None if a request failed.
+fn run_frame_benchmark<A: super::frame_alloc::FrameAllocator>(allocator: &mut A) -> Option<[u64; 3]> {
+    use super::paging::PhysFrame;
+    use crate::arch::x86_64::cpu::rdtsc;
+    
+    let steps: [(usize, usize, usize); 3] = [(4096, 1, PAGE_SIZE), (256, 16, PAGE_SIZE), (16, 512, 0x20_0000)];
+    let mut results = [0; 3];
+    let mut frames: Vec<PhysFrame> = Vec::with_capacity(4096);
+    let mut served = true;
+    for (index, &(runs, count, align)) in steps.iter().enumerate() {
+        let start = rdtsc();
+        for _ in 0..runs {
+            match allocator.allocate_frames(count, align) {
+                Ok(frame) => frames.push(frame),
+                Err(_) => {
+                    served = false;
+                    break;
+                }
+            }
+        }
+        let operations = 2 * frames.len().max(1) as u64;
+        for frame in frames.drain(..) {
+            unsafe { allocator.deallocate_frames(frame, count); }
+        }
+        results[index] = (rdtsc() - start) / operations;
+    }
+    served.then_some(results)
+}
+
+/// Test 15: Memory zones
+/// 
+/// Allocates from the live zones, then checks the zone split and the
+/// fallback reserve on made-up regions and totals.
+pub fn test_zones() {
+    println!("Test 15: Memory Zones");
+    
+    let stats = memory_stats();
+    let (_, dma_end) = Zone::Dma.range();
+    let (_, dma32_end) = Zone::Dma32.range();
+    
+    print!("  15a. Zones add up to all memory... ");
+    let sum: usize = stats.zones.iter().map(|zone| zone.total_frames).sum();
+    let inside = Zone::ALL.iter().all(|&zone| {
+        let layout = zone_layout(zone);
+        layout.regions == 0 || layout.highest_address as u64 <= zone.range().1
+    });
+    if sum == stats.total_frames && inside {
+        println!("OK ({} DMA, {} DMA32, {} Normal frames)",
+            stats.zones[0].total_frames, stats.zones[1].total_frames, stats.zones[2].total_frames);
+    } else {
+        println!("FAILED ({} of {} frames)", sum, stats.total_frames);
+    }
+    
+    print!("  15b. DMA allocations stay below 16MB... ");
+    let dma = allocate_frames_in(Zone::Dma, 4, PAGE_SIZE);
+    let after = memory_stats().zones[Zone::Dma.index()];
+    match dma {
+        Some(addr) if (addr as u64) + 4 * PAGE_SIZE as u64 <= dma_end
+            && after.allocations == stats.zones[Zone::Dma.index()].allocations + 1 =>
+        {
+            println!("OK ({:#X})", addr);
+        }
+        _ => println!("FAILED ({:?})", dma),
+    }
+    
+    print!("  15c. Aligned DMA32 allocations stay below 4GB... ");
+    let dma32 = allocate_frames_in(Zone::Dma32, 512, 0x20_0000);
+    match dma32 {
+        Some(addr) if addr.is_multiple_of(0x20_0000) && (addr as u64) < dma32_end => println!("OK ({:#X})", addr),
+        _ => println!("FAILED ({:?})", dma32),
+    }
+    
+    print!("  15d. Normal allocations fall back without high memory... ");
+    let before = memory_stats();
+    let normal = allocate_frames_in(Zone::Normal, 1, PAGE_SIZE);
+    let after = memory_stats();
+    let fallbacks = |stats: &MemoryStats| stats.zones.iter().map(|zone| zone.fallback_allocations).sum::<u64>();
+    let served = match normal {
+        Some(addr) if before.zones[Zone::Normal.index()].free_frames > 0 => addr as u64 >= dma32_end,
+        Some(_) => fallbacks(&after) == fallbacks(&before) + 1,
+        None => false,
+    };
+    if served {
+        println!("OK ({:#X})", normal.unwrap_or(0));
+    } else {
+        println!("FAILED ({:?})", normal);
     }
     
-    // Reverse
-    for j in 0..i/2 {
-        buffer.swap(j, i - 1 - j);
+    print!("  15e. No fallback means only the zone asked for... ");
+    let strict = allocate_frames_with(Zone::Normal, 1, PAGE_SIZE, Fallback::None);
+    let after = memory_stats().zones[Zone::Normal.index()];
+    let refused = after.failures == stats.zones[Zone::Normal.index()].failures + 1;
+    let correct = match strict {
+        Some(addr) => addr as u64 >= dma32_end,
+        None => stats.zones[Zone::Normal.index()].free_frames == 0 && refused,
+    };
+    if correct {
+        println!("OK ({})", if strict.is_some() { "served" } else { "refused" });
+    } else {
+        println!("FAILED ({:?})", strict);
+    }
+    
+    unsafe {
+        for (addr, count) in [(dma, 4), (dma32, 512), (normal, 1), (strict, 1)] {
+            if let Some(addr) = addr {
+                free_frames(addr, count);
+            }
+        }
+    }
+    
+    print!("  15f. Regions split at zone limits and reserves grow... ");
+    let mut regions = RegionList::new();
+    regions.add(0x10_0000, 0x2000_0000);
+    regions.add(0xF000_0000, 0x1_1000_0000);
+    let page = |addr: u64| (addr / PAGE_SIZE as u64) as usize;
+    let dma_part = regions.clip(0, page(dma_end));
+    let dma32_part = regions.clip(page(dma_end), page(dma32_end));
+    let normal_part = regions.clip(page(dma32_end), usize::MAX);
+    let split = dma_part.total_frames() == 0xF00
+        && dma32_part.as_slice().len() == 2
+        && normal_part.total_frames() == 0x1_0000
+        && dma_part.total_frames() + dma32_part.total_frames() + normal_part.total_frames() == regions.total_frames();
+    let totals = [4096, 1 << 20, 1 << 22];
+    let reserves = (
+        zone::lowmem_reserve(&totals, Zone::Dma, Zone::Dma32),
+        zone::lowmem_reserve(&totals, Zone::Dma, Zone::Normal),
+        zone::lowmem_reserve(&totals, Zone::Dma32, Zone::Normal),
+        zone::lowmem_reserve(&totals, Zone::Dma32, Zone::Dma32),
+    );
+    if split && reserves == (4096, 20480, 16384, 0) {
+        println!("OK");
+    } else {
+        println!("FAILED ({} regions, reserves {:?})", dma32_part.as_slice().len(), reserves);
+    }
+    
+    print!("  15g. Limited allocations stay below the limit... ");
+    let bitmap = check_allocation_limit(BitmapAllocator::new());
+    let buddy = check_allocation_limit(BuddyAllocator::new());
+    if bitmap && buddy {
+        println!("OK");
+    } else {
+        println!("FAILED (bitmap {}, buddy {})", bitmap, buddy);
+    }
+    
+    println!();
+}
+
+/// Check `allocate_frames_below` on a made-up 32MB region with its limit
+/// in the middle
+fn check_allocation_limit<A: PhysicalAllocator>(allocator: A) -> bool {
+    const START: usize = 0x100_0000;
+    const LIMIT: usize = 0x200_0000;
+    let mut allocator = TestAllocator::new(allocator, &[(START as u64, 0x300_0000)]);
+    
+    // Leave 3 frames below the limit, so a run of 4 has to cross it
+    allocator.mark_region_reserved(START, LIMIT - 3 * PAGE_SIZE);
+    let single = allocator.allocate_frames_below(1, PAGE_SIZE, LIMIT);
+    let run = allocator.allocate_frames_below(4, PAGE_SIZE, LIMIT);
+    let unlimited = allocator.allocate_frames(4, PAGE_SIZE);
+    single.is_some_and(|addr| addr < LIMIT) && run.is_none() && unlimited.is_some_and(|addr| addr + 4 * PAGE_SIZE > LIMIT)
+}
+
+/// Test 16: Fenced and reclaimed memory
+/// 
+/// Fences off made-up ranges in an allocator of its own, then checks that
+/// the ACPI tables were copied before their memory was reclaimed and that
+/// the DSDT survived it.
+pub fn test_fenced_memory() {
+    println!("Test 16: Fenced and Reclaimed Memory");
+    
+    const START: u64 = 0x100_0000;
+    
+    print!("  16a. Removing a range splits its region... ");
+    let mut regions = RegionList::new();
+    regions.add(START, START + 0x10_0000);
+    regions.add(START + 0x20_0000, START + 0x30_0000);
+    // Covers parts of frames 0x10 and 0x11 of the first region
+    regions.remove(START + 0x1_0800, START + 0x1_1800);
+    regions.remove(START + 0x20_0000, START + 0x30_0000);
+    let slice = regions.as_slice();
+    if slice.len() == 2 && slice[0].frames == 0x10 && slice[1].start_frame == (START as usize / PAGE_SIZE) + 0x12 {
+        println!("OK ({} frames left)", regions.total_frames());
+    } else {
+        println!("FAILED ({} regions, {} frames)", slice.len(), regions.total_frames());
+    }
+    
+    print!("  16b. memmap ranges parse... ");
+    let parsed = (
+        super::parse_memmap("64K$16M"),
+        super::parse_memmap("0x1000$0x9F000"),
+        super::parse_memmap("64K"),
+        super::parse_memmap("0$1M"),
+    );
+    if parsed == (Some((0x100_0000, 0x101_0000)), Some((0x9_F000, 0xA_0000)), None, None) {
+        println!("OK");
+    } else {
+        println!("FAILED ({:?})", parsed);
+    }
+    
+    print!("  16c. Fenced frames are never handed out... ");
+    let mut allocator = TestAllocator::with_regions(BitmapAllocator::new(), regions);
+    unsafe {
+        // Freeing a fenced frame must not make it usable
+        allocator.free_frame(START as usize + 0x1_0000);
+    }
+    let mut frames = Vec::new();
+    while let Some(frame) = allocator.allocate_frame() {
+        frames.push(frame);
+    }
+    let fenced = |frame: &usize| (START as usize + 0x1_0000..START as usize + 0x1_2000).contains(frame);
+    if frames.len() == 0xFE && !frames.iter().any(fenced) {
+        println!("OK");
+    } else {
+        println!("FAILED ({} frames)", frames.len());
+    }
+    
+    print!("  16d. ACPI tables are read from copies... ");
+    let tables: Vec<_> = acpi::tables().collect();
+    let copied = tables.iter().all(|table| table.data().as_ptr() as u64 != table.addr + acpi::SDT_HEADER_SIZE as u64);
+    if !tables.is_empty() && copied {
+        println!("OK ({} tables)", tables.len());
+    } else {
+        println!("FAILED ({} tables)", tables.len());
+    }
+    
+    print!("  16e. Reclaimable memory is only freed once... ");
+    match boot_info() {
+        Some(boot_info) if !tables.is_empty() && copied => {
+            let reclaimable: usize = memory_ranges(&boot_info)
+                .filter(|&(_, _, kind)| kind == RangeKind::Reclaimable)
+                .map(|(start, end, _)| ((end - start) / PAGE_SIZE as u64) as usize)
+                .sum();
+            let again = unsafe { super::reclaim_acpi_memory(&boot_info) };
+            if again == 0 {
+                println!("OK ({} reclaimable frames in the memory map)", reclaimable);
+            } else {
+                println!("FAILED ({} frames freed again)", again);
+            }
+        }
+        _ => println!("SKIPPED (no copied ACPI tables)"),
+    }
+    
+    print!("  16f. The DSDT is kept in place... ");
+    let kept = acpi::fadt_tables().count();
+    match acpi::dsdt() {
+        Some(dsdt) if dsdt.signature() == "DSDT" => println!("OK ({} bytes, {} ranges kept)", dsdt.len(), kept),
+        Some(dsdt) => println!("FAILED (signature {})", dsdt.signature()),
+        None if acpi::find_table("FACP").is_none() => println!("SKIPPED (no FADT)"),
+        None => println!("FAILED (DSDT not found)"),
+    }
+    
+    println!();
+}
+
+/// Test 17: Boot memory reservations
+/// 
+/// Checks the registry on lists of its own, then that the boot data
+/// recorded at boot is kept from the live allocator.
+pub fn test_reservations() {
+    println!("Test 17: Boot Memory Reservations");
+    
+    print!("  17a. Reservations of the same name merge... ");
+    let mut list = ReservationList::new();
+    let added = [
+        list.add(0x3000, 0x5000, "a"),
+        list.add(0x1000, 0x3000, "a"),
+        list.add(0x4000, 0x6000, "b"),
+    ];
+    let slice = list.as_slice();
+    if added.iter().all(Result::is_ok) && slice.len() == 2 && (slice[0].start, slice[0].end) == (0x1000, 0x5000) {
+        println!("OK");
+    } else {
+        println!("FAILED ({} reservations)", slice.len());
+    }
+    
+    print!("  17b. Releasing frees only frames nothing else holds... ");
+    let mut list = ReservationList::new();
+    let _ = list.add(0x1_0000, 0x1_7800, "a");
+    // Shares frame 0x17 with the first reservation
+    let _ = list.add(0x1_7800, 0x2_0000, "b");
+    let first = list.remove(0x1_0000, 0x1_7800);
+    // Splits the second reservation in two
+    let second = list.remove(0x1_A000, 0x1_C000);
+    let released = (first.total_frames(), second.total_frames(), list.as_slice().len());
+    if released == (7, 2, 2) && first.as_slice()[0].start_frame == 0x10 {
+        println!("OK");
+    } else {
+        println!("FAILED ({:?})", released);
+    }
+    
+    print!("  17c. Full registry and empty ranges are refused... ");
+    let mut list = ReservationList::new();
+    let filled = (0..MAX_RESERVATIONS as u64).all(|index| list.add(index * 0x2000, index * 0x2000 + 0x1000, "x").is_ok());
+    let full = list.add(0x100_0000, 0x100_1000, "y");
+    let empty = ReservationList::new().add(0x5000, 0x5000, "y");
+    // A split that does not fit keeps the reservation whole
+    let split = list.remove(0x400, 0x800);
+    if filled && full == Err(ReserveError::RegistryFull) && empty == Err(ReserveError::EmptyRange)
+        && split.total_frames() == 0 && list.as_slice().len() == MAX_RESERVATIONS {
+        println!("OK");
+    } else {
+        println!("FAILED ({:?}, {:?})", full, empty);
+    }
+    
+    print!("  17d. Boot data is recorded... ");
+    let reservations = reserve::reservations();
+    let kernel = test_reservations as fn() as usize as u64;
+    let cr3 = read_cr3().as_u64();
+    let mut expected = alloc::vec![("frame 0", 0), ("kernel", kernel), ("PML4", cr3)];
+    if let Some(boot_info) = boot_info() {
+        expected.push(("boot information", boot_info.addr() as u64));
+    }
+    let missing: Vec<_> = expected
+        .iter()
+        .filter(|&&(_, addr)| reservations.overlapping(addr, addr + 1).is_none())
+        .map(|&(name, _)| name)
+        .collect();
+    if missing.is_empty() {
+        println!("OK ({} ranges)", reservations.as_slice().len());
+    } else {
+        println!("FAILED ({:?} not reserved)", missing);
+    }
+    
+    print!("  17e. Reserving after boot fails... ");
+    match reserve::reserve(0x100_0000, 0x100_1000, "test") {
+        Err(ReserveError::AllocatorRunning) => println!("OK"),
+        result => println!("FAILED ({:?})", result),
+    }
+    
+    print!("  17f. No DMA frame holding boot data is free... ");
+    let dma_free = memory_stats().zones[Zone::Dma.index()].free_frames;
+    if dma_free == 0 {
+        println!("SKIPPED (no free DMA frames)");
+    } else {
+        let mut frames = Vec::with_capacity(dma_free);
+        while let Some(frame) = allocate_frames_with(Zone::Dma, 1, PAGE_SIZE, Fallback::None) {
+            frames.push(frame);
+        }
+        let held = frames
+            .iter()
+            .find(|&&frame| reservations.overlapping(frame as u64, (frame + PAGE_SIZE) as u64).is_some())
+            .copied();
+        for &frame in &frames {
+            unsafe {
+                free_frame(frame);
+            }
+        }
+        match held {
+            None => println!("OK ({} frames checked)", frames.len()),
+            Some(frame) => println!("FAILED (frame {:#X} is reserved)", frame),
+        }
+    }
+    
+    print!("  17g. Releasing unreserved memory frees nothing... ");
+    match allocate_frame() {
+        Some(frame) => {
+            let free_before = memory_stats().free_frames;
+            let freed = unsafe { reserve::release(frame as u64, (frame + PAGE_SIZE) as u64) };
+            let free_after = memory_stats().free_frames;
+            unsafe {
+                free_frame(frame);
+            }
+            if freed == 0 && free_after == free_before {
+                println!("OK");
+            } else {
+                println!("FAILED ({} frames freed)", freed);
+            }
+        }
+        None => println!("SKIPPED (no free frame)"),
     }
     
-    let s = unsafe { core::str::from_utf8_unchecked(&buffer[..i]) };
-    print(s);
+    println!();
 }
@@ -0,0 +1,118 @@
+/// Physical memory zones
+/// 
+/// Devices differ in the physical addresses they can reach: ISA DMA only
+/// sees the first 16MB, and many PCI devices only 32-bit addresses. The
+/// usable memory is therefore split into zones at these limits, each with
+/// its own frame allocator:
+/// 
+/// ```text
+///     0          16MB                        4GB
+///     [   DMA    ][          DMA32           ][        Normal        ...
+/// ```
+/// 
+/// Memory in a lower zone is reachable by every device that can reach a
+/// higher one, so an allocation from a zone may fall back to the zones
+/// below it. A fallback leaves a lower zone a reserve (like Linux's
+/// `lowmem_reserve`) that grows with the memory above it, so ordinary
+/// allocations cannot use up the memory only some devices can reach.
+
+/// Number of zones
+pub const ZONE_COUNT: usize = 3;
+
+/// End of the memory ISA DMA can reach
+const DMA_END: u64 = 16 * 1024 * 1024;
+
+/// End of the memory 32-bit devices can reach
+const DMA32_END: u64 = 4 * 1024 * 1024 * 1024;
+
+/// A lower zone keeps back 1/256th of the frames in the zones between it
+/// and the zone an allocation falls back from
+const LOWMEM_RESERVE_RATIO: usize = 256;
+
+/// A physical memory zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Zone {
+    /// Below 16MB, for ISA DMA
+    Dma,
+    /// Below 4GB, for devices with 32-bit addresses
+    Dma32,
+    /// Everything above 4GB
+    Normal,
+}
+
+impl Zone {
+    /// All zones, lowest first
+    pub const ALL: [Zone; ZONE_COUNT] = [Zone::Dma, Zone::Dma32, Zone::Normal];
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            Zone::Dma => "DMA",
+            Zone::Dma32 => "DMA32",
+            Zone::Normal => "Normal",
+        }
+    }
+    
+    pub fn index(self) -> usize {
+        self as usize
+    }
+    
+    /// Physical addresses of the zone, as `[start, end)`
+    pub fn range(self) -> (u64, u64) {
+        match self {
+            Zone::Dma => (0, DMA_END),
+            Zone::Dma32 => (DMA_END, DMA32_END),
+            Zone::Normal => (DMA32_END, u64::MAX),
+        }
+    }
+    
+    /// Zone holding a physical address
+    pub fn containing(addr: u64) -> Zone {
+        if addr < DMA_END {
+            Zone::Dma
+        } else if addr < DMA32_END {
+            Zone::Dma32
+        } else {
+            Zone::Normal
+        }
+    }
+    
+    /// Zones an allocation from this zone may use, in the order they are
+    /// tried
+    pub fn fallback_order(self) -> &'static [Zone] {
+        match self {
+            Zone::Dma => &[Zone::Dma],
+            Zone::Dma32 => &[Zone::Dma32, Zone::Dma],
+            Zone::Normal => &[Zone::Normal, Zone::Dma32, Zone::Dma],
+        }
+    }
+}
+
+/// Whether an allocation may use other zones than the one asked for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// Only the zone asked for
+    None,
+    /// The zone asked for, then the lower zones, leaving them their reserve
+    LowerZones,
+}
+
+/// Frames a fallback from `requested` must leave free in `lower`
+/// 
+/// `totals` holds the frames of every zone.
+pub fn lowmem_reserve(totals: &[usize; ZONE_COUNT], lower: Zone, requested: Zone) -> usize {
+    let above: usize = totals[lower.index() + 1..=requested.index()].iter().sum();
+    above / LOWMEM_RESERVE_RATIO
+}
+
+/// Statistics of one zone
+#[derive(Debug, Clone, Copy)]
+pub struct ZoneStats {
+    pub total_frames: usize,
+    pub free_frames: usize,
+    /// Allocations served by the zone
+    pub allocations: u64,
+    /// Allocations served for a higher zone
+    pub fallback_allocations: u64,
+    /// Allocations from the zone that no zone could serve
+    pub failures: u64,
+}
@@ -2,16 +2,36 @@
 /// 
 /// This module contains all the x86_64 specific implementations including:
 /// - Boot process and initialization
+/// - CPU feature detection and model-specific registers
+/// - ACPI table discovery (MADT)
+/// - GDT and TSS (including Interrupt Stack Table)
 /// - Interrupt handling (IDT)
 /// - Memory management (paging, etc.)
-/// - Hardware drivers (VGA, keyboard, etc.)
+/// - Hardware drivers (VGA, serial, PIT, keyboard, etc.)
+/// - Timekeeping: tick sources and a calibrated monotonic clock
+/// - Kernel timers, sleep and timeouts
+/// - Port I/O and interrupt-safe locking primitives
+/// - Panic reporting with symbolized backtraces
+/// - System reset
 
+pub mod acpi;
 pub mod boot;
+pub mod cpu;
+pub mod gdt;
+pub mod panic;
+pub mod port;
+pub mod power;
+pub mod qemu;
+pub mod sync;
+pub mod symbols;
 pub mod interrupts;
 pub mod memory;
 pub mod drivers;
+pub mod time;
+pub mod timer;
 
 // Re-export commonly used functionality for convenience
 pub use interrupts::setup_idt;
-pub use drivers::{clear_screen, print, println};
+pub use drivers::{clear_screen, println};
+pub use drivers::console::ByteSize;
 pub use memory::init_memory;
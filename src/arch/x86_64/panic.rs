@@ -0,0 +1,200 @@
+/// Kernel Panic Handling
+/// 
+/// Called from the `#[panic_handler]` in `main.rs`. A panic report contains
+/// the panic message and source location, the register state at the time
+/// of the panic and a backtrace. The report goes to the error stream of
+/// every console sink, so it shows up on both the screen and the serial port.
+/// 
+/// ## Backtraces
+/// 
+/// The kernel is built with `-C force-frame-pointers=yes` (see
+/// `.cargo/config.toml`), so every function starts with `push rbp; mov rbp, rsp`.
+/// The saved RBP values form a linked list through the stack:
+/// 
+/// ```text
+/// [rbp]      -> caller's saved RBP
+/// [rbp + 8]  -> return address into the caller
+/// ```
+/// 
+/// `boot.s` clears RBP before calling `kernel_main`, so the chain ends with
+/// a null frame pointer. Each return address is printed as `function+0x1a`
+/// when the kernel symbol table is available (see `symbols.rs`).
+/// 
+/// ## Nested panics
+/// 
+/// A panic raised while the panic report is being printed (for example from
+/// a `Display` implementation in the panic message) must not start a second
+/// report, which could recurse until the stack overflows. The panic depth is
+/// tracked in an atomic counter: the second panic prints a one-line notice
+/// and halts, and any further panic halts immediately without printing.
+
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use super::interrupts::entry::{InterruptStackFrame, SavedRegisters};
+use super::interrupts::exceptions::dump_registers;
+use super::interrupts::setup::disable_interrupts;
+use super::memory::paging::VirtAddr;
+use super::symbols::{symbolize, Demangle};
+use crate::eprintln;
+
+/// Maximum number of frames printed in a backtrace
+const MAX_BACKTRACE_DEPTH: usize = 32;
+
+/// Number of panics currently being handled
+static PANIC_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// Handle a kernel panic
+/// 
+/// Disables interrupts, prints the panic report and halts the CPU. In test
+/// builds QEMU is asked to exit with a failure code instead.
+pub fn handle_panic(info: &PanicInfo) -> ! {
+    disable_interrupts();
+    
+    match PANIC_DEPTH.fetch_add(1, Ordering::SeqCst) {
+        0 => report_panic(info),
+        1 => {
+            eprintln!();
+            eprintln!("PANIC while panicking: {}", NestedLocation(info));
+        }
+        _ => {}
+    }
+    
+    halt()
+}
+
+/// Print the full panic report
+fn report_panic(info: &PanicInfo) {
+    eprintln!();
+    eprintln!("========================================");
+    eprintln!("KERNEL PANIC");
+    eprintln!("========================================");
+    match info.location() {
+        Some(location) => eprintln!("  Location: {}:{}:{}", location.file(), location.line(), location.column()),
+        None => eprintln!("  Location: unknown"),
+    }
+    eprintln!("  Message: {}", info.message());
+    
+    let (regs, frame) = capture_registers();
+    dump_registers(&regs, &frame);
+    
+    print_backtrace(regs.rbp);
+    
+    eprintln!("System halted. Please reset to continue.");
+    eprintln!("========================================");
+}
+
+/// Location of a nested panic, formatted without touching the message
+/// (formatting the message is what may have caused the nested panic)
+struct NestedLocation<'a>(&'a PanicInfo<'a>);
+
+impl core::fmt::Display for NestedLocation<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self.0.location() {
+            Some(location) => write!(f, "{}:{}", location.file(), location.line()),
+            None => write!(f, "unknown location"),
+        }
+    }
+}
+
+/// Capture the current register state
+/// 
+/// The values are taken inside the panic handler, so only RSP, RBP, RIP
+/// and the control registers say much about the panic itself. The register
+/// holding the destination pointer shows that pointer.
+#[inline(always)]
+fn capture_registers() -> (SavedRegisters, InterruptStackFrame) {
+    let mut regs = SavedRegisters::default();
+    let (rip, rsp, rflags, cs, ss): (u64, u64, u64, u64, u64);
+    
+    unsafe {
+        core::arch::asm!(
+            "mov [{regs} + 0x00], r15",
+            "mov [{regs} + 0x08], r14",
+            "mov [{regs} + 0x10], r13",
+            "mov [{regs} + 0x18], r12",
+            "mov [{regs} + 0x20], r11",
+            "mov [{regs} + 0x28], r10",
+            "mov [{regs} + 0x30], r9",
+            "mov [{regs} + 0x38], r8",
+            "mov [{regs} + 0x40], rbp",
+            "mov [{regs} + 0x48], rdi",
+            "mov [{regs} + 0x50], rsi",
+            "mov [{regs} + 0x58], rdx",
+            "mov [{regs} + 0x60], rcx",
+            "mov [{regs} + 0x68], rbx",
+            "mov [{regs} + 0x70], rax",
+            regs = in(reg) &mut regs as *mut SavedRegisters,
+            options(nostack, preserves_flags),
+        );
+        core::arch::asm!("lea {}, [rip]", out(reg) rip, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov {}, rsp", out(reg) rsp, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("pushfq", "pop {}", out(reg) rflags, options(nomem, preserves_flags));
+        core::arch::asm!("mov {:r}, cs", out(reg) cs, options(nomem, nostack, preserves_flags));
+        core::arch::asm!("mov {:r}, ss", out(reg) ss, options(nomem, nostack, preserves_flags));
+    }
+    
+    (regs, InterruptStackFrame { rip, cs, rflags, rsp, ss })
+}
+
+/// Print a backtrace by walking the frame pointer chain starting at `rbp`
+/// 
+/// Stops at a null frame pointer, at a frame that is not mapped, or when
+/// the chain stops moving up the stack (a corrupted stack could otherwise
+/// loop forever).
+pub fn print_backtrace(mut rbp: u64) {
+    eprintln!("Backtrace:");
+    
+    for depth in 0..MAX_BACKTRACE_DEPTH {
+        if rbp == 0 || !rbp.is_multiple_of(8) || !is_readable(rbp) || !is_readable(rbp + 8) {
+            break;
+        }
+        
+        let return_address = unsafe { *((rbp + 8) as *const u64) };
+        if return_address == 0 {
+            break;
+        }
+        // The return address points after the call, which may already be
+        // the start of the next function, so look up the call instruction
+        match symbolize(return_address - 1) {
+            Some((name, offset)) => {
+                eprintln!("  #{:<2} {:#018X}  {}+{:#x}", depth, return_address, Demangle(name), offset + 1);
+            }
+            None => eprintln!("  #{:<2} {:#018X}", depth, return_address),
+        }
+        
+        // Callers' frames are always at higher addresses
+        let next = unsafe { *(rbp as *const u64) };
+        if next <= rbp {
+            break;
+        }
+        rbp = next;
+    }
+}
+
+/// Check that an address is canonical and mapped, so it can be read without
+/// faulting while the backtrace is printed
+fn is_readable(addr: u64) -> bool {
+    let upper = addr >> 47;
+    if upper != 0 && upper != 0x1FFFF {
+        return false;
+    }
+    
+    let mapper = unsafe { super::memory::active_mapper() };
+    mapper.translate(VirtAddr::new_unchecked(addr)).is_some()
+}
+
+/// Stop the CPU after a panic
+/// 
+/// Test builds exit QEMU with a failure code (through the isa-debug-exit
+/// device) so scripts see the panic. If the device is not present, or in
+/// normal builds, the CPU halts with interrupts disabled.
+fn halt() -> ! {
+    #[cfg(feature = "run-tests")]
+    super::qemu::exit_qemu(super::qemu::QemuExitCode::Failed);
+    
+    loop {
+        unsafe {
+            core::arch::asm!("cli", "hlt", options(nomem, nostack));
+        }
+    }
+}
@@ -0,0 +1,53 @@
+/// x86 I/O Port Access
+/// 
+/// Legacy devices (serial ports, PIC, PIT, PS/2 controller, CMOS) are
+/// accessed through the separate 16-bit I/O address space using the
+/// IN and OUT instructions.
+/// 
+/// All functions are unsafe: writing to the wrong port can reconfigure
+/// hardware in ways that break memory safety (for example the DMA or
+/// interrupt controllers).
+
+/// Read a byte from an I/O port
+pub unsafe fn inb(port: u16) -> u8 {
+    let value: u8;
+    core::arch::asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    value
+}
+
+/// Write a byte to an I/O port
+pub unsafe fn outb(port: u16, value: u8) {
+    core::arch::asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+}
+
+/// Read a word from an I/O port
+pub unsafe fn inw(port: u16) -> u16 {
+    let value: u16;
+    core::arch::asm!("in ax, dx", out("ax") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    value
+}
+
+/// Write a word to an I/O port
+pub unsafe fn outw(port: u16, value: u16) {
+    core::arch::asm!("out dx, ax", in("dx") port, in("ax") value, options(nomem, nostack, preserves_flags));
+}
+
+/// Read a doubleword from an I/O port
+pub unsafe fn inl(port: u16) -> u32 {
+    let value: u32;
+    core::arch::asm!("in eax, dx", out("eax") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    value
+}
+
+/// Write a doubleword to an I/O port
+pub unsafe fn outl(port: u16, value: u32) {
+    core::arch::asm!("out dx, eax", in("dx") port, in("eax") value, options(nomem, nostack, preserves_flags));
+}
+
+/// Wait a very short time (roughly 1-4 microseconds)
+/// 
+/// Writes to the unused port 0x80 (the POST diagnostic port). Some old
+/// devices need a short delay between consecutive port accesses.
+pub unsafe fn io_wait() {
+    outb(0x80, 0);
+}
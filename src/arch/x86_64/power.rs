@@ -0,0 +1,69 @@
+/// System Reset
+/// 
+/// `reboot` tries the reset methods from the cleanest to the crudest:
+/// 
+/// 1. The ACPI reset register, if the FADT describes one in I/O space
+/// 2. The i8042 controller's reset line (works on nearly every PC and QEMU)
+/// 3. A triple fault: an exception with an empty IDT, which the CPU
+///    answers with a shutdown cycle that the chipset turns into a reset
+/// 
+/// Each method gets a short delay to take effect before the next one runs.
+
+use crate::arch::x86_64::acpi;
+use crate::arch::x86_64::drivers::i8042;
+use crate::arch::x86_64::port::outb;
+use crate::arch::x86_64::time;
+
+/// Offset of the FADT flags in the table data
+const FADT_FLAGS: usize = 112 - 36;
+/// FADT flag: the reset register is supported
+const FADT_RESET_REG_SUP: u32 = 1 << 10;
+/// Offset of the reset register (Generic Address Structure) in the table data
+const FADT_RESET_REG: usize = 116 - 36;
+/// Offset of the value to write to the reset register
+const FADT_RESET_VALUE: usize = 128 - 36;
+/// Generic Address Structure address space: system I/O
+const ADDRESS_SPACE_IO: u8 = 1;
+
+/// Time each reset method gets before the next is tried
+const RESET_WAIT_US: u64 = 50_000;
+
+/// I/O port and value of the ACPI reset register, if the FADT has one
+fn acpi_reset_register() -> Option<(u16, u8)> {
+    let fadt = acpi::find_table("FACP")?;
+    let data = fadt.data();
+    
+    let flags = u32::from_le_bytes(data.get(FADT_FLAGS..FADT_FLAGS + 4)?.try_into().ok()?);
+    if flags & FADT_RESET_REG_SUP == 0 || *data.get(FADT_RESET_REG)? != ADDRESS_SPACE_IO {
+        return None;
+    }
+    let address = u64::from_le_bytes(data.get(FADT_RESET_REG + 4..FADT_RESET_REG + 12)?.try_into().ok()?);
+    Some((u16::try_from(address).ok()?, *data.get(FADT_RESET_VALUE)?))
+}
+
+/// Force a triple fault
+fn triple_fault() -> ! {
+    #[repr(C, packed)]
+    struct EmptyIdt {
+        limit: u16,
+        base: u64,
+    }
+    let idt = EmptyIdt { limit: 0, base: 0 };
+    
+    unsafe {
+        core::arch::asm!("lidt [{}]", "int3", in(reg) &idt, options(noreturn));
+    }
+}
+
+/// Reset the machine
+pub fn reboot() -> ! {
+    if let Some((port, value)) = acpi_reset_register() {
+        unsafe { outb(port, value) };
+        time::delay_us(RESET_WAIT_US);
+    }
+    
+    i8042::pulse_reset();
+    time::delay_us(RESET_WAIT_US);
+    
+    triple_fault()
+}
@@ -0,0 +1,35 @@
+/// QEMU Exit Device
+/// 
+/// Test builds are run with QEMU's `isa-debug-exit` device at port 0xF4
+/// (see `QEMU_TEST_FLAGS` in the Makefile). Writing a value to the port
+/// makes QEMU exit with status `(value << 1) | 1`, so a test run can report
+/// success or failure to the calling script.
+/// 
+/// Without the device, writes to the port are ignored and execution
+/// continues.
+
+use super::port::outl;
+
+/// I/O port of the isa-debug-exit device
+const ISA_DEBUG_EXIT_PORT: u16 = 0xF4;
+
+/// Exit codes reported to QEMU
+/// 
+/// The values avoid 0 and 1 so they cannot be confused with QEMU's own
+/// exit statuses (QEMU exits with 33 for Success and 35 for Failed).
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+/// Exit QEMU with the given code
+/// 
+/// Returns only if the isa-debug-exit device is not present.
+pub fn exit_qemu(code: QemuExitCode) {
+    unsafe {
+        outl(ISA_DEBUG_EXIT_PORT, code as u32);
+    }
+}
@@ -0,0 +1,308 @@
+/// Kernel Symbol Lookup
+/// 
+/// Maps code addresses back to function names using the kernel's own ELF
+/// symbol table, so crash reports show `function+0x1a` instead of a raw
+/// address. GRUB loads `.symtab` and its string table into memory and
+/// reports where through the Multiboot2 ELF-symbols tag (tag 9); `init`
+/// finds them there. No copy of the kernel ELF is needed to read a report.
+/// 
+/// Without the tag (or with a stripped kernel) lookups return `None` and
+/// addresses are printed as plain hex.
+/// 
+/// ## Name demangling
+/// 
+/// Rust symbol names are mangled. `Demangle` turns legacy (`_ZN...E`)
+/// names, which is what the kernel is built with, back into
+/// `crate::module::function` form. Names it cannot decode are printed as
+/// they are.
+/// 
+/// ## Usage
+/// 
+/// ```rust
+/// if let Some((name, offset)) = symbols::symbolize(rip) {
+///     println!("{}+{:#x}", Demangle(name), offset);
+/// }
+/// 
+/// // Or, falling back to hex when there is no symbol:
+/// println!("RIP: {}", Symbolized(rip));
+/// ```
+
+use core::fmt;
+use super::boot::multiboot2::{BootInfo, ElfSectionHeader};
+
+/// ELF64 symbol table entry (`Elf64_Sym`)
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ElfSymbol {
+    name: u32,
+    info: u8,
+    other: u8,
+    shndx: u16,
+    value: u64,
+    size: u64,
+}
+
+impl ElfSymbol {
+    /// Symbol type for functions
+    const TYPE_FUNC: u8 = 2;
+    
+    /// Returns true if the symbol names a function
+    fn is_function(&self) -> bool {
+        self.info & 0xF == Self::TYPE_FUNC
+    }
+}
+
+/// The kernel's symbol and string tables
+#[derive(Clone, Copy)]
+struct SymbolTable {
+    symbols: &'static [ElfSymbol],
+    strings: &'static [u8],
+}
+
+impl SymbolTable {
+    /// Get the name of a symbol from the string table
+    fn name(&self, symbol: &ElfSymbol) -> Option<&'static str> {
+        let start = symbol.name as usize;
+        let bytes = self.strings.get(start..)?;
+        let len = bytes.iter().position(|&b| b == 0)?;
+        core::str::from_utf8(&bytes[..len]).ok()
+    }
+    
+    /// Find the function containing `addr`
+    /// 
+    /// The symbol table is not sorted, so every symbol is checked. Symbols
+    /// without a size (hand-written assembly) match if they are the closest
+    /// symbol below the address.
+    fn lookup(&self, addr: u64) -> Option<(&'static ElfSymbol, u64)> {
+        let mut best: Option<&'static ElfSymbol> = None;
+        
+        for symbol in self.symbols {
+            if !symbol.is_function() || symbol.value == 0 || symbol.value > addr {
+                continue;
+            }
+            
+            // Exact match inside a sized symbol
+            if symbol.size != 0 && addr < symbol.value + symbol.size {
+                return Some((symbol, addr - symbol.value));
+            }
+            
+            if symbol.size == 0 && best.is_none_or(|b| symbol.value > b.value) {
+                best = Some(symbol);
+            }
+        }
+        
+        best.map(|symbol| (symbol, addr - symbol.value))
+    }
+}
+
+/// The symbol table, set once by `init`
+static mut SYMBOL_TABLE: Option<SymbolTable> = None;
+
+/// Get the symbol table, if one was found at boot
+fn table() -> Option<SymbolTable> {
+    unsafe { *core::ptr::addr_of!(SYMBOL_TABLE) }
+}
+
+/// Locate the kernel symbol table through the Multiboot2 ELF-symbols tag
+/// 
+/// Returns the number of symbols found, or `None` if the boot information
+/// has no ELF-symbols tag or the kernel has no symbol table.
+/// 
+/// # Safety
+/// Must be called once during early boot, before interrupts are enabled.
+/// The tables are read through the identity mapping, and their frames
+/// must be kept reserved (the physical allocator does this).
+pub unsafe fn init(boot_info: &BootInfo) -> Option<usize> {
+    let mut sections = boot_info.elf_sections()?;
+    let symtab = sections.find(|s| s.section_type == ElfSectionHeader::TYPE_SYMTAB && s.addr != 0)?;
+    
+    // sh_link of the symbol table is the index of its string table
+    let strtab = boot_info.elf_sections()?.nth(symtab.link as usize)?;
+    if strtab.section_type != ElfSectionHeader::TYPE_STRTAB || strtab.addr == 0 {
+        return None;
+    }
+    
+    let entry_size = core::mem::size_of::<ElfSymbol>() as u64;
+    if symtab.entsize != entry_size || !symtab.addr.is_multiple_of(8) {
+        return None;
+    }
+    
+    let symbols = core::slice::from_raw_parts(
+        symtab.addr as *const ElfSymbol,
+        (symtab.size / entry_size) as usize,
+    );
+    let strings = core::slice::from_raw_parts(strtab.addr as *const u8, strtab.size as usize);
+    
+    *core::ptr::addr_of_mut!(SYMBOL_TABLE) = Some(SymbolTable { symbols, strings });
+    Some(symbols.len())
+}
+
+/// Find the function containing `addr`
+/// 
+/// Returns the (mangled) symbol name and the offset of `addr` from the
+/// start of the function. Use `Demangle` to print the name.
+pub fn symbolize(addr: u64) -> Option<(&'static str, u64)> {
+    let table = table()?;
+    let (symbol, offset) = table.lookup(addr)?;
+    Some((table.name(symbol)?, offset))
+}
+
+/// Display wrapper that prints an address as `function+0x1a`, or as plain
+/// hex if there is no symbol for it
+#[derive(Debug, Clone, Copy)]
+pub struct Symbolized(pub u64);
+
+impl fmt::Display for Symbolized {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match symbolize(self.0) {
+            Some((name, offset)) => write!(f, "{}+{:#x}", Demangle(name), offset),
+            None => write!(f, "{:#018X}", self.0),
+        }
+    }
+}
+
+/// Display wrapper that demangles a Rust symbol name
+/// 
+/// Names that are not mangled, that cannot be decoded or whose decoded
+/// form is longer than `MAX_DEMANGLED_LEN` are printed unchanged.
+#[derive(Debug, Clone, Copy)]
+pub struct Demangle<'a>(pub &'a str);
+
+impl fmt::Display for Demangle<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = self.0;
+        
+        // Decode into a buffer first, so a failure cannot leave half a name
+        // in the output
+        let mut buffer = NameBuffer::new();
+        let decoded = match name.strip_prefix("_ZN") {
+            Some(rest) => demangle_legacy(rest, &mut |s| buffer.push(s)).is_ok(),
+            None => false,
+        };
+        
+        f.write_str(if decoded { buffer.as_str() } else { name })
+    }
+}
+
+/// Longest demangled name `Demangle` prints
+const MAX_DEMANGLED_LEN: usize = 256;
+
+/// Fixed-size buffer a name is demangled into
+struct NameBuffer {
+    bytes: [u8; MAX_DEMANGLED_LEN],
+    len: usize,
+}
+
+impl NameBuffer {
+    fn new() -> Self {
+        NameBuffer { bytes: [0; MAX_DEMANGLED_LEN], len: 0 }
+    }
+    
+    /// Append a piece of the name, failing if it does not fit
+    fn push(&mut self, piece: &str) -> fmt::Result {
+        let end = self.len + piece.len();
+        self.bytes.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(piece.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+    
+    fn as_str(&self) -> &str {
+        // Only whole `str` pieces are appended
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+}
+
+/// Error returned by the demangler for names it cannot decode
+struct Undecodable;
+
+impl From<fmt::Error> for Undecodable {
+    fn from(_: fmt::Error) -> Self {
+        Undecodable
+    }
+}
+
+/// Output callback used by the demangler
+type Output<'a> = dyn FnMut(&str) -> fmt::Result + 'a;
+
+/// Split a decimal length prefix off `s`
+fn split_decimal(s: &str) -> Option<(usize, &str)> {
+    let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+    let value = s[..digits].parse().ok()?;
+    Some((value, &s[digits..]))
+}
+
+/// Decode a legacy mangled name (`_ZN` already stripped)
+/// 
+/// The name is a list of length-prefixed identifiers ending in `E`. The
+/// last identifier is a hash (`h` + 16 hex digits), which is dropped.
+/// Punctuation inside identifiers is escaped as `$LT$`, `$u20$` and so on,
+/// and `..` stands for `::`.
+fn demangle_legacy(mut rest: &str, out: &mut Output) -> Result<(), Undecodable> {
+    let mut first = true;
+    
+    loop {
+        if rest == "E" {
+            return Ok(());
+        }
+        
+        let (len, tail) = split_decimal(rest).ok_or(Undecodable)?;
+        let ident = tail.get(..len).ok_or(Undecodable)?;
+        rest = &tail[len..];
+        
+        let is_hash = ident.len() == 17
+            && ident.starts_with('h')
+            && ident[1..].bytes().all(|b| b.is_ascii_hexdigit());
+        if is_hash && rest == "E" {
+            return Ok(());
+        }
+        
+        if !first {
+            out("::")?;
+        }
+        first = false;
+        
+        write_legacy_ident(ident, out)?;
+    }
+}
+
+/// Write one legacy identifier, decoding its escapes
+fn write_legacy_ident(ident: &str, out: &mut Output) -> Result<(), Undecodable> {
+    // A leading `$` escape is prefixed with `_` to keep the identifier valid
+    let mut rest = if ident.starts_with("_$") { &ident[1..] } else { ident };
+    
+    while !rest.is_empty() {
+        if let Some(tail) = rest.strip_prefix("..") {
+            out("::")?;
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix('$') {
+            let end = tail.find('$').ok_or(Undecodable)?;
+            let escape = &tail[..end];
+            let text = match escape {
+                "SP" => "@",
+                "BP" => "*",
+                "RF" => "&",
+                "LT" => "<",
+                "GT" => ">",
+                "LP" => "(",
+                "RP" => ")",
+                "C" => ",",
+                _ => {
+                    let code = escape.strip_prefix('u').ok_or(Undecodable)?;
+                    let ch = u32::from_str_radix(code, 16).ok().and_then(char::from_u32).ok_or(Undecodable)?;
+                    out(ch.encode_utf8(&mut [0; 4]))?;
+                    ""
+                }
+            };
+            out(text)?;
+            rest = &tail[end + 1..];
+        } else {
+            let end = rest.find(['$', '.']).unwrap_or(rest.len());
+            // A single `.` is a plain character
+            let end = if end == 0 { 1 } else { end };
+            out(&rest[..end])?;
+            rest = &rest[end..];
+        }
+    }
+    
+    Ok(())
+}
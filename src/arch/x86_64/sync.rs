@@ -0,0 +1,215 @@
+/// Synchronization Primitives
+/// 
+/// The kernel runs on a single CPU, but interrupt handlers can still run in
+/// the middle of any code that has interrupts enabled. `SpinLock` disables
+/// interrupts for as long as the lock is held, so data shared between normal
+/// code and interrupt handlers can never be seen half-updated.
+/// 
+/// ## Usage
+/// 
+/// ```rust
+/// static COUNTER: SpinLock<u64> = SpinLock::new(0);
+/// 
+/// *COUNTER.lock() += 1; // Interrupts are restored when the guard drops
+/// ```
+/// 
+/// `EventQueue` is a lock-free ring buffer for passing events from an
+/// interrupt handler to the code that consumes them.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use super::interrupts::setup::{disable_interrupts, enable_interrupts, interrupts_enabled};
+
+/// A spin lock that disables interrupts while held
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    /// Create a new unlocked spin lock
+    pub const fn new(data: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+    
+    /// Acquire the lock, spinning until it is available
+    /// 
+    /// Interrupts are disabled before spinning and restored to their
+    /// previous state when the returned guard is dropped.
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        let were_enabled = interrupts_enabled();
+        disable_interrupts();
+        
+        while self.locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        
+        SpinLockGuard { lock: self, were_enabled }
+    }
+    
+    /// Try to acquire the lock without spinning
+    /// 
+    /// Returns None if the lock is already held. This is the only safe way
+    /// to take a lock from an exception handler, which may have interrupted
+    /// the current holder.
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        let were_enabled = interrupts_enabled();
+        disable_interrupts();
+        
+        if self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            Some(SpinLockGuard { lock: self, were_enabled })
+        } else {
+            if were_enabled {
+                enable_interrupts();
+            }
+            None
+        }
+    }
+    
+    /// Check whether the lock is currently held
+    #[allow(dead_code)]
+    pub fn is_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+    
+    /// Get a raw pointer to the protected data, bypassing the lock
+    /// 
+    /// Dereferencing it is only sound if the holder of the lock cannot be
+    /// running at the same time, or for fields that are not being modified.
+    pub fn data_ptr(&self) -> *mut T {
+        self.data.get()
+    }
+    
+    /// Release the lock without a guard
+    /// 
+    /// # Safety
+    /// Only for use when the holder can never run again (for example when
+    /// panicking), otherwise the protected data may be accessed concurrently.
+    #[allow(dead_code)]
+    pub unsafe fn force_unlock(&self) {
+        self.locked.store(false, Ordering::Release);
+    }
+}
+
+/// Guard that releases a `SpinLock` when dropped
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+    were_enabled: bool,
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+    
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Ordering::Release);
+        if self.were_enabled {
+            enable_interrupts();
+        }
+    }
+}
+
+/// Lock-free queue of `N` events
+/// 
+/// Written only by one interrupt handler (a single producer). Readers
+/// claim an entry by advancing `tail` with a compare-and-swap after
+/// copying it, so several consumers can read concurrently. When the queue
+/// is full new events are dropped and counted.
+pub struct EventQueue<T: Copy, const N: usize> {
+    events: [UnsafeCell<MaybeUninit<T>>; N],
+    /// Number of events ever pushed
+    head: AtomicUsize,
+    /// Number of events ever taken
+    tail: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+unsafe impl<T: Copy + Send, const N: usize> Sync for EventQueue<T, N> {}
+
+impl<T: Copy, const N: usize> EventQueue<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            events: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicU64::new(0),
+        }
+    }
+    
+    /// Append an event (producer only)
+    /// 
+    /// Returns false if the queue is full and the event was dropped.
+    pub fn push(&self, event: T) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        if head.wrapping_sub(self.tail.load(Ordering::Acquire)) >= N {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        unsafe { (*self.events[head % N].get()).write(event) };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        true
+    }
+    
+    /// Take the oldest event
+    pub fn pop(&self) -> Option<T> {
+        loop {
+            let tail = self.tail.load(Ordering::Acquire);
+            if tail == self.head.load(Ordering::Acquire) {
+                return None;
+            }
+            // The producer does not reuse this slot until `tail` moves past
+            // it, and slots below `head` have been written
+            let event = unsafe { (*self.events[tail % N].get()).assume_init() };
+            if self.tail
+                .compare_exchange(tail, tail.wrapping_add(1), Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+            {
+                return Some(event);
+            }
+        }
+    }
+    
+    /// Number of queued events
+    pub fn len(&self) -> usize {
+        self.head.load(Ordering::Acquire).wrapping_sub(self.tail.load(Ordering::Acquire))
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    
+    /// Events dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T: Copy, const N: usize> Default for EventQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,428 @@
+/// Timekeeping
+/// 
+/// Provides the kernel's notion of time:
+/// 
+/// - `monotonic_ns()` / `uptime()`: time since `init`, read from the TSC
+///   when it is available, otherwise counted in ticks
+/// - `ticks()`: number of timer ticks (periods of `1 / hz`) since `init`
+/// 
+/// ## Tick source
+/// 
+/// The local APIC timer is used when the APIC is active; the PIT (channel
+/// 0 on IRQ 0) is the fallback, or can be chosen with `timer=pit`. Both the
+/// TSC and the APIC timer run at frequencies that must be measured, so
+/// `init` calibrates them against PIT channel 2, which needs no interrupts.
+/// 
+/// ## Tick modes
+/// 
+/// In periodic mode the tick source interrupts `hz` times a second (1000
+/// by default) and every interrupt advances the tick counter. In tickless
+/// mode (`tickless` boot option) the APIC timer is programmed one-shot for
+/// the next event, so an idle CPU sleeps in `hlt` for up to `MAX_IDLE_NS`
+/// instead of being woken on every tick. Tickless mode needs the APIC timer
+/// and a calibrated TSC, since the tick counter is then derived from the
+/// clock.
+/// 
+/// Every timer interrupt runs the expired kernel timers (`timer.rs`).
+/// 
+/// ## Wall clock
+/// 
+/// `init_wall_clock` reads the CMOS RTC once; `wall_clock()` then adds the
+/// monotonic time since that read, so the date is never read from the RTC
+/// again and cannot jump backwards. It is accurate to about a second.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use super::boot::cmdline::{self, BootOption, OptionKind};
+use super::cpu;
+use super::drivers::pit;
+use super::drivers::rtc::{self, DateTime, RtcError};
+use super::interrupts::apic::{self, TimerMode};
+use super::interrupts::entry::InterruptContext;
+use super::interrupts::irq::{self, IrqError, IrqReturn, IrqSharing};
+use super::interrupts::setup::without_interrupts;
+use super::timer;
+use crate::println;
+
+/// Names accepted by the `timer` boot option
+const TIMER_NAMES: [&str; 2] = ["apic", "pit"];
+
+/// Boot options of the timekeeping subsystem
+pub static BOOT_OPTIONS: [BootOption; 3] = [
+    BootOption::new("hz", OptionKind::Number, "Timer interrupts per second in periodic mode (default 1000)"),
+    BootOption::new("timer", OptionKind::Choice(&TIMER_NAMES), "Tick source (apic, pit)"),
+    BootOption::new("tickless", OptionKind::Flag, "Program the timer one-shot instead of ticking periodically"),
+];
+
+/// Default tick frequency
+pub const DEFAULT_HZ: u64 = 1000;
+/// Highest tick frequency accepted from the `hz` option
+const MAX_HZ: u64 = 10_000;
+
+/// Longest time a CPU sleeps without a timer interrupt in tickless mode
+pub const MAX_IDLE_NS: u64 = 1_000_000_000;
+
+/// Length of each calibration run
+const CALIBRATION_US: u32 = 10_000;
+/// Number of calibration runs (the fastest one is used)
+const CALIBRATION_RUNS: usize = 3;
+
+/// Divisor of the APIC timer input clock
+const APIC_TIMER_DIVISOR: u8 = 16;
+
+/// Device generating the timer interrupts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickSource {
+    /// PIT channel 0 on IRQ 0
+    Pit,
+    /// Local APIC timer on a dynamically allocated vector
+    ApicTimer,
+}
+
+/// How timer interrupts are generated
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickMode {
+    /// One interrupt per tick
+    Periodic,
+    /// One-shot interrupts at the next event
+    Tickless,
+}
+
+/// Errors that can occur while starting the tick source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeError {
+    /// The timer interrupt handler could not be registered
+    Irq(IrqError),
+}
+
+impl From<IrqError> for TimeError {
+    fn from(error: IrqError) -> Self {
+        TimeError::Irq(error)
+    }
+}
+
+impl core::fmt::Display for TimeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            TimeError::Irq(error) => write!(f, "timer interrupt: {}", error),
+        }
+    }
+}
+
+/// Clock configuration, fixed by `init`
+#[derive(Debug, Clone, Copy)]
+struct Clock {
+    source: TickSource,
+    mode: TickMode,
+    /// Length of a tick in nanoseconds
+    tick_ns: u64,
+    /// TSC frequency in Hz (0 if the TSC is not used)
+    tsc_hz: u64,
+    /// TSC value at `init`
+    tsc_base: u64,
+    /// Nanoseconds per TSC cycle, 32.32 fixed point
+    tsc_mult: u64,
+    /// APIC timer frequency in Hz after the divisor (0 if not calibrated)
+    apic_timer_hz: u64,
+    /// Vector of the APIC timer interrupt
+    apic_vector: u8,
+}
+
+/// Clock configuration, set once by `init` before interrupts are enabled
+static mut CLOCK: Option<Clock> = None;
+
+/// Ticks counted by the periodic timer interrupt
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Unix time in nanoseconds at `monotonic_ns() == 0` (0 if unknown)
+static WALL_CLOCK_BASE_NS: AtomicU64 = AtomicU64::new(0);
+
+/// Clock configuration, if `init` has run
+fn clock() -> Option<Clock> {
+    unsafe { *core::ptr::addr_of!(CLOCK) }
+}
+
+/// Measure the TSC and APIC timer frequencies against PIT channel 2
+/// 
+/// Returns (TSC Hz, APIC timer Hz), with 0 for a counter that is not
+/// available. Must run with interrupts disabled.
+fn calibrate(use_tsc: bool, use_apic: bool) -> (u64, u64) {
+    let count = (pit::PIT_FREQUENCY as u64 * CALIBRATION_US as u64 / 1_000_000) as u16;
+    let mut fastest: Option<(u64, u64)> = None;
+    
+    for _ in 0..CALIBRATION_RUNS {
+        pit::prepare_channel2(count);
+        if use_apic {
+            apic::start_counting(APIC_TIMER_DIVISOR);
+        }
+        
+        let apic_start = if use_apic { apic::timer_count() } else { 0 };
+        let tsc_start = cpu::rdtsc();
+        pit::start_channel2();
+        while !pit::channel2_done() {
+            core::hint::spin_loop();
+        }
+        let tsc_cycles = cpu::rdtsc() - tsc_start;
+        let apic_counts = if use_apic { (apic_start - apic::timer_count()) as u64 } else { 0 };
+        
+        // An SMI or a slow port access only ever makes a run longer
+        if fastest.is_none_or(|(cycles, _)| tsc_cycles < cycles) {
+            fastest = Some((tsc_cycles, apic_counts));
+        }
+    }
+    
+    if use_apic {
+        apic::stop_timer();
+    }
+    
+    let (tsc_cycles, apic_counts) = fastest.unwrap_or((0, 0));
+    let to_hz = |counts: u64| counts * pit::PIT_FREQUENCY as u64 / count as u64;
+    (if use_tsc { to_hz(tsc_cycles) } else { 0 }, to_hz(apic_counts))
+}
+
+/// Calibrate the clocks and start the tick source
+/// 
+/// Must be called after the interrupt controller is initialized and
+/// before interrupts are enabled.
+pub fn init() -> Result<(), TimeError> {
+    let hz = match cmdline::number("hz") {
+        Some(hz) if (1..=MAX_HZ).contains(&hz) => hz,
+        Some(hz) => {
+            println!("Warning: hz={} out of range (1-{}), using {}", hz, MAX_HZ, DEFAULT_HZ);
+            DEFAULT_HZ
+        }
+        None => DEFAULT_HZ,
+    };
+    
+    let use_tsc = cpu::has_tsc();
+    let use_apic = apic::is_enabled() && cmdline::choice("timer") != Some("pit");
+    let (tsc_hz, apic_timer_hz) = without_interrupts(|| calibrate(use_tsc, use_apic));
+    
+    let source = if apic_timer_hz != 0 { TickSource::ApicTimer } else { TickSource::Pit };
+    let mode = match cmdline::flag("tickless") {
+        true if source == TickSource::ApicTimer && tsc_hz != 0 => TickMode::Tickless,
+        true => {
+            println!("Warning: tickless mode needs the APIC timer and the TSC, using periodic ticks");
+            TickMode::Periodic
+        }
+        false => TickMode::Periodic,
+    };
+    
+    let mut clock = Clock {
+        source,
+        mode,
+        tick_ns: 1_000_000_000 / hz,
+        tsc_hz,
+        tsc_base: cpu::rdtsc(),
+        tsc_mult: if tsc_hz != 0 { ((1_000_000_000u128 << 32) / tsc_hz as u128) as u64 } else { 0 },
+        apic_timer_hz,
+        apic_vector: 0,
+    };
+    
+    match source {
+        TickSource::Pit => {
+            clock.tick_ns = pit::start_periodic(hz as u32);
+            set_clock(clock);
+            irq::register_irq(0, "pit", timer_interrupt_handler, 0, IrqSharing::Exclusive)?;
+        }
+        TickSource::ApicTimer => {
+            // The firmware leaves channel 0 running at 18.2 Hz
+            pit::stop();
+            
+            clock.apic_vector = irq::allocate_vector()?;
+            set_clock(clock);
+            irq::register_vector(clock.apic_vector, "apic-timer", timer_interrupt_handler, 0, IrqSharing::Exclusive)?;
+            
+            match mode {
+                TickMode::Periodic => {
+                    let count = apic_counts(&clock, clock.tick_ns);
+                    apic::start_timer(clock.apic_vector, TimerMode::Periodic, APIC_TIMER_DIVISOR, count);
+                }
+                TickMode::Tickless => program_oneshot(&clock, MAX_IDLE_NS),
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Store the clock configuration
+fn set_clock(clock: Clock) {
+    unsafe { *core::ptr::addr_of_mut!(CLOCK) = Some(clock) };
+}
+
+/// APIC timer count for a delay
+fn apic_counts(clock: &Clock, delay_ns: u64) -> u32 {
+    let counts = delay_ns as u128 * clock.apic_timer_hz as u128 / 1_000_000_000;
+    counts.clamp(1, u32::MAX as u128) as u32
+}
+
+/// Arm the APIC timer to interrupt once after a delay
+fn program_oneshot(clock: &Clock, delay_ns: u64) {
+    apic::start_timer(clock.apic_vector, TimerMode::OneShot, APIC_TIMER_DIVISOR, apic_counts(clock, delay_ns));
+}
+
+/// Arm the APIC timer for the earliest pending kernel timer
+/// 
+/// Sleeps at most `MAX_IDLE_NS`. Only used in tickless mode.
+fn program_next_event(clock: &Clock) {
+    let now = monotonic_ns();
+    let delay = match timer::next_deadline() {
+        Some(deadline) => deadline.saturating_sub(now).min(MAX_IDLE_NS),
+        None => MAX_IDLE_NS,
+    };
+    program_oneshot(clock, delay);
+}
+
+/// Make sure a timer interrupt arrives by the earliest kernel timer
+/// 
+/// Called by the kernel timers when a new earliest timer is queued. Only
+/// tickless mode needs to act; periodic ticks check the timers anyway.
+pub fn schedule_event() {
+    if let Some(clock) = clock().filter(|clock| clock.mode == TickMode::Tickless) {
+        without_interrupts(|| program_next_event(&clock));
+    }
+}
+
+/// Timer interrupt handler for both tick sources
+/// 
+/// Counts the tick and runs the expired kernel timers.
+fn timer_interrupt_handler(_context: &mut InterruptContext, _data: usize) -> IrqReturn {
+    let Some(clock) = clock() else {
+        return IrqReturn::NotHandled;
+    };
+    
+    if clock.mode == TickMode::Periodic {
+        TICKS.fetch_add(1, Ordering::Relaxed);
+    }
+    timer::run_expired();
+    if clock.mode == TickMode::Tickless {
+        program_next_event(&clock);
+    }
+    IrqReturn::Handled
+}
+
+/// Nanoseconds since `init`
+/// 
+/// Read from the TSC when it is calibrated, so the value advances between
+/// ticks; otherwise it has tick resolution. Returns 0 before `init`.
+pub fn monotonic_ns() -> u64 {
+    let Some(clock) = clock() else {
+        return 0;
+    };
+    
+    if clock.tsc_hz != 0 {
+        let cycles = cpu::rdtsc().saturating_sub(clock.tsc_base);
+        ((cycles as u128 * clock.tsc_mult as u128) >> 32) as u64
+    } else {
+        TICKS.load(Ordering::Relaxed) * clock.tick_ns
+    }
+}
+
+/// Time since `init`
+pub fn uptime() -> Duration {
+    Duration::from_nanos(monotonic_ns())
+}
+
+/// Read the date from the RTC and start the wall clock
+/// 
+/// Must be called after `init`.
+pub fn init_wall_clock() -> Result<DateTime, RtcError> {
+    let date = rtc::read_time()?;
+    let base = (date.to_unix() * 1_000_000_000).saturating_sub(monotonic_ns());
+    WALL_CLOCK_BASE_NS.store(base, Ordering::Relaxed);
+    Ok(date)
+}
+
+/// Current Unix time in seconds, if the wall clock has been started
+pub fn wall_clock() -> Option<u64> {
+    wall_clock_ns().map(|ns| ns / 1_000_000_000)
+}
+
+/// Current Unix time in nanoseconds, if the wall clock has been started
+pub fn wall_clock_ns() -> Option<u64> {
+    match WALL_CLOCK_BASE_NS.load(Ordering::Relaxed) {
+        0 => None,
+        base => Some(base + monotonic_ns()),
+    }
+}
+
+/// Number of ticks since `init`
+/// 
+/// In tickless mode no interrupt marks each tick, so the count is derived
+/// from the clock instead.
+pub fn ticks() -> u64 {
+    match clock() {
+        Some(clock) if clock.mode == TickMode::Tickless => monotonic_ns() / clock.tick_ns,
+        Some(_) => TICKS.load(Ordering::Relaxed),
+        None => 0,
+    }
+}
+
+/// Length of a tick in nanoseconds
+pub fn tick_ns() -> u64 {
+    clock().map_or(1_000_000_000 / DEFAULT_HZ, |clock| clock.tick_ns)
+}
+
+/// Active tick source and mode, if `init` has run
+pub fn tick_source() -> Option<(TickSource, TickMode)> {
+    clock().map(|clock| (clock.source, clock.mode))
+}
+
+/// Measured TSC frequency in Hz, if the TSC is used
+pub fn tsc_frequency() -> Option<u64> {
+    clock().map(|clock| clock.tsc_hz).filter(|&hz| hz != 0)
+}
+
+/// Measured APIC timer frequency in Hz (after the divisor), if calibrated
+pub fn apic_timer_frequency() -> Option<u64> {
+    clock().map(|clock| clock.apic_timer_hz).filter(|&hz| hz != 0)
+}
+
+/// Busy-wait for a number of microseconds
+/// 
+/// Uses the clock once it runs, and PIT channel 2 before that.
+pub fn delay_us(us: u64) {
+    if tsc_frequency().is_none() {
+        let mut remaining = us;
+        while remaining > 0 {
+            let step = remaining.min(50_000);
+            pit::wait_us(step as u32);
+            remaining -= step;
+        }
+        return;
+    }
+    
+    let end = monotonic_ns() + us * 1000;
+    while monotonic_ns() < end {
+        core::hint::spin_loop();
+    }
+}
+
+/// Print the tick source, mode and measured frequencies
+pub fn print_status() {
+    let Some(clock) = clock() else {
+        println!("Timer: not initialized");
+        return;
+    };
+    
+    let source = match clock.source {
+        TickSource::Pit => "PIT",
+        TickSource::ApicTimer => "APIC timer",
+    };
+    match clock.mode {
+        TickMode::Periodic => println!("Timer: {}, {} Hz periodic", source, 1_000_000_000 / clock.tick_ns),
+        TickMode::Tickless => println!("Timer: {}, tickless (tick {} ns)", source, clock.tick_ns),
+    }
+    
+    if clock.tsc_hz != 0 {
+        let invariant = if cpu::has_invariant_tsc() { "invariant" } else { "not invariant" };
+        println!("  TSC: {}.{:03} MHz ({})", clock.tsc_hz / 1_000_000, clock.tsc_hz / 1000 % 1000, invariant);
+    }
+    if clock.apic_timer_hz != 0 {
+        println!("  APIC timer: {}.{:03} MHz (divisor {})", clock.apic_timer_hz / 1_000_000, clock.apic_timer_hz / 1000 % 1000, APIC_TIMER_DIVISOR);
+    }
+    if let Some(now) = wall_clock() {
+        println!("  Wall clock: {}", DateTime::from_unix(now));
+    }
+}
@@ -0,0 +1,332 @@
+/// Kernel timers, sleep and timeouts
+/// 
+/// Deferred work is queued as timers that run a callback once or
+/// periodically from the timer interrupt:
+/// 
+/// ```rust
+/// fn blink(_id: TimerId, led: usize) { /* ... */ }
+/// 
+/// let id = timer::add_periodic(Duration::from_millis(500), blink, 0)?;
+/// timer::cancel(id)?;
+/// ```
+/// 
+/// `sleep` halts the CPU until a point in time, and `Deadline` /
+/// `poll_until` bound the time spent polling hardware:
+/// 
+/// ```rust
+/// timer::poll_until(Duration::from_millis(10), || status() & READY != 0)?;
+/// ```
+/// 
+/// ## Design
+/// 
+/// Pending timers are kept in a fixed-size binary min-heap ordered by
+/// deadline, so the timer interrupt only looks at the first entry when
+/// nothing has expired. Callbacks run in interrupt context with the queue
+/// unlocked, so they may add or cancel timers, but must not block. In
+/// tickless mode the time module arms the APIC timer for the earliest
+/// deadline.
+/// 
+/// Timers use `time::monotonic_ns`, so they need `time::init` to have run.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+use super::interrupts::setup::{disable_interrupts, enable_interrupts, interrupts_enabled};
+use super::sync::SpinLock;
+use super::time;
+
+/// Maximum number of pending timers
+pub const MAX_TIMERS: usize = 64;
+
+/// Callback run when a timer expires, with the timer's ID and context value
+pub type TimerCallback = fn(TimerId, usize);
+
+/// Identifies a pending timer
+/// 
+/// IDs are never reused, so a stale ID cannot cancel another timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimerId(u64);
+
+/// Errors returned by the timer functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerError {
+    /// All `MAX_TIMERS` slots are in use
+    QueueFull,
+    /// The timer has already fired (one-shot) or been cancelled
+    NotFound,
+    /// A periodic timer was given a zero period
+    InvalidPeriod,
+    /// A deadline passed before the awaited condition became true
+    TimedOut,
+}
+
+impl core::fmt::Display for TimerError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            TimerError::QueueFull => write!(f, "timer queue full"),
+            TimerError::NotFound => write!(f, "no such timer"),
+            TimerError::InvalidPeriod => write!(f, "zero timer period"),
+            TimerError::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// A pending timer
+#[derive(Clone, Copy)]
+struct Timer {
+    id: TimerId,
+    /// Expiry time in `monotonic_ns` nanoseconds
+    deadline: u64,
+    /// Period in nanoseconds (0 for one-shot timers)
+    period: u64,
+    callback: TimerCallback,
+    data: usize,
+}
+
+/// Callback of unused heap slots
+fn no_callback(_id: TimerId, _data: usize) {}
+
+const EMPTY_TIMER: Timer = Timer { id: TimerId(0), deadline: 0, period: 0, callback: no_callback, data: 0 };
+
+/// Binary min-heap of pending timers, ordered by deadline
+struct TimerQueue {
+    timers: [Timer; MAX_TIMERS],
+    len: usize,
+}
+
+impl TimerQueue {
+    const fn new() -> Self {
+        Self { timers: [EMPTY_TIMER; MAX_TIMERS], len: 0 }
+    }
+    
+    /// Earliest pending timer
+    fn peek(&self) -> Option<&Timer> {
+        self.timers[..self.len].first()
+    }
+    
+    fn push(&mut self, timer: Timer) -> Result<(), TimerError> {
+        if self.len == MAX_TIMERS {
+            return Err(TimerError::QueueFull);
+        }
+        self.timers[self.len] = timer;
+        self.len += 1;
+        self.sift_up(self.len - 1);
+        Ok(())
+    }
+    
+    /// Remove the timer at a heap position
+    fn remove(&mut self, index: usize) -> Timer {
+        let timer = self.timers[index];
+        self.len -= 1;
+        if index != self.len {
+            self.timers[index] = self.timers[self.len];
+            self.sift_down(index);
+            self.sift_up(index);
+        }
+        timer
+    }
+    
+    fn position(&self, id: TimerId) -> Option<usize> {
+        self.timers[..self.len].iter().position(|timer| timer.id == id)
+    }
+    
+    fn sift_up(&mut self, mut index: usize) {
+        while index > 0 {
+            let parent = (index - 1) / 2;
+            if self.timers[parent].deadline <= self.timers[index].deadline {
+                break;
+            }
+            self.timers.swap(parent, index);
+            index = parent;
+        }
+    }
+    
+    fn sift_down(&mut self, mut index: usize) {
+        loop {
+            let mut smallest = index;
+            for child in [2 * index + 1, 2 * index + 2] {
+                if child < self.len && self.timers[child].deadline < self.timers[smallest].deadline {
+                    smallest = child;
+                }
+            }
+            if smallest == index {
+                break;
+            }
+            self.timers.swap(smallest, index);
+            index = smallest;
+        }
+    }
+}
+
+/// Pending timers
+static QUEUE: SpinLock<TimerQueue> = SpinLock::new(TimerQueue::new());
+
+/// Next timer ID to hand out
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Convert a duration to nanoseconds, saturating at `u64::MAX`
+fn duration_ns(duration: Duration) -> u64 {
+    duration.as_nanos().min(u64::MAX as u128) as u64
+}
+
+/// Queue a timer and move the next timer interrupt forward if needed
+fn add(delay: Duration, period: u64, callback: TimerCallback, data: usize) -> Result<TimerId, TimerError> {
+    let id = TimerId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let deadline = time::monotonic_ns().saturating_add(duration_ns(delay));
+    
+    let earliest = {
+        let mut queue = QUEUE.lock();
+        queue.push(Timer { id, deadline, period, callback, data })?;
+        queue.peek().map(|timer| timer.id) == Some(id)
+    };
+    if earliest {
+        time::schedule_event();
+    }
+    Ok(id)
+}
+
+/// Run a callback once after a delay
+pub fn add_oneshot(delay: Duration, callback: TimerCallback, data: usize) -> Result<TimerId, TimerError> {
+    add(delay, 0, callback, data)
+}
+
+/// Run a callback every `period`, starting one period from now
+/// 
+/// If the timer falls behind (for example while interrupts were disabled),
+/// missed runs are skipped rather than run back to back.
+pub fn add_periodic(period: Duration, callback: TimerCallback, data: usize) -> Result<TimerId, TimerError> {
+    let period_ns = duration_ns(period);
+    if period_ns == 0 {
+        return Err(TimerError::InvalidPeriod);
+    }
+    add(period, period_ns, callback, data)
+}
+
+/// Cancel a pending timer
+/// 
+/// A periodic timer may cancel itself from its callback.
+pub fn cancel(id: TimerId) -> Result<(), TimerError> {
+    let mut queue = QUEUE.lock();
+    let index = queue.position(id).ok_or(TimerError::NotFound)?;
+    queue.remove(index);
+    Ok(())
+}
+
+/// Whether a timer is still pending
+pub fn is_pending(id: TimerId) -> bool {
+    QUEUE.lock().position(id).is_some()
+}
+
+/// Number of pending timers
+pub fn pending_count() -> usize {
+    QUEUE.lock().len
+}
+
+/// Deadline of the earliest pending timer, in `monotonic_ns` nanoseconds
+pub fn next_deadline() -> Option<u64> {
+    QUEUE.lock().peek().map(|timer| timer.deadline)
+}
+
+/// Run the callbacks of all expired timers
+/// 
+/// Called from the timer interrupt. Periodic timers are queued again
+/// before their callback runs.
+pub fn run_expired() {
+    loop {
+        let now = time::monotonic_ns();
+        let expired = {
+            let mut queue = QUEUE.lock();
+            match queue.peek() {
+                Some(timer) if timer.deadline <= now => {
+                    let timer = queue.remove(0);
+                    if timer.period != 0 {
+                        let mut next = timer.deadline.saturating_add(timer.period);
+                        if next <= now {
+                            next = now.saturating_add(timer.period);
+                        }
+                        // The slot was just freed, so this cannot fail
+                        let _ = queue.push(Timer { deadline: next, ..timer });
+                    }
+                    timer
+                }
+                _ => break,
+            }
+        };
+        (expired.callback)(expired.id, expired.data);
+    }
+}
+
+/// A point in time used to bound waits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Deadline(u64);
+
+impl Deadline {
+    /// The deadline `timeout` from now
+    pub fn after(timeout: Duration) -> Self {
+        Deadline(time::monotonic_ns().saturating_add(duration_ns(timeout)))
+    }
+    
+    /// Whether the deadline has passed
+    pub fn expired(&self) -> bool {
+        time::monotonic_ns() >= self.0
+    }
+    
+    /// Time left until the deadline (zero once it has passed)
+    pub fn remaining(&self) -> Duration {
+        Duration::from_nanos(self.0.saturating_sub(time::monotonic_ns()))
+    }
+}
+
+/// Poll a condition until it is true or `timeout` has passed
+/// 
+/// Meant for waiting on hardware status bits. The condition is checked once
+/// more after the deadline, so a slow poll cannot cause a false timeout.
+pub fn poll_until<F: FnMut() -> bool>(timeout: Duration, mut condition: F) -> Result<(), TimerError> {
+    let deadline = Deadline::after(timeout);
+    while !deadline.expired() {
+        if condition() {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    if condition() { Ok(()) } else { Err(TimerError::TimedOut) }
+}
+
+/// Halt the CPU until a deadline
+/// 
+/// With interrupts disabled this busy-waits.
+pub fn sleep_until(deadline: Deadline) {
+    if time::tick_source().is_none() {
+        // No clock yet: the deadline counts from zero
+        time::delay_us(deadline.0 / 1000);
+        return;
+    }
+    if !interrupts_enabled() {
+        while !deadline.expired() {
+            core::hint::spin_loop();
+        }
+        return;
+    }
+    
+    // Make sure an interrupt arrives at the deadline in tickless mode
+    let wakeup = add_oneshot(deadline.remaining(), no_callback, 0);
+    
+    loop {
+        disable_interrupts();
+        if deadline.expired() {
+            break;
+        }
+        // `sti` only takes effect after `hlt` starts, so an interrupt
+        // arriving after the check above still wakes the CPU
+        unsafe { core::arch::asm!("sti; hlt", options(nomem, nostack)) };
+    }
+    enable_interrupts();
+    
+    if let Ok(id) = wakeup {
+        let _ = cancel(id);
+    }
+}
+
+/// Halt the CPU for a duration
+pub fn sleep(duration: Duration) {
+    sleep_until(Deadline::after(duration));
+}
@@ -2,34 +2,158 @@
 #![no_main]
 
 use core::panic::PanicInfo;
-use arch::{clear_screen, println, setup_idt, init_memory};
+use arch::{clear_screen, println, setup_idt, init_memory, gdt};
+use arch::interrupts::{init_interrupt_controller, enable_interrupts};
+use arch::interrupts::apic::{self, ApicMode};
+use arch::interrupts::irq::InterruptController;
+use arch::boot::BootInfo;
+use arch::boot::cmdline;
+use arch::drivers::init_console;
+use arch::drivers::console::{log_enabled, LogLevel};
+
+extern crate alloc;
 
 mod arch;
 
-#[cfg(feature = "run-tests")]
+mod shell;
 mod tests;
 
 /// This function is called on panic.
+/// 
+/// Prints the panic message, location, registers and a backtrace, then halts.
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    loop {}
+fn panic(info: &PanicInfo) -> ! {
+    arch::panic::handle_panic(info)
+}
+
+/// Find the kernel symbol table through the Multiboot2 ELF-symbols tag
+fn load_kernel_symbols(boot_info: &BootInfo) {
+    match unsafe { arch::symbols::init(boot_info) } {
+        Some(count) => println!("Kernel symbols loaded ({} symbols)", count),
+        None => println!("Kernel symbols not available, crash reports will show raw addresses"),
+    }
+}
+
+/// Register the boot options declared by each subsystem
+fn register_boot_options() {
+    let _ = cmdline::register_options(&cmdline::BOOT_OPTIONS);
+    let _ = cmdline::register_options(&arch::drivers::BOOT_OPTIONS);
+    let _ = cmdline::register_options(&arch::drivers::keyboard::BOOT_OPTIONS);
+    let _ = cmdline::register_options(&arch::drivers::mouse::BOOT_OPTIONS);
+    let _ = cmdline::register_options(&arch::interrupts::BOOT_OPTIONS);
+    let _ = cmdline::register_options(&arch::memory::BOOT_OPTIONS);
+    let _ = cmdline::register_options(&arch::time::BOOT_OPTIONS);
+    let _ = cmdline::register_options(&shell::BOOT_OPTIONS);
+    
+    #[cfg(feature = "run-tests")]
+    let _ = cmdline::register_options(&tests::BOOT_OPTIONS);
 }
 
 /// Kernel entry point called by the bootloader
 #[no_mangle]
 pub extern "C" fn kernel_main(multiboot_info_addr: usize, multiboot_magic: usize) -> ! {
+    // Validate the boot information passed by GRUB and read the kernel
+    // command line first, since boot options select the console devices
+    let boot_info = unsafe { arch::boot::init(multiboot_info_addr, multiboot_magic) };
+    if let Some(command_line) = boot_info.ok().and_then(|info| info.command_line()) {
+        unsafe { cmdline::init(command_line) };
+    }
+    register_boot_options();
+    
+    // Bring up the console (VGA and serial) next so that all output is captured
+    let serial_result = init_console();
+    
     // Clear the screen
     clear_screen();
     
     // Print our message - now we're truly in 64-bit long mode!
     println("Hello from NoodleOS - 64-bit Long Mode!");
     
+    if serial_result.is_err() {
+        println("Warning: COM1 not found, output is on VGA only");
+    }
+    
+    cmdline::report_problems();
+    if cmdline::flag("help") {
+        cmdline::print_options();
+    }
+    
+    // Load the kernel GDT and TSS (the IDT refers to their selectors and IST stacks)
+    gdt::init();
+    println("GDT and TSS loaded successfully!");
+    
     // Initialize the IDT
     setup_idt();
     println("IDT initialized successfully!");
     
-    // Initialize memory subsystem
-    init_memory(multiboot_info_addr, multiboot_magic);
+    match boot_info {
+        Ok(boot_info) => {
+            if log_enabled(LogLevel::Info) {
+                boot_info.print_summary();
+            }
+            
+            // Locate the kernel symbol table so crash reports show function names
+            load_kernel_symbols(&boot_info);
+            
+            // Initialize memory subsystem
+            init_memory(&boot_info);
+            
+            // Find the ACPI tables, which describe the interrupt controllers
+            match arch::acpi::init(&boot_info) {
+                Ok(_) if log_enabled(LogLevel::Debug) => arch::acpi::print_tables(),
+                Ok(_) => {}
+                Err(error) => println!("Warning: ACPI tables not available: {}", error),
+            }
+            
+            // Keep copies of the ACPI tables, so the memory the firmware
+            // stored them in can be used
+            if arch::acpi::copy_tables().is_some() {
+                unsafe { arch::memory::reclaim_acpi_memory(&boot_info) };
+            }
+        }
+        Err(error) => println!("Invalid Multiboot2 boot information: {}", error),
+    }
+    
+    // Now that frames can be allocated, move the IST stacks to guarded
+    // memory and put a guard page under the boot stack
+    gdt::init_ist_stacks();
+    if arch::memory::stack::protect_boot_stack().is_err() {
+        println("Warning: could not add a guard page to the boot stack");
+    }
+    
+    // Move the IRQs off the exception vectors (switching to the APICs if
+    // present), calibrate the clocks and start the timer tick, send serial
+    // console output from its interrupt and start taking hardware interrupts
+    let controller = init_interrupt_controller();
+    if let Err(error) = arch::time::init() {
+        println!("Warning: timer tick not started: {}", error);
+    }
+    if let Err(error) = arch::time::init_wall_clock() {
+        println!("Warning: wall clock not available: {}", error);
+    }
+    let _ = arch::drivers::enable_console_interrupts();
+    enable_interrupts();
+    match (controller, apic::mode()) {
+        (InterruptController::Apic, Some(ApicMode::X2Apic)) => println("x2APIC and I/O APIC enabled, interrupts enabled"),
+        (InterruptController::Apic, _) => println("Local APIC and I/O APIC enabled, interrupts enabled"),
+        (InterruptController::Pic, _) => println("PIC remapped, interrupts enabled"),
+    }
+    arch::time::print_status();
+    
+    // The keyboard and mouse commands time out through the clock, so the
+    // PS/2 devices come up once interrupts are running
+    if !cmdline::flag("nokeyboard") {
+        match arch::drivers::keyboard::init() {
+            Ok(set) => println!("PS/2 keyboard enabled (scancode set {}, keymap {})", set.number(), arch::drivers::keyboard::keymap_name()),
+            Err(error) => println!("Warning: PS/2 keyboard not available: {}", error),
+        }
+    }
+    if !cmdline::flag("nomouse") {
+        match arch::drivers::mouse::init() {
+            Ok(kind) => println!("PS/2 mouse enabled ({})", kind.name()),
+            Err(error) => println!("Warning: PS/2 mouse not available: {}", error),
+        }
+    }
     
     // Run tests if enabled
     #[cfg(feature = "run-tests")]
@@ -38,6 +162,10 @@ pub extern "C" fn kernel_main(multiboot_info_addr: usize, multiboot_magic: usize
     }
     
     println("Kernel initialization complete.");
+    if !cmdline::flag("noshell") {
+        println("System ready. Type 'help' for a list of commands.");
+        shell::run();
+    }
     println("System ready. CPU will now halt.");
     
     // Halt the CPU - simple infinite loop
@@ -0,0 +1,207 @@
+/// Shell Commands
+/// 
+/// Each command is a function taking the shell and the words after the
+/// command name. Commands print their own usage message when the
+/// arguments are wrong.
+
+use core::str::SplitWhitespace;
+use crate::arch::ByteSize;
+use crate::arch::boot::{self, cmdline};
+use crate::arch::drivers::{self, serial, CONSOLE_SERIAL_PORT};
+use crate::arch::drivers::rtc::DateTime;
+use crate::arch::interrupts::{irq, pic};
+use crate::arch::memory::{self, constants::PAGE_SIZE, heap, physical, reserve, slab, VirtAddr, Zone};
+use crate::arch::{power, time};
+use crate::{print, println};
+use super::Shell;
+
+/// A shell command
+pub struct Command {
+    pub name: &'static str,
+    /// Arguments, shown by `help`
+    pub usage: &'static str,
+    pub help: &'static str,
+    pub run: fn(&mut Shell, SplitWhitespace),
+}
+
+/// All shell commands, in the order `help` lists them
+pub static COMMANDS: [Command; 14] = [
+    Command { name: "help", usage: "", help: "List the commands", run: help },
+    Command { name: "clear", usage: "", help: "Clear the screen", run: clear },
+    Command { name: "history", usage: "", help: "Show the previous command lines", run: history },
+    Command { name: "meminfo", usage: "", help: "Physical memory and heap usage", run: meminfo },
+    Command { name: "slabinfo", usage: "", help: "Slab cache statistics", run: slabinfo },
+    Command { name: "mmap", usage: "", help: "Memory map from the bootloader", run: mmap },
+    Command { name: "reserved", usage: "", help: "Memory kept from the frame allocator at boot", run: reserved },
+    Command { name: "translate", usage: "<vaddr>", help: "Physical address a virtual address maps to", run: translate },
+    Command { name: "idt", usage: "", help: "Interrupt descriptor table", run: idt },
+    Command { name: "irqstats", usage: "", help: "Device vectors, handlers and interrupt counts", run: irqstats },
+    Command { name: "uptime", usage: "", help: "Time since boot and wall clock time", run: uptime },
+    Command { name: "cmdline", usage: "", help: "Kernel command line and boot options", run: boot_options },
+    Command { name: "run-test", usage: "[name]", help: "Run a kernel test (lists the tests without a name)", run: run_test },
+    Command { name: "reboot", usage: "", help: "Reset the machine", run: reboot },
+];
+
+/// Look up a command by name
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+fn help(_shell: &mut Shell, _args: SplitWhitespace) {
+    for command in &COMMANDS {
+        print!("  {} {}", command.name, command.usage);
+        let width = command.name.len() + command.usage.len() + 1;
+        println!("{:>pad$}{}", "", command.help, pad = 20usize.saturating_sub(width));
+    }
+}
+
+fn clear(_shell: &mut Shell, _args: SplitWhitespace) {
+    drivers::clear_screen();
+    // Also clear a serial terminal and move its cursor home
+    serial::write_str(CONSOLE_SERIAL_PORT, "\x1b[2J\x1b[H");
+}
+
+fn history(shell: &mut Shell, _args: SplitWhitespace) {
+    for (number, line) in shell.editor.history().iter() {
+        println!("{:>4}  {}", number, line);
+    }
+}
+
+fn meminfo(_shell: &mut Shell, _args: SplitWhitespace) {
+    let stats = physical::memory_stats();
+    let size = |frames: usize| ByteSize((frames * PAGE_SIZE) as u64);
+    println!("Physical memory ({} byte frames):", PAGE_SIZE);
+    println!("  Total:     {:>10} frames  {}", stats.total_frames, size(stats.total_frames));
+    println!("  Free:      {:>10} frames  {}", stats.free_frames, size(stats.free_frames));
+    println!("  Allocated: {:>10} frames  {}", stats.allocated_frames, size(stats.allocated_frames));
+    println!("  Allocator: {}", physical::ALLOCATOR_NAME);
+    println!("  Zone        Frames      Free    Allocs  Fallback  Failed");
+    for zone in Zone::ALL {
+        let zone_stats = stats.zones[zone.index()];
+        println!(
+            "  {:<6}  {:>10}  {:>8}  {:>8}  {:>8}  {:>6}",
+            zone.name(),
+            zone_stats.total_frames,
+            zone_stats.free_frames,
+            zone_stats.allocations,
+            zone_stats.fallback_allocations,
+            zone_stats.failures
+        );
+    }
+    for zone in Zone::ALL {
+        if let Some(orders) = physical::order_stats(zone).filter(|_| stats.zones[zone.index()].total_frames > 0) {
+            print!("  {} free blocks by order:", zone.name());
+            for order in &orders {
+                print!(" {}", order.free_blocks);
+            }
+            println!();
+        }
+    }
+    
+    let heap = heap::heap_stats();
+    println!("Kernel heap ({}):", heap::ALLOCATOR_NAME);
+    println!("  Mapped:    {}", ByteSize(heap.size as u64));
+    println!("  In use:    {} in {} allocations", ByteSize(heap.used as u64), heap.allocations);
+    if heap.failures > 0 {
+        println!("  Failed:    {} allocations", heap.failures);
+    }
+}
+
+fn slabinfo(_shell: &mut Shell, _args: SplitWhitespace) {
+    slab::print_caches();
+}
+
+fn mmap(_shell: &mut Shell, _args: SplitWhitespace) {
+    match boot::multiboot2::boot_info() {
+        Some(boot_info) => boot_info.print_memory_map(),
+        None => println!("No boot information available"),
+    }
+}
+
+fn reserved(_shell: &mut Shell, _args: SplitWhitespace) {
+    reserve::print_reservations();
+}
+
+fn translate(_shell: &mut Shell, mut args: SplitWhitespace) {
+    let Some(addr) = args.next().and_then(parse_address) else {
+        println!("usage: translate <vaddr>  (hexadecimal, 0x prefix optional)");
+        return;
+    };
+    let upper = addr >> 47;
+    if upper != 0 && upper != 0x1FFFF {
+        println!("{:#X} is not a canonical address", addr);
+        return;
+    }
+    
+    let mapper = unsafe { memory::active_mapper() };
+    match mapper.translate(VirtAddr::new(addr)) {
+        Some(phys) => println!("{:#018X} -> {:#018X}", addr, phys.as_u64()),
+        None => println!("{:#018X} is not mapped", addr),
+    }
+}
+
+/// Parse an address, which is hexadecimal even without the 0x prefix
+fn parse_address(value: &str) -> Option<u64> {
+    let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+    let mut addr: u64 = 0;
+    let mut any = false;
+    for c in digits.chars().filter(|&c| c != '_') {
+        addr = addr.checked_mul(16)?.checked_add(c.to_digit(16)? as u64)?;
+        any = true;
+    }
+    any.then_some(addr)
+}
+
+fn idt(_shell: &mut Shell, _args: SplitWhitespace) {
+    crate::arch::interrupts::print_idt();
+}
+
+fn irqstats(_shell: &mut Shell, _args: SplitWhitespace) {
+    println!("Interrupt controller: {:?}", irq::controller());
+    irq::print_vectors();
+    let (irq7, irq15) = pic::spurious_counts();
+    if irq7 + irq15 > 0 {
+        println!("Spurious PIC interrupts: IRQ 7: {}, IRQ 15: {}", irq7, irq15);
+    }
+}
+
+fn uptime(_shell: &mut Shell, _args: SplitWhitespace) {
+    let uptime = time::uptime();
+    let seconds = uptime.as_secs();
+    println!(
+        "Up {}:{:02}:{:02}.{:03} ({} ticks)",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        uptime.subsec_millis(),
+        time::ticks()
+    );
+    if let Some(now) = time::wall_clock() {
+        println!("Wall clock: {}", DateTime::from_unix(now));
+    }
+}
+
+fn boot_options(_shell: &mut Shell, _args: SplitWhitespace) {
+    println!("Command line: {}", cmdline::command_line());
+    cmdline::print_options();
+}
+
+fn run_test(_shell: &mut Shell, mut args: SplitWhitespace) {
+    let Some(name) = args.next() else {
+        println!("Available tests:");
+        for test in &crate::tests::NAMED_TESTS {
+            println!("  {:<16} {}", test.name, test.description);
+        }
+        return;
+    };
+    match crate::tests::find_test(name) {
+        Some(test) => (test.run)(),
+        None => println!("Unknown test '{}'; run-test without a name lists the tests", name),
+    }
+}
+
+fn reboot(_shell: &mut Shell, _args: SplitWhitespace) {
+    println!("Rebooting...");
+    serial::flush(CONSOLE_SERIAL_PORT);
+    power::reboot();
+}
@@ -0,0 +1,150 @@
+/// Shell Input
+/// 
+/// Turns keyboard events and bytes from the serial console into editing
+/// `Key`s. Serial terminals send the cursor keys as ANSI escape sequences:
+/// 
+/// ```text
+/// ESC [ A / B / C / D    Up, Down, Right, Left (ESC O A ... in application mode)
+/// ESC [ H / F            Home, End (also ESC [ 1 ~ / 4 ~ and ESC [ 7 ~ / 8 ~)
+/// ESC [ 3 ~              Delete
+/// ```
+/// 
+/// Enter arrives as CR, LF or CR LF, and Backspace as DEL (0x7F) or BS.
+
+use crate::arch::drivers::keyboard::scancode::KeyCode;
+use crate::arch::drivers::keyboard::{self, KeyEvent};
+use crate::arch::drivers::{serial, CONSOLE_SERIAL_PORT};
+use super::line::Key;
+
+/// Key for a control character typed on either console
+fn control_key(c: u8) -> Option<Key> {
+    Some(match c {
+        b'\r' | b'\n' => Key::Enter,
+        0x08 | 0x7F => Key::Backspace,
+        0x01 => Key::Home,
+        0x03 => Key::Cancel,
+        0x05 => Key::End,
+        0x15 => Key::ClearLine,
+        0x20..=0x7E => Key::Char(c),
+        _ => return None,
+    })
+}
+
+/// Key for a keyboard event
+pub fn key_from_event(event: &KeyEvent) -> Option<Key> {
+    if !event.pressed {
+        return None;
+    }
+    if let Some(ch) = event.ch {
+        return u8::try_from(ch).ok().and_then(control_key);
+    }
+    
+    // The keypad keys only move the cursor with Num Lock off, when they
+    // type no character
+    Some(match event.key {
+        KeyCode::Left | KeyCode::Keypad4 => Key::Left,
+        KeyCode::Right | KeyCode::Keypad6 => Key::Right,
+        KeyCode::Up | KeyCode::Keypad8 => Key::Up,
+        KeyCode::Down | KeyCode::Keypad2 => Key::Down,
+        KeyCode::Home | KeyCode::Keypad7 => Key::Home,
+        KeyCode::End | KeyCode::Keypad1 => Key::End,
+        KeyCode::Delete | KeyCode::KeypadPeriod => Key::Delete,
+        _ => return None,
+    })
+}
+
+/// Position in an escape sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EscapeState {
+    Ground,
+    /// ESC received
+    Escape,
+    /// ESC [ received, with the number collected so far
+    Csi(u8),
+    /// ESC O received
+    Ss3,
+}
+
+/// Decodes the bytes a serial terminal sends
+#[derive(Debug, Clone)]
+pub struct SerialDecoder {
+    state: EscapeState,
+    /// The last byte was CR, so an LF right after it is part of the same Enter
+    after_cr: bool,
+}
+
+impl SerialDecoder {
+    pub const fn new() -> Self {
+        SerialDecoder { state: EscapeState::Ground, after_cr: false }
+    }
+    
+    /// Feed a byte, returning the key it completes
+    pub fn feed(&mut self, byte: u8) -> Option<Key> {
+        let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+        
+        match self.state {
+            EscapeState::Ground => match byte {
+                0x1B => {
+                    self.state = EscapeState::Escape;
+                    None
+                }
+                b'\n' if after_cr => None,
+                _ => control_key(byte),
+            },
+            EscapeState::Escape => {
+                self.state = match byte {
+                    b'[' => EscapeState::Csi(0),
+                    b'O' => EscapeState::Ss3,
+                    _ => EscapeState::Ground,
+                };
+                None
+            }
+            EscapeState::Csi(number) if byte.is_ascii_digit() => {
+                self.state = EscapeState::Csi(number.saturating_mul(10).saturating_add(byte - b'0'));
+                None
+            }
+            EscapeState::Csi(number) => {
+                self.state = EscapeState::Ground;
+                match (byte, number) {
+                    (b'~', 1 | 7) => Some(Key::Home),
+                    (b'~', 4 | 8) => Some(Key::End),
+                    (b'~', 3) => Some(Key::Delete),
+                    (b'~', _) => None,
+                    _ => cursor_key(byte),
+                }
+            }
+            EscapeState::Ss3 => {
+                self.state = EscapeState::Ground;
+                cursor_key(byte)
+            }
+        }
+    }
+}
+
+/// Key for the final byte of a cursor key sequence
+fn cursor_key(byte: u8) -> Option<Key> {
+    Some(match byte {
+        b'A' => Key::Up,
+        b'B' => Key::Down,
+        b'C' => Key::Right,
+        b'D' => Key::Left,
+        b'H' => Key::Home,
+        b'F' => Key::End,
+        _ => return None,
+    })
+}
+
+/// Take the next key from the keyboard or the serial console
+pub fn read_key(decoder: &mut SerialDecoder) -> Option<Key> {
+    while let Some(event) = keyboard::read_event() {
+        if let Some(key) = key_from_event(&event) {
+            return Some(key);
+        }
+    }
+    while let Some(byte) = serial::read_byte(CONSOLE_SERIAL_PORT) {
+        if let Some(key) = decoder.feed(byte) {
+            return Some(key);
+        }
+    }
+    None
+}
@@ -0,0 +1,274 @@
+/// Line Editor
+/// 
+/// Collects a command line from `Key`s, with cursor movement, insertion
+/// and deletion anywhere in the line, and a history recalled with Up and
+/// Down. The line is redrawn with only `\r`, `\x08` (cursor left) and
+/// spaces, which the VGA driver and serial terminals both understand.
+/// 
+/// Lines are ASCII and shorter than the screen width, so the prompt and
+/// line always fit on one screen row and `\r` returns to the prompt.
+
+/// Longest command line
+pub const MAX_LINE: usize = 70;
+
+/// Number of lines kept in the history
+pub const HISTORY_SIZE: usize = 16;
+
+/// An editing key, decoded from the keyboard or a serial terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// A printable ASCII character
+    Char(u8),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    /// Abandon the line (Ctrl+C)
+    Cancel,
+    /// Delete everything before the cursor (Ctrl+U)
+    ClearLine,
+}
+
+/// A line of ASCII text
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    bytes: [u8; MAX_LINE],
+    len: usize,
+}
+
+impl Line {
+    const fn new() -> Self {
+        Line { bytes: [0; MAX_LINE], len: 0 }
+    }
+    
+    fn as_str(&self) -> &str {
+        // Only printable ASCII is ever inserted
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+/// Previously entered lines, oldest overwritten first
+#[derive(Debug, Clone)]
+pub struct History {
+    lines: [Line; HISTORY_SIZE],
+    /// Lines added since the start, so `added % HISTORY_SIZE` is the next slot
+    added: usize,
+}
+
+impl History {
+    pub const fn new() -> Self {
+        History { lines: [Line::new(); HISTORY_SIZE], added: 0 }
+    }
+    
+    /// Number of lines kept
+    pub fn len(&self) -> usize {
+        self.added.min(HISTORY_SIZE)
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.added == 0
+    }
+    
+    /// Add a line, unless it is empty or repeats the newest line
+    fn push(&mut self, line: &Line) {
+        if line.as_str().trim().is_empty() || self.get(0) == Some(line.as_str()) {
+            return;
+        }
+        self.lines[self.added % HISTORY_SIZE] = *line;
+        self.added += 1;
+    }
+    
+    /// Line `age` entries back (0 is the newest)
+    pub fn get(&self, age: usize) -> Option<&str> {
+        if age >= self.len() {
+            return None;
+        }
+        Some(self.lines[(self.added - 1 - age) % HISTORY_SIZE].as_str())
+    }
+    
+    /// Kept lines with their number, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &str)> {
+        let first = self.added - self.len();
+        (first..self.added).map(move |number| (number + 1, self.lines[number % HISTORY_SIZE].as_str()))
+    }
+}
+
+/// Editing state of the command line
+pub struct LineEditor {
+    prompt: &'static str,
+    /// Where the prompt and line are drawn
+    output: fn(&str),
+    line: Line,
+    cursor: usize,
+    /// Characters currently drawn after the prompt
+    shown: usize,
+    history: History,
+    /// History entry being shown, if the user went back
+    browsing: Option<usize>,
+    /// The line being typed before browsing started
+    stash: Line,
+}
+
+impl LineEditor {
+    pub const fn new(prompt: &'static str, output: fn(&str)) -> Self {
+        LineEditor {
+            prompt,
+            output,
+            line: Line::new(),
+            cursor: 0,
+            shown: 0,
+            history: History::new(),
+            browsing: None,
+            stash: Line::new(),
+        }
+    }
+    
+    /// Start a new line and show the prompt
+    pub fn start(&mut self) {
+        self.line.len = 0;
+        self.cursor = 0;
+        self.shown = 0;
+        self.browsing = None;
+        (self.output)(self.prompt);
+    }
+    
+    /// The line typed so far
+    pub fn line(&self) -> &str {
+        self.line.as_str()
+    }
+    
+    /// Position of the cursor in the line
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+    
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+    
+    /// Handle a key; returns true when Enter completed the line
+    /// 
+    /// The completed line stays available through `line` until `start`
+    /// and is added to the history.
+    pub fn feed(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(c) => {
+                if self.line.len == MAX_LINE {
+                    return false;
+                }
+                self.line.bytes.copy_within(self.cursor..self.line.len, self.cursor + 1);
+                self.line.bytes[self.cursor] = c;
+                self.line.len += 1;
+                self.cursor += 1;
+                if self.cursor == self.line.len {
+                    // Typing at the end needs no redraw
+                    let mut buffer = [0; 4];
+                    (self.output)((c as char).encode_utf8(&mut buffer));
+                    self.shown = self.line.len;
+                } else {
+                    self.redraw();
+                }
+            }
+            Key::Enter => {
+                (self.output)("\n");
+                self.history.push(&self.line);
+                self.browsing = None;
+                return true;
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.remove(self.cursor, 1);
+            }
+            Key::Delete if self.cursor < self.line.len => self.remove(self.cursor, 1),
+            Key::ClearLine if self.cursor > 0 => {
+                let count = self.cursor;
+                self.cursor = 0;
+                self.remove(0, count);
+            }
+            Key::Left if self.cursor > 0 => {
+                self.cursor -= 1;
+                (self.output)("\x08");
+            }
+            Key::Right if self.cursor < self.line.len => {
+                // Redrawing the character moves the cursor past it
+                let c = self.line.bytes[self.cursor];
+                self.cursor += 1;
+                let mut buffer = [0; 4];
+                (self.output)((c as char).encode_utf8(&mut buffer));
+            }
+            Key::Home => {
+                self.cursor = 0;
+                self.redraw();
+            }
+            Key::End => {
+                self.cursor = self.line.len;
+                self.redraw();
+            }
+            Key::Up => self.browse(self.browsing.map_or(0, |age| age + 1)),
+            Key::Down => match self.browsing {
+                Some(0) => {
+                    self.browsing = None;
+                    self.line = self.stash;
+                    self.cursor = self.line.len;
+                    self.redraw();
+                }
+                Some(age) => self.browse(age - 1),
+                None => {}
+            },
+            Key::Cancel => {
+                (self.output)("^C\n");
+                self.start();
+            }
+            _ => {}
+        }
+        false
+    }
+    
+    /// Remove `count` characters at `at` and redraw
+    fn remove(&mut self, at: usize, count: usize) {
+        self.line.bytes.copy_within(at + count..self.line.len, at);
+        self.line.len -= count;
+        self.redraw();
+    }
+    
+    /// Show a history entry in place of the line
+    fn browse(&mut self, age: usize) {
+        let Some(entry) = self.history.get(age) else {
+            return;
+        };
+        if self.browsing.is_none() {
+            self.stash = self.line;
+        }
+        let mut line = Line::new();
+        line.bytes[..entry.len()].copy_from_slice(entry.as_bytes());
+        line.len = entry.len();
+        
+        self.line = line;
+        self.cursor = line.len;
+        self.browsing = Some(age);
+        self.redraw();
+    }
+    
+    /// Draw the prompt and line again, blank out characters left over from
+    /// a longer line, and move the cursor back to its position
+    fn redraw(&mut self) {
+        let output = self.output;
+        output("\r");
+        output(self.prompt);
+        output(self.line.as_str());
+        
+        let blank = self.shown.saturating_sub(self.line.len);
+        for _ in 0..blank {
+            output(" ");
+        }
+        for _ in self.cursor..self.line.len + blank {
+            output("\x08");
+        }
+        self.shown = self.line.len;
+    }
+}
@@ -0,0 +1,98 @@
+/// Kernel Shell
+/// 
+/// A command shell that runs once the kernel is initialized. It reads keys
+/// from the PS/2 keyboard and the serial console alike, and its output goes
+/// to every console sink, so the same session shows on the VGA screen and
+/// on a serial terminal.
+/// 
+/// - `line`: line editing and history
+/// - `input`: keyboard events and serial escape sequences to editing keys
+/// - `commands`: the command table
+/// 
+/// While no key is waiting the CPU halts until the next interrupt.
+
+pub mod commands;
+pub mod input;
+pub mod line;
+
+use crate::arch::boot::cmdline::{BootOption, OptionKind};
+use crate::arch::drivers;
+use crate::arch::interrupts::setup::disable_interrupts;
+use crate::arch::interrupts::enable_interrupts;
+use crate::println;
+use input::SerialDecoder;
+use line::LineEditor;
+
+/// Boot options of the shell
+pub static BOOT_OPTIONS: [BootOption; 1] = [
+    BootOption::new("noshell", OptionKind::Flag, "Halt after initialization instead of starting the shell"),
+];
+
+/// Shown before each command line
+const PROMPT: &str = "noodle> ";
+
+/// Shell state
+pub struct Shell {
+    pub editor: LineEditor,
+    serial: SerialDecoder,
+}
+
+impl Shell {
+    pub const fn new() -> Self {
+        Shell {
+            editor: LineEditor::new(PROMPT, drivers::print),
+            serial: SerialDecoder::new(),
+        }
+    }
+    
+    /// Wait for the next key
+    /// 
+    /// Interrupts are disabled while checking for input, and `sti; hlt`
+    /// enables them only as the CPU halts, so a key arriving in between
+    /// wakes the CPU instead of waiting for the next interrupt.
+    fn wait_key(&mut self) -> line::Key {
+        loop {
+            disable_interrupts();
+            if let Some(key) = input::read_key(&mut self.serial) {
+                enable_interrupts();
+                return key;
+            }
+            unsafe {
+                core::arch::asm!("sti; hlt", options(nomem, nostack));
+            }
+        }
+    }
+    
+    /// Run the command on the completed line
+    fn execute(&mut self) {
+        let mut buffer = [0; line::MAX_LINE];
+        let line = self.editor.line();
+        let len = line.len();
+        buffer[..len].copy_from_slice(line.as_bytes());
+        let line = core::str::from_utf8(&buffer[..len]).unwrap_or("");
+        
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return;
+        };
+        match commands::find(name) {
+            Some(command) => (command.run)(self, words),
+            None => println!("{}: command not found (type 'help' for a list)", name),
+        }
+    }
+}
+
+/// Run the shell
+pub fn run() -> ! {
+    let mut shell = Shell::new();
+    loop {
+        shell.editor.start();
+        loop {
+            let key = shell.wait_key();
+            if shell.editor.feed(key) {
+                break;
+            }
+        }
+        shell.execute();
+    }
+}
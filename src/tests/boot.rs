@@ -0,0 +1,344 @@
+/// Boot Information Testing Module
+/// 
+/// This module contains tests for the Multiboot2 boot information parser
+/// and the kernel command line parser. The tests build small boot
+/// information structures in a static buffer, so they do not depend on
+/// what the bootloader passed.
+
+use crate::arch::println;
+use crate::arch::boot::cmdline::{self, BootOption, OptionKind};
+use crate::arch::boot::multiboot2::{BootInfo, BootInfoError, EfiMemoryType, FramebufferKind, MemoryType, TagType};
+use crate::arch::memory::physical::{memory_ranges, RangeKind};
+
+/// Size of the test buffer
+const BUFFER_SIZE: usize = 256;
+
+/// 8-byte aligned buffer for building boot information structures
+#[repr(C, align(8))]
+struct TestBuffer([u8; BUFFER_SIZE]);
+
+/// Buffer the test structures are built in
+static mut BUFFER: TestBuffer = TestBuffer([0; BUFFER_SIZE]);
+
+/// Builder for a boot information structure in `BUFFER`
+struct Builder {
+    len: usize,
+}
+
+impl Builder {
+    /// Start a new structure (leaves room for the header)
+    fn new() -> Self {
+        unsafe { (*core::ptr::addr_of_mut!(BUFFER)).0 = [0; BUFFER_SIZE] };
+        Self { len: 8 }
+    }
+    
+    /// Write bytes at an offset
+    fn write(&mut self, offset: usize, bytes: &[u8]) {
+        let buffer = unsafe { &mut (*core::ptr::addr_of_mut!(BUFFER)).0 };
+        buffer[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+    
+    /// Append a tag with the given payload
+    fn tag(mut self, tag_type: u32, payload: &[u8]) -> Self {
+        let size = 8 + payload.len();
+        self.write(self.len, &tag_type.to_le_bytes());
+        self.write(self.len + 4, &(size as u32).to_le_bytes());
+        self.write(self.len + 8, payload);
+        self.len += size.next_multiple_of(8);
+        self
+    }
+    
+    /// Append the end tag and write the header
+    fn finish(self) -> usize {
+        let builder = self.tag(TagType::End as u32, &[]);
+        builder.finish_without_end()
+    }
+    
+    /// Write the header without appending an end tag
+    fn finish_without_end(mut self) -> usize {
+        let len = self.len as u32;
+        self.write(0, &len.to_le_bytes());
+        core::ptr::addr_of!(BUFFER) as usize
+    }
+}
+
+/// Report a check result
+fn check(name: &str, ok: bool) {
+    if ok {
+        crate::println!("  OK: {}", name);
+    } else {
+        crate::println!("  FAILED: {}", name);
+    }
+}
+
+/// Test parsing a well-formed structure with several tags
+pub fn test_typed_accessors() {
+    println("Test 1: Typed Tag Accessors");
+    
+    // Module: start, end, command line
+    let mut module = [0u8; 16];
+    module[0..4].copy_from_slice(&0x200000u32.to_le_bytes());
+    module[4..8].copy_from_slice(&0x201000u32.to_le_bytes());
+    module[8..14].copy_from_slice(b"initrd");
+    
+    // Basic meminfo: lower 639 KB, upper 130048 KB
+    let mut meminfo = [0u8; 8];
+    meminfo[0..4].copy_from_slice(&639u32.to_le_bytes());
+    meminfo[4..8].copy_from_slice(&130048u32.to_le_bytes());
+    
+    // Framebuffer: RGB 1024x768x32 at 0xFD000000
+    let mut framebuffer = [0u8; 30];
+    framebuffer[0..8].copy_from_slice(&0xFD00_0000u64.to_le_bytes());
+    framebuffer[8..12].copy_from_slice(&4096u32.to_le_bytes());
+    framebuffer[12..16].copy_from_slice(&1024u32.to_le_bytes());
+    framebuffer[16..20].copy_from_slice(&768u32.to_le_bytes());
+    framebuffer[20] = 32;
+    framebuffer[21] = 1;
+    framebuffer[24..30].copy_from_slice(&[16, 8, 8, 8, 0, 8]);
+    
+    let addr = Builder::new()
+        .tag(TagType::BootCommandLine as u32, b"log=debug quiet\0")
+        .tag(TagType::BootLoaderName as u32, b"GRUB 2.12\0")
+        .tag(TagType::Module as u32, &module)
+        .tag(TagType::BasicMemInfo as u32, &meminfo)
+        .tag(TagType::FramebufferInfo as u32, &framebuffer)
+        .tag(TagType::ImageLoadBasePhysicalAddress as u32, &0x100000u32.to_le_bytes())
+        .finish();
+    
+    let boot_info = match unsafe { BootInfo::new(addr) } {
+        Ok(boot_info) => boot_info,
+        Err(_) => {
+            check("Well-formed structure accepted", false);
+            println("");
+            return;
+        }
+    };
+    check("Well-formed structure accepted", true);
+    check("Tag iterator finds all tags", boot_info.tags().count() == 6);
+    check("Command line", boot_info.command_line() == Some("log=debug quiet"));
+    check("Bootloader name", boot_info.bootloader_name() == Some("GRUB 2.12"));
+    
+    let mut modules = boot_info.modules();
+    check(
+        "Module",
+        matches!(modules.next(), Some(m) if m.start == 0x200000 && m.end == 0x201000 && m.cmdline == "initrd")
+            && modules.next().is_none(),
+    );
+    check(
+        "Basic memory info",
+        matches!(boot_info.basic_memory_info(), Some(info) if info.mem_lower == 639 && info.mem_upper == 130048),
+    );
+    check(
+        "Framebuffer",
+        matches!(boot_info.framebuffer(), Some(fb) if fb.addr == 0xFD00_0000 && fb.width == 1024
+            && fb.height == 768 && fb.bpp == 32
+            && matches!(fb.kind, FramebufferKind::Rgb { red_position: 16, .. })),
+    );
+    check("Image load base", boot_info.image_load_base() == Some(0x100000));
+    check("Missing tags return None", boot_info.memory_map().is_none() && boot_info.acpi_new_rsdp().is_none());
+    
+    println("");
+}
+
+/// Test that malformed structures are rejected
+pub fn test_malformed() {
+    println("Test 2: Malformed Structures");
+    
+    check("Null address rejected", unsafe { BootInfo::new(0) }.err() == Some(BootInfoError::NullAddress));
+    
+    let addr = Builder::new().finish();
+    check("Misaligned address rejected", matches!(unsafe { BootInfo::new(addr + 4) }, Err(BootInfoError::Misaligned(_))));
+    
+    let addr = Builder::new().tag(TagType::BootCommandLine as u32, b"x\0").finish_without_end();
+    check("Missing end tag rejected", unsafe { BootInfo::new(addr) }.err() == Some(BootInfoError::MissingEndTag));
+    
+    // A tag whose size runs past total_size
+    let mut builder = Builder::new().tag(TagType::BootCommandLine as u32, b"x\0");
+    builder.write(12, &200u32.to_le_bytes());
+    let addr = builder.finish();
+    check(
+        "Oversized tag rejected",
+        unsafe { BootInfo::new(addr) }.err() == Some(BootInfoError::TagOutOfBounds(8)),
+    );
+    
+    // A tag smaller than its own header
+    let mut builder = Builder::new().tag(TagType::BootCommandLine as u32, b"x\0");
+    builder.write(12, &4u32.to_le_bytes());
+    let addr = builder.finish();
+    check(
+        "Undersized tag rejected",
+        unsafe { BootInfo::new(addr) }.err() == Some(BootInfoError::TagOutOfBounds(8)),
+    );
+    
+    let mut builder = Builder::new();
+    builder.write(0, &4u32.to_le_bytes());
+    check(
+        "Invalid total size rejected",
+        unsafe { BootInfo::new(core::ptr::addr_of!(BUFFER) as usize) }.err() == Some(BootInfoError::InvalidTotalSize(4)),
+    );
+    
+    println("");
+}
+
+/// Options declared for the command line test
+static TEST_OPTIONS: [BootOption; 6] = [
+    BootOption::new("test.flag", OptionKind::Flag, "Test flag"),
+    BootOption::new("test.number", OptionKind::Number, "Test number"),
+    BootOption::new("test.size", OptionKind::Size, "Test size"),
+    BootOption::new("test.str", OptionKind::Str, "Test string"),
+    BootOption::new("test.list", OptionKind::List, "Test list"),
+    BootOption::new("test.choice", OptionKind::Choice(&["on", "off"]), "Test choice"),
+];
+
+/// Test command line tokenizing and typed option queries
+pub fn test_cmdline() {
+    println("Test 3: Kernel Command Line");
+    
+    let mut tokens = cmdline::Tokens::new("  a=1 flag  b=\"x y\" c= ");
+    check(
+        "Tokenizer splits options and handles quotes",
+        tokens.next() == Some(("a", Some("1")))
+            && tokens.next() == Some(("flag", None))
+            && tokens.next() == Some(("b", Some("x y")))
+            && tokens.next() == Some(("c", Some("")))
+            && tokens.next().is_none(),
+    );
+    check(
+        "Sizes and numbers parse",
+        cmdline::parse_size("512M") == Some(512 * 1024 * 1024)
+            && cmdline::parse_size("4k") == Some(4096)
+            && cmdline::parse_size("0x1000") == Some(0x1000)
+            && cmdline::parse_size("12Q").is_none()
+            && cmdline::parse_number("0xFF") == Some(255),
+    );
+    
+    let registered = cmdline::register_options(&TEST_OPTIONS);
+    check("Option table registered", registered.is_ok());
+    check(
+        "Duplicate option table rejected",
+        cmdline::register_options(&TEST_OPTIONS) == Err(cmdline::CmdlineError::AlreadyRegistered),
+    );
+    
+    // Query a test command line, then restore the real one
+    let saved = cmdline::command_line();
+    unsafe {
+        cmdline::init(
+            "test.flag test.number=7 test.number=0x10 test.size=2M test.str=\"a b\" \
+             test.list=x,,y test.choice=maybe",
+        );
+    }
+    
+    check("Flag present", cmdline::flag("test.flag"));
+    check("Last occurrence wins", cmdline::number("test.number") == Some(16));
+    check("Size", cmdline::size("test.size") == Some(2 * 1024 * 1024));
+    check("Quoted string", cmdline::string("test.str") == Some("a b"));
+    check(
+        "List skips empty items",
+        cmdline::list("test.list").is_some_and(|mut items| {
+            items.next() == Some("x") && items.next() == Some("y") && items.next().is_none()
+        }),
+    );
+    check("Invalid choice ignored", cmdline::choice("test.choice").is_none());
+    check("Wrong kind returns nothing", cmdline::number("test.size").is_none() && !cmdline::flag("test.str"));
+    check("Missing option returns nothing", cmdline::string("test.missing").is_none());
+    
+    unsafe { cmdline::init(saved) };
+    
+    println("");
+}
+
+/// Test the boot information passed by the bootloader
+pub fn test_bootloader_info() {
+    use crate::arch::boot::multiboot2::boot_info;
+    
+    println("Test 4: Bootloader Boot Information");
+    
+    match boot_info() {
+        Some(boot_info) => {
+            check("Boot information available", true);
+            check("Memory map present", boot_info.memory_map().is_some());
+        }
+        None => check("Boot information available", false),
+    }
+    
+    println("");
+}
+
+/// Test how the allocator classifies the Multiboot2 and EFI memory maps
+pub fn test_memory_ranges() {
+    println("Test 5: Memory Map Classification");
+    
+    // Memory map: entry size 24, version 0, then base, length, type
+    let mut mmap = [0u8; 8 + 3 * 24];
+    mmap[0..4].copy_from_slice(&24u32.to_le_bytes());
+    let entries = [
+        (0x10_0000u64, 0xF0_0000u64, MemoryType::Available),
+        (0x20_0000, 0x1000, MemoryType::BadRam),
+        (0x100_0000, 0x1_0000, MemoryType::AcpiReclaimable),
+    ];
+    for (i, &(base, length, kind)) in entries.iter().enumerate() {
+        let offset = 8 + i * 24;
+        mmap[offset..offset + 8].copy_from_slice(&base.to_le_bytes());
+        mmap[offset + 8..offset + 16].copy_from_slice(&length.to_le_bytes());
+        mmap[offset + 16..offset + 20].copy_from_slice(&(kind as u32).to_le_bytes());
+    }
+    
+    // EFI memory map: descriptor size 40, version 1, then type, physical
+    // start (at 8) and page count (at 24) of each descriptor
+    let mut efi = [0u8; 8 + 3 * 40];
+    efi[0..4].copy_from_slice(&40u32.to_le_bytes());
+    efi[4..8].copy_from_slice(&1u32.to_le_bytes());
+    let descriptors = [
+        (EfiMemoryType::Unusable, 0x30_0000u64, 1u64),
+        (EfiMemoryType::AcpiReclaim, 0x200_0000, 2),
+        (EfiMemoryType::BootServicesData, 0x40_0000, 4),
+    ];
+    for (i, &(kind, start, pages)) in descriptors.iter().enumerate() {
+        let offset = 8 + i * 40;
+        efi[offset..offset + 4].copy_from_slice(&(kind as u32).to_le_bytes());
+        efi[offset + 8..offset + 16].copy_from_slice(&start.to_le_bytes());
+        efi[offset + 24..offset + 32].copy_from_slice(&pages.to_le_bytes());
+    }
+    
+    let expected = [
+        (0x10_0000, 0x100_0000, RangeKind::Available),
+        (0x20_0000, 0x20_1000, RangeKind::Fenced),
+        (0x100_0000, 0x101_0000, RangeKind::Reclaimable),
+        (0x30_0000, 0x30_1000, RangeKind::Fenced),
+        (0x200_0000, 0x200_2000, RangeKind::Reclaimable),
+        (0x40_0000, 0x40_4000, RangeKind::Reserved),
+    ];
+    let matches = |addr: usize, count: usize| match unsafe { BootInfo::new(addr) } {
+        Ok(boot_info) => memory_ranges(&boot_info).eq(expected[..count].iter().copied()),
+        Err(_) => false,
+    };
+    
+    let addr = Builder::new()
+        .tag(TagType::MemoryMap as u32, &mmap)
+        .tag(TagType::EfiMemoryMap as u32, &efi)
+        .finish();
+    check("Bad RAM fenced, ACPI reclaimable, boot services memory free", matches(addr, 5));
+    
+    let addr = Builder::new()
+        .tag(TagType::MemoryMap as u32, &mmap)
+        .tag(TagType::EfiMemoryMap as u32, &efi)
+        .tag(TagType::EfiBootServicesNotTerminated as u32, &[])
+        .finish();
+    check("Boot services memory reserved while they run", matches(addr, 6));
+    
+    println("");
+}
+
+/// Run all boot information tests
+pub fn run_boot_tests() {
+    println("=== RUNNING BOOT INFORMATION TESTS ===");
+    println("");
+    
+    test_typed_accessors();
+    test_malformed();
+    test_cmdline();
+    test_bootloader_info();
+    test_memory_ranges();
+    
+    println("=== BOOT INFORMATION TESTS COMPLETE ===");
+}
@@ -3,7 +3,7 @@
 /// This module contains tests to verify exception handlers are working correctly.
 /// Tests are enabled via Cargo features to keep production builds clean.
 
-use crate::arch::drivers::vga::println;
+use crate::arch::println;
 
 /// Test divide by zero exception handler
 /// 
@@ -58,11 +58,13 @@ pub fn test_invalid_opcode() {
 /// Test breakpoint exception handler
 /// 
 /// This function triggers a breakpoint exception (#BP) for testing.
-/// Currently commented out as we haven't implemented the handler yet.
-#[allow(dead_code)]
+/// Unlike the other exception tests this one does not halt the system:
+/// the breakpoint handler returns with `iretq` and execution continues
+/// after the INT3 instruction.
 pub fn test_breakpoint() {
     println("=== EXCEPTION TEST: Breakpoint ===");
     println("This will trigger a breakpoint exception...");
+    println("Expected: Handler reports the breakpoint and execution resumes");
     println("");
     
     unsafe {
@@ -70,7 +72,142 @@ pub fn test_breakpoint() {
         core::arch::asm!("int3");  // Software breakpoint
     }
     
-    println("ERROR: Breakpoint did not trigger exception!");
+    println("OK: Execution resumed after breakpoint");
+    println("");
+}
+
+/// Test double fault handling on a kernel stack overflow
+/// 
+/// This function recurses until the boot stack runs into its guard page.
+/// The resulting page fault cannot be delivered on the overflowed stack, so
+/// the CPU raises a double fault, which runs on its own IST stack.
+/// 
+/// # Safety
+/// This function will cause the system to halt when the exception occurs.
+/// Only use this for testing exception handlers.
+pub fn test_stack_overflow() {
+    use crate::arch::gdt::{ist_stack, DOUBLE_FAULT_IST_INDEX};
+    
+    println("=== EXCEPTION TEST: Stack Overflow ===");
+    println("This will overflow the kernel stack...");
+    println("Expected: Double fault handler should report the fault");
+    println("");
+    
+    if ist_stack(DOUBLE_FAULT_IST_INDEX) == 0 {
+        println("ERROR: No double fault IST stack is configured!");
+        return;
+    }
+    
+    recurse(0);
+    
+    // This line should never be reached if exception handler works
+    println("ERROR: Stack overflow did not trigger exception!");
+}
+
+/// Recurse forever, using stack space on every call
+#[inline(never)]
+#[allow(unconditional_recursion)]
+fn recurse(depth: u64) -> u64 {
+    // black_box keeps the frame alive so the recursion can't become a loop
+    let frame = core::hint::black_box([depth; 32]);
+    recurse(frame[31] + 1) + frame[0]
+}
+
+/// Test the panic handler
+/// 
+/// Panics a few calls deep so the backtrace has several frames. The panic
+/// message contains a value whose `Display` implementation panics again,
+/// which exercises nested panic detection: the report should show the
+/// first panic's location and registers, then a "PANIC while panicking"
+/// notice, and the system should halt instead of recursing.
+/// 
+/// # Safety
+/// This function will halt the system (or exit QEMU in test builds).
+pub fn test_panic() {
+    println("=== EXCEPTION TEST: Panic ===");
+    println("This will panic inside nested function calls...");
+    println("Expected: Panic report with message, location, registers and backtrace,");
+    println("          then a nested panic notice while printing the message");
+    println("");
+    
+    panic_outer(3);
+    
+    // This line should never be reached
+    println("ERROR: Panic handler returned!");
+}
+
+/// Recurse `depth` times before panicking, to build up a backtrace
+#[inline(never)]
+fn panic_outer(depth: u64) -> u64 {
+    if core::hint::black_box(depth) == 0 {
+        panic!("test panic: {}", PanicsOnDisplay);
+    }
+    panic_outer(depth - 1) + 1
+}
+
+/// A value that panics when it is formatted
+struct PanicsOnDisplay;
+
+impl core::fmt::Display for PanicsOnDisplay {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("formatting message... ")?;
+        panic!("nested panic while formatting");
+    }
+}
+
+/// Test kernel symbol lookup used by crash reports
+/// 
+/// Looks up addresses inside known functions and checks the demangled
+/// names and offsets. Skipped if the bootloader did not provide the
+/// ELF-symbols tag.
+pub fn test_symbols() {
+    use crate::arch::symbols::{symbolize, Demangle};
+    use core::fmt::Write;
+    
+    println("=== EXCEPTION TEST: Kernel Symbols ===");
+    
+    let addr = test_symbols as *const () as u64;
+    let Some((name, offset)) = symbolize(addr + 4) else {
+        println("SKIPPED: Kernel symbol table not available");
+        println("");
+        return;
+    };
+    
+    if offset == 4 {
+        println("OK: Offset inside function is correct");
+    } else {
+        println("FAILED: Wrong offset inside function");
+    }
+    
+    // Demangle into a fixed buffer and compare
+    struct Buffer {
+        bytes: [u8; 128],
+        len: usize,
+    }
+    
+    impl Write for Buffer {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            let end = self.len + s.len();
+            self.bytes.get_mut(self.len..end).ok_or(core::fmt::Error)?.copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+    
+    let mut buffer = Buffer { bytes: [0; 128], len: 0 };
+    let expected = "noodleos::tests::exceptions::test_symbols";
+    if write!(buffer, "{}", Demangle(name)).is_ok() && &buffer.bytes[..buffer.len] == expected.as_bytes() {
+        println("OK: Symbol name demangled");
+    } else {
+        println("FAILED: Unexpected symbol name");
+    }
+    
+    match symbolize(crate::kernel_main as *const () as u64) {
+        Some(("kernel_main", 0)) => println("OK: Unmangled symbol found at offset 0"),
+        _ => println("FAILED: kernel_main not found"),
+    }
+    
+    println("");
 }
 
 /// Run all exception tests based on enabled features
@@ -78,6 +215,9 @@ pub fn run_exception_tests() {
     println("=== RUNNING EXCEPTION TESTS ===");
     println("");
     
+    // Symbol lookup does not raise an exception, so it always runs
+    test_symbols();
+    
     #[cfg(feature = "test-divide-by-zero")]
     {
         test_divide_by_zero();
@@ -89,18 +229,35 @@ pub fn run_exception_tests() {
     //     test_invalid_opcode();
     // }
     
-    // #[cfg(feature = "test-breakpoint")]
-    // {
-    //     test_breakpoint();
-    // }
+    #[cfg(feature = "test-breakpoint")]
+    {
+        test_breakpoint();
+    }
+    
+    #[cfg(feature = "test-stack-overflow")]
+    {
+        test_stack_overflow();
+    }
+    
+    #[cfg(feature = "test-panic")]
+    {
+        test_panic();
+    }
     
-    #[cfg(not(any(feature = "test-divide-by-zero")))]
+    #[cfg(not(any(
+        feature = "test-divide-by-zero",
+        feature = "test-breakpoint",
+        feature = "test-stack-overflow",
+        feature = "test-panic"
+    )))]
     {
         println("No exception tests enabled.");
         println("Available exception test features:");
         println("  --features test-divide-by-zero");
         println("  --features test-invalid-opcode (future)");
-        println("  --features test-breakpoint (future)");
+        println("  --features test-breakpoint");
+        println("  --features test-stack-overflow");
+        println("  --features test-panic");
     }
     
     println("");
@@ -2,7 +2,7 @@
 /// 
 /// This module contains tests for memory management functionality.
 
-use crate::arch::drivers::vga::println;
+use crate::arch::println;
 
 /// Run all memory tests
 pub fn run_memory_tests() {
@@ -19,6 +19,31 @@ pub fn run_memory_tests() {
         
         // Run CR3 register test
         crate::arch::x86_64::memory::tests::test_cr3_access();
+        
+        // Run guarded kernel stack test
+        crate::arch::x86_64::memory::tests::test_kernel_stack();
+        
+        // Run kernel heap test
+        crate::arch::x86_64::memory::tests::test_heap();
+        
+        // Run slab allocator test
+        crate::arch::x86_64::memory::tests::test_slab();
+        
+        // Run sparse memory map test
+        crate::arch::x86_64::memory::tests::test_sparse_physical_memory();
+        
+        // Run buddy allocator test and compare it with the bitmap allocator
+        crate::arch::x86_64::memory::tests::test_buddy_allocator();
+        crate::arch::x86_64::memory::tests::bench_frame_allocators();
+        
+        // Run memory zone test
+        crate::arch::x86_64::memory::tests::test_zones();
+        
+        // Run fenced and reclaimed memory test
+        crate::arch::x86_64::memory::tests::test_fenced_memory();
+        
+        // Run boot memory reservation test
+        crate::arch::x86_64::memory::tests::test_reservations();
     }
     
     #[cfg(not(feature = "test-memory"))]
@@ -2,38 +2,120 @@
 /// 
 /// This module contains all testing functionality for the operating system.
 /// Tests are organized by category and enabled via Cargo features.
+/// The `tests` boot option selects which of the compiled-in categories
+/// run, e.g. `tests=memory,boot` (all of them run without the option).
+/// 
+/// The tests are part of every build: the shell's `run-test` command runs
+/// the ones in `NAMED_TESTS` on demand. The Cargo features only decide
+/// which categories run at boot.
 
 pub mod exceptions;
 pub mod memory;
 pub mod hardware;
+pub mod boot;
+pub mod shell;
+
+use crate::arch::boot::cmdline::{self, BootOption, OptionKind};
+
+/// Boot options read by the test runner
+pub static BOOT_OPTIONS: [BootOption; 1] = [
+    BootOption::new("tests", OptionKind::List, "Test categories to run (e.g. tests=memory,boot)"),
+];
+
+/// A test that can be run on its own
+/// 
+/// Only tests that return are listed; the exception tests that halt the
+/// system still need their Cargo feature.
+pub struct NamedTest {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub run: fn(),
+}
+
+/// Tests available to the shell's `run-test` command
+pub static NAMED_TESTS: [NamedTest; 27] = [
+    NamedTest { name: "hardware", description: "All hardware tests", run: hardware::run_hardware_tests },
+    NamedTest { name: "serial", description: "Serial port configuration", run: hardware::test_serial },
+    NamedTest { name: "console", description: "Console sinks", run: hardware::test_console },
+    NamedTest { name: "pic", description: "8259 PIC masks and spurious IRQs", run: hardware::test_pic },
+    NamedTest { name: "apic", description: "Local APIC and I/O APIC", run: hardware::test_apic },
+    NamedTest { name: "irq", description: "IRQ handler registration", run: hardware::test_irq_registration },
+    NamedTest { name: "timer", description: "Tick source and monotonic clock", run: hardware::test_timer },
+    NamedTest { name: "kernel-timers", description: "Kernel timers, sleep and timeouts", run: hardware::test_kernel_timers },
+    NamedTest { name: "rtc", description: "CMOS RTC and wall clock", run: hardware::test_rtc },
+    NamedTest { name: "keyboard", description: "Keyboard decoding and keymaps", run: hardware::test_keyboard },
+    NamedTest { name: "mouse", description: "Mouse packet decoding", run: hardware::test_mouse },
+    NamedTest { name: "shell", description: "Shell line editing and input decoding", run: shell::run_shell_tests },
+    NamedTest { name: "boot", description: "Multiboot2 parser and command line", run: boot::run_boot_tests },
+    NamedTest { name: "physical-memory", description: "Physical frame allocator", run: crate::arch::x86_64::memory::tests::test_physical_allocator },
+    NamedTest { name: "virtual-memory", description: "Page mapping and translation", run: crate::arch::x86_64::memory::tests::test_virtual_memory },
+    NamedTest { name: "cr3", description: "CR3 register access", run: crate::arch::x86_64::memory::tests::test_cr3_access },
+    NamedTest { name: "kernel-stack", description: "Guarded kernel stacks", run: crate::arch::x86_64::memory::tests::test_kernel_stack },
+    NamedTest { name: "heap", description: "Kernel heap and allocators", run: crate::arch::x86_64::memory::tests::test_heap },
+    NamedTest { name: "slab", description: "Slab caches", run: crate::arch::x86_64::memory::tests::test_slab },
+    NamedTest { name: "physical-regions", description: "Frame allocator over a sparse memory map", run: crate::arch::x86_64::memory::tests::test_sparse_physical_memory },
+    NamedTest { name: "buddy", description: "Buddy frame allocator", run: crate::arch::x86_64::memory::tests::test_buddy_allocator },
+    NamedTest { name: "frame-bench", description: "Bitmap and buddy allocator timings", run: crate::arch::x86_64::memory::tests::bench_frame_allocators },
+    NamedTest { name: "zones", description: "DMA, DMA32 and Normal memory zones", run: crate::arch::x86_64::memory::tests::test_zones },
+    NamedTest { name: "fenced-memory", description: "Bad RAM, memmap exclusions and ACPI reclaim", run: crate::arch::x86_64::memory::tests::test_fenced_memory },
+    NamedTest { name: "reservations", description: "Boot memory reservations", run: crate::arch::x86_64::memory::tests::test_reservations },
+    NamedTest { name: "symbols", description: "Kernel symbol lookup", run: exceptions::test_symbols },
+    NamedTest { name: "breakpoint", description: "Breakpoint exception (resumes)", run: exceptions::test_breakpoint },
+];
+
+/// Look up a test by name
+pub fn find_test(name: &str) -> Option<&'static NamedTest> {
+    NAMED_TESTS.iter().find(|test| test.name == name)
+}
+
+/// Check whether the `tests` boot option selects a test category
+#[allow(dead_code)]
+fn category_selected(category: &str) -> bool {
+    match cmdline::list("tests") {
+        Some(mut categories) => categories.any(|c| c == category),
+        None => true,
+    }
+}
 
 /// Run all enabled tests based on Cargo features
 pub fn run_all_tests() {
-    use crate::arch::drivers::vga::println;
+    use crate::arch::println;
     
     println("=== NOODLEOS TEST SUITE ===");
     
     // Exception tests
     #[cfg(feature = "test-exceptions")]
-    {
+    if category_selected("exceptions") {
         exceptions::run_exception_tests();
     }
     
     // Memory tests (placeholder for future)
     #[cfg(feature = "test-memory")]
-    {
+    if category_selected("memory") {
         memory::run_memory_tests();
     }
     
     // Hardware tests (placeholder for future)
     #[cfg(feature = "test-hardware")]
-    {
+    if category_selected("hardware") {
         hardware::run_hardware_tests();
     }
     
+    // Boot information tests
+    #[cfg(feature = "test-boot")]
+    if category_selected("boot") {
+        boot::run_boot_tests();
+    }
+    
+    // Shell line editor and input decoding tests
+    #[cfg(feature = "test-shell")]
+    if category_selected("shell") {
+        shell::run_shell_tests();
+    }
+    
     // Virtual memory tests
     #[cfg(feature = "test-virtual-memory")]
-    {
+    if category_selected("virtual-memory") {
         crate::arch::x86_64::memory::tests::test_virtual_memory();
         crate::arch::x86_64::memory::tests::test_cr3_access();
     }
@@ -43,7 +125,9 @@ pub fn run_all_tests() {
         feature = "test-exceptions",
         feature = "test-memory",
         feature = "test-virtual-memory",
-        feature = "test-hardware"
+        feature = "test-hardware",
+        feature = "test-boot",
+        feature = "test-shell"
     )))]
     {
         println("No test categories enabled.");
@@ -53,9 +137,14 @@ pub fn run_all_tests() {
         println("Available test categories:");
         println("  test-exceptions      - IDT and exception handling tests");
         println("  test-divide-by-zero  - Divide by zero exception test");
+        println("  test-breakpoint      - Breakpoint exception test (resumes)");
+        println("  test-stack-overflow  - Kernel stack overflow (double fault on IST)");
+        println("  test-panic           - Panic report, backtrace and nested panic");
         println("  test-memory          - Physical and virtual memory tests");
         println("  test-virtual-memory  - Virtual memory system tests only");
         println("  test-hardware        - Hardware driver tests (future)");
+        println("  test-boot            - Multiboot2 boot information parser tests");
+        println("  test-shell           - Shell line editor and input decoding tests");
         println("");
         println("Example: cargo build --features run-tests,test-memory");
     }
@@ -0,0 +1,145 @@
+/// Shell Testing Module
+/// 
+/// Tests the shell's line editor and input decoding without a console:
+/// the editor draws into a discarding output function.
+
+use crate::arch::println;
+use crate::shell::input::SerialDecoder;
+use crate::shell::line::{Key, LineEditor, HISTORY_SIZE, MAX_LINE};
+
+/// Output function that discards what the editor draws
+fn discard(_text: &str) {}
+
+/// Feed a string of keys to an editor as characters
+fn type_str(editor: &mut LineEditor, text: &str) {
+    for byte in text.bytes() {
+        editor.feed(Key::Char(byte));
+    }
+}
+
+/// Print the result of a check
+fn check(name: &str, passed: bool) {
+    if passed {
+        crate::println!("OK: {}", name);
+    } else {
+        crate::println!("FAILED: {}", name);
+    }
+}
+
+/// Test cursor movement, insertion and deletion
+pub fn test_line_editing() {
+    println("=== SHELL TEST: Line Editing ===");
+    
+    let mut editor = LineEditor::new("> ", discard);
+    editor.start();
+    type_str(&mut editor, "helo");
+    editor.feed(Key::Left);
+    type_str(&mut editor, "l");
+    check("Insert in the middle of the line", editor.line() == "hello" && editor.cursor() == 4);
+    
+    editor.feed(Key::Home);
+    editor.feed(Key::Delete);
+    editor.feed(Key::End);
+    editor.feed(Key::Backspace);
+    check("Delete, Backspace, Home and End", editor.line() == "ell" && editor.cursor() == 3);
+    
+    editor.feed(Key::Left);
+    editor.feed(Key::ClearLine);
+    check("Ctrl+U deletes before the cursor", editor.line() == "l" && editor.cursor() == 0);
+    
+    editor.feed(Key::Left);
+    editor.feed(Key::Backspace);
+    check("Cursor stops at the line start", editor.line() == "l" && editor.cursor() == 0);
+    
+    editor.start();
+    for _ in 0..MAX_LINE + 5 {
+        editor.feed(Key::Char(b'x'));
+    }
+    check("Line length is limited", editor.line().len() == MAX_LINE);
+    
+    editor.start();
+    type_str(&mut editor, "abc");
+    editor.feed(Key::Cancel);
+    check("Ctrl+C abandons the line", editor.line().is_empty());
+    
+    println("");
+}
+
+/// Test the history: recall, skipping repeats and the size limit
+pub fn test_history() {
+    println("=== SHELL TEST: History ===");
+    
+    let mut editor = LineEditor::new("> ", discard);
+    for line in ["one", "two", "two", "", "three"] {
+        editor.start();
+        type_str(&mut editor, line);
+        check("Enter completes the line", editor.feed(Key::Enter));
+    }
+    let history = editor.history();
+    check("Empty and repeated lines are not kept", history.len() == 3);
+    
+    editor.start();
+    type_str(&mut editor, "partial");
+    editor.feed(Key::Up);
+    editor.feed(Key::Up);
+    check("Up recalls older lines", editor.line() == "two");
+    editor.feed(Key::Up);
+    editor.feed(Key::Up);
+    check("Up stops at the oldest line", editor.line() == "one");
+    editor.feed(Key::Down);
+    editor.feed(Key::Down);
+    editor.feed(Key::Down);
+    check("Down returns to the line being typed", editor.line() == "partial");
+    
+    let mut editor = LineEditor::new("> ", discard);
+    for i in 0..HISTORY_SIZE + 4 {
+        editor.start();
+        editor.feed(Key::Char(b'a' + i as u8));
+        editor.feed(Key::Enter);
+    }
+    let history = editor.history();
+    let oldest = history.iter().next();
+    check("Oldest lines are dropped when full", history.len() == HISTORY_SIZE && oldest == Some((5, "e")));
+    
+    println("");
+}
+
+/// Test decoding of serial terminal input
+pub fn test_serial_input() {
+    println("=== SHELL TEST: Serial Input ===");
+    
+    let mut decoder = SerialDecoder::new();
+    let mut keys = [None; 12];
+    let mut count = 0;
+    let bytes = b"a\x1b[D\x1b[3~\x1bOH\x1b[4~\x7f\r\n\n\x1b[5~\x03\x1b[B";
+    for &byte in bytes {
+        if let Some(key) = decoder.feed(byte) {
+            if count < keys.len() {
+                keys[count] = Some(key);
+            }
+            count += 1;
+        }
+    }
+    let expected = [
+        Key::Char(b'a'), Key::Left, Key::Delete, Key::Home, Key::End, Key::Backspace,
+        Key::Enter, Key::Enter, Key::Cancel, Key::Down,
+    ];
+    check(
+        "Escape sequences, CR LF and control characters",
+        count == expected.len() && keys.iter().zip(expected.iter()).all(|(key, expected)| *key == Some(*expected)),
+    );
+    
+    println("");
+}
+
+/// Run all shell tests
+pub fn run_shell_tests() {
+    println("=== RUNNING SHELL TESTS ===");
+    println("");
+    
+    test_line_editing();
+    test_history();
+    test_serial_input();
+    
+    println("=== SHELL TESTS COMPLETE ===");
+}